@@ -2,6 +2,18 @@ use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
+/// Config table holding named profiles.
+const PROFILES_KEY: &str = "profiles";
+
+/// Environment variable used for the bot token when the config does not provide one.
+pub(crate) const TOKEN_ENV_VAR: &str = "TG_BOT_TOKEN";
+
+/// `/start` payload prefix of registration links, followed by `registration_token`.
+pub(crate) const REGISTER_PAYLOAD_PREFIX: &str = "register_";
+/// Longest `/start` payload Telegram passes on.
+const START_PAYLOAD_MAX_CHARS: usize = 64;
+const REGISTRATION_TOKEN_MIN_CHARS: usize = 16;
+
 const GIT_VERSION: &str = env!("GIT_VERSION");
 const GIT_VERSION_STR: &str = concat!('\0', "Ver.:", env!("GIT_VERSION"), '\0');
 
@@ -16,6 +28,9 @@ pub(crate) struct Args {
     /// Path to config file.
     #[clap(short, long, value_parser, default_value = "config.toml")]
     pub(crate) config: String,
+    /// Name of a `[profiles.<name>]` table in the config file to use.
+    #[clap(short, long, value_parser)]
+    pub(crate) profile: Option<String>,
     #[clap(subcommand)]
     pub(crate) action: Option<Action>,
 }
@@ -27,6 +42,9 @@ pub(crate) enum Action {
         /// Path to config file.
         #[clap(short, long, value_parser, default_value = "config.toml")]
         config: String,
+        /// Name of a `[profiles.<name>]` table in the config file to use.
+        #[clap(short, long, value_parser)]
+        profile: Option<String>,
     },
     /// Install as service (Linux only).
     Install,
@@ -35,12 +53,37 @@ pub(crate) enum Action {
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub(crate) struct AppConfig {
     pub(crate) tg_bot_token: Option<String>,
+    pub(crate) tg_bot_token_file: Option<String>,
     pub(crate) tg_chat_id: Option<Vec<String>>,
+    pub(crate) admin_chat_ids: Option<Vec<String>>,
+    pub(crate) unauthorized_message: Option<String>,
     pub(crate) data_dir: Option<String>,
     pub(crate) input_timezone: Option<String>,
+    pub(crate) note_max_length: Option<usize>,
+    pub(crate) timestamp_precision: Option<String>,
+    pub(crate) duplicate_window_seconds: Option<u64>,
+    pub(crate) duplicate_action: Option<String>,
     pub(crate) glucose_after_meal_reminder_minutes: Option<u64>,
     pub(crate) glucose_after_meal_reminder_count: Option<u32>,
     pub(crate) glucose_after_meal_reminder_interval_minutes: Option<u64>,
+    pub(crate) voice_transcription_command: Option<String>,
+    pub(crate) privacy_notice: Option<bool>,
+    pub(crate) timezone_prompt: Option<bool>,
+    pub(crate) sync_writes: Option<bool>,
+    pub(crate) log_entry_notes: Option<bool>,
+    pub(crate) startup_selftest: Option<bool>,
+    pub(crate) backup_dir: Option<String>,
+    pub(crate) backup_keep: Option<usize>,
+    pub(crate) operator_stats: Option<bool>,
+    pub(crate) nudge_after_days: Option<u64>,
+    pub(crate) nudge_min_interval_days: Option<u64>,
+    pub(crate) nudge_quiet_hours: Option<String>,
+    pub(crate) iob_model: Option<String>,
+    pub(crate) iob_duration_minutes: Option<u64>,
+    pub(crate) api_listen: Option<String>,
+    pub(crate) api_token: Option<String>,
+    pub(crate) api_public_url: Option<String>,
+    pub(crate) registration_token: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -55,4 +98,121 @@ impl AppConfig {
         let config = toml::from_str(s)?;
         Ok(config)
     }
+    pub fn from_file_with_profile<P: AsRef<Path>>(
+        path: P,
+        profile: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let content = fs_err::read_to_string(path)?;
+        Self::from_str_with_profile(content, profile)
+    }
+    /// Parses the config, letting keys of `[profiles.<profile>]` override top-level keys.
+    pub fn from_str_with_profile<S: AsRef<str>>(
+        content: S,
+        profile: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let mut table = toml::from_str::<toml::Table>(content.as_ref())?;
+        let profiles = table.remove(PROFILES_KEY);
+        if let Some(name) = profile {
+            let selected = profiles
+                .as_ref()
+                .and_then(|profiles| profiles.get(name))
+                .ok_or_else(|| anyhow::anyhow!("profile '{name}' not found in config"))?;
+            let selected = selected
+                .as_table()
+                .ok_or_else(|| anyhow::anyhow!("profile '{name}' must be a table"))?;
+            table.extend(selected.clone());
+        }
+        let config = table.try_into()?;
+        Ok(config)
+    }
+
+    /// Parses `tg_chat_id` in order. Blank or non-numeric entries are errors naming their
+    /// 1-based position; repeated ids are kept once and group ids (negative) are left
+    /// out, both returned as warnings.
+    pub(crate) fn chat_ids(&self) -> anyhow::Result<(Vec<i64>, Vec<String>)> {
+        let entries = self
+            .tg_chat_id
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("tg_chat_id is required in config"))?;
+        let mut ids = Vec::with_capacity(entries.len());
+        let mut warnings = Vec::new();
+        for (index, raw) in entries.iter().enumerate() {
+            let position = index + 1;
+            let trimmed = raw.trim();
+            if trimmed.is_empty() {
+                anyhow::bail!("tg_chat_id entry {position} is empty");
+            }
+            let id = trimmed
+                .parse::<i64>()
+                .map_err(|e| anyhow::anyhow!("invalid tg_chat_id entry {position} '{raw}': {e}"))?;
+            if id <= 0 {
+                warnings.push(format!(
+                    "tg_chat_id entry {position} ({id}) is a group or channel and is ignored; only private chats are served"
+                ));
+                continue;
+            }
+            match ids.iter().position(|&seen| seen == id) {
+                Some(first) => warnings.push(format!(
+                    "tg_chat_id entry {position} repeats entry {} ({id})",
+                    first + 1
+                )),
+                None => ids.push(id),
+            }
+        }
+        Ok((ids, warnings))
+    }
+
+    /// Validates `registration_token`, the secret of `/start register_<token>` links. It
+    /// has to fit Telegram's 64-character `/start` payload of letters, digits, `_` and `-`.
+    pub(crate) fn registration_token(&self) -> anyhow::Result<Option<String>> {
+        let Some(token) = self
+            .registration_token
+            .as_deref()
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+        else {
+            return Ok(None);
+        };
+        let max = START_PAYLOAD_MAX_CHARS - REGISTER_PAYLOAD_PREFIX.len();
+        if !(REGISTRATION_TOKEN_MIN_CHARS..=max).contains(&token.len())
+            || !token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            anyhow::bail!(
+                "registration_token must be {REGISTRATION_TOKEN_MIN_CHARS}-{max} characters of A-Z, a-z, 0-9, _ and -"
+            );
+        }
+        Ok(Some(token.to_string()))
+    }
+
+    /// Resolves the bot token: `tg_bot_token`, then `tg_bot_token_file`, then `TG_BOT_TOKEN`.
+    pub fn bot_token(&self) -> anyhow::Result<String> {
+        self.bot_token_with_env(std::env::var(TOKEN_ENV_VAR).ok())
+    }
+    pub(crate) fn bot_token_with_env(&self, env_token: Option<String>) -> anyhow::Result<String> {
+        // Errors name the source only; the token itself must never end up in logs.
+        if let Some(token) = &self.tg_bot_token {
+            return non_empty_token(token, "tg_bot_token");
+        }
+        if let Some(file) = &self.tg_bot_token_file {
+            let content = fs_err::read_to_string(file)
+                .map_err(|e| anyhow::anyhow!("failed to read tg_bot_token_file: {e}"))?;
+            return non_empty_token(&content, "tg_bot_token_file");
+        }
+        if let Some(token) = env_token {
+            return non_empty_token(&token, TOKEN_ENV_VAR);
+        }
+        anyhow::bail!(
+            "bot token is required: set tg_bot_token or tg_bot_token_file in config, or {TOKEN_ENV_VAR}"
+        )
+    }
+}
+
+fn non_empty_token(raw: &str, source: &str) -> anyhow::Result<String> {
+    let token = raw.trim();
+    if token.is_empty() {
+        anyhow::bail!("{source} is empty");
+    }
+    Ok(token.to_string())
 }
@@ -48,4 +48,4 @@ pub(crate) fn install_systemd_service() -> anyhow::Result<()> {
     file.write_all(SERVICE_FILE)?;
     println!("service file written to {}", service_path.display());
     Ok(())
-}
\ No newline at end of file
+}
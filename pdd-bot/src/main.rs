@@ -10,16 +10,18 @@ mod tgbot;
 async fn main() -> ExitCode {
     let args = args::Args::parse();
     match args.action {
-        Some(args::Action::CheckConfig { config }) => match config_check(config).await {
-            Ok(()) => {
-                println!("config is ok");
-                ExitCode::SUCCESS
-            }
-            Err(e) => {
-                eprintln!("bad config: {e}");
-                ExitCode::from(3)
+        Some(args::Action::CheckConfig { config, profile }) => {
+            match config_check(config, profile.as_deref()).await {
+                Ok(()) => {
+                    println!("config is ok");
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("bad config: {e}");
+                    ExitCode::from(3)
+                }
             }
-        },
+        }
         Some(args::Action::Install) => match install::install() {
             Ok(()) => ExitCode::SUCCESS,
             Err(e) => {
@@ -28,7 +30,7 @@ async fn main() -> ExitCode {
             }
         },
         None => {
-            if let Err(e) = tgbot::run(args.config).await {
+            if let Err(e) = tgbot::run(args.config, args.profile.as_deref()).await {
                 eprintln!("error: {e}");
                 ExitCode::FAILURE
             } else {
@@ -38,6 +40,13 @@ async fn main() -> ExitCode {
     }
 }
 
-async fn config_check<P: AsRef<Path> + Send>(_path: P) -> anyhow::Result<()> {
+async fn config_check<P: AsRef<Path> + Send>(path: P, profile: Option<&str>) -> anyhow::Result<()> {
+    let config = args::AppConfig::from_file_with_profile(path, profile)?;
+    config.bot_token()?;
+    let (_, warnings) = config.chat_ids()?;
+    config.registration_token()?;
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
     Ok(())
 }
@@ -1,27 +1,171 @@
 use crate::args;
-use chrono::{Datelike, LocalResult, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono::{
+    DateTime, Datelike, LocalResult, NaiveDate, NaiveTime, SecondsFormat, TimeZone, Timelike, Utc,
+};
 use chrono_tz::Tz;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use teloxide::net::Download;
 use teloxide::prelude::*;
-use teloxide::types::{KeyboardButton, KeyboardMarkup};
+use teloxide::types::{
+    InlineKeyboardMarkup, InputFile, KeyboardButton, KeyboardMarkup, MessageId, ParseMode,
+};
 use tokio::sync::Mutex;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+#[cfg(feature = "api")]
+mod api;
+#[cfg(not(feature = "api"))]
+mod api {
+    use super::AppState;
+    use std::sync::Arc;
+
+    /// Stand-in for builds without the `api` feature: setting `api_listen` is an error.
+    pub(super) async fn start(
+        listen: Option<&str>,
+        _token: Option<&str>,
+        _state: &Arc<AppState>,
+    ) -> anyhow::Result<()> {
+        if listen.is_some_and(|listen| !listen.trim().is_empty()) {
+            anyhow::bail!("api_listen is set, but pdd-bot was built without the api feature");
+        }
+        Ok(())
+    }
+}
+mod backup;
+mod batch;
+mod bolus;
+mod calendar;
+mod chart;
+mod checksum;
+mod clock;
+mod commands;
+mod debug;
+mod edits;
+mod export;
+mod fasting;
+mod goals;
+mod history;
+mod insulin;
+mod language;
+mod merge;
+mod nudge;
+mod paging;
+mod pdf;
+mod plain;
+mod plot;
+mod predict;
+mod records;
+mod registration;
+mod reports;
+mod routing;
+mod settings;
+mod share;
+mod stats;
+mod status;
+mod storage;
+mod summary;
+mod tagging;
+mod timezone;
+mod today;
+mod units;
+mod voice;
+
+use routing::Command;
+
+/// `data_dir` when the config file leaves it out.
+const DEFAULT_DATA_DIR: &str = "data";
 const DEFAULT_AFTER_MEAL_REMINDER_MINUTES: u64 = 150;
 const DEFAULT_AFTER_MEAL_REMINDER_COUNT: u32 = 3;
 const DEFAULT_AFTER_MEAL_REMINDER_INTERVAL_MINUTES: u64 = 15;
+/// Delay of the single `/postmeal` reminder after a before-meal reading.
+const POSTMEAL_REMINDER_MINUTES: u64 = 120;
+const DEFAULT_NOTE_MAX_LENGTH: usize = 200;
+/// Pending entries the user has not answered within this time are dropped.
+const PENDING_EXPIRY: Duration = Duration::from_secs(30 * 60);
+const PENDING_SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// A chat outside `allowed_chat_ids` is told so again after this long.
+const TOLD_UNAUTHORIZED_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+const TOLD_UNAUTHORIZED_MAX: usize = 1000;
+const DEFAULT_DUPLICATE_WINDOW_SECONDS: u64 = 60;
+const DUPLICATE_WARNING: &str = "\n⚠️ Looks like a duplicate of your last reading — saved anyway.";
+const DUPLICATE_SKIPPED: &str = "Looks like a duplicate of your last reading — not saved.";
+const PRIVACY_NOTICE: &str = "Privacy notice: your entries are stored as plain text CSV/TXT files \
+on the server running this bot and are not encrypted by it. Anyone with access to that server \
+can read them. Send /privacy to see this notice again.";
+/// Reply to messages in a group, where the diary would take entries from anyone.
+const GROUP_MESSAGE: &str = "This diary bot only works in private chats, so nothing sent in \
+this group is saved. Message me directly to keep your own diary.";
 const BTN_GLUCOSE_BEFORE_MEAL: &str = "🩸 Glucose: Before meal";
 const BTN_GLUCOSE_AFTER_MEAL: &str = "🩸 Glucose: After meal";
 const BTN_WEIGHT: &str = "⚖️ Weight";
+const BTN_KETONES: &str = "🧪 Ketones";
+const BTN_FOOD: &str = "🍽 Food";
 const BTN_SHOW_MENU: &str = "📋 Show menu";
+const BTN_MORE_MEDS: &str = "📂 More meds ▾";
 const MED_BUTTON_PREFIX: &str = "💊 ";
+/// Medication buttons on the menu keyboard; with more medications the rest are behind
+/// [`BTN_MORE_MEDS`].
+const MENU_MEDICATION_BUTTONS: usize = 8;
+const MEDICATION_FUZZY_MIN_LEN: usize = 4;
+const MEDICATION_FUZZY_MAX_DISTANCE: usize = 2;
+/// Longest medication name, so the button label stays readable.
+const MEDICATION_NAME_MAX_CHARS: usize = 40;
+/// Weight entries further apart than this are not compared by `/weightalert`.
+const WEIGHT_ALERT_WINDOW_DAYS: i64 = 7;
+const RENAME_KEEP_LOG_FLAG: &str = "--keep-log";
+/// Reply to a message whose handling failed; `/verbose on` appends the error.
+const FAILURE_REPLY: &str = "Sorry, that could not be saved or processed. Please try again later.";
+const PRECISION_USAGE: &str =
+    "Usage: /precision <glucose|weight|ketones|insulin|carbs> <1|0.5|0.1|0.05|0.01>";
 const MEDICATIONS_FILE: &str = "medications.txt";
+/// Largest medication list accepted as an uploaded document.
+const MEDICATION_IMPORT_MAX_BYTES: u32 = 64 * 1024;
 const MEDICATION_LOG_FILE: &str = "medication_log.csv";
+const GLUCOSE_FILE: &str = "glucose.csv";
+const WEIGHT_FILE: &str = "weight.csv";
+const MEALS_FILE: &str = "meals.csv";
+const FASTING_FILE: &str = "fasting.csv";
+const KETONES_FILE: &str = "ketones.csv";
+const FOOD_FILE: &str = "food.csv";
+const INSULIN_FILE: &str = "insulin.csv";
+const GLUCOSE_MIN_MMOL_L: f64 = 1.0;
+const GLUCOSE_MAX_MMOL_L: f64 = 35.0;
+const TIME_RANGES_MAX: usize = 6;
+/// Appended to a glucose confirmation by `/inrange` when the reading is well inside the range.
+const IN_RANGE_NOTE: &str = "Nicely in range";
+const MG_DL_PER_MMOL_L: f64 = 18.0;
+const DEFAULT_SOURCE: &str = "manual";
+const SOURCE_MARKER: char = '!';
+const SOURCE_MAX_LEN: usize = 20;
+/// Tag that marks a glucose entry as checked by the user, like a trailing `!`.
+const CONFIRMED_TAG: &str = "#confirmed";
+const CONFIRMED_VALUE: &str = "yes";
+const GLUCOSE_HEADER: &str =
+    "timestamp,chat_id,tag,value,note,source,message_id,unit,confirmed,trend,tags";
+const GLUCOSE_NOTE_COLUMN: usize = 4;
+const GLUCOSE_MESSAGE_ID_COLUMN: usize = 6;
+const GLUCOSE_UNIT_COLUMN: usize = 7;
+/// Space-separated `#tags` added with `/tag`.
+const GLUCOSE_TAGS_COLUMN: usize = 10;
+const WEIGHT_HEADER: &str = "timestamp,chat_id,value_kg,message_id";
+const WEIGHT_MESSAGE_ID_COLUMN: usize = 3;
+const KETONES_HEADER: &str = "timestamp,chat_id,value_mmol_l,note,message_id";
+const KETONES_NOTE_COLUMN: usize = 3;
+const KETONES_MESSAGE_ID_COLUMN: usize = 4;
+const KETONES_MIN_MMOL_L: f64 = 0.0;
+const KETONES_MAX_MMOL_L: f64 = 10.0;
+const KETONES_WARNING_MMOL_L: f64 = 1.5;
+const FOOD_HEADER: &str = "timestamp,chat_id,item,grams,note";
+const FOOD_MAX_GRAMS: f64 = 5000.0;
+const MEALS_HEADER: &str = "timestamp,chat_id,note";
+const MEDICATION_LOG_HEADER: &str = "timestamp,chat_id,medication";
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 enum GlucoseTag {
     BeforeMeal,
     AfterMeal,
@@ -34,28 +178,526 @@ impl GlucoseTag {
             GlucoseTag::AfterMeal => "after_meal",
         }
     }
+
+    fn from_csv_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "before_meal" => Some(GlucoseTag::BeforeMeal),
+            "after_meal" => Some(GlucoseTag::AfterMeal),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Parsed glucose input. Several values share the same timestamp, note and source.
+#[derive(Debug, Clone, PartialEq)]
+struct GlucoseInput {
+    values: Vec<f64>,
+    timestamp: Option<String>,
+    note: Option<String>,
+    source: Option<String>,
+    /// Set by `#confirmed` or a trailing `!`: no out-of-range warning for this entry.
+    confirmed: bool,
+    /// Trend arrow reported by the meter, e.g. `^` or `↑`.
+    trend: Option<GlucoseTrend>,
+}
+
+/// Trend arrow of a CGM or meter reading; stored as the Nightscout `direction` name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GlucoseTrend {
+    DoubleUp,
+    SingleUp,
+    FortyFiveUp,
+    Flat,
+    FortyFiveDown,
+    SingleDown,
+    DoubleDown,
+}
+
+impl GlucoseTrend {
+    /// Parses a typed token (`^^`, `^`, `->`, `v`, `vv`) or an arrow (`⇈`, `↑`, `↗`, `→`,
+    /// `↘`, `↓`, `⇊`, also doubled single arrows and the emoji forms).
+    fn parse(token: &str) -> Option<Self> {
+        let token = token.replace('\u{fe0f}', "");
+        match token.to_lowercase().as_str() {
+            "^^" | "⇈" | "↑↑" | "⬆⬆" => Some(GlucoseTrend::DoubleUp),
+            "^" | "↑" | "⬆" => Some(GlucoseTrend::SingleUp),
+            "↗" => Some(GlucoseTrend::FortyFiveUp),
+            "->" | "→" | "➡" => Some(GlucoseTrend::Flat),
+            "↘" => Some(GlucoseTrend::FortyFiveDown),
+            "v" | "↓" | "⬇" => Some(GlucoseTrend::SingleDown),
+            "vv" | "⇊" | "↓↓" | "⬇⬇" => Some(GlucoseTrend::DoubleDown),
+            _ => None,
+        }
+    }
+
+    /// Name in Nightscout's `direction` enum.
+    fn nightscout_direction(self) -> &'static str {
+        match self {
+            GlucoseTrend::DoubleUp => "DoubleUp",
+            GlucoseTrend::SingleUp => "SingleUp",
+            GlucoseTrend::FortyFiveUp => "FortyFiveUp",
+            GlucoseTrend::Flat => "Flat",
+            GlucoseTrend::FortyFiveDown => "FortyFiveDown",
+            GlucoseTrend::SingleDown => "SingleDown",
+            GlucoseTrend::DoubleDown => "DoubleDown",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 enum PendingEntry {
     GlucoseBeforeMeal,
     GlucoseAfterMeal,
     Weight,
+    Ketones,
+    Food,
+    ConfirmClear(ClearTarget),
+    ConfirmMedication(String),
+    ConfirmImport(Vec<GlucoseInput>),
+    ConfirmUnitConversion(settings::GlucoseUnit),
+    ConfirmResetSettings,
+    EditRow(history::RowEdit),
+}
+
+/// Pending entry with the time it was set, so stale ones can be swept.
+#[derive(Debug, Clone)]
+struct TimedPending {
+    entry: PendingEntry,
+    created: Instant,
+}
+
+/// Outcome of [`add_medication`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MedicationAdd {
+    /// Holds the name as stored, after normalization.
+    Added(String),
+    AlreadyExists(String),
+    /// The name would be confused with a button or command; holds the reply.
+    Rejected(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MedicationMatch {
+    /// Same name ignoring case and extra whitespace; holds the stored spelling.
+    Exact(String),
+    /// Single close spelling that needs confirmation.
+    Fuzzy(String),
+    None,
+}
+
+/// Per-user data files that `/clear` may truncate and `/export` may filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClearTarget {
+    Glucose,
+    Weight,
+    Ketones,
+    Insulin,
+    Meals,
+    Food,
+    MedicationLog,
+}
+
+impl ClearTarget {
+    const ALL: [ClearTarget; 7] = [
+        ClearTarget::Glucose,
+        ClearTarget::Weight,
+        ClearTarget::Ketones,
+        ClearTarget::Insulin,
+        ClearTarget::Meals,
+        ClearTarget::Food,
+        ClearTarget::MedicationLog,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            ClearTarget::Glucose => "glucose",
+            ClearTarget::Weight => "weight",
+            ClearTarget::Ketones => "ketones",
+            ClearTarget::Insulin => "insulin",
+            ClearTarget::Meals => "meals",
+            ClearTarget::Food => "food",
+            ClearTarget::MedicationLog => "medlog",
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            ClearTarget::Glucose => GLUCOSE_FILE,
+            ClearTarget::Weight => WEIGHT_FILE,
+            ClearTarget::Ketones => KETONES_FILE,
+            ClearTarget::Insulin => INSULIN_FILE,
+            ClearTarget::Meals => MEALS_FILE,
+            ClearTarget::Food => FOOD_FILE,
+            ClearTarget::MedicationLog => MEDICATION_LOG_FILE,
+        }
+    }
+
+    /// Current header of the file; older files are upgraded to it at startup.
+    fn header(self) -> &'static str {
+        match self {
+            ClearTarget::Glucose => GLUCOSE_HEADER,
+            ClearTarget::Weight => WEIGHT_HEADER,
+            ClearTarget::Ketones => KETONES_HEADER,
+            ClearTarget::Insulin => insulin::INSULIN_HEADER,
+            ClearTarget::Meals => MEALS_HEADER,
+            ClearTarget::Food => FOOD_HEADER,
+            ClearTarget::MedicationLog => MEDICATION_LOG_HEADER,
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|target| target.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Message-sending side of the bot, abstracted so handlers can be driven in tests.
+pub(crate) trait Responder: Clone + Send + Sync + 'static {
+    fn send_text(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    /// Sends text rendered in a monospace block, for tables and grids.
+    fn send_preformatted(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    /// Sends a file as a document with a short caption.
+    fn send_document(
+        &self,
+        chat_id: ChatId,
+        file_name: &str,
+        bytes: Vec<u8>,
+        caption: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    /// Sends an image shown in the chat itself, with a short caption.
+    fn send_photo(
+        &self,
+        chat_id: ChatId,
+        file_name: &str,
+        bytes: Vec<u8>,
+        caption: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    /// Sends text with buttons attached to the message itself; a tap comes back as a
+    /// callback query carrying the button's data.
+    fn send_inline(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        buttons: InlineKeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+impl Responder for Bot {
+    fn send_text(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let request = self
+            .send_message(chat_id, text.into())
+            .reply_markup(keyboard);
+        async move {
+            request.await?;
+            Ok(())
+        }
+    }
+
+    fn send_preformatted(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let html = format!("<pre>{}</pre>", html_escape(&text.into()));
+        let request = self
+            .send_message(chat_id, html)
+            .parse_mode(ParseMode::Html)
+            .reply_markup(keyboard);
+        async move {
+            request.await?;
+            Ok(())
+        }
+    }
+
+    fn send_document(
+        &self,
+        chat_id: ChatId,
+        file_name: &str,
+        bytes: Vec<u8>,
+        caption: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let file = InputFile::memory(bytes).file_name(file_name.to_string());
+        let request = Requester::send_document(self, chat_id, file)
+            .caption(caption.into())
+            .reply_markup(keyboard);
+        async move {
+            request.await?;
+            Ok(())
+        }
+    }
+
+    fn send_photo(
+        &self,
+        chat_id: ChatId,
+        file_name: &str,
+        bytes: Vec<u8>,
+        caption: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let file = InputFile::memory(bytes).file_name(file_name.to_string());
+        let request = Requester::send_photo(self, chat_id, file)
+            .caption(caption.into())
+            .reply_markup(keyboard);
+        async move {
+            request.await?;
+            Ok(())
+        }
+    }
+
+    fn send_inline(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        buttons: InlineKeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let request = self
+            .send_message(chat_id, text.into())
+            .reply_markup(buttons);
+        async move {
+            request.await?;
+            Ok(())
+        }
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Precision of timestamps written to CSV files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum TimestampPrecision {
+    #[default]
+    Minute,
+    Second,
+}
+
+impl TimestampPrecision {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "minute" => Some(TimestampPrecision::Minute),
+            "second" => Some(TimestampPrecision::Second),
+            _ => None,
+        }
+    }
+
+    /// Formats `dt` as RFC 3339 truncated to this precision, with the UTC offset `tz` has
+    /// at that moment (e.g. `+02:00`), so the file shows local wall time. All stored
+    /// timestamps go through here.
+    fn format(self, dt: DateTime<Utc>, tz: Tz) -> String {
+        let truncated = match self {
+            TimestampPrecision::Minute => dt.with_second(0).and_then(|dt| dt.with_nanosecond(0)),
+            TimestampPrecision::Second => dt.with_nanosecond(0),
+        };
+        truncated
+            .unwrap_or(dt)
+            .with_timezone(&tz)
+            .to_rfc3339_opts(SecondsFormat::Secs, false)
+    }
+}
+
+/// What to do with a glucose entry that repeats the last saved reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum DuplicateAction {
+    #[default]
+    Warn,
+    Skip,
+}
+
+impl DuplicateAction {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "warn" => Some(DuplicateAction::Warn),
+            "skip" => Some(DuplicateAction::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// Detection of accidental resends: same tag and value within `window_seconds`.
+#[derive(Debug, Clone, Copy)]
+struct DuplicateCheck {
+    /// `0` disables the check.
+    window_seconds: u64,
+    action: DuplicateAction,
+}
+
+/// Settings that affect how user input is parsed.
+#[derive(Debug, Clone)]
+struct InputOptions {
+    tz: Tz,
+    /// Maximum note length in characters; `0` means unlimited.
+    note_max_len: usize,
+    timestamp_precision: TimestampPrecision,
+    /// Source of the current time for parsing and for stamping new rows.
+    clock: Arc<dyn clock::Clock>,
+    /// The chat's `/units`; with mg/dL, whole numbers are read as mg/dL.
+    glucose_unit: settings::GlucoseUnit,
+    /// `sync_writes`: fsync data files after every write, so a saved entry survives a
+    /// power loss right after the confirmation was sent.
+    sync_writes: bool,
+    /// `log_entry_notes`: include what the user typed in the "entry saved" log line.
+    log_entry_notes: bool,
+}
+
+impl AppState {
+    /// Current time from the configured clock.
+    fn now(&self) -> DateTime<Utc> {
+        self.input.clock.now()
+    }
+
+    /// Input options for `chat_id`: its `/timezone` when set, otherwise `input_timezone`,
+    /// and its `/units`.
+    fn input_for(&self, chat_id: ChatId) -> InputOptions {
+        let mut input = self.input.clone();
+        let user_settings = settings_or_default(self, chat_id);
+        if let Some(tz) = user_settings.tz() {
+            input.tz = tz;
+        }
+        input.glucose_unit = user_settings.glucose_unit.unwrap_or_default();
+        input
+    }
+
+    /// Whether `chat_id` is in `tg_chat_id` or registered itself with a link. Only private
+    /// chats are allowed: in a group anyone could log into the diary.
+    fn is_allowed(&self, chat_id: ChatId) -> bool {
+        chat_id.is_user()
+            && (self.allowed_chat_ids.contains(&chat_id) || self.registered().contains(&chat_id))
+    }
+
+    /// Every allowed chat: the configured ones and the registered ones.
+    fn allowed_chats(&self) -> Vec<ChatId> {
+        let mut chats = self.allowed_chat_ids.iter().copied().collect::<Vec<_>>();
+        chats.extend(self.registered().difference(&self.allowed_chat_ids));
+        chats
+    }
+
+    fn registered(&self) -> std::sync::RwLockReadGuard<'_, HashSet<ChatId>> {
+        self.registered_chat_ids
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Runs `rewrite`, which reads some of `chat_id`'s files and replaces them, while
+    /// holding that chat's file lock. Appends take the same lock through
+    /// [`storage::locked`], so a rewrite started from a button, a reminder or another
+    /// task cannot interleave with them and drop a new row or another rewrite's change.
+    /// The lock is not reentrant: `rewrite` must not take it again.
+    fn rewrite_files<T>(&self, chat_id: ChatId, rewrite: impl FnOnce() -> T) -> T {
+        let lock = Arc::clone(
+            self.file_locks
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .entry(chat_id)
+                .or_default(),
+        );
+        let _guard = lock
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        rewrite()
+    }
+}
+
+/// Compares without stopping at the first differing byte, so response times do not
+/// reveal how much of a guessed token was right.
+fn same_token(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+impl InputOptions {
+    /// Stored form of `dt`: `timestamp_precision` with the offset of `tz`, which is the chat's
+    /// `/timezone` for options from [`AppState::input_for`].
+    fn format_timestamp(&self, dt: DateTime<Utc>) -> String {
+        self.timestamp_precision.format(dt, self.tz)
+    }
+
+    /// Stored form of the current time; every writer stamps new rows with this.
+    fn now_timestamp(&self) -> String {
+        self.format_timestamp(self.clock.now())
+    }
 }
 
 #[derive(Debug, Clone)]
 struct AppState {
-    pending_by_chat: Arc<Mutex<HashMap<ChatId, PendingEntry>>>,
+    pending_by_chat: Arc<Mutex<HashMap<ChatId, TimedPending>>>,
+    last_listing_by_chat: Arc<Mutex<HashMap<ChatId, history::LastListing>>>,
     after_meal_reminder_generations: Arc<Mutex<HashMap<ChatId, u64>>>,
     allowed_chat_ids: HashSet<ChatId>,
+    /// Chats that joined with a `/start register_<token>` link; allowed like
+    /// `allowed_chat_ids`. Read by the sync API handlers too, hence a std lock.
+    registered_chat_ids: Arc<std::sync::RwLock<HashSet<ChatId>>>,
+    /// Secret of registration links; `None` turns registration off.
+    registration_token: Option<String>,
+    /// Reply to chats outside `allowed_chat_ids`; `None` keeps the bot silent to them.
+    unauthorized_message: Option<String>,
+    /// Chats outside `allowed_chat_ids` that already got `unauthorized_message`, with when.
+    told_unauthorized: Arc<Mutex<HashMap<ChatId, Instant>>>,
+    /// Per-chat locks held while a data file is rewritten, see [`AppState::rewrite_files`].
+    file_locks: Arc<std::sync::Mutex<HashMap<ChatId, Arc<std::sync::Mutex<()>>>>>,
+    /// Chats that may use `/debug`; a subset of `allowed_chat_ids`.
+    admin_chat_ids: HashSet<ChatId>,
+    /// When the bot started; `/status` reports uptime and task runs from it.
+    started_at: DateTime<Utc>,
     data_dir: PathBuf,
-    input_tz: Tz,
+    /// Config file the bot was started with; `/merge` reads other profiles from it.
+    config_path: Option<PathBuf>,
+    input: InputOptions,
+    duplicates: DuplicateCheck,
     glucose_after_meal_reminder_minutes: u64,
     glucose_after_meal_reminder_count: u32,
     glucose_after_meal_reminder_interval_minutes: u64,
+    /// Speech-to-text for voice replies to a glucose prompt; `None` when not configured.
+    transcriber: Option<Arc<dyn voice::Transcriber>>,
+    /// Show [`PRIVACY_NOTICE`] on a chat's first message.
+    privacy_notice: bool,
+    /// Ask a chat without a `/timezone` for one on its first message.
+    timezone_prompt: bool,
+    /// Daily backups of the data directory; `None` when `backup_dir` is not set.
+    backup: Option<backup::BackupOptions>,
+    /// Write daily entry and active user counts to `stats.csv` in `data_dir`.
+    operator_stats: bool,
+    nudge: nudge::NudgeOptions,
+    iob: insulin::IobOptions,
+    /// Start of `/share` links: `api_public_url`, or the `api_listen` address over HTTP.
+    /// `None` when the read API is off.
+    share_url: Option<String>,
 }
 
-pub(crate) async fn run<P: AsRef<Path> + Send>(path: P) -> anyhow::Result<()> {
+pub(crate) async fn run<P: AsRef<Path> + Send>(
+    path: P,
+    profile: Option<&str>,
+) -> anyhow::Result<()> {
     init_tracing();
     tracing::info!(
         "{}, version: {}",
@@ -63,26 +705,29 @@ pub(crate) async fn run<P: AsRef<Path> + Send>(path: P) -> anyhow::Result<()> {
         args::get_version_str()
     );
     let path = path.as_ref();
-    let config = args::AppConfig::from_file(path)?;
-    let tg_bot_token = config
-        .tg_bot_token
-        .ok_or_else(|| anyhow::anyhow!("tg_bot_token is required in config"))?;
-    let tg_chat_id = config
-        .tg_chat_id
-        .ok_or_else(|| anyhow::anyhow!("tg_chat_id is required in config"))?;
-    let allowed_chat_ids = tg_chat_id
+    let config = args::AppConfig::from_file_with_profile(path, profile)?;
+    let tg_bot_token = config.bot_token()?;
+    let (chat_ids, warnings) = config.chat_ids()?;
+    for warning in warnings {
+        tracing::warn!("{warning}");
+    }
+    let allowed_chat_ids = chat_ids.into_iter().map(ChatId).collect::<HashSet<_>>();
+    let registration_token = config.registration_token()?;
+    let admin_chat_ids = config
+        .admin_chat_ids
         .iter()
+        .flatten()
         .map(|id| {
             id.parse::<i64>()
                 .map(ChatId)
-                .map_err(|e| anyhow::anyhow!("invalid tg_chat_id '{id}': {e}"))
+                .map_err(|e| anyhow::anyhow!("invalid admin_chat_ids entry '{id}': {e}"))
         })
         .collect::<anyhow::Result<HashSet<_>>>()?;
     let data_dir = config
         .data_dir
         .clone()
         .map(PathBuf::from)
-        .unwrap_or_else(|| PathBuf::from("data"));
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR));
     let input_tz_name = config
         .input_timezone
         .clone()
@@ -92,6 +737,22 @@ pub(crate) async fn run<P: AsRef<Path> + Send>(path: P) -> anyhow::Result<()> {
             "invalid input_timezone '{input_tz_name}'. Use IANA timezone, e.g. Europe/Kyiv or UTC"
         )
     })?;
+    let note_max_len = config.note_max_length.unwrap_or(DEFAULT_NOTE_MAX_LENGTH);
+    let timestamp_precision = match config.timestamp_precision.as_deref() {
+        Some(name) => TimestampPrecision::parse(name).ok_or_else(|| {
+            anyhow::anyhow!("invalid timestamp_precision '{name}'. Use minute or second")
+        })?,
+        None => TimestampPrecision::default(),
+    };
+    let duplicate_action = match config.duplicate_action.as_deref() {
+        Some(name) => DuplicateAction::parse(name).ok_or_else(|| {
+            anyhow::anyhow!("invalid duplicate_action '{name}'. Use warn or skip")
+        })?,
+        None => DuplicateAction::default(),
+    };
+    let duplicate_window_seconds = config
+        .duplicate_window_seconds
+        .unwrap_or(DEFAULT_DUPLICATE_WINDOW_SECONDS);
     let glucose_after_meal_reminder_minutes = config
         .glucose_after_meal_reminder_minutes
         .unwrap_or(DEFAULT_AFTER_MEAL_REMINDER_MINUTES);
@@ -101,33 +762,158 @@ pub(crate) async fn run<P: AsRef<Path> + Send>(path: P) -> anyhow::Result<()> {
     let glucose_after_meal_reminder_interval_minutes = config
         .glucose_after_meal_reminder_interval_minutes
         .unwrap_or(DEFAULT_AFTER_MEAL_REMINDER_INTERVAL_MINUTES);
+    let transcriber = config
+        .voice_transcription_command
+        .as_deref()
+        .map(voice::CommandTranscriber::parse)
+        .transpose()?
+        .map(|transcriber| Arc::new(transcriber) as Arc<dyn voice::Transcriber>);
+    let backup = config.backup_dir.as_ref().map(|dir| backup::BackupOptions {
+        dir: PathBuf::from(dir),
+        keep: config.backup_keep.unwrap_or(backup::DEFAULT_BACKUP_KEEP),
+    });
+    if backup.as_ref().is_some_and(|backup| backup.keep == 0) {
+        anyhow::bail!("backup_keep must be at least 1");
+    }
+    let nudge_quiet_hours = config
+        .nudge_quiet_hours
+        .as_deref()
+        .unwrap_or(nudge::DEFAULT_NUDGE_QUIET_HOURS);
+    let nudge = nudge::NudgeOptions {
+        after_days: config
+            .nudge_after_days
+            .unwrap_or(nudge::DEFAULT_NUDGE_AFTER_DAYS),
+        min_interval_days: config
+            .nudge_min_interval_days
+            .unwrap_or(nudge::DEFAULT_NUDGE_MIN_INTERVAL_DAYS),
+        quiet_hours: match nudge_quiet_hours {
+            "" => None,
+            window => Some(settings::parse_time_window(window).ok_or_else(|| {
+                anyhow::anyhow!("invalid nudge_quiet_hours '{window}'. Use HH:MM-HH:MM or \"\"")
+            })?),
+        },
+    };
+    if nudge.after_days == 0 {
+        anyhow::bail!("nudge_after_days must be at least 1");
+    }
+    let iob = insulin::IobOptions {
+        model: match config.iob_model.as_deref() {
+            Some(name) => insulin::IobModel::parse(name).ok_or_else(|| {
+                anyhow::anyhow!("invalid iob_model '{name}'. Use linear or bilinear")
+            })?,
+            None => insulin::IobModel::default(),
+        },
+        duration_minutes: config
+            .iob_duration_minutes
+            .unwrap_or(insulin::DEFAULT_IOB_DURATION_MINUTES),
+    };
+    iob.validate()?;
     fs_err::create_dir_all(&data_dir)?;
+    storage::upgrade_headers(&data_dir, config.sync_writes.unwrap_or(false))?;
+    let clock: Arc<dyn clock::Clock> = Arc::new(clock::RealClock);
+    if config.startup_selftest.unwrap_or(false) {
+        storage::self_test(&data_dir, clock.as_ref())
+            .inspect_err(|err| tracing::error!("{err}"))?;
+        tracing::info!("startup self-test passed in {}", data_dir.display());
+    }
+    let registered_chat_ids = registration::load_registered(&data_dir)?;
 
-    let state = AppState {
+    let state = Arc::new(AppState {
         pending_by_chat: Arc::new(Mutex::new(HashMap::new())),
+        last_listing_by_chat: Arc::new(Mutex::new(HashMap::new())),
         after_meal_reminder_generations: Arc::new(Mutex::new(HashMap::new())),
         allowed_chat_ids,
+        registered_chat_ids: Arc::new(std::sync::RwLock::new(registered_chat_ids)),
+        registration_token,
+        unauthorized_message: config
+            .unauthorized_message
+            .filter(|message| !message.trim().is_empty()),
+        told_unauthorized: Arc::new(Mutex::new(HashMap::new())),
+        file_locks: Arc::default(),
+        admin_chat_ids,
+        started_at: clock.now(),
         data_dir,
-        input_tz,
+        config_path: Some(path.to_path_buf()),
+        input: InputOptions {
+            tz: input_tz,
+            note_max_len,
+            timestamp_precision,
+            clock,
+            glucose_unit: settings::GlucoseUnit::default(),
+            sync_writes: config.sync_writes.unwrap_or(false),
+            log_entry_notes: config.log_entry_notes.unwrap_or(false),
+        },
+        duplicates: DuplicateCheck {
+            window_seconds: duplicate_window_seconds,
+            action: duplicate_action,
+        },
         glucose_after_meal_reminder_minutes,
         glucose_after_meal_reminder_count,
         glucose_after_meal_reminder_interval_minutes,
-    };
+        transcriber,
+        privacy_notice: config.privacy_notice.unwrap_or(true),
+        timezone_prompt: config.timezone_prompt.unwrap_or(true),
+        backup,
+        operator_stats: config.operator_stats.unwrap_or(false),
+        nudge,
+        iob,
+        share_url: share::share_url(
+            config.api_listen.as_deref(),
+            config.api_public_url.as_deref(),
+        ),
+    });
+    spawn_pending_sweeper(Arc::clone(&state));
+    backup::spawn_backup_task(Arc::clone(&state));
+    stats::spawn_stats_task(Arc::clone(&state));
+    api::start(
+        config.api_listen.as_deref(),
+        config.api_token.as_deref(),
+        &state,
+    )
+    .await?;
 
     let bot = Bot::new(tg_bot_token);
-    tracing::info!("Running with config: {}", path.display());
+    nudge::spawn_nudge_task(bot.clone(), Arc::clone(&state));
+    match profile {
+        Some(profile) => tracing::info!(
+            "Running with config: {}, profile: {profile}",
+            path.display()
+        ),
+        None => tracing::info!("Running with config: {}", path.display()),
+    }
 
-    let shared_state = Arc::new(state);
-    teloxide::repl(bot, move |bot: Bot, message: Message| {
-        let state = Arc::clone(&shared_state);
-        async move {
-            if let Err(err) = handle_message(bot, message, state).await {
-                tracing::error!("handler error: {err}");
-            }
-            respond(())
-        }
-    })
-    .await;
+    let handler = dptree::entry()
+        .branch(Update::filter_message().endpoint(
+            |bot: Bot, message: Message, state: Arc<AppState>| async move {
+                if let Err(err) = handle_message(bot, message, state).await {
+                    tracing::error!("handler error: {err}");
+                }
+                respond(())
+            },
+        ))
+        .branch(Update::filter_edited_message().endpoint(
+            |bot: Bot, message: Message, state: Arc<AppState>| async move {
+                if let Err(err) = edits::handle_edited_message(bot, message, state).await {
+                    tracing::error!("edit handler error: {err}");
+                }
+                respond(())
+            },
+        ))
+        .branch(Update::filter_callback_query().endpoint(
+            |bot: Bot, query: CallbackQuery, state: Arc<AppState>| async move {
+                if let Err(err) = handle_callback_query(bot, query, state).await {
+                    tracing::error!("callback handler error: {err}");
+                }
+                respond(())
+            },
+        ));
+    Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![state])
+        .default_handler(|_update| Box::pin(async {}))
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
 
     Ok(())
 }
@@ -140,215 +926,2245 @@ fn build_menu_keyboard(medications: &[String]) -> KeyboardMarkup {
         ],
         vec![
             KeyboardButton::new(BTN_WEIGHT),
+            KeyboardButton::new(BTN_KETONES),
+        ],
+        vec![
+            KeyboardButton::new(BTN_FOOD),
             KeyboardButton::new(BTN_SHOW_MENU),
         ],
     ];
 
-    for meds_chunk in medications.chunks(2) {
-        let mut row = Vec::with_capacity(2);
-        for med in meds_chunk {
-            row.push(KeyboardButton::new(format!("{MED_BUTTON_PREFIX}{med}")));
-        }
-        rows.push(row);
+    if medications.len() > MENU_MEDICATION_BUTTONS {
+        rows.extend(medication_rows(&medications[..MENU_MEDICATION_BUTTONS]));
+        rows.push(vec![KeyboardButton::new(BTN_MORE_MEDS)]);
+    } else {
+        rows.extend(medication_rows(medications));
     }
 
     KeyboardMarkup::new(rows).resize_keyboard()
 }
 
+/// Buttons of the medications past the first [`MENU_MEDICATION_BUTTONS`], sent for
+/// [`BTN_MORE_MEDS`], and [`BTN_SHOW_MENU`] to go back. A tap logs the medication like
+/// a menu button.
+fn more_medications_keyboard(medications: &[String]) -> KeyboardMarkup {
+    let mut rows = medication_rows(
+        medications
+            .get(MENU_MEDICATION_BUTTONS..)
+            .unwrap_or_default(),
+    );
+    rows.push(vec![KeyboardButton::new(BTN_SHOW_MENU)]);
+    KeyboardMarkup::new(rows).resize_keyboard()
+}
+
+fn medication_rows(medications: &[String]) -> Vec<Vec<KeyboardButton>> {
+    medications
+        .chunks(2)
+        .map(|meds| {
+            meds.iter()
+                .map(|med| KeyboardButton::new(format!("{MED_BUTTON_PREFIX}{med}")))
+                .collect()
+        })
+        .collect()
+}
+
 async fn menu_keyboard(state: &AppState, chat_id: ChatId) -> KeyboardMarkup {
     let medications = load_medications(&state.data_dir, chat_id).unwrap_or_default();
     build_menu_keyboard(&medications)
 }
 
-async fn handle_message(bot: Bot, message: Message, state: Arc<AppState>) -> anyhow::Result<()> {
-    let chat_id = message.chat.id;
-    if !state.allowed_chat_ids.contains(&chat_id) {
-        return Ok(());
+/// `/menutext` reply: the buttons of `keyboard` row by row, as the chat sees them.
+fn menu_text(keyboard: &KeyboardMarkup, language: settings::Language, no_emoji: bool) -> String {
+    let mut lines = vec!["Menu buttons:".to_string()];
+    for (index, row) in keyboard.keyboard.iter().enumerate() {
+        let labels = row
+            .iter()
+            .map(|button| {
+                let label = language::localized_label(&button.text, language);
+                if no_emoji {
+                    plain::plain_label(&label)
+                } else {
+                    label
+                }
+            })
+            .collect::<Vec<_>>();
+        lines.push(format!("Row {}: {}", index + 1, labels.join(" | ")));
     }
+    lines.join("\n")
+}
 
+async fn handle_message(bot: Bot, message: Message, state: Arc<AppState>) -> anyhow::Result<()> {
+    if let Some(voice) = message.voice() {
+        let file_id = voice.file.id.clone();
+        let audio = async {
+            let file = bot.get_file(file_id).await?;
+            let mut audio = Vec::new();
+            bot.download_file(&file.path, &mut audio).await?;
+            Ok(audio)
+        };
+        return handle_voice(&bot, message.chat.id, message.id, audio, state).await;
+    }
+    if let Some(document) = message.document() {
+        let file_id = document.file.id.clone();
+        let content = async {
+            let file = bot.get_file(file_id).await?;
+            let mut content = Vec::new();
+            bot.download_file(&file.path, &mut content).await?;
+            Ok(content)
+        };
+        return handle_document(
+            &bot,
+            message.chat.id,
+            document.file_name.as_deref().unwrap_or_default(),
+            document.file.size,
+            content,
+            state,
+        )
+        .await;
+    }
     let text = match message.text() {
-        Some(text) => text.trim(),
+        Some(text) => text,
         None => return Ok(()),
     };
+    handle_text(&bot, message.chat.id, message.id, text, state).await
+}
 
-    if text == "/help" {
-        bot.send_message(chat_id, help_text())
-            .reply_markup(menu_keyboard(&state, chat_id).await)
-            .await?;
+/// Answers a tap on an inline button, which stops its spinner, and handles its data.
+async fn handle_callback_query(
+    bot: Bot,
+    query: CallbackQuery,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    bot.answer_callback_query(query.id.clone()).await?;
+    let (Some(message), Some(data)) = (query.message.as_ref(), query.data.as_deref()) else {
         return Ok(());
-    }
+    };
+    handle_callback(&bot, message.chat().id, data, state).await
+}
 
-    if let Some((tag, payload)) = parse_glucose_add_command(text) {
-        let payload = payload.trim();
-        if payload.is_empty() {
-            bot.send_message(
-                chat_id,
-                "Usage:\n/addgb <value> [MM/DD hh:mm] [@note]\n/addga <value> [MM/DD hh:mm] [@note]",
-            )
-            .reply_markup(menu_keyboard(&state, chat_id).await)
-            .await?;
-            return Ok(());
+/// Handles the data of a tapped inline button. Only `/history` sends such buttons.
+async fn handle_callback<R: Responder>(
+    bot: &R,
+    chat_id: ChatId,
+    data: &str,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    let bot = &plain::ChatResponder::new(bot.clone(), state.clone());
+    if !state.is_allowed(chat_id) {
+        return notify_unauthorized(bot, &state, chat_id).await;
+    }
+    match history::history_callback(&state, chat_id, data).await {
+        Ok((text, Some(buttons))) => bot.send_inline(chat_id, text, buttons).await,
+        Ok((text, None)) => {
+            bot.send_text(chat_id, text, menu_keyboard(&state, chat_id).await)
+                .await
+        }
+        Err(err) => {
+            report_failure(bot, &state, chat_id, &err).await;
+            Err(err)
         }
+    }
+}
 
-        let (value, timestamp, note) = match parse_glucose_payload(payload, state.input_tz) {
-            Ok(ok) => ok,
-            Err(msg) => {
-                bot.send_message(chat_id, msg.to_string())
-                    .reply_markup(menu_keyboard(&state, chat_id).await)
-                    .await?;
-                return Ok(());
+/// Handles an uploaded document: a `.txt` file of `size` bytes is imported as a
+/// medication list, one name per line like `medications.txt`. `content` is only awaited
+/// for such a file.
+async fn handle_document<R: Responder>(
+    bot: &R,
+    chat_id: ChatId,
+    file_name: &str,
+    size: u32,
+    content: impl Future<Output = anyhow::Result<Vec<u8>>>,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    let bot = &plain::ChatResponder::new(bot.clone(), state.clone());
+    if !state.is_allowed(chat_id) {
+        return notify_unauthorized(bot, &state, chat_id).await;
+    }
+    let reply = if !file_name.to_lowercase().ends_with(".txt") {
+        "To import medications, send a .txt file with one name per line, like medications.txt."
+            .to_string()
+    } else if size > MEDICATION_IMPORT_MAX_BYTES {
+        format!(
+            "{file_name} is too large to import ({} KB, max {} KB).",
+            size.div_ceil(1024),
+            MEDICATION_IMPORT_MAX_BYTES / 1024
+        )
+    } else {
+        let imported = match content.await.map(String::from_utf8) {
+            Ok(Ok(text)) => {
+                let file_name = file_name.to_string();
+                storage::locked(&state, chat_id, move |state| {
+                    import_medications(state, chat_id, &file_name, &text)
+                })
+                .await
             }
+            Ok(Err(_)) => Ok(format!("{file_name} is not UTF-8 text, nothing imported.")),
+            Err(err) => Err(err),
         };
-
-        append_glucose_csv(
-            &state.data_dir,
-            chat_id,
-            tag,
-            value,
-            timestamp.as_deref(),
-            note.as_deref(),
-        )?;
-        update_after_meal_reminders(&bot, &state, chat_id, tag).await;
-        bot.send_message(chat_id, "Glucose entry saved ✅")
-            .reply_markup(menu_keyboard(&state, chat_id).await)
-            .await?;
-        return Ok(());
-    }
-
-    if let Some(name) = parse_addmed_command(text) {
-        if name.is_empty() {
-            bot.send_message(chat_id, "Usage: /addmed <medication name>")
-                .reply_markup(menu_keyboard(&state, chat_id).await)
-                .await?;
-            return Ok(());
+        match imported {
+            Ok(reply) => reply,
+            Err(err) => {
+                report_failure(bot, &state, chat_id, &err).await;
+                return Err(err);
+            }
         }
+    };
+    bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+        .await
+}
 
-        if add_medication(&state, chat_id, name).await? {
-            bot.send_message(chat_id, format!("Medication added: {name}"))
-                .reply_markup(menu_keyboard(&state, chat_id).await)
-                .await?;
-        } else {
-            bot.send_message(chat_id, format!("Medication already exists: {name}"))
-                .reply_markup(menu_keyboard(&state, chat_id).await)
-                .await?;
-        }
+/// Handles a voice message sent while a glucose prompt is pending: the transcript is
+/// handled like a typed value when it holds exactly one number. Other voice messages
+/// are ignored. `audio` is only awaited when a transcriber is configured.
+async fn handle_voice<R: Responder>(
+    bot: &R,
+    chat_id: ChatId,
+    message_id: MessageId,
+    audio: impl Future<Output = anyhow::Result<Vec<u8>>>,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    let bot = &plain::ChatResponder::new(bot.clone(), state.clone());
+    if !state.is_allowed(chat_id) {
+        return notify_unauthorized(bot, &state, chat_id).await;
+    }
+    if !matches!(
+        get_pending(&state, chat_id).await,
+        Some(PendingEntry::GlucoseBeforeMeal | PendingEntry::GlucoseAfterMeal)
+    ) {
         return Ok(());
     }
-
-    match text {
-        "/start" | "/menu" | BTN_SHOW_MENU => {
-            send_menu(&bot, chat_id, &state).await?;
-            return Ok(());
-        }
-        BTN_GLUCOSE_BEFORE_MEAL => {
-            set_pending(&state, chat_id, PendingEntry::GlucoseBeforeMeal).await;
-            bot.send_message(
-                chat_id,
-                "Enter glucose: <value> [date time] [@note], e.g. 5.8 2/1 9:05 @before breakfast",
-            )
-            .reply_markup(menu_keyboard(&state, chat_id).await)
-            .await?;
-            return Ok(());
+    let Some(transcriber) = state.transcriber.clone() else {
+        bot.send_text(
+            chat_id,
+            "Voice input is not available. Please type the value.",
+            menu_keyboard(&state, chat_id).await,
+        )
+        .await?;
+        return Ok(());
+    };
+    let transcript = match audio.await {
+        Ok(audio) => transcriber.transcribe(audio).await,
+        Err(err) => Err(err),
+    };
+    let value = match transcript {
+        Ok(transcript) => voice::parse_spoken_number(&transcript),
+        Err(err) => {
+            tracing::warn!("voice transcription failed for chat {}: {err}", chat_id.0);
+            None
         }
-        BTN_GLUCOSE_AFTER_MEAL => {
-            set_pending(&state, chat_id, PendingEntry::GlucoseAfterMeal).await;
-            bot.send_message(
+    };
+    match value {
+        Some(value) => handle_text(bot, chat_id, message_id, &value.to_string(), state).await,
+        None => {
+            bot.send_text(
                 chat_id,
-                "Enter glucose: <value> [date time] [@note], e.g. 7.2 2/1 11:00 @after lunch",
+                "Could not recognize a single number. Please type the value.",
+                menu_keyboard(&state, chat_id).await,
             )
-            .reply_markup(menu_keyboard(&state, chat_id).await)
             .await?;
-            return Ok(());
+            Ok(())
         }
-        BTN_WEIGHT => {
-            set_pending(&state, chat_id, PendingEntry::Weight).await;
-            bot.send_message(chat_id, "Enter weight value (kg), for example: 78.4")
-                .reply_markup(menu_keyboard(&state, chat_id).await)
-                .await?;
-            return Ok(());
-        }
-        _ => {}
     }
+}
 
-    if let Some(medication_name) = parse_medication_button(text) {
-        if medication_exists(&state, chat_id, medication_name).await {
-            append_medication_log_csv(&state.data_dir, chat_id, medication_name)?;
-            bot.send_message(
-                chat_id,
-                format!("Medication usage saved ✅ ({medication_name})"),
-            )
-            .reply_markup(menu_keyboard(&state, chat_id).await)
-            .await?;
-        } else {
-            bot.send_message(chat_id, "Unknown medication. Use /addmed <name> first.")
-                .reply_markup(menu_keyboard(&state, chat_id).await)
+/// Tells the chat that its message failed with [`FAILURE_REPLY`], so it is not left
+/// without an answer. With `/verbose on` the reply also carries the error and its causes.
+/// The caller still returns `err`, which is logged.
+async fn report_failure<R: Responder>(
+    bot: &R,
+    state: &AppState,
+    chat_id: ChatId,
+    err: &anyhow::Error,
+) {
+    let mut reply = FAILURE_REPLY.to_string();
+    if settings_or_default(state, chat_id)
+        .verbose_errors
+        .unwrap_or(false)
+    {
+        reply.push_str(&format!("\nError: {err:#}"));
+    }
+    let keyboard = menu_keyboard(state, chat_id).await;
+    if let Err(send_err) = bot.send_text(chat_id, reply, keyboard).await {
+        tracing::warn!(
+            "failed to report an error to chat {}: {send_err}",
+            chat_id.0
+        );
+    }
+}
+
+/// Sends `unauthorized_message` to a chat that is not allowed, with `{chat_id}` replaced
+/// by its id, or [`GROUP_MESSAGE`] to a group. A chat is told again only after
+/// [`TOLD_UNAUTHORIZED_EXPIRY`] and gets no reply in between; past
+/// [`TOLD_UNAUTHORIZED_MAX`] remembered chats the one told longest ago is forgotten and may
+/// be told sooner.
+async fn notify_unauthorized<R: Responder>(
+    bot: &R,
+    state: &AppState,
+    chat_id: ChatId,
+) -> anyhow::Result<()> {
+    if !chat_id.is_user() {
+        if mark_told_unauthorized(state, chat_id, Instant::now()).await {
+            tracing::info!(
+                "telling group {} the bot only works in private chats",
+                chat_id.0
+            );
+            bot.send_text(chat_id, GROUP_MESSAGE, build_menu_keyboard(&[]))
                 .await?;
         }
         return Ok(());
     }
+    let Some(message) = &state.unauthorized_message else {
+        return Ok(());
+    };
+    if !mark_told_unauthorized(state, chat_id, Instant::now()).await {
+        return Ok(());
+    }
+    tracing::info!("telling chat {} it is not allowed", chat_id.0);
+    bot.send_text(
+        chat_id,
+        message.replace("{chat_id}", &chat_id.0.to_string()),
+        build_menu_keyboard(&[]),
+    )
+    .await
+}
 
-    if let Some(pending) = get_pending(&state, chat_id).await {
-        match pending {
-            PendingEntry::GlucoseBeforeMeal | PendingEntry::GlucoseAfterMeal => {
-                match parse_glucose_payload(text, state.input_tz) {
-                    Ok((value, timestamp, note)) => {
+/// Records that `chat_id` is told it is not served at `now`. Returns `false` when it was
+/// already told within [`TOLD_UNAUTHORIZED_EXPIRY`]. At most [`TOLD_UNAUTHORIZED_MAX`] chats
+/// are remembered; the one told longest ago is forgotten first.
+async fn mark_told_unauthorized(state: &AppState, chat_id: ChatId, now: Instant) -> bool {
+    let mut told = state.told_unauthorized.lock().await;
+    told.retain(|_, at| now.saturating_duration_since(*at) < TOLD_UNAUTHORIZED_EXPIRY);
+    if told.contains_key(&chat_id) {
+        return false;
+    }
+    if told.len() >= TOLD_UNAUTHORIZED_MAX
+        && let Some(oldest) = told
+            .iter()
+            .min_by_key(|(_, at)| **at)
+            .map(|(chat, _)| *chat)
+    {
+        told.remove(&oldest);
+    }
+    told.insert(chat_id, now);
+    true
+}
+
+async fn handle_text<R: Responder>(
+    bot: &R,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: &str,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    let bot = &plain::ChatResponder::new(bot.clone(), state.clone());
+    let text = text.trim();
+    let command = routing::parse_command(text);
+    if !state.is_allowed(chat_id) {
+        if let Command::Start { payload } = command
+            && registration::register(&state, chat_id, payload)?
+        {
+            bot.send_text(
+                chat_id,
+                "This chat is registered. Welcome to your diabetes diary!",
+                menu_keyboard(&state, chat_id).await,
+            )
+            .await?;
+            return send_menu(bot, chat_id, &state).await;
+        }
+        return notify_unauthorized(bot, &state, chat_id).await;
+    }
+
+    if command != Command::Privacy
+        && state.privacy_notice
+        && !settings_or_default(&state, chat_id)
+            .privacy_acknowledged
+            .unwrap_or(false)
+    {
+        bot.send_text(
+            chat_id,
+            PRIVACY_NOTICE,
+            menu_keyboard(&state, chat_id).await,
+        )
+        .await?;
+        storage::blocking(&state, move |state| {
+            acknowledge_privacy_notice(state, chat_id)
+        })
+        .await?;
+    }
+
+    let ask_timezone = !matches!(command, Command::Timezone { .. });
+    if let Err(err) = run_command(bot, chat_id, message_id, text, command, Arc::clone(&state)).await
+    {
+        report_failure(bot, &state, chat_id, &err).await;
+        return Err(err);
+    }
+    // Sent last, so the reply to the command does not replace its buttons.
+    if ask_timezone {
+        timezone::prompt_if_unset(bot, &state, chat_id).await?;
+    }
+    Ok(())
+}
+
+/// Replies to an allowed chat's `command`; `text` is the message it was parsed from.
+async fn run_command<R: Responder>(
+    bot: &R,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: &str,
+    command: Command<'_>,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    match command {
+        Command::Privacy => {
+            bot.send_text(
+                chat_id,
+                PRIVACY_NOTICE,
+                menu_keyboard(&state, chat_id).await,
+            )
+            .await?;
+            storage::blocking(&state, move |state| {
+                acknowledge_privacy_notice(state, chat_id)
+            })
+            .await?;
+        }
+        Command::Debug { raw } if state.admin_chat_ids.contains(&chat_id) => {
+            let reply = if raw.is_empty() {
+                "Usage: /debug <raw text>".to_string()
+            } else {
+                debug::describe_route(&state, chat_id, raw).await
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Tail { target } if state.admin_chat_ids.contains(&chat_id) => {
+            let target = target.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                debug::tail_reply(state, chat_id, &target)
+            })
+            .await?;
+            bot.send_preformatted(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Status if state.admin_chat_ids.contains(&chat_id) => {
+            let reply = storage::blocking(&state, status::status_report).await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::MenuText => {
+            let keyboard = menu_keyboard(&state, chat_id).await;
+            let user_settings = settings_or_default(&state, chat_id);
+            let text = menu_text(
+                &keyboard,
+                user_settings.language.unwrap_or_default(),
+                user_settings.no_emoji.unwrap_or(false),
+            );
+            bot.send_text(chat_id, text, keyboard).await?;
+        }
+        Command::Commands => {
+            paging::send_long_message(
+                bot,
+                chat_id,
+                commands::commands_text(),
+                menu_keyboard(&state, chat_id).await,
+            )
+            .await?;
+        }
+        Command::Help => {
+            paging::send_long_message(
+                bot,
+                chat_id,
+                help_text(),
+                menu_keyboard(&state, chat_id).await,
+            )
+            .await?;
+        }
+        Command::AddGlucose { tag, payload } => {
+            if payload.is_empty() {
+                bot.send_text(
+                    chat_id,
+                    "Usage:\n/addgb <value> [MM/DD hh:mm] [@note]\n/addga <value> [MM/DD hh:mm] [@note]",
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+                return Ok(());
+            }
+
+            let input = match parse_glucose_payload(payload, &state.input_for(chat_id)) {
+                Ok(ok) => ok,
+                Err(msg) => {
+                    bot.send_text(
+                        chat_id,
+                        msg.to_string(),
+                        menu_keyboard(&state, chat_id).await,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            };
+
+            let count = input.values.len();
+            let reply = save_glucose_entry(bot, &state, chat_id, message_id, tag, input, |mark| {
+                typed_glucose_saved(mark, count)
+            })
+            .await?
+            .unwrap_or_else(|| DUPLICATE_SKIPPED.to_string());
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::MealEffect => {
+            let report = storage::blocking(&state, move |state| {
+                let glucose =
+                    records::read_records::<records::GlucoseRecord>(&state.data_dir, chat_id)?;
+                let meals = records::read_records::<records::MealRecord>(&state.data_dir, chat_id)?;
+                let mut report = reports::meal_effect_report(
+                    &glucose.rows,
+                    &meals.rows,
+                    state.input_for(chat_id).tz,
+                    state.now(),
+                );
+                append_skipped_note(&mut report, glucose.skipped() + meals.skipped());
+                Ok(report)
+            })
+            .await?;
+            paging::send_long_message(bot, chat_id, report, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Month => {
+            let overview = storage::blocking(&state, move |state| {
+                let glucose = records::read_glucose_records(&state.data_dir, chat_id)?;
+                let tz = state.input_for(chat_id).tz;
+                let week_start = settings::load_settings(&state.data_dir, chat_id)?
+                    .week_start
+                    .unwrap_or_default();
+                Ok(reports::month_overview(
+                    &glucose,
+                    tz,
+                    state.now().with_timezone(&tz).date_naive(),
+                    week_start.weekday(),
+                ))
+            })
+            .await?;
+            paging::send_long_preformatted(
+                bot,
+                chat_id,
+                overview,
+                menu_keyboard(&state, chat_id).await,
+            )
+            .await?;
+        }
+        Command::MedEffect(args) => {
+            let reply = match args {
+                Ok((name, days)) => {
+                    let medication = match match_medication(&state, chat_id, name).await {
+                        MedicationMatch::Exact(name) | MedicationMatch::Fuzzy(name) => name,
+                        MedicationMatch::None => normalize_medication_name(name),
+                    };
+                    storage::blocking(&state, move |state| {
+                        let glucose = records::read_records::<records::GlucoseRecord>(
+                            &state.data_dir,
+                            chat_id,
+                        )?;
+                        let mut report = reports::medication_effect_report(
+                            &glucose.rows,
+                            &read_medication_log(&state.data_dir, chat_id)?,
+                            &medication,
+                            state.input_for(chat_id).tz,
+                            state.now(),
+                            days,
+                        );
+                        append_skipped_note(&mut report, glucose.skipped());
+                        Ok(report)
+                    })
+                    .await?
+                }
+                Err(usage) => usage,
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Goals => {
+            let reply = storage::blocking(&state, move |state| {
+                Ok(goals::goals_report(
+                    &settings::load_settings(&state.data_dir, chat_id)?,
+                    &records::read_glucose_records(&state.data_dir, chat_id)?,
+                    &records::read_weight_records(&state.data_dir, chat_id)?,
+                    &load_medications(&state.data_dir, chat_id)?,
+                    &read_medication_log(&state.data_dir, chat_id)?,
+                    state.input_for(chat_id).tz,
+                    state.now(),
+                ))
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Summary => {
+            let (glucose, weights) = storage::blocking(&state, move |state| {
+                Ok((
+                    records::read_glucose_records(&state.data_dir, chat_id)?,
+                    records::read_weight_records(&state.data_dir, chat_id)?,
+                ))
+            })
+            .await?;
+            let user_settings = settings_or_default(&state, chat_id);
+            let axis = chart::GlucoseAxis::for_settings(&user_settings);
+            let summary = summary::clinic_summary(
+                &glucose,
+                &weights,
+                user_settings.general_range(),
+                state.input_for(chat_id).tz,
+                state.now(),
+            );
+            send_chart(
+                bot,
+                &state,
+                chat_id,
+                summary::SUMMARY_FILE_NAME,
+                || summary::render_pdf(&summary, axis),
+                format!(
+                    "Glucose summary for the last {} days",
+                    summary::SUMMARY_DAYS
+                ),
+                || summary::numbers(&summary),
+            )
+            .await?;
+        }
+        Command::Calendar => {
+            let schedule = storage::blocking(&state, move |state| {
+                Ok(calendar::scheduled(
+                    &load_medications(&state.data_dir, chat_id)?,
+                    &settings::load_settings(&state.data_dir, chat_id)?.med_times,
+                ))
+            })
+            .await?;
+            if schedule.is_empty() {
+                bot.send_text(
+                    chat_id,
+                    "No scheduled medications. Set daily times with /medtime <name> <HH:MM>[,HH:MM...] first.",
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+                return Ok(());
+            }
+            let now = state.now();
+            let events = schedule.iter().map(|(_, times)| times.len()).sum::<usize>();
+            let ics = calendar::medication_calendar(
+                chat_id,
+                &schedule,
+                now.with_timezone(&state.input_for(chat_id).tz).date_naive(),
+                now,
+            );
+            bot.send_document(
+                chat_id,
+                calendar::CALENDAR_FILE_NAME,
+                ics.into_bytes(),
+                format!("Medication reminders: {events} daily events. Open the file to add them to your calendar."),
+                menu_keyboard(&state, chat_id).await,
+            )
+            .await?;
+        }
+        Command::Today(Err(usage)) => {
+            bot.send_text(chat_id, usage, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Today(Ok(format)) => {
+            let now = state.now();
+            let (glucose, insulin, carbs, meals) = storage::blocking(&state, move |state| {
+                Ok((
+                    records::read_glucose_records(&state.data_dir, chat_id)?,
+                    records::read_records(&state.data_dir, chat_id)?.rows,
+                    records::read_records(&state.data_dir, chat_id)?.rows,
+                    records::read_records(&state.data_dir, chat_id)?.rows,
+                ))
+            })
+            .await?;
+            let tz = state.input_for(chat_id).tz;
+            let data = today::day_data(
+                &glucose,
+                &insulin,
+                &carbs,
+                &meals,
+                tz,
+                now.with_timezone(&tz).date_naive(),
+            );
+            let user_settings = settings_or_default(&state, chat_id);
+            let axis = chart::GlucoseAxis::for_settings(&user_settings);
+            send_chart(
+                bot,
+                &state,
+                chat_id,
+                &today::file_name(&data, format),
+                || today::render(&data, user_settings.general_range(), axis, tz, format),
+                today::caption(&data),
+                || today::numbers(&data, tz),
+            )
+            .await?;
+        }
+        Command::Chart(Err(usage)) => {
+            bot.send_text(chat_id, usage, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Chart(Ok((metric, days, format))) => {
+            let tz = state.input_for(chat_id).tz;
+            let points = storage::blocking(&state, move |state| {
+                chart::read_points(&state.data_dir, chat_id, metric)
+            })
+            .await?;
+            let chart = chart::Chart::new(metric, points, tz, state.now(), days);
+            let user_settings = settings_or_default(&state, chat_id);
+            let axis = chart::GlucoseAxis::for_settings(&user_settings);
+            send_chart(
+                bot,
+                &state,
+                chat_id,
+                &chart::file_name(&chart, format),
+                || chart::render(&chart, tz, user_settings.general_range(), axis, format),
+                chart::caption(&chart, tz),
+                || chart::numbers(&chart, tz),
+            )
+            .await?;
+        }
+        Command::Flags(days) => {
+            let reply = match days {
+                Ok(days) => {
+                    storage::blocking(&state, move |state| {
+                        let glucose = records::read_records::<records::GlucoseRecord>(
+                            &state.data_dir,
+                            chat_id,
+                        )?;
+                        let mut report = reports::flags_report(
+                            &glucose.rows,
+                            &settings_or_default(state, chat_id),
+                            state.input_for(chat_id).tz,
+                            state.now(),
+                            days,
+                        );
+                        append_skipped_note(&mut report, glucose.skipped());
+                        Ok(report)
+                    })
+                    .await?
+                }
+                Err(usage) => usage,
+            };
+            paging::send_long_message(bot, chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Trend(days) => {
+            let reply = match days {
+                Ok(days) => {
+                    storage::blocking(&state, move |state| {
+                        let glucose = records::read_records::<records::GlucoseRecord>(
+                            &state.data_dir,
+                            chat_id,
+                        )?;
+                        let mut report = reports::trend_report(
+                            &glucose.rows,
+                            state.input_for(chat_id).tz,
+                            state.now(),
+                            days,
+                        );
+                        append_skipped_note(&mut report, glucose.skipped());
+                        Ok(report)
+                    })
+                    .await?
+                }
+                Err(usage) => usage,
+            };
+            paging::send_long_message(bot, chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::WeekdayAverages(days) => {
+            let reply = match days {
+                Ok(days) => {
+                    storage::blocking(&state, move |state| {
+                        let glucose = records::read_records::<records::GlucoseRecord>(
+                            &state.data_dir,
+                            chat_id,
+                        )?;
+                        let week_start = settings_or_default(state, chat_id)
+                            .week_start
+                            .unwrap_or_default();
+                        let mut report = reports::weekday_report(
+                            &glucose.rows,
+                            state.input_for(chat_id).tz,
+                            state.now(),
+                            days,
+                            week_start.weekday(),
+                        );
+                        append_skipped_note(&mut report, glucose.skipped());
+                        Ok(report)
+                    })
+                    .await?
+                }
+                Err(usage) => usage,
+            };
+            paging::send_long_preformatted(
+                bot,
+                chat_id,
+                reply,
+                menu_keyboard(&state, chat_id).await,
+            )
+            .await?;
+        }
+        Command::Predict(tag) => {
+            let reply = match tag {
+                Ok(tag) => {
+                    storage::blocking(&state, move |state| {
+                        let glucose = records::read_glucose_records(&state.data_dir, chat_id)?;
+                        Ok(predict::predict_report(
+                            &glucose,
+                            tag,
+                            state.input_for(chat_id).tz,
+                            state.now(),
+                        ))
+                    })
+                    .await?
+                }
+                Err(usage) => usage,
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Meal { payload } => {
+            match parse_time_and_note(payload, &state.input_for(chat_id)) {
+                Ok((timestamp, note)) => {
+                    storage::locked(&state, chat_id, move |state| {
+                        append_meal_csv(
+                            &state.data_dir,
+                            chat_id,
+                            &state.input_for(chat_id),
+                            timestamp.as_deref(),
+                            note.as_deref(),
+                        )
+                    })
+                    .await?;
+                    bot.send_text(
+                        chat_id,
+                        save_confirmation(&state, chat_id, settings::ConfirmMetric::Meal)
+                            .text(|mark| format!("Meal saved {mark}")),
+                        menu_keyboard(&state, chat_id).await,
+                    )
+                    .await?;
+                }
+                Err(msg) => {
+                    bot.send_text(
+                        chat_id,
+                        msg.to_string(),
+                        menu_keyboard(&state, chat_id).await,
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        Command::Export(Ok(export::ExportRequest::Health(period))) => {
+            let export = storage::blocking(&state, move |state| {
+                export::health_export(
+                    &state.data_dir,
+                    chat_id,
+                    state.input_for(chat_id).tz,
+                    period,
+                )
+            })
+            .await?;
+            let period = match period {
+                Some((from, to)) => format!(" from {from} to {to}"),
+                None => String::new(),
+            };
+            if export.rows == 0 {
+                bot.send_text(
+                    chat_id,
+                    format!("No glucose or weight entries{period}."),
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+            } else {
+                send_export(
+                    bot,
+                    &state,
+                    chat_id,
+                    &export.file_name,
+                    export.content.into_bytes(),
+                    format!("Apple Health export{period}: {} records", export.rows),
+                )
+                .await?;
+            }
+        }
+        Command::Export(Ok(export::ExportRequest::Long(period, timestamps))) => {
+            let export = storage::blocking(&state, move |state| {
+                export::long_export(
+                    &state.data_dir,
+                    chat_id,
+                    state.input_for(chat_id).tz,
+                    period,
+                    timestamps,
+                )
+            })
+            .await?;
+            let period = match period {
+                Some((from, to)) => format!(" from {from} to {to}"),
+                None => String::new(),
+            };
+            if export.rows == 0 {
+                bot.send_text(
+                    chat_id,
+                    format!("No measurements{period}."),
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+            } else {
+                send_export(
+                    bot,
+                    &state,
+                    chat_id,
+                    &export.file_name,
+                    export.content.into_bytes(),
+                    format!("Long-format export{period}: {} values", export.rows),
+                )
+                .await?;
+            }
+        }
+        Command::Export(Err(msg)) => {
+            bot.send_text(chat_id, msg, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Export(Ok(export::ExportRequest::File(range))) => {
+            let export = storage::blocking(&state, move |state| {
+                export::export_range(&state.data_dir, chat_id, state.input_for(chat_id).tz, range)
+            })
+            .await?;
+            let period = format!("{} to {}", range.from, range.to);
+            if export.rows == 0 {
+                bot.send_text(
+                    chat_id,
+                    format!("No {} entries from {period}.", range.target.name()),
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+            } else {
+                send_export(
+                    bot,
+                    &state,
+                    chat_id,
+                    &export.file_name,
+                    export.content.into_bytes(),
+                    format!(
+                        "{} from {period}: {} rows",
+                        range.target.name(),
+                        export.rows
+                    ),
+                )
+                .await?;
+            }
+        }
+        Command::Merge(source) => {
+            let reply = match source {
+                Ok(source) => {
+                    storage::blocking(&state, move |state| {
+                        state.rewrite_files(chat_id, || {
+                            merge::merge_reply(state, chat_id, &source, state.now())
+                        })
+                    })
+                    .await?
+                }
+                Err(usage) => usage,
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Share(days) => {
+            let reply = match days {
+                Ok(days) => {
+                    storage::blocking(&state, move |state| {
+                        share::share_command(state, chat_id, days)
+                    })
+                    .await?
+                }
+                Err(usage) => usage,
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Revoke => {
+            let reply =
+                storage::blocking(&state, move |state| share::revoke_command(state, chat_id))
+                    .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Tag(Err(usage)) => {
+            bot.send_text(chat_id, usage, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Tag(Ok(request)) => {
+            let tz = state.input_for(chat_id).tz;
+            let week_start = settings_or_default(&state, chat_id)
+                .week_start
+                .unwrap_or_default();
+            let (from, to) = request
+                .window
+                .days(state.now().with_timezone(&tz).date_naive(), week_start);
+            let tag = request.tag.clone();
+            let result = storage::blocking(&state, move |state| {
+                tagging::tag_glucose(state, chat_id, &tag, (from, to), tz)
+            })
+            .await?;
+            let tag = request.tag;
+            let reply = match result {
+                tagging::Tagged {
+                    tagged: 0,
+                    already: 0,
+                } => format!("No glucose readings from {from} to {to}."),
+                tagging::Tagged { tagged: 0, already } => {
+                    format!(
+                        "All {already} glucose readings from {from} to {to} already have {tag}."
+                    )
+                }
+                tagging::Tagged { tagged, already } => {
+                    let mut reply = format!(
+                        "Tagged {tagged} glucose readings from {from} to {to} with {tag} ✅"
+                    );
+                    if already > 0 {
+                        reply.push_str(&format!(" ({already} already had it)"));
+                    }
+                    reply
+                }
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::ResetSettings => {
+            let changed = storage::blocking(&state, move |state| {
+                settings::load_settings(&state.data_dir, chat_id)?.changed_from_defaults()
+            })
+            .await?;
+            let reply = if changed.is_empty() {
+                "All settings are already at their defaults.".to_string()
+            } else {
+                set_pending(&state, chat_id, PendingEntry::ConfirmResetSettings).await;
+                format!(
+                    "This will reset these settings to their defaults: {}. Logged data and \
+medications are kept. Reply \"yes\" to confirm, anything else cancels.",
+                    changed.join(", ")
+                )
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Clear {
+            target: target_name,
+        } => {
+            let Some(target) = ClearTarget::parse(target_name) else {
+                let names = ClearTarget::ALL.map(ClearTarget::name).join("|");
+                bot.send_text(
+                    chat_id,
+                    format!("Usage: /clear <{names}>"),
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+                return Ok(());
+            };
+            let rows = storage::blocking(&state, move |state| {
+                count_data_rows(&user_data_dir(&state.data_dir, chat_id).join(target.file_name()))
+            })
+            .await?;
+            if rows == 0 {
+                bot.send_text(
+                    chat_id,
+                    format!("Nothing to clear: {} has no entries.", target.name()),
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+                return Ok(());
+            }
+            set_pending(&state, chat_id, PendingEntry::ConfirmClear(target)).await;
+            bot.send_text(
+            chat_id,
+            format!(
+                "This will remove all {rows} {} entries. Reply \"yes\" to confirm, anything else cancels.",
+                target.name()
+            ),
+            menu_keyboard(&state, chat_id).await,
+        )
+        .await?;
+        }
+        Command::Food { payload } => {
+            if payload.is_empty() {
+                bot.send_text(
+                    chat_id,
+                    "Usage: /food <item> [grams g] [@note]",
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+                return Ok(());
+            }
+            let reply = match parse_food_payload(payload, &state.input_for(chat_id)) {
+                Ok(food) => {
+                    storage::locked(&state, chat_id, move |state| {
+                        save_food(state, chat_id, food)
+                    })
+                    .await?
+                }
+                Err(err) => err.to_string(),
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::AddKetone { payload } => {
+            if payload.is_empty() {
+                bot.send_text(
+                    chat_id,
+                    "Usage: /addketone <value> [date time] [@note]",
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+                return Ok(());
+            }
+            let reply = match parse_ketone_payload(payload, &state.input_for(chat_id)) {
+                Ok(reading) => {
+                    storage::locked(&state, chat_id, move |state| {
+                        save_ketones(state, chat_id, message_id, reading)
+                    })
+                    .await?
+                }
+                Err(err) => err.to_string(),
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::AddInsulin { payload } => {
+            if payload.is_empty() {
+                bot.send_text(
+                    chat_id,
+                    "Usage: /addinsulin <units> [meal|correction] [date time] [@note]",
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+                return Ok(());
+            }
+            let reply = match insulin::parse_insulin_payload(payload, &state.input_for(chat_id)) {
+                Ok(dose) => {
+                    storage::locked(&state, chat_id, move |state| {
+                        insulin::save_insulin(state, chat_id, message_id, dose)
+                    })
+                    .await?
+                }
+                Err(err) => err.to_string(),
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Iob => {
+            let now = state.now();
+            let reply = storage::blocking(&state, move |state| {
+                insulin::iob_report(state, chat_id, now)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Last(count) => {
+            let reply = match count {
+                Ok(count) => history::last_entries(&state, chat_id, count).await?,
+                Err(usage) => usage,
+            };
+            paging::send_long_message(bot, chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::History => match storage::blocking(&state, move |state| {
+            history::history_listing(state, chat_id)
+        })
+        .await?
+        {
+            Some((text, buttons)) => bot.send_inline(chat_id, text, buttons).await?,
+            None => {
+                bot.send_text(
+                    chat_id,
+                    "No entries yet.",
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?
+            }
+        },
+        Command::Del { index } => {
+            let reply = history::delete_listed_entry(&state, chat_id, index).await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Note { payload } => {
+            let reply = history::annotate_entry(&state, chat_id, payload).await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::DualUnits { payload } => {
+            let toggle = settings::Toggle {
+                usage: "/dualunits <on|off>",
+                name: "Dual units display",
+                verb: "is",
+                flag: |user_settings| &mut user_settings.dual_units,
+            };
+            let reply = toggle
+                .apply(
+                    &state,
+                    chat_id,
+                    payload,
+                    &format!("e.g. {}", format_dual_units(5.8)),
+                )
+                .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::NoEmoji { payload } => {
+            let toggle = settings::Toggle {
+                usage: "/noemoji <on|off>",
+                name: "Emoji-free mode",
+                verb: "is",
+                flag: |user_settings| &mut user_settings.no_emoji,
+            };
+            let reply = toggle.apply(&state, chat_id, payload, "").await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Verbose { payload } => {
+            let toggle = settings::Toggle {
+                usage: "/verbose <on|off>",
+                name: "Error details",
+                verb: "are",
+                flag: |user_settings| &mut user_settings.verbose_errors,
+            };
+            let reply = toggle.apply(&state, chat_id, payload, "").await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Timezone { payload } => {
+            let (reply, keyboard) = timezone::timezone_command(&state, chat_id, payload).await?;
+            bot.send_text(chat_id, reply, keyboard).await?;
+        }
+        Command::Checksums { payload } => {
+            let toggle = settings::Toggle {
+                usage: "/checksums <on|off>",
+                name: "Export checksums",
+                verb: "are",
+                flag: |user_settings| &mut user_settings.export_checksums,
+            };
+            let reply = toggle.apply(&state, chat_id, payload, "").await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::SetLanguage { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                let payload = payload.as_str();
+                let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                let reply = match settings::Language::parse(payload) {
+                    _ if payload.is_empty() => format!(
+                        "Menu buttons are in {}. Usage: /setlanguage <{}>",
+                        user_settings.language.unwrap_or_default().name(),
+                        settings::Language::ALL
+                            .map(settings::Language::code)
+                            .join("|")
+                    ),
+                    Some(language) => {
+                        user_settings.language = Some(language);
+                        settings::save_settings(state, chat_id, &user_settings)?;
+                        format!("Menu buttons are now in {} ✅", language.name())
+                    }
+                    None => format!(
+                        "Usage: /setlanguage <{}>",
+                        settings::Language::ALL
+                            .map(settings::Language::code)
+                            .join("|")
+                    ),
+                };
+                Ok(reply)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Precision { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                precision_command(state, chat_id, &payload)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Convert(request) => {
+            let reply = match request {
+                Ok((value, unit)) => units::convert_reply(value, unit),
+                Err(usage) => usage,
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Units(request) => {
+            let reply = match request {
+                Ok(units::UnitsRequest::Show) => {
+                    let user_settings = storage::blocking(&state, move |state| {
+                        settings::load_settings(&state.data_dir, chat_id)
+                    })
+                    .await?;
+                    let current = user_settings.glucose_unit.unwrap_or_default();
+                    format!(
+                        "New glucose entries are stored in {}. {}\n\
+Use /units <mmol|mgdl> [convert].",
+                        current.csv_name(),
+                        match current {
+                            settings::GlucoseUnit::Mmol => "Values are entered in mmol/L.",
+                            settings::GlucoseUnit::Mgdl =>
+                                "Values are entered in mg/dL as whole numbers such as 104; \
+a decimal such as 5.8 is read as mmol/L.",
+                        }
+                    )
+                }
+                Ok(units::UnitsRequest::Set(unit)) => {
+                    storage::blocking(&state, move |state| {
+                        let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                        user_settings.glucose_unit = Some(unit);
+                        settings::save_settings(state, chat_id, &user_settings)
+                    })
+                    .await?;
+                    format!(
+                        "New glucose entries will be stored in {} ✅\n\
+Existing rows keep their unit; use /units {} convert to rewrite them.",
+                        unit.csv_name(),
+                        unit.command_name()
+                    )
+                }
+                Ok(units::UnitsRequest::Convert(unit)) => {
+                    let rows = storage::blocking(&state, move |state| {
+                        count_data_rows(&user_data_dir(&state.data_dir, chat_id).join(GLUCOSE_FILE))
+                    })
+                    .await?;
+                    if rows == 0 {
+                        format!(
+                            "Nothing to convert: there are no glucose rows yet. \
+Use /units {} to store new readings in {}.",
+                            unit.command_name(),
+                            unit.csv_name()
+                        )
+                    } else {
+                        set_pending(&state, chat_id, PendingEntry::ConfirmUnitConversion(unit))
+                            .await;
+                        format!(
+                            "This will rewrite all {rows} glucose rows in {}. A backup copy is saved first. \
+Reply \"yes\" to confirm, anything else cancels.",
+                            unit.csv_name()
+                        )
+                    }
+                }
+                Err(usage) => usage,
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::DefaultTag { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                let payload = payload.as_str();
+                let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                let reply = match payload.to_lowercase().as_str() {
+                    "" => match user_settings.default_glucose_tag {
+                        Some(tag) => format!(
+                            "A bare number is saved as {} glucose.",
+                            tag.as_csv_tag().replace('_', "-")
+                        ),
+                        None => "A bare number is not saved. Use /defaulttag <before|after>."
+                            .to_string(),
+                    },
+                    "before" | "after" | "off" => {
+                        let tag = match payload.to_lowercase().as_str() {
+                            "before" => Some(GlucoseTag::BeforeMeal),
+                            "after" => Some(GlucoseTag::AfterMeal),
+                            _ => None,
+                        };
+                        user_settings.default_glucose_tag = tag;
+                        settings::save_settings(state, chat_id, &user_settings)?;
+                        match tag {
+                            Some(tag) => format!(
+                                "Default tag set ✅ a bare number is saved as {} glucose",
+                                tag.as_csv_tag().replace('_', "-")
+                            ),
+                            None => "Default tag cleared ✅".to_string(),
+                        }
+                    }
+                    _ => "Usage: /defaulttag <before|after|off>".to_string(),
+                };
+                Ok(reply)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::WeightGoal { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                let payload = payload.as_str();
+                let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                let reply = if payload.is_empty() {
+                    match user_settings.weight_goal {
+                        Some(goal) => format!("Weight goal is {goal:.1} kg."),
+                        None => "No weight goal set. Use /weightgoal <kg>.".to_string(),
+                    }
+                } else if payload.eq_ignore_ascii_case("off") {
+                    user_settings.weight_goal = None;
+                    settings::save_settings(state, chat_id, &user_settings)?;
+                    "Weight goal cleared ✅".to_string()
+                } else {
+                    match parse_decimal(payload).filter(|goal| *goal > 0.0) {
+                        Some(goal) => {
+                            user_settings.weight_goal = Some(goal);
+                            settings::save_settings(state, chat_id, &user_settings)?;
+                            format!("Weight goal set ✅ ({goal:.1} kg)")
+                        }
+                        None => "Usage: /weightgoal <kg|off>".to_string(),
+                    }
+                };
+                Ok(reply)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::AverageGoal { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                let payload = payload.as_str();
+                let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                let usage = format!(
+                    "Usage: /avggoal <percent|off>, percent up to {:.0}",
+                    goals::AVERAGE_GOAL_MAX_PERCENT
+                );
+                let reply = if payload.is_empty() {
+                    match &user_settings.average_goal {
+                        Some(goal) => format!(
+                            "Average goal: -{:.0}% from {:.1} to {:.1} mmol/L.",
+                            goal.percent,
+                            goal.baseline,
+                            goal.target()
+                        ),
+                        None => "No average goal set. Use /avggoal <percent>.".to_string(),
+                    }
+                } else if payload.eq_ignore_ascii_case("off") {
+                    user_settings.average_goal = None;
+                    settings::save_settings(state, chat_id, &user_settings)?;
+                    "Average goal cleared ✅".to_string()
+                } else {
+                    match parse_decimal(payload.trim_end_matches('%').trim_end())
+                        .filter(|percent| *percent > 0.0 && *percent <= goals::AVERAGE_GOAL_MAX_PERCENT)
+                    {
+                        Some(percent) => {
+                            let now = state.now();
+                            let glucose = records::read_glucose_records(&state.data_dir, chat_id)?;
+                            let since = now - chrono::Duration::days(goals::GOALS_DAYS);
+                            match goals::window_average(&glucose, since, now) {
+                                Some((baseline, _)) => {
+                                    let goal = settings::AverageGoal {
+                                        baseline,
+                                        percent,
+                                        set_at: state.input_for(chat_id).format_timestamp(now),
+                                    };
+                                    let reply = format!(
+                                        "Average goal set ✅ -{percent:.0}% from {baseline:.1} mmol/L \
+    (last {} days) to {:.1} mmol/L",
+                                        goals::GOALS_DAYS,
+                                        goal.target()
+                                    );
+                                    user_settings.average_goal = Some(goal);
+                                    settings::save_settings(state, chat_id, &user_settings)?;
+                                    reply
+                                }
+                                None => format!(
+                                    "No glucose readings in the last {} days to use as the baseline.",
+                                    goals::GOALS_DAYS
+                                ),
+                            }
+                        }
+                        None => usage,
+                    }
+                };
+                Ok(reply)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::WeightAlert { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                let payload = payload.as_str();
+                let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                let reply = if payload.is_empty() {
+                    match user_settings.weight_alert {
+                        Some(threshold) => format!(
+                            "Weight changes over {threshold:.1} kg within {WEIGHT_ALERT_WINDOW_DAYS} days get a note."
+                        ),
+                        None => "Weight change notes are off. Use /weightalert <kg>.".to_string(),
+                    }
+                } else if payload.eq_ignore_ascii_case("off") {
+                    user_settings.weight_alert = None;
+                    settings::save_settings(state, chat_id, &user_settings)?;
+                    "Weight change notes turned off ✅".to_string()
+                } else {
+                    match parse_decimal(payload).filter(|threshold| *threshold > 0.0) {
+                        Some(threshold) => {
+                            user_settings.weight_alert = Some(threshold);
+                            settings::save_settings(state, chat_id, &user_settings)?;
+                            format!("Weight change note set ✅ (over {threshold:.1} kg)")
+                        }
+                        None => "Usage: /weightalert <kg|off>".to_string(),
+                    }
+                };
+                Ok(reply)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Fast { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::locked(&state, chat_id, move |state| {
+                fasting::fast_command(state, chat_id, &payload)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Nudge { payload } => {
+            let toggle = settings::Toggle {
+                usage: "/nudge <on|off>",
+                name: "Nudges",
+                verb: "are",
+                flag: |user_settings| &mut user_settings.nudge,
+            };
+            let reply = toggle
+                .apply(
+                    &state,
+                    chat_id,
+                    payload,
+                    &format!(
+                        "You'll get one reminder after {} days without entries.",
+                        state.nudge.after_days
+                    ),
+                )
+                .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::PostMeal { payload } => {
+            let toggle = settings::Toggle {
+                usage: "/postmeal <on|off>",
+                name: "Post-meal reminder",
+                verb: "is",
+                flag: |user_settings| &mut user_settings.postmeal_reminder,
+            };
+            let reply = toggle.apply(
+                &state,
+                chat_id,
+                payload,
+                &format!(
+                    "After a before-meal reading you'll get one reminder {} hours later, unless an \
+after-meal reading comes first or it falls in quiet hours.",
+                    POSTMEAL_REMINDER_MINUTES / 60
+                ),
+            ).await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::InRange { payload } => {
+            let toggle = settings::Toggle {
+                usage: "/inrange <on|off>",
+                name: "In-range note",
+                verb: "is",
+                flag: |user_settings| &mut user_settings.in_range_note,
+            };
+            let reply = toggle
+                .apply(
+                    &state,
+                    chat_id,
+                    payload,
+                    "Glucose readings in the middle half of your target range get a short note.",
+                )
+                .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Confirm { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                let payload = payload.as_str();
+                let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                let metric = payload
+                    .split_once(char::is_whitespace)
+                    .and_then(|(metric, _)| settings::ConfirmMetric::parse(metric));
+                let reply = if payload.is_empty() {
+                    let short = settings::ConfirmMetric::ALL
+                        .into_iter()
+                        .filter(|metric| {
+                            user_settings.confirm_style(*metric) == settings::ConfirmStyle::Short
+                        })
+                        .map(settings::ConfirmMetric::name)
+                        .collect::<Vec<_>>();
+                    let mut reply = format!(
+                        "Saves are confirmed with {}.",
+                        confirmation_text(state, chat_id)
+                    );
+                    if !short.is_empty() {
+                        reply.push_str(&format!("\nShort for: {}", short.join(", ")));
+                    }
+                    reply
+                } else if payload.eq_ignore_ascii_case("reset") {
+                    user_settings.confirmation = None;
+                    settings::save_settings(state, chat_id, &user_settings)?;
+                    format!("Confirmation reset to {}", settings::DEFAULT_CONFIRMATION)
+                } else if metric.is_some() {
+                    match settings::parse_confirm_style(payload) {
+                        Some((metric, style)) => {
+                            if style == settings::ConfirmStyle::default() {
+                                user_settings.confirm_styles.remove(&metric);
+                            } else {
+                                user_settings.confirm_styles.insert(metric, style);
+                            }
+                            settings::save_settings(state, chat_id, &user_settings)?;
+                            format!(
+                                "Confirmations for {} are now {}.",
+                                metric.name(),
+                                style.name()
+                            )
+                        }
+                        None => "Usage: /confirm <metric> <short|detailed>".to_string(),
+                    }
+                } else {
+                    match settings::parse_confirmation(payload) {
+                        Ok(mark) => {
+                            let reply = format!("Confirmation updated {mark}");
+                            user_settings.confirmation = Some(mark);
+                            settings::save_settings(state, chat_id, &user_settings)?;
+                            reply
+                        }
+                        Err(msg) => {
+                            format!("{msg} Usage: /confirm [mark|reset|<metric> <short|detailed>]")
+                        }
+                    }
+                };
+                Ok(reply)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::WeekStart { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                let payload = payload.as_str();
+                let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                let reply = if payload.is_empty() {
+                    format!(
+                        "Week starts on {}.",
+                        user_settings.week_start.unwrap_or_default().name()
+                    )
+                } else if let Some(week_start) = settings::WeekStart::parse(payload) {
+                    user_settings.week_start = Some(week_start);
+                    settings::save_settings(state, chat_id, &user_settings)?;
+                    format!("Week start updated ✅ ({})", week_start.name())
+                } else {
+                    "Usage: /weekstart <monday|sunday>".to_string()
+                };
+                Ok(reply)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::SetRange { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                let payload = payload.as_str();
+                let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                let reply = match apply_setrange(&mut user_settings, payload) {
+                    Ok(true) => {
+                        settings::save_settings(state, chat_id, &user_settings)?;
+                        format!(
+                            "Target ranges updated ✅\n{}",
+                            describe_ranges(&user_settings)
+                        )
+                    }
+                    Ok(false) => describe_ranges(&user_settings),
+                    Err(msg) => msg.to_string(),
+                };
+                Ok(reply)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::SetBolus { payload } => {
+            let payload = payload.to_string();
+            let reply = storage::blocking(&state, move |state| {
+                let payload = payload.as_str();
+                let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                let reply = match bolus::apply_setbolus(&mut user_settings, payload) {
+                    Ok(true) => {
+                        settings::save_settings(state, chat_id, &user_settings)?;
+                        format!(
+                            "Bolus settings updated ✅\n{}",
+                            bolus::describe_bolus_settings(&user_settings)
+                        )
+                    }
+                    Ok(false) => bolus::describe_bolus_settings(&user_settings),
+                    Err(msg) => msg.to_string(),
+                };
+                Ok(reply)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::Bolus { payload } => {
+            let payload = payload.to_owned();
+            let reply = storage::locked(&state, chat_id, move |state| {
+                bolus::suggest_bolus(state, chat_id, &payload)
+            })
+            .await?;
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::MedTime(request) => {
+            let reply = match request {
+                Ok((name, times)) => {
+                    storage::blocking(&state, move |state| {
+                        set_medication_times(state, chat_id, &name, times)
+                    })
+                    .await?
+                }
+                Err(usage) => usage,
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::RenameMed { payload } => {
+            let reply = match parse_rename_payload(payload) {
+                Some(rename) => {
+                    storage::locked(&state, chat_id, move |state| {
+                        rename_medication(state, chat_id, &rename)
+                    })
+                    .await?
+                }
+                None => "Usage: /renamemed <old> -> <new> [--keep-log]".to_string(),
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        Command::AddMed { name } => {
+            if name.is_empty() {
+                bot.send_text(
+                    chat_id,
+                    "Usage: /addmed <medication name>",
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+                return Ok(());
+            }
+
+            let name = name.to_string();
+            let added = storage::locked(&state, chat_id, move |state| {
+                add_medication(state, chat_id, &name)
+            })
+            .await?;
+            let reply = match added {
+                MedicationAdd::Added(name) => format!("Medication added: {name}"),
+                MedicationAdd::AlreadyExists(name) => format!("Medication already exists: {name}"),
+                MedicationAdd::Rejected(reason) => reason,
+            };
+            bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                .await?;
+        }
+        // Registration links of allowed chats and unknown payloads just open the menu.
+        Command::Menu | Command::Start { .. } => send_menu(bot, chat_id, &state).await?,
+        Command::MoreMeds => {
+            let medications = load_medications(&state.data_dir, chat_id).unwrap_or_default();
+            if medications.len() > MENU_MEDICATION_BUTTONS {
+                bot.send_text(
+                    chat_id,
+                    format!(
+                        "More medications ({}). Tap one to log it, or {BTN_SHOW_MENU} to go back.",
+                        medications.len() - MENU_MEDICATION_BUTTONS
+                    ),
+                    more_medications_keyboard(&medications),
+                )
+                .await?;
+            } else {
+                send_menu(bot, chat_id, &state).await?;
+            }
+        }
+        Command::EntryButton(button) => {
+            set_pending(&state, chat_id, button.pending()).await;
+            bot.send_text(
+                chat_id,
+                button.prompt(),
+                menu_keyboard(&state, chat_id).await,
+            )
+            .await?;
+        }
+        Command::MedButton {
+            name: medication_name,
+        } => match match_medication(&state, chat_id, medication_name).await {
+            MedicationMatch::Exact(name) => {
+                let logged = name.clone();
+                storage::locked(&state, chat_id, move |state| {
+                    append_medication_log_csv(
+                        &state.data_dir,
+                        chat_id,
+                        &state.input_for(chat_id),
+                        &logged,
+                    )
+                })
+                .await?;
+                bot.send_text(
+                    chat_id,
+                    save_confirmation(&state, chat_id, settings::ConfirmMetric::Med)
+                        .text(|mark| format!("Medication usage saved {mark} ({name})")),
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+            }
+            MedicationMatch::Fuzzy(name) => {
+                bot.send_text(
+                    chat_id,
+                    format!("Did you mean {name}? Reply \"yes\" to log it, anything else cancels."),
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+                set_pending(&state, chat_id, PendingEntry::ConfirmMedication(name)).await;
+            }
+            MedicationMatch::None => {
+                bot.send_text(
+                    chat_id,
+                    "Unknown medication. Use /addmed <name> first.",
+                    menu_keyboard(&state, chat_id).await,
+                )
+                .await?;
+            }
+        },
+        Command::Debug { .. } | Command::Status | Command::Tail { .. } | Command::Plain { .. } => {
+            handle_plain_text(bot, chat_id, message_id, text, state).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Sends an `/export` file, followed by its `.sha256` file when the chat turned on
+/// `/checksums`. The hash is taken over the same bytes as the document.
+async fn send_export<R: Responder>(
+    bot: &R,
+    state: &AppState,
+    chat_id: ChatId,
+    file_name: &str,
+    bytes: Vec<u8>,
+    caption: String,
+) -> anyhow::Result<()> {
+    let checksum = settings_or_default(state, chat_id)
+        .export_checksums
+        .unwrap_or(false)
+        .then(|| checksum::checksum_file(file_name, &bytes));
+    bot.send_document(
+        chat_id,
+        file_name,
+        bytes,
+        caption,
+        menu_keyboard(state, chat_id).await,
+    )
+    .await?;
+    if let Some((checksum_name, content)) = checksum {
+        let caption =
+            format!("SHA-256 of {file_name}. Check it with: sha256sum -c {checksum_name}");
+        bot.send_document(
+            chat_id,
+            &checksum_name,
+            content.into_bytes(),
+            caption,
+            menu_keyboard(state, chat_id).await,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Text that is not a command or button: a pasted batch of readings, the answer to a
+/// pending entry, or anything else.
+async fn handle_plain_text<R: Responder>(
+    bot: &R,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: &str,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    if let Some(batch) = batch::parse_glucose_batch(text, &state.input_for(chat_id)) {
+        let preview = batch::batch_preview(&batch, state.input_for(chat_id).tz);
+        set_pending(&state, chat_id, PendingEntry::ConfirmImport(batch.inputs)).await;
+        bot.send_text(chat_id, preview, menu_keyboard(&state, chat_id).await)
+            .await?;
+        return Ok(());
+    }
+
+    if let Some(pending) = get_pending(&state, chat_id).await {
+        match pending {
+            PendingEntry::GlucoseBeforeMeal | PendingEntry::GlucoseAfterMeal => {
+                match parse_glucose_payload(text, &state.input_for(chat_id)) {
+                    Ok(input) => {
                         let tag = match pending {
                             PendingEntry::GlucoseBeforeMeal => GlucoseTag::BeforeMeal,
                             PendingEntry::GlucoseAfterMeal => GlucoseTag::AfterMeal,
-                            PendingEntry::Weight => unreachable!(),
+                            _ => unreachable!(),
                         };
-                        append_glucose_csv(
-                            &state.data_dir,
+                        let reply = save_glucose_entry(
+                            bot,
+                            &state,
                             chat_id,
+                            message_id,
                             tag,
-                            value,
-                            timestamp.as_deref(),
-                            note.as_deref(),
-                        )?;
-                        update_after_meal_reminders(&bot, &state, chat_id, tag).await;
+                            input,
+                            |mark| format!("Saved {mark}"),
+                        )
+                        .await?
+                        .unwrap_or_else(|| DUPLICATE_SKIPPED.to_string());
                         clear_pending(&state, chat_id).await;
-                        bot.send_message(chat_id, "Saved ✅")
-                            .reply_markup(menu_keyboard(&state, chat_id).await)
+                        bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
                             .await?;
                     }
                     Err(msg) => {
-                        bot.send_message(chat_id, msg.to_string())
-                            .reply_markup(menu_keyboard(&state, chat_id).await)
-                            .await?;
+                        bot.send_text(
+                            chat_id,
+                            msg.to_string(),
+                            menu_keyboard(&state, chat_id).await,
+                        )
+                        .await?;
                     }
                 }
             }
             PendingEntry::Weight => {
                 if let Some(value) = parse_decimal(text) {
-                    append_measurement_csv(&state.data_dir, chat_id, pending, value)?;
+                    let value = round_for_chat(
+                        &state.data_dir,
+                        chat_id,
+                        settings::PrecisionMetric::Weight,
+                        value,
+                    );
+                    // Earlier weights, read once before saving for the goal and change notes.
+                    let (earlier, saved_at) = storage::locked(&state, chat_id, move |state| {
+                        let earlier = records::read_weight_records(&state.data_dir, chat_id)
+                            .inspect_err(|err| {
+                                tracing::warn!(
+                                    "failed to read weights for chat {}: {err}",
+                                    chat_id.0
+                                )
+                            })
+                            .unwrap_or_default();
+                        let saved_at = state.now();
+                        append_measurement_csv(
+                            &state.data_dir,
+                            chat_id,
+                            &state.input_for(chat_id),
+                            pending,
+                            value,
+                            Some(message_id),
+                        )?;
+                        Ok((earlier, saved_at))
+                    })
+                    .await?;
                     clear_pending(&state, chat_id).await;
-                    bot.send_message(chat_id, "Saved ✅")
-                        .reply_markup(menu_keyboard(&state, chat_id).await)
+                    let confirm =
+                        save_confirmation(&state, chat_id, settings::ConfirmMetric::Weight);
+                    let mut reply = confirm.text(|mark| format!("Saved {mark}"));
+                    if !confirm.short
+                        && let Some(progress) =
+                            weight_goal_progress(&state, chat_id, &earlier, value)
+                    {
+                        reply.push('\n');
+                        reply.push_str(&progress);
+                    }
+                    if let Some(note) =
+                        weight_change_note(&state, chat_id, &earlier, saved_at, value)
+                    {
+                        reply.push('\n');
+                        reply.push_str(&note);
+                    }
+                    bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
                         .await?;
                 } else {
-                    bot.send_message(
+                    bot.send_text(
                         chat_id,
                         "Could not parse number. Use format like 78.4 (dot or comma).",
+                        menu_keyboard(&state, chat_id).await,
                     )
-                    .reply_markup(menu_keyboard(&state, chat_id).await)
                     .await?;
                 }
             }
+            PendingEntry::Ketones => {
+                let reply = match parse_ketone_payload(text, &state.input_for(chat_id)) {
+                    Ok(reading) => {
+                        let reply = storage::locked(&state, chat_id, move |state| {
+                            save_ketones(state, chat_id, message_id, reading)
+                        })
+                        .await?;
+                        clear_pending(&state, chat_id).await;
+                        reply
+                    }
+                    Err(err) => err.to_string(),
+                };
+                bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                    .await?;
+            }
+            PendingEntry::Food => {
+                let reply = match parse_food_payload(text, &state.input_for(chat_id)) {
+                    Ok(food) => {
+                        let reply = storage::locked(&state, chat_id, move |state| {
+                            save_food(state, chat_id, food)
+                        })
+                        .await?;
+                        clear_pending(&state, chat_id).await;
+                        reply
+                    }
+                    Err(err) => err.to_string(),
+                };
+                bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                    .await?;
+            }
+            PendingEntry::ConfirmClear(target) => {
+                clear_pending(&state, chat_id).await;
+                let reply = if text.eq_ignore_ascii_case("yes") {
+                    let path = user_data_dir(&state.data_dir, chat_id).join(target.file_name());
+                    let removed = storage::blocking(&state, move |state| {
+                        state.rewrite_files(chat_id, || {
+                            truncate_to_header(&path, state.input.sync_writes)
+                        })
+                    })
+                    .await?;
+                    tracing::info!(
+                        "chat {} cleared {} ({removed} rows)",
+                        chat_id.0,
+                        target.name()
+                    );
+                    format!("Cleared {}: {removed} rows removed.", target.name())
+                } else {
+                    "Clear cancelled.".to_string()
+                };
+                bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                    .await?;
+            }
+            PendingEntry::EditRow(edit) => {
+                clear_pending(&state, chat_id).await;
+                let text = text.to_string();
+                let reply = storage::blocking(&state, move |state| {
+                    history::apply_row_edit(state, chat_id, &edit, &text)
+                })
+                .await?;
+                bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                    .await?;
+            }
+            PendingEntry::ConfirmResetSettings => {
+                clear_pending(&state, chat_id).await;
+                let reply = if text.eq_ignore_ascii_case("yes") {
+                    let changed = storage::blocking(&state, move |state| {
+                        let user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                        let changed = user_settings.changed_from_defaults()?;
+                        settings::save_settings(state, chat_id, &user_settings.reset())?;
+                        Ok(changed)
+                    })
+                    .await?;
+                    tracing::info!("chat {} reset its settings", chat_id.0);
+                    if changed.is_empty() {
+                        "All settings were already at their defaults.".to_string()
+                    } else {
+                        format!("Settings reset to defaults ✅ {}", changed.join(", "))
+                    }
+                } else {
+                    "Reset cancelled.".to_string()
+                };
+                bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                    .await?;
+            }
+            PendingEntry::ConfirmUnitConversion(unit) => {
+                clear_pending(&state, chat_id).await;
+                let reply = if text.eq_ignore_ascii_case("yes") {
+                    let conversion = storage::blocking(&state, move |state| {
+                        let conversion = state.rewrite_files(chat_id, || {
+                            units::convert_glucose_file(
+                                &state.data_dir,
+                                chat_id,
+                                unit,
+                                state.now(),
+                                state.input.sync_writes,
+                            )
+                        })?;
+                        let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+                        user_settings.glucose_unit = Some(unit);
+                        settings::save_settings(state, chat_id, &user_settings)?;
+                        Ok(conversion)
+                    })
+                    .await?;
+                    let mut reply = format!(
+                        "Converted {} glucose rows to {} ✅",
+                        conversion.converted,
+                        unit.csv_name()
+                    );
+                    if let Some(backup) = conversion.backup {
+                        reply.push_str(&format!("\nBackup: {backup}"));
+                    }
+                    reply
+                } else {
+                    "Conversion cancelled.".to_string()
+                };
+                bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                    .await?;
+            }
+            PendingEntry::ConfirmMedication(name) => {
+                clear_pending(&state, chat_id).await;
+                let reply = if text.eq_ignore_ascii_case("yes") {
+                    let logged = name.clone();
+                    storage::locked(&state, chat_id, move |state| {
+                        append_medication_log_csv(
+                            &state.data_dir,
+                            chat_id,
+                            &state.input_for(chat_id),
+                            &logged,
+                        )
+                    })
+                    .await?;
+                    save_confirmation(&state, chat_id, settings::ConfirmMetric::Med)
+                        .text(|mark| format!("Medication usage saved {mark} ({name})"))
+                } else {
+                    "Medication not logged.".to_string()
+                };
+                bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                    .await?;
+            }
+            PendingEntry::ConfirmImport(inputs) => {
+                clear_pending(&state, chat_id).await;
+                let tag = match text.to_lowercase().as_str() {
+                    "before" => Some(GlucoseTag::BeforeMeal),
+                    "after" => Some(GlucoseTag::AfterMeal),
+                    _ => None,
+                };
+                let reply = match tag {
+                    Some(tag) => {
+                        let saved = storage::locked(&state, chat_id, move |state| {
+                            batch::import_batch(
+                                &state.data_dir,
+                                chat_id,
+                                &state.input_for(chat_id),
+                                tag,
+                                &inputs,
+                            )
+                        })
+                        .await?;
+                        format!(
+                            "Imported {saved} readings {} ({})",
+                            confirmation_text(&state, chat_id),
+                            tag.as_csv_tag().replace('_', " ")
+                        )
+                    }
+                    None => "Import cancelled.".to_string(),
+                };
+                bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+                    .await?;
+            }
         }
         return Ok(());
     }
 
-    bot.send_message(
+    if parse_decimal(text).is_some()
+        && let Some(tag) = settings_or_default(&state, chat_id).default_glucose_tag
+    {
+        let reply = match parse_glucose_payload(text, &state.input_for(chat_id)) {
+            Ok(input) => {
+                let count = input.values.len();
+                let saved =
+                    save_glucose_entry(bot, &state, chat_id, message_id, tag, input, |mark| {
+                        typed_glucose_saved(mark, count)
+                    });
+                match saved.await? {
+                    Some(confirmation) => format!(
+                        "{confirmation}\n(Saved as {} glucose, your /defaulttag.)",
+                        tag.as_csv_tag().replace('_', "-")
+                    ),
+                    None => DUPLICATE_SKIPPED.to_string(),
+                }
+            }
+            Err(err) => err.to_string(),
+        };
+        bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+            .await?;
+        return Ok(());
+    }
+
+    bot.send_text(
         chat_id,
         "Choose an action from menu. Type /menu to show buttons or /addmed <name>.",
+        menu_keyboard(&state, chat_id).await,
     )
-    .reply_markup(menu_keyboard(&state, chat_id).await)
     .await?;
     Ok(())
 }
 
-async fn update_after_meal_reminders(
-    bot: &Bot,
+/// Saves a glucose entry and returns the confirmation starting with `headline` around
+/// the save mark, or `None` when `duplicate_action` skips it as a duplicate.
+async fn save_glucose_entry<R: Responder>(
+    bot: &R,
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+    message_id: MessageId,
+    tag: GlucoseTag,
+    input: GlucoseInput,
+    headline: impl FnOnce(&str) -> String,
+) -> anyhow::Result<Option<String>> {
+    let checked = input.clone();
+    let duplicate = storage::blocking(state, move |state| {
+        is_duplicate_glucose(state, chat_id, tag, &checked)
+    })
+    .await?;
+    if duplicate && state.duplicates.action == DuplicateAction::Skip {
+        return Ok(None);
+    }
+    let saved = input.clone();
+    storage::locked(state, chat_id, move |state| {
+        append_glucose_input(
+            &state.data_dir,
+            chat_id,
+            &state.input_for(chat_id),
+            tag,
+            &saved,
+            Some(message_id),
+        )
+    })
+    .await?;
+    update_after_meal_reminders(bot, state, chat_id, tag).await;
+    let confirm = save_confirmation(state, chat_id, settings::ConfirmMetric::Glucose);
+    let mut confirmation = confirm.text(headline);
+    if !confirm.short {
+        append_dual_units(&mut confirmation, state, chat_id, &input.values);
+    }
+    if duplicate {
+        confirmation.push_str(DUPLICATE_WARNING);
+    }
+    append_range_warnings(&mut confirmation, state, chat_id, tag, &input);
+    Ok(Some(confirmation))
+}
+
+/// Confirmation of `count` glucose values typed as `/addgb`, `/addga` or a bare number.
+fn typed_glucose_saved(mark: &str, count: usize) -> String {
+    if count > 1 {
+        format!("Glucose entries saved {mark} ({count})")
+    } else {
+        format!("Glucose entry saved {mark}")
+    }
+}
+
+async fn update_after_meal_reminders<R: Responder>(
+    bot: &R,
     state: &Arc<AppState>,
     chat_id: ChatId,
     tag: GlucoseTag,
@@ -357,9 +3173,20 @@ async fn update_after_meal_reminders(
         GlucoseTag::BeforeMeal => schedule_after_meal_reminders(bot, state, chat_id).await,
         GlucoseTag::AfterMeal => cancel_after_meal_reminders(state, chat_id).await,
     }
-}
-
-async fn schedule_after_meal_reminders(bot: &Bot, state: &Arc<AppState>, chat_id: ChatId) {
+}
+
+async fn schedule_after_meal_reminders<R: Responder>(
+    bot: &R,
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+) {
+    if settings_or_default(state, chat_id)
+        .postmeal_reminder
+        .unwrap_or(false)
+    {
+        schedule_postmeal_reminder(bot, state, chat_id).await;
+        return;
+    }
     let reminder_minutes = state.glucose_after_meal_reminder_minutes;
     let reminder_count = state.glucose_after_meal_reminder_count;
     if reminder_minutes == 0 || reminder_count == 0 {
@@ -386,15 +3213,15 @@ async fn schedule_after_meal_reminders(bot: &Bot, state: &Arc<AppState>, chat_id
             }
 
             if let Err(err) = bot
-                .send_message(
+                .send_text(
                     chat_id,
                     format!(
                         "Time to measure glucose after meal. Reminder {}/{}.",
                         reminder_index + 1,
                         reminder_count
                     ),
+                    menu_keyboard(&state, chat_id).await,
                 )
-                .reply_markup(menu_keyboard(&state, chat_id).await)
                 .await
             {
                 tracing::error!("after meal reminder error: {err}");
@@ -403,6 +3230,38 @@ async fn schedule_after_meal_reminders(bot: &Bot, state: &Arc<AppState>, chat_id
     });
 }
 
+/// `/postmeal` reminder: one message [`POSTMEAL_REMINDER_MINUTES`] after the reading. It
+/// shares the generation of the configured series, so an after-meal reading or a newer
+/// before-meal reading cancels it, and it is dropped when due in `nudge_quiet_hours`.
+async fn schedule_postmeal_reminder<R: Responder>(bot: &R, state: &Arc<AppState>, chat_id: ChatId) {
+    let reminder_generation = next_after_meal_reminder_generation(state, chat_id).await;
+    let bot = bot.clone();
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(POSTMEAL_REMINDER_MINUTES * 60)).await;
+        if !is_current_after_meal_reminder_generation(&state, chat_id, reminder_generation).await {
+            return;
+        }
+        if nudge::in_quiet_hours(&state.nudge, state.now(), state.input_for(chat_id).tz) {
+            tracing::info!(
+                "post-meal reminder for chat {} skipped in quiet hours",
+                chat_id.0
+            );
+            return;
+        }
+        if let Err(err) = bot
+            .send_text(
+                chat_id,
+                "Time to measure glucose after meal.",
+                menu_keyboard(&state, chat_id).await,
+            )
+            .await
+        {
+            tracing::error!("post-meal reminder error: {err}");
+        }
+    });
+}
+
 async fn next_after_meal_reminder_generation(state: &AppState, chat_id: ChatId) -> u64 {
     let mut lock = state.after_meal_reminder_generations.lock().await;
     let generation = lock.entry(chat_id).or_insert(0);
@@ -425,78 +3284,254 @@ async fn is_current_after_meal_reminder_generation(
     lock.get(&chat_id).copied() == Some(reminder_generation)
 }
 
-async fn send_menu(bot: &Bot, chat_id: ChatId, state: &AppState) -> anyhow::Result<()> {
-    bot.send_message(
+async fn send_menu<R: Responder>(bot: &R, chat_id: ChatId, state: &AppState) -> anyhow::Result<()> {
+    bot.send_text(
         chat_id,
         "Diabetes diary menu:\n- Glucose before meal\n- Glucose after meal\n- Weight\n- Medications\nUse /addmed <name> to add medication button.\nUse /addgb or /addga for direct glucose entry with optional date/time.",
+        menu_keyboard(state, chat_id).await,
     )
-    .reply_markup(menu_keyboard(state, chat_id).await)
     .await?;
     Ok(())
 }
 
-fn parse_glucose_add_command(text: &str) -> Option<(GlucoseTag, &str)> {
-    let mappings = [
-        ("/addgb", GlucoseTag::BeforeMeal),
-        ("/add_glucose_before", GlucoseTag::BeforeMeal),
-        ("/addga", GlucoseTag::AfterMeal),
-        ("/add_glucose_after", GlucoseTag::AfterMeal),
-    ];
-
-    for (cmd, tag) in mappings {
-        if text == cmd {
-            return Some((tag, ""));
-        }
-
-        let with_space = format!("{cmd} ");
-        if let Some(rest) = text.strip_prefix(&with_space) {
-            return Some((tag, rest.trim()));
-        }
-    }
-
-    None
-}
-
-fn help_text() -> &'static str {
-    "Commands:\n\
-/menu - show menu buttons\n\
-/help - show this help\n\
-/addmed <name> - add medication button\n\
-/addgb <value> [date time] [@note] - add glucose before meal\n\
-/addga <value> [date time] [@note] - add glucose after meal\n\n\
+fn help_text() -> String {
+    format!(
+        "Commands:\n\
+{}\n\
 Date/time examples:\n\
 - 2/1 9:05\n\
 - 02/01 09:05\n\
 - 24/2/1 9:05\n\
 - 2024/2/1 9:05\n\
 If year is omitted, current year is used.\n\
-Note example: @before breakfast\n\n\
-Warning: data is stored as plain text CSV/TXT and is not encrypted by this bot."
+Note example: @before breakfast\n\
+Several readings: /addgb 5.8, 6.1, 5.9 (same time and note)\n\
+Source marker: /addgb 5.8 !cgm (default: manual)\n\
+Paste several lines of date time value to import them in one go.\n\n\
+Warning: data is stored as plain text CSV/TXT and is not encrypted by this bot.",
+        commands::help_lines()
+    )
 }
 
-fn parse_glucose_payload(
-    payload: &str,
-    input_tz: Tz,
-) -> anyhow::Result<(f64, Option<String>, Option<String>)> {
+fn parse_glucose_payload(payload: &str, options: &InputOptions) -> anyhow::Result<GlucoseInput> {
     let (without_note, note) = split_note(payload);
-    let mut parts = without_note.split_whitespace();
-    let value_raw = parts
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Missing glucose value"))?;
-    let value = parse_decimal(value_raw)
-        .ok_or_else(|| anyhow::anyhow!("Invalid glucose value. Example: 5.8"))?;
+    check_note_length(note.as_deref(), options.note_max_len)?;
+    let (without_flag, confirmed) = split_confirmed(without_note);
+    let (without_source, source) = split_source(&without_flag)?;
+    let (without_trend, trend) = split_trend(&without_source)?;
+    let mut parts = without_trend.split_whitespace().peekable();
+    let mut values = Vec::new();
+    loop {
+        let raw = parts.next().ok_or_else(|| {
+            if values.is_empty() {
+                anyhow::anyhow!("Missing glucose value")
+            } else {
+                anyhow::anyhow!("Missing glucose value after separator. Example: 5.8, 6.1")
+            }
+        })?;
+        let (raw, mut more) = match raw.strip_suffix([',', ';']) {
+            Some(stripped) => (stripped, true),
+            None => (raw, false),
+        };
+        if !raw.is_empty() {
+            values.push(parse_entered_glucose(raw, options.glucose_unit)?);
+        } else if !more {
+            return Err(anyhow::anyhow!("Missing glucose value"));
+        }
+        if let Some(&separator) = parts.peek()
+            && (separator == "," || separator == ";")
+        {
+            parts.next();
+            more = true;
+        }
+        if !more {
+            break;
+        }
+    }
 
     let rest = parts.collect::<Vec<_>>().join(" ");
     if rest.trim().is_empty() {
-        return Ok((value, None, note));
+        return Ok(GlucoseInput {
+            values,
+            timestamp: None,
+            note,
+            source,
+            confirmed,
+            trend,
+        });
+    }
+
+    let dt = parse_flexible_datetime(&rest, options).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Invalid date/time. Examples: 2/1 9:05, 02/01 09:05, 24/2/1 9:05, 2024/2/1 9:05, 2024-02-01T09:05, yesterday 9:05"
+        )
+    })?;
+    Ok(GlucoseInput {
+        values,
+        timestamp: Some(options.format_timestamp(dt)),
+        note,
+        source,
+        confirmed,
+        trend,
+    })
+}
+
+fn parse_glucose_value(raw: &str) -> anyhow::Result<f64> {
+    let value = parse_decimal(raw).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Invalid glucose value '{raw}'. Example: 5.8 (separate several readings with ', ')"
+        )
+    })?;
+    if !(GLUCOSE_MIN_MMOL_L..=GLUCOSE_MAX_MMOL_L).contains(&value) {
+        return Err(anyhow::anyhow!(
+            "Glucose value {value} is out of range ({GLUCOSE_MIN_MMOL_L:.1}-{GLUCOSE_MAX_MMOL_L:.1} mmol/L)"
+        ));
+    }
+    Ok(value)
+}
+
+/// Glucose value typed in a chat whose `/units` is `unit`, in mmol/L. With mg/dL a whole
+/// number is a mg/dL reading such as `104`, divided without rounding so the mg/dL row
+/// reads `104` again; decimals stay mmol/L.
+fn parse_entered_glucose(raw: &str, unit: settings::GlucoseUnit) -> anyhow::Result<f64> {
+    let mg_dl = match unit {
+        settings::GlucoseUnit::Mgdl => raw.parse::<u32>().ok(),
+        settings::GlucoseUnit::Mmol => None,
+    };
+    let Some(mg_dl) = mg_dl else {
+        return parse_glucose_value(raw);
+    };
+    let (min, max) = (
+        mmol_l_to_mg_dl(GLUCOSE_MIN_MMOL_L),
+        mmol_l_to_mg_dl(GLUCOSE_MAX_MMOL_L),
+    );
+    if !(min..=max).contains(&f64::from(mg_dl)) {
+        return Err(anyhow::anyhow!(
+            "Glucose value {mg_dl} mg/dL is out of range ({min:.0}-{max:.0} mg/dL). \
+For mmol/L, type a decimal such as {mg_dl}.0"
+        ));
+    }
+    Ok(f64::from(mg_dl) / MG_DL_PER_MMOL_L)
+}
+
+/// Extracts the override flag from the part before the note: a `#confirmed` tag anywhere
+/// or a `!` ending the last word, on its own or attached (e.g. `2.1!`).
+fn split_confirmed(input: &str) -> (String, bool) {
+    let mut confirmed = false;
+    let mut rest = input
+        .split_whitespace()
+        .filter(|token| {
+            let is_tag = token.eq_ignore_ascii_case(CONFIRMED_TAG);
+            confirmed |= is_tag;
+            !is_tag
+        })
+        .collect::<Vec<_>>();
+    if let Some(last) = rest.last_mut()
+        && !last.starts_with(SOURCE_MARKER)
+        && let Some(stripped) = last.strip_suffix('!')
+    {
+        *last = stripped;
+        confirmed = true;
+    } else if rest.last() == Some(&"!") {
+        rest.pop();
+        confirmed = true;
+    }
+    (rest.join(" "), confirmed)
+}
+
+/// Extracts an optional `!source` marker (e.g. `!cgm`) from the part before the note.
+fn split_source(input: &str) -> anyhow::Result<(String, Option<String>)> {
+    let mut source = None;
+    let mut rest = Vec::new();
+    for token in input.split_whitespace() {
+        let Some(name) = token.strip_prefix(SOURCE_MARKER) else {
+            rest.push(token);
+            continue;
+        };
+        let name = name.to_lowercase();
+        if name.is_empty()
+            || name.chars().count() > SOURCE_MAX_LEN
+            || !name
+                .chars()
+                .all(|ch| ch.is_alphanumeric() || ch == '-' || ch == '_')
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid source '{token}'. Use letters, digits, '-' or '_', e.g. !cgm"
+            ));
+        }
+        if source.replace(name).is_some() {
+            return Err(anyhow::anyhow!("Only one !source marker is allowed"));
+        }
+    }
+    Ok((rest.join(" "), source))
+}
+
+/// Extracts an optional trend arrow token (see [`GlucoseTrend::parse`]) from the part
+/// before the note.
+fn split_trend(input: &str) -> anyhow::Result<(String, Option<GlucoseTrend>)> {
+    let mut trend = None;
+    let mut rest = Vec::new();
+    for token in input.split_whitespace() {
+        let Some(parsed) = GlucoseTrend::parse(token) else {
+            rest.push(token);
+            continue;
+        };
+        if trend.replace(parsed).is_some() {
+            return Err(anyhow::anyhow!("Only one trend arrow is allowed"));
+        }
     }
+    Ok((rest.join(" "), trend))
+}
+
+/// Parses `<value> [date time] [@note]` for blood ketones.
+fn parse_ketone_payload(
+    payload: &str,
+    options: &InputOptions,
+) -> anyhow::Result<(f64, Option<String>, Option<String>)> {
+    let payload = payload.trim();
+    let (value_raw, rest) = payload
+        .split_once(char::is_whitespace)
+        .unwrap_or((payload, ""));
+    if value_raw.is_empty() || value_raw.starts_with('@') {
+        return Err(anyhow::anyhow!("Missing ketone value"));
+    }
+    let value = parse_decimal(value_raw)
+        .ok_or_else(|| anyhow::anyhow!("Invalid ketone value '{value_raw}'. Example: 0.6"))?;
+    if !(KETONES_MIN_MMOL_L..=KETONES_MAX_MMOL_L).contains(&value) {
+        return Err(anyhow::anyhow!(
+            "Ketone value {value} is out of range ({KETONES_MIN_MMOL_L:.1}-{KETONES_MAX_MMOL_L:.1} mmol/L)"
+        ));
+    }
+    let (timestamp, note) = parse_time_and_note(rest, options)?;
+    Ok((value, timestamp, note))
+}
 
-    let dt = parse_flexible_datetime(&rest, input_tz).ok_or_else(|| {
+/// Parses `[date time] [@note]`; used by entries that carry no value.
+fn parse_time_and_note(
+    payload: &str,
+    options: &InputOptions,
+) -> anyhow::Result<(Option<String>, Option<String>)> {
+    let (without_note, note) = split_note(payload);
+    check_note_length(note.as_deref(), options.note_max_len)?;
+    if without_note.is_empty() {
+        return Ok((None, note));
+    }
+    let dt = parse_flexible_datetime(without_note, options).ok_or_else(|| {
         anyhow::anyhow!(
-            "Invalid date/time. Examples: 2/1 9:05, 02/01 09:05, 24/2/1 9:05, 2024/2/1 9:05"
+            "Invalid date/time. Examples: 2/1 9:05, 02/01 09:05, 24/2/1 9:05, 2024/2/1 9:05, 2024-02-01T09:05, yesterday 9:05"
         )
     })?;
-    Ok((value, Some(dt.to_rfc3339()), note))
+    Ok((Some(options.format_timestamp(dt)), note))
+}
+
+fn check_note_length(note: Option<&str>, max_len: usize) -> anyhow::Result<()> {
+    let len = note.map_or(0, |note| note.chars().count());
+    if max_len > 0 && len > max_len {
+        return Err(anyhow::anyhow!(
+            "Note is too long ({len} characters, max {max_len}). Please shorten it."
+        ));
+    }
+    Ok(())
 }
 
 fn split_note(input: &str) -> (&str, Option<String>) {
@@ -516,8 +3551,39 @@ fn split_note(input: &str) -> (&str, Option<String>) {
     }
 }
 
-fn parse_flexible_datetime(input: &str, input_tz: Tz) -> Option<chrono::DateTime<Utc>> {
-    let normalized = input.trim().replace(['-', '.'], "/");
+/// Words that stand for a date relative to today, with how many days back they point,
+/// by language. All languages are accepted, so `сьогодні 9:05` works next to
+/// `today 9:05`.
+const RELATIVE_DAY_WORDS: [(&str, &[(&str, u64)]); 2] = [
+    ("en", &[("today", 0), ("yesterday", 1)]),
+    ("uk", &[("сьогодні", 0), ("вчора", 1), ("учора", 1)]),
+];
+
+/// Days back from today for a relative date word in any language, case-insensitive.
+fn relative_day_offset(word: &str) -> Option<u64> {
+    let word = word.to_lowercase();
+    RELATIVE_DAY_WORDS
+        .iter()
+        .flat_map(|(_, words)| words.iter())
+        .find(|(relative, _)| *relative == word)
+        .map(|(_, days_back)| *days_back)
+}
+
+/// Parses `[YY/]M/D H:MM` with `/`, `-` or `.` in the date, in `input_timezone`. ISO-8601
+/// forms are accepted too: `2024-02-01T09:05[:SS]`, and with an offset such as `Z` or
+/// `+02:00` the given offset is used instead of `input_timezone`. The date may also be a
+/// [`RELATIVE_DAY_WORDS`] entry such as `yesterday 9:05`.
+fn parse_flexible_datetime(input: &str, options: &InputOptions) -> Option<chrono::DateTime<Utc>> {
+    let input_tz = options.tz;
+    let input = input.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(input) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    let input = match input.split_once('T') {
+        Some((date, time)) if date.contains('-') => format!("{date} {time}"),
+        _ => input.to_string(),
+    };
+    let normalized = input.replace(['-', '.'], "/");
     let mut parts = normalized.split_whitespace();
     let date_part = parts.next()?;
     let time_part = parts.next()?;
@@ -525,83 +3591,711 @@ fn parse_flexible_datetime(input: &str, input_tz: Tz) -> Option<chrono::DateTime
         return None;
     }
 
+    let relative_date = relative_day_offset(date_part).map(|days_back| {
+        options.clock.now().with_timezone(&input_tz).date_naive() - chrono::Days::new(days_back)
+    });
     let date_parts = date_part.split('/').collect::<Vec<_>>();
-    if !(date_parts.len() == 2 || date_parts.len() == 3) {
+    if relative_date.is_none() && !(date_parts.len() == 2 || date_parts.len() == 3) {
         return None;
     }
 
     let time_parts = time_part.split(':').collect::<Vec<_>>();
-    if time_parts.len() != 2 {
+    if !(time_parts.len() == 2 || time_parts.len() == 3) {
+        return None;
+    }
+
+    let hour = time_parts[0].parse::<u32>().ok()?;
+    let minute = time_parts[1].parse::<u32>().ok()?;
+    let second = match time_parts.get(2) {
+        Some(second) => second.parse::<u32>().ok()?,
+        None => 0,
+    };
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let (year, month, day) = if let Some(date) = relative_date {
+        (date.year(), date.month(), date.day())
+    } else if date_parts.len() == 2 {
+        let month = date_parts[0].parse::<u32>().ok()?;
+        let day = date_parts[1].parse::<u32>().ok()?;
+        let current_year = options.clock.now().with_timezone(&input_tz).year();
+        (current_year, month, day)
+    } else {
+        let year_raw = date_parts[0].parse::<i32>().ok()?;
+        let month = date_parts[1].parse::<u32>().ok()?;
+        let day = date_parts[2].parse::<u32>().ok()?;
+
+        let year = if (0..=99).contains(&year_raw) {
+            2000 + year_raw
+        } else {
+            year_raw
+        };
+        (year, month, day)
+    };
+
+    let date = NaiveDate::from_ymd_opt(year, month, day)?;
+    let time = NaiveTime::from_hms_opt(hour, minute, second)?;
+    let naive = date.and_time(time);
+    match input_tz.from_local_datetime(&naive) {
+        LocalResult::Single(dt) => Some(dt.with_timezone(&Utc)),
+        LocalResult::Ambiguous(dt, _) => Some(dt.with_timezone(&Utc)),
+        LocalResult::None => None,
+    }
+}
+
+/// Applies `/setrange` arguments. Returns `Ok(false)` when there is nothing to change.
+fn apply_setrange(
+    user_settings: &mut settings::UserSettings,
+    payload: &str,
+) -> anyhow::Result<bool> {
+    let usage = "Usage: /setrange [before|after|HH:MM-HH:MM] <low> <high>, \
+or /setrange before|after|HH:MM-HH:MM off";
+    let parts = payload.split_whitespace().collect::<Vec<_>>();
+    if parts.is_empty() {
+        return Ok(false);
+    }
+    if let Some((start, end)) = settings::parse_time_window(parts[0]) {
+        let window = format!("{}-{}", start.format("%H:%M"), end.format("%H:%M"));
+        return apply_time_range(user_settings, window, &parts[1..], usage);
+    }
+    let (slot, args) = match parts[0].to_lowercase().as_str() {
+        "before" => (&mut user_settings.before_meal_range, &parts[1..]),
+        "after" => (&mut user_settings.after_meal_range, &parts[1..]),
+        _ => (&mut user_settings.target_range, &parts[..]),
+    };
+    match args {
+        [off] if off.eq_ignore_ascii_case("off") => {
+            *slot = None;
+            Ok(true)
+        }
+        [low, high] => {
+            *slot = Some(parse_target_range(low, high, usage)?);
+            Ok(true)
+        }
+        _ => Err(anyhow::anyhow!("{usage}")),
+    }
+}
+
+/// Sets, replaces or removes (`off`) the time-of-day range for `window`.
+fn apply_time_range(
+    user_settings: &mut settings::UserSettings,
+    window: String,
+    args: &[&str],
+    usage: &str,
+) -> anyhow::Result<bool> {
+    let existing = user_settings
+        .time_ranges
+        .iter()
+        .position(|range| range.window == window);
+    match (args, existing) {
+        ([off], Some(index)) if off.eq_ignore_ascii_case("off") => {
+            user_settings.time_ranges.remove(index);
+            Ok(true)
+        }
+        ([off], None) if off.eq_ignore_ascii_case("off") => {
+            Err(anyhow::anyhow!("No target range is set for {window}"))
+        }
+        ([low, high], _) => {
+            let range = parse_target_range(low, high, usage)?;
+            let time_range = settings::TimeRange {
+                window,
+                low: range.low,
+                high: range.high,
+            };
+            match existing {
+                Some(index) => user_settings.time_ranges[index] = time_range,
+                None if user_settings.time_ranges.len() >= TIME_RANGES_MAX => {
+                    return Err(anyhow::anyhow!(
+                        "At most {TIME_RANGES_MAX} time ranges. Remove one with /setrange HH:MM-HH:MM off"
+                    ));
+                }
+                None => user_settings.time_ranges.push(time_range),
+            }
+            Ok(true)
+        }
+        _ => Err(anyhow::anyhow!("{usage}")),
+    }
+}
+
+fn parse_target_range(
+    low: &str,
+    high: &str,
+    usage: &str,
+) -> anyhow::Result<settings::GlucoseRange> {
+    let (Some(low), Some(high)) = (parse_decimal(low), parse_decimal(high)) else {
+        return Err(anyhow::anyhow!("{usage}"));
+    };
+    let bounds = GLUCOSE_MIN_MMOL_L..=GLUCOSE_MAX_MMOL_L;
+    if !bounds.contains(&low) || !bounds.contains(&high) || low >= high {
+        return Err(anyhow::anyhow!(
+            "Invalid range. Low must be below high, both within {GLUCOSE_MIN_MMOL_L:.1}-{GLUCOSE_MAX_MMOL_L:.1} mmol/L"
+        ));
+    }
+    Ok(settings::GlucoseRange { low, high })
+}
+
+fn describe_ranges(user_settings: &settings::UserSettings) -> String {
+    let general = match user_settings.target_range {
+        Some(range) => range.to_string(),
+        None => format!("{} (default)", settings::DEFAULT_TARGET_RANGE),
+    };
+    let specific = |range: Option<settings::GlucoseRange>| match range {
+        Some(range) => range.to_string(),
+        None => "general".to_string(),
+    };
+    let mut description = format!(
+        "Target ranges (mmol/L):\n- general: {general}\n- before meal: {}\n- after meal: {}",
+        specific(user_settings.before_meal_range),
+        specific(user_settings.after_meal_range)
+    );
+    for time_range in &user_settings.time_ranges {
+        description.push_str(&format!(
+            "\n- {}: {}",
+            time_range.window,
+            time_range.range()
+        ));
+    }
+    description
+}
+
+/// Settings for confirmations: a broken settings file must not block saving data.
+fn settings_or_default(state: &AppState, chat_id: ChatId) -> settings::UserSettings {
+    settings::load_settings(&state.data_dir, chat_id).unwrap_or_else(|err| {
+        tracing::warn!("failed to load settings for chat {}: {err}", chat_id.0);
+        settings::UserSettings::default()
+    })
+}
+
+/// Mark ending save confirmations: the chat's `/confirm` choice or ✅.
+fn confirmation_text(state: &AppState, chat_id: ChatId) -> String {
+    settings_or_default(state, chat_id)
+        .confirmation
+        .unwrap_or_else(|| settings::DEFAULT_CONFIRMATION.to_string())
+}
+
+/// How a save of one metric is confirmed: the chat's mark, alone when `/confirm` set the
+/// metric to short.
+struct SaveConfirmation {
+    mark: String,
+    short: bool,
+}
+
+impl SaveConfirmation {
+    /// The mark alone when short, else the detailed text built around it. Callers add
+    /// warnings after this either way.
+    fn text(&self, detailed: impl FnOnce(&str) -> String) -> String {
+        if self.short {
+            self.mark.clone()
+        } else {
+            detailed(&self.mark)
+        }
+    }
+}
+
+fn save_confirmation(
+    state: &AppState,
+    chat_id: ChatId,
+    metric: settings::ConfirmMetric,
+) -> SaveConfirmation {
+    let user_settings = settings_or_default(state, chat_id);
+    SaveConfirmation {
+        short: user_settings.confirm_style(metric) == settings::ConfirmStyle::Short,
+        mark: user_settings
+            .confirmation
+            .unwrap_or_else(|| settings::DEFAULT_CONFIRMATION.to_string()),
+    }
+}
+
+fn mmol_l_to_mg_dl(value: f64) -> f64 {
+    value * MG_DL_PER_MMOL_L
+}
+
+/// Glucose value in both units, e.g. `5.8 mmol/L (104 mg/dL)`.
+fn format_dual_units(value: f64) -> String {
+    format!("{value:.1} mmol/L ({:.0} mg/dL)", mmol_l_to_mg_dl(value))
+}
+
+/// Records that the chat has seen [`PRIVACY_NOTICE`], so it is not sent again.
+fn acknowledge_privacy_notice(state: &AppState, chat_id: ChatId) -> anyhow::Result<()> {
+    let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+    if user_settings.privacy_acknowledged != Some(true) {
+        user_settings.privacy_acknowledged = Some(true);
+        settings::save_settings(state, chat_id, &user_settings)?;
+    }
+    Ok(())
+}
+
+/// Tells the user that malformed CSV lines were left out of a report, e.g. `(2 rows skipped)`.
+fn append_skipped_note(report: &mut String, skipped: usize) {
+    match skipped {
+        0 => {}
+        1 => report.push_str("\n(1 row skipped)"),
+        n => report.push_str(&format!("\n({n} rows skipped)")),
+    }
+}
+
+/// Progress line for a just-saved weight when the user set `/weightgoal`.
+fn weight_goal_progress(
+    state: &AppState,
+    chat_id: ChatId,
+    earlier: &[records::WeightRecord],
+    current: f64,
+) -> Option<String> {
+    let goal = settings_or_default(state, chat_id).weight_goal?;
+    let start = earlier
+        .iter()
+        .min_by_key(|record| record.timestamp)
+        .map_or(current, |record| record.value);
+    Some(reports::weight_goal_progress(start, current, goal))
+}
+
+/// Gentle note when a weight saved at `saved_at` differs by more than the `/weightalert`
+/// threshold from the entry right before it among `earlier`; rows dated after it are
+/// ignored. Entries further apart than [`WEIGHT_ALERT_WINDOW_DAYS`] are not compared, so
+/// a slow change over a long gap does not look sudden.
+fn weight_change_note(
+    state: &AppState,
+    chat_id: ChatId,
+    earlier: &[records::WeightRecord],
+    saved_at: DateTime<Utc>,
+    value: f64,
+) -> Option<String> {
+    let threshold = settings_or_default(state, chat_id).weight_alert?;
+    let previous = earlier
+        .iter()
+        .filter(|record| record.timestamp <= saved_at)
+        .max_by_key(|record| record.timestamp)?;
+    let elapsed = saved_at - previous.timestamp;
+    let change = value - previous.value;
+    if elapsed > chrono::Duration::days(WEIGHT_ALERT_WINDOW_DAYS) || change.abs() <= threshold {
+        return None;
+    }
+    let since = match (elapsed.num_hours() + 12) / 24 {
+        0 => "earlier today".to_string(),
+        1 => "1 day ago".to_string(),
+        days => format!("{days} days ago"),
+    };
+    Some(format!(
+        "Note: weight changed by {change:+.1} kg since {since} ({:.1} kg). Quick changes can be worth mentioning to your care team.",
+        previous.value
+    ))
+}
+
+/// Appends the saved values in both units when the user enabled `/dualunits`.
+fn append_dual_units(confirmation: &mut String, state: &AppState, chat_id: ChatId, values: &[f64]) {
+    if !settings_or_default(state, chat_id)
+        .dual_units
+        .unwrap_or(false)
+    {
+        return;
+    }
+    let values = values
+        .iter()
+        .map(|&value| format_dual_units(value))
+        .collect::<Vec<_>>();
+    confirmation.push(' ');
+    confirmation.push_str(&values.join(", "));
+}
+
+/// Adds a line per saved value that is outside the user's range for `tag` at the
+/// reading's local time, naming the band that was applied. With `/inrange` on, a
+/// reading whose values all sit in the middle half of that range gets [`IN_RANGE_NOTE`].
+fn append_range_warnings(
+    confirmation: &mut String,
+    state: &AppState,
+    chat_id: ChatId,
+    tag: GlucoseTag,
+    input: &GlucoseInput,
+) {
+    if input.confirmed {
+        return;
+    }
+    let user_settings = settings_or_default(state, chat_id);
+    let taken_at = input
+        .timestamp
+        .as_deref()
+        .and_then(records::parse_timestamp)
+        .unwrap_or_else(|| state.now());
+    let local_time = taken_at.with_timezone(&state.input_for(chat_id).tz).time();
+    let (range, label) = user_settings.range_at(tag, local_time);
+    let unit = user_settings.glucose_unit.unwrap_or_default();
+    let target = range.format_in(unit);
+    for &value in &input.values {
+        let shown = unit.format_stored(user_settings.round_glucose(value, unit));
+        if value < range.low {
+            confirmation.push_str(&format!(
+                "\n⚠️ {shown} is below your {label} target ({target})"
+            ));
+        } else if value > range.high {
+            confirmation.push_str(&format!(
+                "\n⚠️ {shown} is above your {label} target ({target})"
+            ));
+        }
+    }
+    if user_settings.in_range_note.unwrap_or(false)
+        && !input.values.is_empty()
+        && input
+            .values
+            .iter()
+            .all(|&value| range.comfortably_contains(value))
+    {
+        confirmation.push('\n');
+        confirmation.push_str(IN_RANGE_NOTE);
+        confirmation.push_str(" 🎯");
+    }
+}
+
+/// Parses `<item> [grams g] [@note]`; the amount is the last word, e.g. `45g` or `45 g`.
+fn parse_food_payload(
+    payload: &str,
+    options: &InputOptions,
+) -> anyhow::Result<(String, Option<f64>, Option<String>)> {
+    let (without_note, note) = split_note(payload);
+    check_note_length(note.as_deref(), options.note_max_len)?;
+    let mut words = without_note.split_whitespace().collect::<Vec<_>>();
+    let mut grams_raw = None;
+    if let [_, .., amount, unit] = words.as_slice()
+        && unit.eq_ignore_ascii_case("g")
+        && parse_decimal(amount).is_some()
+    {
+        grams_raw = Some(*amount);
+        words.truncate(words.len() - 2);
+    } else if let [_, .., last] = words.as_slice()
+        && let Some(amount) = last.strip_suffix(['g', 'G'])
+        && parse_decimal(amount).is_some()
+    {
+        grams_raw = Some(amount);
+        words.pop();
+    }
+    let grams = grams_raw.and_then(parse_decimal);
+    if let Some(grams) = grams
+        && !(grams > 0.0 && grams <= FOOD_MAX_GRAMS)
+    {
+        return Err(anyhow::anyhow!(
+            "Amount {grams} g is out of range (up to {FOOD_MAX_GRAMS:.0} g)"
+        ));
+    }
+    let item = words.join(" ");
+    if item.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Missing food item. Example: oatmeal 45g @breakfast"
+        ));
+    }
+    Ok((item, grams, note))
+}
+
+fn save_food(
+    state: &AppState,
+    chat_id: ChatId,
+    (item, grams, note): (String, Option<f64>, Option<String>),
+) -> anyhow::Result<String> {
+    let file = user_data_dir(&state.data_dir, chat_id).join(FOOD_FILE);
+    append_line_if_needed(&file, FOOD_HEADER, state.input.sync_writes)?;
+    let grams_field = grams.map(|grams| grams.to_string()).unwrap_or_default();
+    append_csv_line(
+        &file,
+        &format!(
+            "{},{},\"{}\",{grams_field},\"{}\"",
+            state.input_for(chat_id).now_timestamp(),
+            chat_id.0,
+            csv_escape(&item),
+            csv_escape(note.as_deref().unwrap_or(""))
+        ),
+        state.input.sync_writes,
+    )?;
+    let logged = match &note {
+        Some(note) => format!("{item} @{note}"),
+        None => item.clone(),
+    };
+    log_saved_entry(&state.input, chat_id, "food", grams, None, Some(&logged));
+    Ok(
+        save_confirmation(state, chat_id, settings::ConfirmMetric::Food).text(|mark| match grams {
+            Some(grams) => format!("Food saved {mark} ({item}, {grams} g)"),
+            None => format!("Food saved {mark} ({item})"),
+        }),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MedicationRename {
+    old: String,
+    new: String,
+    /// Rewrite matching `medication_log.csv` rows to the new name.
+    migrate_log: bool,
+}
+
+/// Parses `<old> -> <new> [--keep-log]`; two single-word names may omit the arrow.
+fn parse_rename_payload(payload: &str) -> Option<MedicationRename> {
+    let (payload, migrate_log) = match payload.strip_suffix(RENAME_KEEP_LOG_FLAG) {
+        Some(rest) => (rest.trim(), false),
+        None => (payload, true),
+    };
+    let (old, new) = match payload.split_once("->") {
+        Some((old, new)) => (old, new),
+        None => match payload.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [old, new] => (*old, *new),
+            _ => return None,
+        },
+    };
+    let (old, new) = (
+        normalize_medication_name(old),
+        normalize_medication_name(new),
+    );
+    if old.is_empty() || new.is_empty() {
         return None;
     }
+    Some(MedicationRename {
+        old,
+        new,
+        migrate_log,
+    })
+}
+
+/// Renames a medication button. If `new` already exists, `old` is merged into it.
+fn rename_medication(
+    state: &AppState,
+    chat_id: ChatId,
+    rename: &MedicationRename,
+) -> anyhow::Result<String> {
+    let medications = load_medications(&state.data_dir, chat_id)?;
+    let Some(old) = medications
+        .iter()
+        .find(|existing| existing.eq_ignore_ascii_case(&rename.old))
+        .cloned()
+    else {
+        return Ok(format!("Medication not found: {}", rename.old));
+    };
+    let existing_new = medications
+        .iter()
+        .find(|existing| existing.eq_ignore_ascii_case(&rename.new) && **existing != old)
+        .cloned();
+    let merged = existing_new.is_some();
+    if !merged && let Some(reason) = medication_name_error(&rename.new) {
+        return Ok(reason);
+    }
+    let new = existing_new.unwrap_or_else(|| rename.new.clone());
+
+    let mut updated = Vec::with_capacity(medications.len());
+    for name in medications {
+        if name == old {
+            if !merged {
+                updated.push(new.clone());
+            }
+        } else {
+            updated.push(name);
+        }
+    }
+    let mut content = updated.join("\n");
+    content.push('\n');
+    write_file_atomically(
+        &medications_path(&state.data_dir, chat_id),
+        &content,
+        state.input.sync_writes,
+    )?;
 
-    let hour = time_parts[0].parse::<u32>().ok()?;
-    let minute = time_parts[1].parse::<u32>().ok()?;
-    if hour > 23 || minute > 59 {
-        return None;
+    let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+    if let Some(times) = user_settings.med_times.remove(&old) {
+        let merged_times = user_settings.med_times.entry(new.clone()).or_default();
+        merged_times.extend(times);
+        merged_times.sort();
+        merged_times.dedup();
+        settings::save_settings(state, chat_id, &user_settings)?;
     }
 
-    let (year, month, day) = if date_parts.len() == 2 {
-        let month = date_parts[0].parse::<u32>().ok()?;
-        let day = date_parts[1].parse::<u32>().ok()?;
-        let current_year = Utc::now().with_timezone(&input_tz).year();
-        (current_year, month, day)
+    let mut reply = if merged {
+        format!("Medication {old} merged into {new} ✅")
     } else {
-        let year_raw = date_parts[0].parse::<i32>().ok()?;
-        let month = date_parts[1].parse::<u32>().ok()?;
-        let day = date_parts[2].parse::<u32>().ok()?;
-
-        let year = if (0..=99).contains(&year_raw) {
-            2000 + year_raw
-        } else {
-            year_raw
-        };
-        (year, month, day)
+        format!("Medication renamed: {old} → {new} ✅")
     };
-
-    let date = NaiveDate::from_ymd_opt(year, month, day)?;
-    let time = NaiveTime::from_hms_opt(hour, minute, 0)?;
-    let naive = date.and_time(time);
-    match input_tz.from_local_datetime(&naive) {
-        LocalResult::Single(dt) => Some(dt.with_timezone(&Utc)),
-        LocalResult::Ambiguous(dt, _) => Some(dt.with_timezone(&Utc)),
-        LocalResult::None => None,
+    if rename.migrate_log {
+        let rows = rename_medication_log_rows(
+            &state.data_dir,
+            chat_id,
+            &old,
+            &new,
+            state.input.sync_writes,
+        )?;
+        reply.push_str(&format!("\nLog rows updated: {rows}"));
+    } else {
+        reply.push_str("\nLog rows kept with the old name.");
     }
+    tracing::info!("chat {} renamed medication {old} to {new}", chat_id.0);
+    Ok(reply)
 }
 
-fn parse_addmed_command(text: &str) -> Option<&str> {
-    for prefix in ["/addmed", "/add_medication"] {
-        if text == prefix {
-            return Some("");
+/// Sets or, with `times` of `None`, removes the `/calendar` times of a listed medication.
+fn set_medication_times(
+    state: &AppState,
+    chat_id: ChatId,
+    name: &str,
+    times: Option<Vec<NaiveTime>>,
+) -> anyhow::Result<String> {
+    let Some(name) = load_medications(&state.data_dir, chat_id)?
+        .into_iter()
+        .find(|existing| existing.eq_ignore_ascii_case(name))
+    else {
+        return Ok(format!("Medication not found: {name}"));
+    };
+    let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+    let reply = match times {
+        Some(times) => {
+            let times = times
+                .iter()
+                .map(|time| time.format("%H:%M").to_string())
+                .collect::<Vec<_>>();
+            let reply = format!(
+                "Daily times of {name}: {}. Use /calendar to export them.",
+                times.join(", ")
+            );
+            user_settings.med_times.insert(name, times);
+            reply
         }
-        let with_space = format!("{prefix} ");
-        if let Some(rest) = text.strip_prefix(&with_space) {
-            return Some(rest.trim());
+        None => {
+            user_settings.med_times.remove(&name);
+            format!("Daily times of {name} removed.")
         }
-    }
-    None
+    };
+    settings::save_settings(state, chat_id, &user_settings)?;
+    Ok(reply)
+}
+
+/// Time and medication name of each row of `medication_log.csv`.
+fn read_medication_log(
+    data_dir: &Path,
+    chat_id: ChatId,
+) -> anyhow::Result<Vec<(DateTime<Utc>, String)>> {
+    let path = user_data_dir(data_dir, chat_id).join(MEDICATION_LOG_FILE);
+    Ok(records::read_raw_rows(&path)?
+        .into_iter()
+        .map(|row| {
+            (
+                row.timestamp,
+                row.fields.get(2).cloned().unwrap_or_default(),
+            )
+        })
+        .collect())
 }
 
-fn parse_medication_button(text: &str) -> Option<&str> {
-    text.strip_prefix(MED_BUTTON_PREFIX).map(str::trim)
+/// Rewrites the medication column of `medication_log.csv` rows named `old`.
+fn rename_medication_log_rows(
+    data_dir: &Path,
+    chat_id: ChatId,
+    old: &str,
+    new: &str,
+    sync: bool,
+) -> anyhow::Result<usize> {
+    let path = user_data_dir(data_dir, chat_id).join(MEDICATION_LOG_FILE);
+    if !path.exists() {
+        return Ok(0);
+    }
+    let content = fs_err::read_to_string(&path)?;
+    let mut result = String::with_capacity(content.len());
+    let mut updated = 0;
+    for (index, line) in content.lines().enumerate() {
+        let fields = records::parse_csv_line(line);
+        let matches = index > 0
+            && fields
+                .get(2)
+                .is_some_and(|name| normalize_medication_name(name).eq_ignore_ascii_case(old));
+        if matches {
+            result.push_str(&format!(
+                "{},{},\"{}\"",
+                fields[0],
+                fields[1],
+                csv_escape(new)
+            ));
+            updated += 1;
+        } else {
+            result.push_str(line);
+        }
+        result.push('\n');
+    }
+    if updated > 0 {
+        write_file_atomically(&path, &result, sync)?;
+    }
+    Ok(updated)
 }
 
+/// Medication name as stored and matched: control characters count as spaces, invisible
+/// formatting characters are dropped, a leading `💊` copied from a button is removed and
+/// whitespace runs become one space. Quotes and commas are kept; the log quotes names.
 fn normalize_medication_name(name: &str) -> String {
-    name.split_whitespace().collect::<Vec<_>>().join(" ")
+    let cleaned = name
+        .chars()
+        .filter(|c| !is_invisible_format(*c))
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>();
+    let mut rest = cleaned.trim_start();
+    while let Some(stripped) = rest.strip_prefix(MED_BUTTON_PREFIX.trim_end()) {
+        rest = stripped.trim_start();
+    }
+    rest.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
-async fn medication_exists(state: &AppState, chat_id: ChatId, name: &str) -> bool {
-    let normalized = normalize_medication_name(name);
+/// Zero-width and text direction characters that would hide or reorder a button label.
+fn is_invisible_format(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}'
+    )
+}
+
+async fn match_medication(state: &AppState, chat_id: ChatId, name: &str) -> MedicationMatch {
     let medications = load_medications(&state.data_dir, chat_id).unwrap_or_default();
-    medications
+    find_medication(&medications, name)
+}
+
+fn find_medication(medications: &[String], name: &str) -> MedicationMatch {
+    let normalized = normalize_medication_name(name);
+    if let Some(existing) = medications
         .iter()
-        .any(|existing| existing.eq_ignore_ascii_case(&normalized))
+        .find(|existing| existing.to_lowercase() == normalized.to_lowercase())
+    {
+        return MedicationMatch::Exact(existing.clone());
+    }
+    if normalized.chars().count() < MEDICATION_FUZZY_MIN_LEN {
+        return MedicationMatch::None;
+    }
+
+    let lowered = normalized.to_lowercase();
+    let mut candidates = medications
+        .iter()
+        .map(|existing| (levenshtein(&existing.to_lowercase(), &lowered), existing))
+        .filter(|(distance, _)| *distance <= MEDICATION_FUZZY_MAX_DISTANCE)
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(distance, _)| *distance);
+    match candidates.as_slice() {
+        [(_, only)] => MedicationMatch::Fuzzy((*only).clone()),
+        [(best, name), (next, _), ..] if best < next => MedicationMatch::Fuzzy((*name).clone()),
+        _ => MedicationMatch::None,
+    }
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
 }
 
-async fn add_medication(state: &AppState, chat_id: ChatId, name: &str) -> anyhow::Result<bool> {
+fn add_medication(state: &AppState, chat_id: ChatId, name: &str) -> anyhow::Result<MedicationAdd> {
     let normalized = normalize_medication_name(name);
     if normalized.is_empty() {
-        return Ok(false);
+        return Ok(MedicationAdd::Rejected(
+            "Usage: /addmed <medication name>".to_string(),
+        ));
+    }
+    if let Some(reason) = medication_name_error(&normalized) {
+        return Ok(MedicationAdd::Rejected(reason));
     }
 
     let medications = load_medications(&state.data_dir, chat_id).unwrap_or_default();
@@ -609,11 +4303,93 @@ async fn add_medication(state: &AppState, chat_id: ChatId, name: &str) -> anyhow
         .iter()
         .any(|existing| existing.eq_ignore_ascii_case(&normalized))
     {
-        return Ok(false);
+        return Ok(MedicationAdd::AlreadyExists(normalized));
+    }
+
+    append_medication_name(
+        &state.data_dir,
+        chat_id,
+        &normalized,
+        state.input.sync_writes,
+    )?;
+    Ok(MedicationAdd::Added(normalized))
+}
+
+/// Adds every line of an uploaded medication list through [`add_medication`], so names
+/// are normalized and checked as with `/addmed`, and returns the reply with the counts.
+fn import_medications(
+    state: &AppState,
+    chat_id: ChatId,
+    file_name: &str,
+    text: &str,
+) -> anyhow::Result<String> {
+    let (mut added, mut existing, mut rejected) = (0, 0, Vec::new());
+    for (index, line) in text.lines().enumerate() {
+        if normalize_medication_name(line).is_empty() {
+            continue;
+        }
+        match add_medication(state, chat_id, line)? {
+            MedicationAdd::Added(_) => added += 1,
+            MedicationAdd::AlreadyExists(_) => existing += 1,
+            MedicationAdd::Rejected(reason) => {
+                rejected.push(format!("- line {}: {reason}", index + 1));
+            }
+        }
+    }
+    let mut reply = format!(
+        "Imported {file_name}: {added} added, {existing} skipped as already in your list, {} rejected.",
+        rejected.len()
+    );
+    for reason in rejected {
+        reply.push('\n');
+        reply.push_str(&reason);
     }
+    Ok(reply)
+}
 
-    append_medication_name(&state.data_dir, chat_id, &normalized)?;
-    Ok(true)
+/// Why `name` cannot be used for a medication: it is longer than
+/// [`MEDICATION_NAME_MAX_CHARS`], starts with `/` or a menu button emoji, or equals a
+/// button label or command word such as `Weight` or `menu`.
+fn medication_name_error(name: &str) -> Option<String> {
+    let buttons = [
+        BTN_GLUCOSE_BEFORE_MEAL,
+        BTN_GLUCOSE_AFTER_MEAL,
+        BTN_WEIGHT,
+        BTN_KETONES,
+        BTN_FOOD,
+        BTN_SHOW_MENU,
+        BTN_MORE_MEDS,
+    ];
+    let starts_like_button = buttons
+        .iter()
+        .chain(&[MED_BUTTON_PREFIX])
+        .filter_map(|label| label.chars().next())
+        .any(|emoji| name.starts_with(emoji));
+    if name.chars().count() > MEDICATION_NAME_MAX_CHARS {
+        return Some(format!(
+            "Medication name must be at most {MEDICATION_NAME_MAX_CHARS} characters."
+        ));
+    }
+    if name.starts_with('/') || starts_like_button {
+        return Some(format!(
+            "Medication name can't start with / or a menu button emoji: {name}"
+        ));
+    }
+    let is_button_label = buttons
+        .iter()
+        .copied()
+        .chain(language::translated_labels())
+        .any(|label| {
+            label
+                .split_once(' ')
+                .is_some_and(|(_, text)| text.to_lowercase() == name.to_lowercase())
+        });
+    let is_command = commands::command_words().any(|word| word.eq_ignore_ascii_case(name));
+    (is_button_label || is_command).then(|| {
+        format!(
+            "\"{name}\" is reserved for a menu button or command. Choose another medication name."
+        )
+    })
 }
 
 fn load_medications(data_dir: &Path, chat_id: ChatId) -> anyhow::Result<Vec<String>> {
@@ -648,41 +4424,74 @@ fn medications_path(data_dir: &Path, chat_id: ChatId) -> PathBuf {
     user_data_dir(data_dir, chat_id).join(MEDICATIONS_FILE)
 }
 
-fn append_medication_name(data_dir: &Path, chat_id: ChatId, name: &str) -> anyhow::Result<()> {
+fn append_medication_name(
+    data_dir: &Path,
+    chat_id: ChatId,
+    name: &str,
+    sync: bool,
+) -> anyhow::Result<()> {
     let path = medications_path(data_dir, chat_id);
     if let Some(parent) = path.parent() {
         fs_err::create_dir_all(parent)?;
     }
-    if !path.exists() {
-        fs_err::write(&path, format!("{name}\n"))?;
-        return Ok(());
-    }
-    append_csv_line(&path, name)
+    append_csv_line(&path, name, sync)
 }
 
 fn append_medication_log_csv(
     data_dir: &Path,
     chat_id: ChatId,
+    options: &InputOptions,
     medication: &str,
 ) -> anyhow::Result<()> {
     let file = user_data_dir(data_dir, chat_id).join(MEDICATION_LOG_FILE);
-    append_line_if_needed(&file, "timestamp,chat_id,medication")?;
-    let ts = chrono::Utc::now().to_rfc3339();
-    let escaped_medication = medication.replace('"', "\"\"");
+    append_line_if_needed(&file, MEDICATION_LOG_HEADER, options.sync_writes)?;
+    let ts = options.now_timestamp();
     append_csv_line(
         &file,
-        &format!("{ts},{},\"{escaped_medication}\"", chat_id.0),
-    )
+        &format!("{ts},{},\"{}\"", chat_id.0, csv_escape(medication)),
+        options.sync_writes,
+    )?;
+    log_saved_entry(options, chat_id, "medication", None, None, Some(medication));
+    Ok(())
 }
 
 async fn set_pending(state: &AppState, chat_id: ChatId, pending: PendingEntry) {
     let mut lock = state.pending_by_chat.lock().await;
-    lock.insert(chat_id, pending);
+    lock.insert(
+        chat_id,
+        TimedPending {
+            entry: pending,
+            created: Instant::now(),
+        },
+    );
 }
 
 async fn get_pending(state: &AppState, chat_id: ChatId) -> Option<PendingEntry> {
     let lock = state.pending_by_chat.lock().await;
-    lock.get(&chat_id).copied()
+    lock.get(&chat_id)
+        .filter(|pending| pending.created.elapsed() < PENDING_EXPIRY)
+        .map(|pending| pending.entry.clone())
+}
+
+/// Drops pending entries older than [`PENDING_EXPIRY`] at `now`. Returns how many were removed.
+async fn sweep_stale_pending(state: &AppState, now: Instant) -> usize {
+    let mut lock = state.pending_by_chat.lock().await;
+    let before = lock.len();
+    lock.retain(|_, pending| now.saturating_duration_since(pending.created) < PENDING_EXPIRY);
+    before - lock.len()
+}
+
+fn spawn_pending_sweeper(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PENDING_SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let removed = sweep_stale_pending(&state, Instant::now()).await;
+            if removed > 0 {
+                tracing::debug!("swept {removed} stale pending entries");
+            }
+        }
+    });
 }
 
 async fn clear_pending(state: &AppState, chat_id: ChatId) {
@@ -698,78 +4507,542 @@ fn parse_decimal(input: &str) -> Option<f64> {
 fn append_measurement_csv(
     data_dir: &Path,
     chat_id: ChatId,
+    options: &InputOptions,
     pending: PendingEntry,
     value: f64,
+    message_id: Option<MessageId>,
 ) -> anyhow::Result<()> {
     match pending {
         PendingEntry::GlucoseBeforeMeal | PendingEntry::GlucoseAfterMeal => {
             let tag = match pending {
                 PendingEntry::GlucoseBeforeMeal => GlucoseTag::BeforeMeal,
                 PendingEntry::GlucoseAfterMeal => GlucoseTag::AfterMeal,
-                PendingEntry::Weight => unreachable!(),
+                _ => unreachable!(),
             };
-            append_glucose_csv(data_dir, chat_id, tag, value, None, None)?;
+            let input = GlucoseInput {
+                values: vec![value],
+                timestamp: None,
+                note: None,
+                source: None,
+                confirmed: false,
+                trend: None,
+            };
+            append_glucose_input(data_dir, chat_id, options, tag, &input, message_id)?;
         }
         PendingEntry::Weight => {
-            let file = user_data_dir(data_dir, chat_id).join("weight.csv");
-            append_line_if_needed(&file, "timestamp,chat_id,value_kg")?;
-            let ts = chrono::Utc::now().to_rfc3339();
-            append_csv_line(&file, &format!("{ts},{},{}", chat_id.0, value))?;
+            let file = user_data_dir(data_dir, chat_id).join(WEIGHT_FILE);
+            append_line_if_needed(&file, WEIGHT_HEADER, options.sync_writes)?;
+            let ts = options.now_timestamp();
+            append_csv_line(
+                &file,
+                &weight_csv_line(&ts, chat_id, value, message_id),
+                options.sync_writes,
+            )?;
+            log_saved_entry(options, chat_id, "weight", Some(value), None, None);
         }
+        PendingEntry::Ketones
+        | PendingEntry::Food
+        | PendingEntry::ConfirmClear(_)
+        | PendingEntry::ConfirmMedication(_)
+        | PendingEntry::ConfirmImport(_)
+        | PendingEntry::ConfirmUnitConversion(_)
+        | PendingEntry::ConfirmResetSettings
+        | PendingEntry::EditRow(_) => unreachable!(),
     }
 
     Ok(())
 }
 
-fn append_glucose_csv(
-    data_dir: &Path,
+fn glucose_csv_line(
+    timestamp: &str,
     chat_id: ChatId,
     tag: GlucoseTag,
     value: f64,
+    input: &GlucoseInput,
+    message_id: Option<MessageId>,
+    unit: settings::GlucoseUnit,
+) -> String {
+    format!(
+        "{timestamp},{},{},{},\"{}\",{},{},{},{},{},",
+        chat_id.0,
+        tag.as_csv_tag(),
+        unit.format_stored(value),
+        csv_escape(input.note.as_deref().unwrap_or("")),
+        input.source.as_deref().unwrap_or(DEFAULT_SOURCE),
+        message_id_field(message_id),
+        unit.csv_name(),
+        if input.confirmed { CONFIRMED_VALUE } else { "" },
+        input
+            .trend
+            .map(GlucoseTrend::nightscout_direction)
+            .unwrap_or("")
+    )
+}
+
+/// Sends a chart, or its numbers as text when drawing fails or panics, so the data
+/// still reaches the user. PNG images go as photos so Telegram shows them in the chat;
+/// other files, such as SVG and PDF, as documents.
+async fn send_chart<R: Responder>(
+    bot: &R,
+    state: &AppState,
+    chat_id: ChatId,
+    file_name: &str,
+    render: impl FnOnce() -> anyhow::Result<Vec<u8>>,
+    caption: String,
+    numbers: impl FnOnce() -> String,
+) -> anyhow::Result<()> {
+    let rendered = std::panic::catch_unwind(std::panic::AssertUnwindSafe(render))
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("the chart renderer crashed")));
+    match rendered {
+        Ok(file) => {
+            let keyboard = menu_keyboard(state, chat_id).await;
+            if file_name.ends_with(".png") {
+                bot.send_photo(chat_id, file_name, file, caption, keyboard)
+                    .await
+            } else {
+                bot.send_document(chat_id, file_name, file, caption, keyboard)
+                    .await
+            }
+        }
+        Err(err) => {
+            tracing::warn!("failed to render {file_name} for chat {}: {err}", chat_id.0);
+            paging::send_long_message(
+                bot,
+                chat_id,
+                format!(
+                    "Could not draw the chart ({err}). Here are its numbers:\n{}",
+                    numbers()
+                ),
+                menu_keyboard(state, chat_id).await,
+            )
+            .await
+        }
+    }
+}
+
+/// Handles `/precision [<metric> <step>]`: lists the rounding steps or sets one.
+fn precision_command(state: &AppState, chat_id: ChatId, payload: &str) -> anyhow::Result<String> {
+    let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+    let glucose_unit = user_settings.glucose_unit.unwrap_or_default();
+    if payload.is_empty() {
+        let mut lines = vec!["New entries are rounded to:".to_string()];
+        for metric in settings::PrecisionMetric::ALL {
+            lines.push(format!(
+                "- {}: {} {}",
+                metric.name(),
+                user_settings.precision_step(metric),
+                metric.unit(glucose_unit)
+            ));
+        }
+        lines.push(PRECISION_USAGE.to_string());
+        return Ok(lines.join("\n"));
+    }
+    let Some((metric, step)) = settings::parse_precision(payload) else {
+        return Ok(PRECISION_USAGE.to_string());
+    };
+    let steps = metric.steps(glucose_unit);
+    if !steps.contains(&step) {
+        return Ok(format!(
+            "Steps for {} in {}: {}.",
+            metric.name(),
+            metric.unit(glucose_unit),
+            steps
+                .iter()
+                .map(f64::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if step == metric.default_step(glucose_unit) {
+        user_settings.precision.remove(&metric);
+    } else {
+        user_settings.precision.insert(metric, step);
+    }
+    settings::save_settings(state, chat_id, &user_settings)?;
+    Ok(format!(
+        "New {} entries are rounded to {step} {} ✅",
+        metric.name(),
+        metric.unit(glucose_unit)
+    ))
+}
+
+/// Settings that shape saved rows; defaults when they cannot be loaded, so an entry is
+/// still saved.
+fn settings_for_saving(data_dir: &Path, chat_id: ChatId) -> settings::UserSettings {
+    settings::load_settings(data_dir, chat_id)
+        .inspect_err(|err| tracing::warn!("failed to load settings for chat {}: {err}", chat_id.0))
+        .unwrap_or_default()
+}
+
+/// `value` of `metric` rounded to this chat's `/precision` step.
+fn round_for_chat(
+    data_dir: &Path,
+    chat_id: ChatId,
+    metric: settings::PrecisionMetric,
+    value: f64,
+) -> f64 {
+    settings_for_saving(data_dir, chat_id).round_value(metric, value)
+}
+
+fn weight_csv_line(
+    timestamp: &str,
+    chat_id: ChatId,
+    value: f64,
+    message_id: Option<MessageId>,
+) -> String {
+    format!(
+        "{timestamp},{},{},{}",
+        chat_id.0,
+        value,
+        message_id_field(message_id)
+    )
+}
+
+fn message_id_field(message_id: Option<MessageId>) -> String {
+    message_id.map(|id| id.0.to_string()).unwrap_or_default()
+}
+
+/// Saves a parsed ketone reading and returns the confirmation text.
+fn save_ketones(
+    state: &AppState,
+    chat_id: ChatId,
+    message_id: MessageId,
+    (value, timestamp, note): (f64, Option<String>, Option<String>),
+) -> anyhow::Result<String> {
+    let value = round_for_chat(
+        &state.data_dir,
+        chat_id,
+        settings::PrecisionMetric::Ketones,
+        value,
+    );
+    let file = user_data_dir(&state.data_dir, chat_id).join(KETONES_FILE);
+    append_line_if_needed(&file, KETONES_HEADER, state.input.sync_writes)?;
+    let ts = timestamp.unwrap_or_else(|| state.input_for(chat_id).now_timestamp());
+    append_csv_line(
+        &file,
+        &ketones_csv_line(&ts, chat_id, value, note.as_deref(), Some(message_id)),
+        state.input.sync_writes,
+    )?;
+    log_saved_entry(
+        &state.input,
+        chat_id,
+        "ketones",
+        Some(value),
+        None,
+        note.as_deref(),
+    );
+    Ok(ketones_confirmation(
+        value,
+        &save_confirmation(state, chat_id, settings::ConfirmMetric::Ketones),
+    ))
+}
+
+fn ketones_confirmation(value: f64, confirm: &SaveConfirmation) -> String {
+    let mut reply = confirm.text(|mark| format!("Ketones saved {mark}"));
+    if value >= KETONES_WARNING_MMOL_L {
+        reply.push_str(&format!(
+            "\n⚠️ elevated ketones ({value} mmol/L, warning at {KETONES_WARNING_MMOL_L:.1})"
+        ));
+    }
+    reply
+}
+
+fn ketones_csv_line(
+    timestamp: &str,
+    chat_id: ChatId,
+    value: f64,
+    note: Option<&str>,
+    message_id: Option<MessageId>,
+) -> String {
+    format!(
+        "{timestamp},{},{},\"{}\",{}",
+        chat_id.0,
+        value,
+        csv_escape(note.unwrap_or("")),
+        message_id_field(message_id)
+    )
+}
+
+fn append_meal_csv(
+    data_dir: &Path,
+    chat_id: ChatId,
+    options: &InputOptions,
     timestamp: Option<&str>,
     note: Option<&str>,
 ) -> anyhow::Result<()> {
-    let file = user_data_dir(data_dir, chat_id).join("glucose.csv");
-    append_line_if_needed(&file, "timestamp,chat_id,tag,value_mmol_l,note")?;
-    let ts = match timestamp {
-        Some(raw) => chrono::DateTime::parse_from_rfc3339(raw)
-            .map(|dt| dt.with_timezone(&Utc).to_rfc3339())
-            .unwrap_or_else(|_| Utc::now().to_rfc3339()),
-        None => Utc::now().to_rfc3339(),
-    };
+    let file = user_data_dir(data_dir, chat_id).join(MEALS_FILE);
+    append_line_if_needed(&file, MEALS_HEADER, options.sync_writes)?;
+    let ts = timestamp
+        .map(str::to_string)
+        .unwrap_or_else(|| options.now_timestamp());
     let escaped_note = csv_escape(note.unwrap_or(""));
     append_csv_line(
         &file,
-        &format!(
-            "{ts},{},{},{},\"{escaped_note}\"",
-            chat_id.0,
-            tag.as_csv_tag(),
-            value
-        ),
-    )
+        &format!("{ts},{},\"{escaped_note}\"", chat_id.0),
+        options.sync_writes,
+    )?;
+    log_saved_entry(options, chat_id, "meal", None, None, note);
+    Ok(())
+}
+
+fn append_glucose_input(
+    data_dir: &Path,
+    chat_id: ChatId,
+    options: &InputOptions,
+    tag: GlucoseTag,
+    input: &GlucoseInput,
+    message_id: Option<MessageId>,
+) -> anyhow::Result<()> {
+    let file = user_data_dir(data_dir, chat_id).join(GLUCOSE_FILE);
+    append_line_if_needed(&file, GLUCOSE_HEADER, options.sync_writes)?;
+    let timestamp = input
+        .timestamp
+        .clone()
+        .unwrap_or_else(|| options.now_timestamp());
+    let user_settings = settings_for_saving(data_dir, chat_id);
+    let unit = user_settings.glucose_unit.unwrap_or_default();
+    for &value in &input.values {
+        let value = user_settings.round_glucose(value, unit);
+        append_csv_line(
+            &file,
+            &glucose_csv_line(&timestamp, chat_id, tag, value, input, message_id, unit),
+            options.sync_writes,
+        )?;
+        log_saved_entry(
+            options,
+            chat_id,
+            "glucose",
+            Some(value),
+            Some(tag.as_csv_tag()),
+            input.note.as_deref(),
+        );
+    }
+    Ok(())
+}
+
+/// Whether `input` repeats the last row of `glucose.csv`: same tag and last value,
+/// timestamp within the configured window.
+fn is_duplicate_glucose(
+    state: &AppState,
+    chat_id: ChatId,
+    tag: GlucoseTag,
+    input: &GlucoseInput,
+) -> anyhow::Result<bool> {
+    let window = state.duplicates.window_seconds;
+    let Some(&value) = input.values.last() else {
+        return Ok(false);
+    };
+    if window == 0 {
+        return Ok(false);
+    }
+    let path = user_data_dir(&state.data_dir, chat_id).join(GLUCOSE_FILE);
+    let Some(last) = records::last_data_line(&path)? else {
+        return Ok(false);
+    };
+    let fields = records::parse_csv_line(&last);
+    let (Some(last_ts), Some(last_tag), Some(last_value), Ok(last_unit)) = (
+        fields.first().and_then(|raw| records::parse_timestamp(raw)),
+        fields.get(2),
+        fields.get(3).and_then(|raw| parse_decimal(raw)),
+        records::glucose_unit_field(&fields),
+    ) else {
+        return Ok(false);
+    };
+    let last_value = last_unit.to_mmol_l(last_value);
+    let timestamp = input
+        .timestamp
+        .as_deref()
+        .and_then(records::parse_timestamp)
+        .unwrap_or_else(|| state.now());
+    let elapsed = (timestamp - last_ts).num_seconds().unsigned_abs();
+    Ok(last_tag == tag.as_csv_tag() && (last_value - value).abs() < 0.01 && elapsed <= window)
 }
 
 fn csv_escape(value: &str) -> String {
     value.replace('"', "\"\"")
 }
 
-fn append_line_if_needed(path: &Path, header: &str) -> anyhow::Result<()> {
+/// Creates `path` with `header` if it does not exist yet. Existing files keep theirs:
+/// older headers are upgraded once at startup by [`storage::upgrade_headers`].
+fn append_line_if_needed(path: &Path, header: &str, sync: bool) -> anyhow::Result<()> {
     if let Some(parent) = path.parent() {
         fs_err::create_dir_all(parent)?;
     }
     if !path.exists() {
-        fs_err::write(path, format!("{header}\n"))?;
+        return append_csv_line(path, header, sync);
+    }
+    Ok(())
+}
+
+/// Columns renamed since older versions, as (old name, new name).
+const RENAMED_COLUMNS: [(&str, &str); 1] = [
+    // Glucose rows hold mg/dL too since `/units`.
+    ("value_mmol_l", "value"),
+];
+
+/// Rewrites a file created with an older header, see [`upgraded_csv`].
+fn upgrade_csv_header(path: &Path, header: &str, sync: bool) -> anyhow::Result<()> {
+    let content = fs_err::read_to_string(path)?;
+    let Some(upgraded) = upgraded_csv(&content, header) else {
+        return Ok(());
+    };
+    write_file_atomically(path, &upgraded, sync)?;
+    tracing::info!("upgraded header of {}", path.display());
+    Ok(())
+}
+
+/// `content` moved to `header` when its own header is an older version of it: columns
+/// renamed since get their new name, and rows are padded with empty fields for columns
+/// appended since, which readers treat as defaults. `None` when there is nothing to do.
+fn upgraded_csv(content: &str, header: &str) -> Option<String> {
+    let current = content.lines().next().unwrap_or("");
+    let current_columns = current.split(',').collect::<Vec<_>>();
+    let columns = header.split(',').collect::<Vec<_>>();
+    let older = current != header
+        && current_columns.len() <= columns.len()
+        && current_columns
+            .iter()
+            .zip(&columns)
+            .all(|(old, new)| old == new || RENAMED_COLUMNS.contains(&(*old, *new)));
+    if !older {
+        return None;
+    }
+    let padding = ",".repeat(columns.len() - current_columns.len());
+    let mut upgraded = format!("{header}\n");
+    for line in content.lines().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        upgraded.push_str(line);
+        upgraded.push_str(&padding);
+        upgraded.push('\n');
+    }
+    Some(upgraded)
+}
+
+fn count_data_rows(path: &Path) -> anyhow::Result<usize> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let content = fs_err::read_to_string(path)?;
+    Ok(data_row_count(&content))
+}
+
+fn data_row_count(content: &str) -> usize {
+    content
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .count()
+}
+
+/// Keeps only the header line of a CSV file and returns the number of removed rows.
+fn truncate_to_header(path: &Path, sync: bool) -> anyhow::Result<usize> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let content = fs_err::read_to_string(path)?;
+    let removed = data_row_count(&content);
+    let header = content.lines().next().unwrap_or("");
+    write_file_atomically(path, &format!("{header}\n"), sync)?;
+    Ok(removed)
+}
+
+/// Logs one saved row for operators auditing activity. The privacy boundary: the
+/// chat id, metric, number and fixed tag are always logged; free text (notes, food
+/// and medication names) is replaced by `has_note` unless `log_entry_notes` is set.
+fn log_saved_entry(
+    options: &InputOptions,
+    chat_id: ChatId,
+    metric: &str,
+    value: Option<f64>,
+    tag: Option<&str>,
+    note: Option<&str>,
+) {
+    let has_note = note.is_some_and(|note| !note.is_empty());
+    let note = note.filter(|_| options.log_entry_notes);
+    tracing::info!(
+        chat_id = chat_id.0,
+        metric,
+        value,
+        tag,
+        has_note,
+        note,
+        "entry saved"
+    );
+}
+
+/// Logs a rewritten row of `file` like a new entry, so edits and `/tag` show up in the
+/// audit too. `note` is what the user typed for the change.
+fn log_saved_row(
+    options: &InputOptions,
+    chat_id: ChatId,
+    file: &str,
+    line: &str,
+    note: Option<&str>,
+) {
+    let fields = records::parse_csv_line(line);
+    let (metric, value_column, tag) = match file {
+        GLUCOSE_FILE => ("glucose", 3, fields.get(2).map(String::as_str)),
+        WEIGHT_FILE => ("weight", 2, None),
+        KETONES_FILE => ("ketones", 2, None),
+        _ => return,
+    };
+    let value = fields
+        .get(value_column)
+        .and_then(|value| value.parse().ok());
+    log_saved_entry(options, chat_id, metric, value, tag, note);
+}
+
+fn sync_if_enabled(file: &fs_err::File, sync: bool) -> anyhow::Result<()> {
+    if sync {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Makes a rename or a newly created file in `path`'s directory durable: the directory
+/// entry lives in the parent and is not covered by syncing the file. Skipped on Windows,
+/// which cannot open a directory as a file.
+fn sync_parent_dir(path: &Path, sync: bool) -> anyhow::Result<()> {
+    if !sync || cfg!(not(unix)) {
+        return Ok(());
     }
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    fs_err::File::open(parent)?.sync_all()?;
     Ok(())
 }
 
-fn append_csv_line(path: &Path, line: &str) -> anyhow::Result<()> {
+/// Writes to a sibling temp file and renames it over `path`, so readers never see a partial file.
+fn write_file_atomically(path: &Path, content: &str, sync: bool) -> anyhow::Result<()> {
+    use std::io::Write;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = fs_err::File::create(&tmp)?;
+    file.write_all(content.as_bytes())?;
+    sync_if_enabled(&file, sync)?;
+    drop(file);
+    fs_err::rename(&tmp, path)?;
+    sync_parent_dir(path, sync)
+}
+
+fn append_csv_line(path: &Path, line: &str, sync: bool) -> anyhow::Result<()> {
     use std::io::Write;
+    let created = !path.exists();
     let mut file = fs_err::OpenOptions::new()
         .create(true)
         .append(true)
         .open(path)?;
     writeln!(file, "{line}")?;
+    file.flush()?;
+    sync_if_enabled(&file, sync)?;
+    if created {
+        sync_parent_dir(path, sync)?;
+    }
     Ok(())
 }
 
@@ -785,3 +5058,6 @@ fn init_tracing() {
         )
         .init();
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,366 @@
+use super::records::{GlucoseRecord, InsulinRecord, MealRecord, WeightRecord, read_records};
+use super::*;
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_API_DAYS: i64 = 7;
+const API_MAX_DAYS: i64 = 365;
+const API_TOKEN_MIN_CHARS: usize = 16;
+
+/// State of the read API: the bot state and the bearer token every request must carry.
+#[derive(Clone)]
+struct ApiState {
+    app: Arc<AppState>,
+    token: Arc<str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiQuery {
+    chat: i64,
+    days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiRows<T> {
+    chat: i64,
+    days: i64,
+    rows: Vec<T>,
+}
+
+/// Data returned for a `/share` link: one chat's rows of every type.
+#[derive(Debug, Serialize)]
+struct SharedData {
+    days: i64,
+    glucose: Vec<GlucoseRow>,
+    weight: Vec<WeightRow>,
+    insulin: Vec<InsulinRow>,
+    meals: Vec<MealRow>,
+}
+
+#[derive(Debug, Serialize)]
+struct GlucoseRow {
+    timestamp: String,
+    tag: String,
+    value_mmol_l: f64,
+    note: String,
+    source: String,
+}
+
+#[derive(Debug, Serialize)]
+struct WeightRow {
+    timestamp: String,
+    value_kg: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct InsulinRow {
+    timestamp: String,
+    units: f64,
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct MealRow {
+    timestamp: String,
+    note: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiError {
+    error: String,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        Json(ApiError {
+            error: message.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// Checks the bearer token and the query, and returns the allowed chat and the start of
+/// the requested window. Every endpoint calls this before touching any file.
+fn authorize(
+    api: &ApiState,
+    headers: &HeaderMap,
+    query: &ApiQuery,
+) -> Result<(ChatId, i64, DateTime<Utc>), Box<Response>> {
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !given.is_some_and(|given| same_token(&api.token, given.trim())) {
+        return Err(Box::new(error(
+            StatusCode::UNAUTHORIZED,
+            "missing or invalid bearer token",
+        )));
+    }
+    let chat_id = ChatId(query.chat);
+    if !api.app.is_allowed(chat_id) {
+        return Err(Box::new(error(StatusCode::NOT_FOUND, "unknown chat")));
+    }
+    let days = query.days.unwrap_or(DEFAULT_API_DAYS);
+    if !(1..=API_MAX_DAYS).contains(&days) {
+        return Err(Box::new(error(
+            StatusCode::BAD_REQUEST,
+            &format!("days must be between 1 and {API_MAX_DAYS}"),
+        )));
+    }
+    Ok((chat_id, days, api.app.now() - chrono::Duration::days(days)))
+}
+
+fn timestamp(options: &InputOptions, timestamp: DateTime<Utc>) -> String {
+    options.format_timestamp(timestamp)
+}
+
+/// Reads the chat's rows of one record type from `since` on and maps them for JSON.
+fn read_since<T: records::CsvRecord, R>(
+    app: &AppState,
+    chat_id: ChatId,
+    since: DateTime<Utc>,
+    when: impl Fn(&T) -> DateTime<Utc>,
+    row: impl Fn(&InputOptions, &T) -> R,
+) -> anyhow::Result<Vec<R>> {
+    let options = app.input_for(chat_id);
+    Ok(read_records::<T>(&app.data_dir, chat_id)?
+        .rows
+        .iter()
+        .filter(|record| when(record) >= since)
+        .map(|record| row(&options, record))
+        .collect())
+}
+
+fn read_failed(chat_id: ChatId, err: &anyhow::Error) -> Response {
+    tracing::error!("api read for chat {} failed: {err}", chat_id.0);
+    error(StatusCode::INTERNAL_SERVER_ERROR, "failed to read data")
+}
+
+/// Serves one record type for the chat and window of an authorized request.
+async fn rows<T: records::CsvRecord + 'static, R: Serialize + Send + 'static>(
+    api: &ApiState,
+    headers: &HeaderMap,
+    query: &ApiQuery,
+    when: impl Fn(&T) -> DateTime<Utc> + Send + 'static,
+    row: impl Fn(&InputOptions, &T) -> R + Send + 'static,
+) -> Response {
+    let (chat_id, days, since) = match authorize(api, headers, query) {
+        Ok(allowed) => allowed,
+        Err(response) => return *response,
+    };
+    let read = storage::blocking(&api.app, move |app| {
+        read_since(app, chat_id, since, when, row)
+    })
+    .await;
+    match read {
+        Ok(rows) => Json(ApiRows {
+            chat: chat_id.0,
+            days,
+            rows,
+        })
+        .into_response(),
+        Err(err) => read_failed(chat_id, &err),
+    }
+}
+
+fn glucose_row(options: &InputOptions, record: &GlucoseRecord) -> GlucoseRow {
+    GlucoseRow {
+        timestamp: timestamp(options, record.timestamp),
+        tag: record.tag.clone(),
+        value_mmol_l: record.value,
+        note: record.note.clone(),
+        source: record.source.clone(),
+    }
+}
+
+fn weight_row(options: &InputOptions, record: &WeightRecord) -> WeightRow {
+    WeightRow {
+        timestamp: timestamp(options, record.timestamp),
+        value_kg: record.value,
+    }
+}
+
+fn insulin_row(options: &InputOptions, record: &InsulinRecord) -> InsulinRow {
+    InsulinRow {
+        timestamp: timestamp(options, record.timestamp),
+        units: record.units,
+        kind: record.kind.name(),
+    }
+}
+
+fn meal_row(options: &InputOptions, record: &MealRecord) -> MealRow {
+    MealRow {
+        timestamp: timestamp(options, record.timestamp),
+        note: record.note.clone(),
+    }
+}
+
+async fn glucose(
+    State(api): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<ApiQuery>,
+) -> Response {
+    rows(
+        &api,
+        &headers,
+        &query,
+        |record: &GlucoseRecord| record.timestamp,
+        glucose_row,
+    )
+    .await
+}
+
+async fn weight(
+    State(api): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<ApiQuery>,
+) -> Response {
+    rows(
+        &api,
+        &headers,
+        &query,
+        |record: &WeightRecord| record.timestamp,
+        weight_row,
+    )
+    .await
+}
+
+async fn insulin(
+    State(api): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<ApiQuery>,
+) -> Response {
+    rows(
+        &api,
+        &headers,
+        &query,
+        |record: &InsulinRecord| record.timestamp,
+        insulin_row,
+    )
+    .await
+}
+
+async fn meals(
+    State(api): State<ApiState>,
+    headers: HeaderMap,
+    Query(query): Query<ApiQuery>,
+) -> Response {
+    rows(
+        &api,
+        &headers,
+        &query,
+        |record: &MealRecord| record.timestamp,
+        meal_row,
+    )
+    .await
+}
+
+/// Data behind a `/share` link. The token in the path replaces the bearer token and
+/// reaches only its own chat; unknown, expired and revoked links all get `404`.
+async fn shared(State(api): State<ApiState>, Path(token): Path<String>) -> Response {
+    let found = storage::blocking(&api.app, move |app| share::find_share(app, &token)).await;
+    let (chat_id, days) = match found {
+        Ok(Some(found)) => found,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "unknown or expired link"),
+        Err(err) => {
+            tracing::error!("share link lookup failed: {err}");
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "failed to read data");
+        }
+    };
+    let data = storage::blocking(&api.app, move |app| {
+        let since = app.now() - chrono::Duration::days(days);
+        Ok(SharedData {
+            days,
+            glucose: read_since(
+                app,
+                chat_id,
+                since,
+                |record: &GlucoseRecord| record.timestamp,
+                glucose_row,
+            )?,
+            weight: read_since(
+                app,
+                chat_id,
+                since,
+                |record: &WeightRecord| record.timestamp,
+                weight_row,
+            )?,
+            insulin: read_since(
+                app,
+                chat_id,
+                since,
+                |record: &InsulinRecord| record.timestamp,
+                insulin_row,
+            )?,
+            meals: read_since(
+                app,
+                chat_id,
+                since,
+                |record: &MealRecord| record.timestamp,
+                meal_row,
+            )?,
+        })
+    })
+    .await;
+    match data {
+        Ok(data) => Json(data).into_response(),
+        Err(err) => read_failed(chat_id, &err),
+    }
+}
+
+fn router(state: Arc<AppState>, token: &str) -> axum::Router {
+    axum::Router::new()
+        .route("/api/glucose", get(glucose))
+        .route("/api/weight", get(weight))
+        .route("/api/insulin", get(insulin))
+        .route("/api/meals", get(meals))
+        .route(&format!("{}{{token}}", share::SHARE_PATH), get(shared))
+        .with_state(ApiState {
+            app: state,
+            token: Arc::from(token),
+        })
+}
+
+/// Starts the read API when `api_listen` is set. The token is required and the address
+/// is bound here, so a bad setting stops startup instead of failing later.
+pub(super) async fn start(
+    listen: Option<&str>,
+    token: Option<&str>,
+    state: &Arc<AppState>,
+) -> anyhow::Result<()> {
+    let Some(addr) = listen.map(str::trim).filter(|addr| !addr.is_empty()) else {
+        return Ok(());
+    };
+    let token = token.map(str::trim).unwrap_or("");
+    if token.chars().count() < API_TOKEN_MIN_CHARS {
+        anyhow::bail!(
+            "api_token of at least {API_TOKEN_MIN_CHARS} characters is required with api_listen"
+        );
+    }
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow::anyhow!("cannot listen on api_listen '{addr}': {e}"))?;
+    tracing::info!("read API listening on {addr}");
+    spawn_api_server(listener, token, Arc::clone(state));
+    Ok(())
+}
+
+/// Serves the read API on `listener` until the process exits.
+pub(super) fn spawn_api_server(
+    listener: tokio::net::TcpListener,
+    token: &str,
+    state: Arc<AppState>,
+) {
+    let app = router(state, token);
+    tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, app).await {
+            tracing::error!("api server stopped: {err}");
+        }
+    });
+}
@@ -0,0 +1,127 @@
+use super::*;
+
+pub(super) const DEFAULT_BACKUP_KEEP: usize = 14;
+pub(super) const BACKUP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const SNAPSHOT_DATE_FORMAT: &str = "%Y-%m-%d";
+/// Suffix of a snapshot that is still being copied; renamed away once complete.
+const PARTIAL_SUFFIX: &str = ".partial";
+/// Suffix of a snapshot being removed by rotation.
+const REMOVING_SUFFIX: &str = ".removing";
+
+/// Daily copies of every user's data directory, kept as
+/// `<backup_dir>/<user_id>/<YYYY-MM-DD>/`.
+#[derive(Debug, Clone)]
+pub(super) struct BackupOptions {
+    pub(super) dir: PathBuf,
+    /// Snapshots kept per user; older ones are removed after each backup.
+    pub(super) keep: usize,
+}
+
+/// Backs up every user directory under `data_dir` as the snapshot for `date`, then
+/// rotates that user's snapshots. A failing user is logged and does not stop the others.
+pub(super) fn backup_all(
+    data_dir: &Path,
+    options: &BackupOptions,
+    date: NaiveDate,
+) -> anyhow::Result<()> {
+    if !data_dir.exists() {
+        return Ok(());
+    }
+    for entry in fs_err::read_dir(data_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !entry.file_type()?.is_dir() || name.parse::<i64>().is_err() {
+            continue;
+        }
+        let user_backups = options.dir.join(&name);
+        let result = snapshot_user(&entry.path(), &user_backups, date)
+            .and_then(|()| rotate_snapshots(&user_backups, options.keep));
+        match result {
+            Ok(removed) => {
+                tracing::info!("backed up user {name} ({date})");
+                for path in removed {
+                    tracing::info!("removed old backup {}", path.display());
+                }
+            }
+            Err(err) => tracing::error!("backup of user {name} failed: {err}"),
+        }
+    }
+    Ok(())
+}
+
+/// Copies the files of `user_dir` into `<user_backups>/<date>`. The copy is made under a
+/// `.partial` name and renamed when complete, so a snapshot is either whole or absent.
+fn snapshot_user(user_dir: &Path, user_backups: &Path, date: NaiveDate) -> anyhow::Result<()> {
+    let name = date.format(SNAPSHOT_DATE_FORMAT).to_string();
+    let target = user_backups.join(&name);
+    let partial = user_backups.join(format!("{name}{PARTIAL_SUFFIX}"));
+    if partial.exists() {
+        fs_err::remove_dir_all(&partial)?;
+    }
+    fs_err::create_dir_all(&partial)?;
+    for entry in fs_err::read_dir(user_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if !entry.file_type()?.is_file() || file_name.to_string_lossy().ends_with(".tmp") {
+            continue;
+        }
+        fs_err::copy(entry.path(), partial.join(&file_name))?;
+    }
+    if target.exists() {
+        remove_snapshot(&target)?;
+    }
+    fs_err::rename(&partial, &target)?;
+    Ok(())
+}
+
+/// Removes all but the newest `keep` snapshots and returns the removed paths.
+fn rotate_snapshots(user_backups: &Path, keep: usize) -> anyhow::Result<Vec<PathBuf>> {
+    let mut snapshots = Vec::new();
+    for entry in fs_err::read_dir(user_backups)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Ok(date) = NaiveDate::parse_from_str(&name, SNAPSHOT_DATE_FORMAT) {
+            snapshots.push((date, entry.path()));
+        }
+    }
+    snapshots.sort_by_key(|(date, _)| std::cmp::Reverse(*date));
+    let mut removed = Vec::new();
+    for (_, path) in snapshots.into_iter().skip(keep) {
+        remove_snapshot(&path)?;
+        removed.push(path);
+    }
+    Ok(removed)
+}
+
+/// Renames the snapshot out of the way first, so it never appears half deleted.
+fn remove_snapshot(path: &Path) -> anyhow::Result<()> {
+    let mut removing = path.as_os_str().to_owned();
+    removing.push(REMOVING_SUFFIX);
+    let removing = PathBuf::from(removing);
+    fs_err::rename(path, &removing)?;
+    fs_err::remove_dir_all(&removing)?;
+    Ok(())
+}
+
+/// Runs [`backup_all`] at startup and then once a day.
+pub(super) fn spawn_backup_task(state: Arc<AppState>) {
+    let Some(options) = state.backup.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(BACKUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let data_dir = state.data_dir.clone();
+            let options = options.clone();
+            let today = state.now().with_timezone(&state.input.tz).date_naive();
+            let result =
+                tokio::task::spawn_blocking(move || backup_all(&data_dir, &options, today)).await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => tracing::error!("backup failed: {err}"),
+                Err(err) => tracing::error!("backup task panicked: {err}"),
+            }
+        }
+    });
+}
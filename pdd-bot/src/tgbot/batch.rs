@@ -0,0 +1,127 @@
+use super::*;
+
+/// Source stored for imported readings that carry no `!source` marker.
+const IMPORT_SOURCE: &str = "import";
+const PREVIEW_LINES: usize = 5;
+const GLUCOSE_UNIT: &str = "mmol/l";
+
+/// Readings parsed from a pasted multi-line message.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct GlucoseBatch {
+    pub(super) inputs: Vec<GlucoseInput>,
+    /// One message per line that could not be parsed.
+    pub(super) failures: Vec<String>,
+}
+
+/// Parses a pasted block of readings, one per line, e.g. `2024/02/01 08:00 5.8`.
+/// Returns `None` unless the text has several lines and at least one of them is a reading.
+pub(super) fn parse_glucose_batch(text: &str, options: &InputOptions) -> Option<GlucoseBatch> {
+    let lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect::<Vec<_>>();
+    if lines.len() < 2 {
+        return None;
+    }
+    let mut batch = GlucoseBatch {
+        inputs: Vec::new(),
+        failures: Vec::new(),
+    };
+    for (line_no, line) in lines {
+        match parse_batch_line(line, options) {
+            Ok(input) => batch.inputs.push(input),
+            Err(err) => batch.failures.push(format!("line {line_no}: {err}")),
+        }
+    }
+    (!batch.inputs.is_empty()).then_some(batch)
+}
+
+/// Accepts the meter layout `date time value [mmol/L]` as well as the regular
+/// `value date time [@note]` one. Every line needs its own date/time.
+fn parse_batch_line(line: &str, options: &InputOptions) -> anyhow::Result<GlucoseInput> {
+    let mut tokens = line.split_whitespace().collect::<Vec<_>>();
+    let starts_with_value = tokens
+        .first()
+        .is_some_and(|first| parse_decimal(first.trim_end_matches([',', ';'])).is_some());
+    let mut input = if starts_with_value {
+        parse_glucose_payload(line, options)?
+    } else {
+        if tokens
+            .last()
+            .is_some_and(|last| last.eq_ignore_ascii_case(GLUCOSE_UNIT))
+        {
+            tokens.pop();
+        }
+        let value = tokens
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("Missing glucose value"))?;
+        parse_glucose_payload(&format!("{value} {}", tokens.join(" ")), options)?
+    };
+    if input.timestamp.is_none() {
+        anyhow::bail!("Missing date/time");
+    }
+    input
+        .source
+        .get_or_insert_with(|| IMPORT_SOURCE.to_string());
+    Ok(input)
+}
+
+/// Confirmation prompt with the reading count, a short preview and per-line failures.
+pub(super) fn batch_preview(batch: &GlucoseBatch, tz: Tz) -> String {
+    let count = batch
+        .inputs
+        .iter()
+        .map(|input| input.values.len())
+        .sum::<usize>();
+    let mut lines = vec![format!("Found {count} readings to import:")];
+    for input in batch.inputs.iter().take(PREVIEW_LINES) {
+        let when = input
+            .timestamp
+            .as_deref()
+            .and_then(records::parse_timestamp)
+            .map(|ts| ts.with_timezone(&tz).format("%m/%d %H:%M").to_string())
+            .unwrap_or_default();
+        let values = input
+            .values
+            .iter()
+            .map(|value| format!("{value:.1}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        lines.push(format!("- {when} {values}"));
+    }
+    if batch.inputs.len() > PREVIEW_LINES {
+        lines.push(format!(
+            "- ... and {} more lines",
+            batch.inputs.len() - PREVIEW_LINES
+        ));
+    }
+    if !batch.failures.is_empty() {
+        lines.push(format!("Skipped {} lines:", batch.failures.len()));
+        lines.extend(batch.failures.iter().map(|failure| format!("- {failure}")));
+    }
+    lines.push(
+        "Reply 'before' or 'after' to import them with that meal tag, anything else cancels."
+            .to_string(),
+    );
+    lines.join("\n")
+}
+
+/// Saves confirmed readings. Returns the number of stored values.
+pub(super) fn import_batch(
+    data_dir: &Path,
+    chat_id: ChatId,
+    options: &InputOptions,
+    tag: GlucoseTag,
+    inputs: &[GlucoseInput],
+) -> anyhow::Result<usize> {
+    let mut saved = 0;
+    for input in inputs {
+        // Rows are not tied to the pasted message: editing it must not rewrite the whole batch.
+        append_glucose_input(data_dir, chat_id, options, tag, input, None)?;
+        saved += input.values.len();
+    }
+    tracing::info!("chat {} imported {saved} glucose readings", chat_id.0);
+    Ok(saved)
+}
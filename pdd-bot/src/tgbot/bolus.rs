@@ -0,0 +1,175 @@
+use super::settings::{BolusSettings, UserSettings, round_to_step};
+use super::*;
+
+pub(super) const BOLUS_FILE: &str = "bolus_suggestions.csv";
+const BOLUS_HEADER: &str = "timestamp,chat_id,carbs_g,glucose_mmol_l,carb_ratio_g_per_u,correction_mmol_l_per_u,target_mmol_l,suggested_u";
+const DEFAULT_BOLUS_TARGET_MMOL_L: f64 = 6.0;
+const CARB_RATIO_MAX_G_PER_U: f64 = 100.0;
+const CORRECTION_FACTOR_MAX_MMOL_L_PER_U: f64 = 20.0;
+const CARBS_MAX_G: f64 = 500.0;
+const DISCLAIMER: &str =
+    "⚠️ Informational only, not medical advice. Check the dose yourself before injecting.";
+
+/// Applies `/setbolus` arguments. Returns `Ok(false)` when there is nothing to change.
+pub(super) fn apply_setbolus(
+    user_settings: &mut UserSettings,
+    payload: &str,
+) -> anyhow::Result<bool> {
+    let usage = "Usage: /setbolus <carb ratio g/U> <correction factor mmol/L per U> [target mmol/L], or /setbolus off";
+    let parts = payload.split_whitespace().collect::<Vec<_>>();
+    let (ratio, factor, target) = match parts.as_slice() {
+        [] => return Ok(false),
+        [off] if off.eq_ignore_ascii_case("off") => {
+            user_settings.bolus = None;
+            return Ok(true);
+        }
+        [ratio, factor] => (*ratio, *factor, None),
+        [ratio, factor, target] => (*ratio, *factor, Some(*target)),
+        _ => anyhow::bail!("{usage}"),
+    };
+    let (Some(carb_ratio), Some(correction_factor)) = (parse_decimal(ratio), parse_decimal(factor))
+    else {
+        anyhow::bail!("{usage}");
+    };
+    if !(carb_ratio > 0.0 && carb_ratio <= CARB_RATIO_MAX_G_PER_U) {
+        anyhow::bail!("Carb ratio must be above 0 and at most {CARB_RATIO_MAX_G_PER_U:.0} g/U");
+    }
+    if !(correction_factor > 0.0 && correction_factor <= CORRECTION_FACTOR_MAX_MMOL_L_PER_U) {
+        anyhow::bail!(
+            "Correction factor must be above 0 and at most {CORRECTION_FACTOR_MAX_MMOL_L_PER_U:.0} mmol/L per U"
+        );
+    }
+    let target = match target {
+        Some(raw) => parse_glucose_value(raw)?,
+        None => DEFAULT_BOLUS_TARGET_MMOL_L,
+    };
+    user_settings.bolus = Some(BolusSettings {
+        carb_ratio,
+        correction_factor,
+        target,
+    });
+    Ok(true)
+}
+
+pub(super) fn describe_bolus_settings(user_settings: &UserSettings) -> String {
+    match user_settings.bolus {
+        Some(bolus) => format!(
+            "Bolus settings:\n- carb ratio: {:.1} g/U\n- correction factor: {:.1} mmol/L per U\n- target: {:.1} mmol/L",
+            bolus.carb_ratio, bolus.correction_factor, bolus.target
+        ),
+        None => "Bolus settings are not set. Use /setbolus <carb ratio g/U> <correction factor mmol/L per U> [target mmol/L]".to_string(),
+    }
+}
+
+/// Dose split into the carb part and the correction part (negative below target), each
+/// rounded to 0.1 U as shown in the reply.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct BolusSuggestion {
+    pub(super) carb_units: f64,
+    pub(super) correction_units: f64,
+}
+
+impl BolusSuggestion {
+    pub(super) fn compute(bolus: &BolusSettings, carbs: f64, glucose: f64) -> Self {
+        Self {
+            carb_units: round_to_step(carbs / bolus.carb_ratio, 0.1),
+            correction_units: round_to_step(
+                (glucose - bolus.target) / bolus.correction_factor,
+                0.1,
+            ),
+        }
+    }
+
+    /// Sum of the rounded parts, so it matches the reply; a correction below target never
+    /// makes it negative.
+    pub(super) fn total(&self) -> f64 {
+        round_to_step((self.carb_units + self.correction_units).max(0.0), 0.1)
+    }
+}
+
+/// Handles `/bolus <carbs g> <glucose mmol/L>` and logs the suggestion to its own file.
+pub(super) fn suggest_bolus(
+    state: &AppState,
+    chat_id: ChatId,
+    payload: &str,
+) -> anyhow::Result<String> {
+    let user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+    let Some(bolus) = user_settings.bolus else {
+        return Ok(format!(
+            "{DISCLAIMER}\nSet your ratios first: /setbolus <carb ratio g/U> <correction factor mmol/L per U> [target mmol/L]"
+        ));
+    };
+    let parts = payload.split_whitespace().collect::<Vec<_>>();
+    let [carbs_raw, glucose_raw] = parts.as_slice() else {
+        return Ok("Usage: /bolus <carbs g> <glucose mmol/L>, e.g. /bolus 60 8.5".to_string());
+    };
+    let carbs = match parse_decimal(carbs_raw) {
+        Some(carbs) if (0.0..=CARBS_MAX_G).contains(&carbs) => {
+            user_settings.round_value(settings::PrecisionMetric::Carbs, carbs)
+        }
+        _ => {
+            return Ok(format!(
+                "Carbs must be a number from 0 to {CARBS_MAX_G:.0} g"
+            ));
+        }
+    };
+    let glucose = match parse_glucose_value(glucose_raw) {
+        Ok(glucose) => glucose,
+        Err(err) => return Ok(err.to_string()),
+    };
+
+    let suggestion = BolusSuggestion::compute(&bolus, carbs, glucose);
+    append_bolus_csv(
+        &state.data_dir,
+        chat_id,
+        &state.input_for(chat_id),
+        &bolus,
+        carbs,
+        glucose,
+        &suggestion,
+    )?;
+    Ok(format!(
+        "{DISCLAIMER}\nSuggested bolus: {:.1} U\n- carbs: {carbs:.0} g / {:.1} g/U = {:.1} U\n- correction: ({glucose:.1} - {:.1}) / {:.1} = {:+.1} U\nThis is not logged as a dose.",
+        suggestion.total(),
+        bolus.carb_ratio,
+        suggestion.carb_units,
+        bolus.target,
+        bolus.correction_factor,
+        suggestion.correction_units,
+    ))
+}
+
+fn append_bolus_csv(
+    data_dir: &Path,
+    chat_id: ChatId,
+    options: &InputOptions,
+    bolus: &BolusSettings,
+    carbs: f64,
+    glucose: f64,
+    suggestion: &BolusSuggestion,
+) -> anyhow::Result<()> {
+    let file = user_data_dir(data_dir, chat_id).join(BOLUS_FILE);
+    append_line_if_needed(&file, BOLUS_HEADER, options.sync_writes)?;
+    append_csv_line(
+        &file,
+        &format!(
+            "{},{},{carbs},{glucose},{},{},{},{:.1}",
+            options.now_timestamp(),
+            chat_id.0,
+            bolus.carb_ratio,
+            bolus.correction_factor,
+            bolus.target,
+            suggestion.total()
+        ),
+        options.sync_writes,
+    )?;
+    log_saved_entry(
+        options,
+        chat_id,
+        "bolus",
+        Some(suggestion.total()),
+        None,
+        None,
+    );
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use std::collections::BTreeMap;
+use teloxide::types::ChatId;
+
+pub(super) const CALENDAR_FILE_NAME: &str = "medications.ics";
+const MED_TIMES_MAX: usize = 8;
+/// Content lines longer than this many octets are folded (RFC 5545, section 3.1).
+const ICS_LINE_MAX_OCTETS: usize = 75;
+const EVENT_DURATION: &str = "PT5M";
+
+/// `/medtime` request: a medication name and its daily times, or `None` to remove them.
+pub(super) type MedTimes = (String, Option<Vec<NaiveTime>>);
+
+/// Parses `/medtime <name> <HH:MM>[,HH:MM...]` or `/medtime <name> off`. The name may
+/// contain spaces; the times are the last word.
+pub(super) fn parse_medtime_args(args: &str) -> Result<MedTimes, String> {
+    let usage = || {
+        format!(
+            "Usage: /medtime <name> <HH:MM>[,HH:MM...] (up to {MED_TIMES_MAX}) or /medtime <name> off"
+        )
+    };
+    let Some((name, times)) = args.rsplit_once(char::is_whitespace) else {
+        return Err(usage());
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(usage());
+    }
+    if times.eq_ignore_ascii_case("off") {
+        return Ok((name.to_string(), None));
+    }
+    let mut parsed = times
+        .split(',')
+        .map(|time| NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| usage()))
+        .collect::<Result<Vec<_>, _>>()?;
+    parsed.sort();
+    parsed.dedup();
+    if parsed.len() > MED_TIMES_MAX {
+        return Err(usage());
+    }
+    Ok((name.to_string(), Some(parsed)))
+}
+
+/// Scheduled medications in list order with their parsed times. Times of medications
+/// that are no longer in the list are left out.
+pub(super) fn scheduled(
+    medications: &[String],
+    med_times: &BTreeMap<String, Vec<String>>,
+) -> Vec<(String, Vec<NaiveTime>)> {
+    medications
+        .iter()
+        .filter_map(|name| {
+            let times = med_times
+                .get(name)?
+                .iter()
+                .filter_map(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+                .collect::<Vec<_>>();
+            (!times.is_empty()).then(|| (name.clone(), times))
+        })
+        .collect()
+}
+
+/// Escapes a TEXT value (RFC 5545, section 3.3.11).
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Appends `line` with CRLF, folding it into continuation lines that start with a space
+/// so no line is longer than [`ICS_LINE_MAX_OCTETS`]. Folds never split a character.
+fn push_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > ICS_LINE_MAX_OCTETS {
+            ics.push_str("\r\n ");
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// UID part from a medication name: lowercase letters and digits, anything else `-`.
+fn uid_slug(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_lowercase().next().unwrap_or(c)
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// iCalendar file with one daily event per medication time, starting on `start`. Times
+/// are floating local times, so the phone shows them at the same wall-clock time in
+/// whatever timezone it is in, across daylight saving changes.
+pub(super) fn medication_calendar(
+    chat_id: ChatId,
+    schedule: &[(String, Vec<NaiveTime>)],
+    start: NaiveDate,
+    now: DateTime<Utc>,
+) -> String {
+    let mut ics = String::new();
+    for line in [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//pdd-bot//medication reminders//EN",
+        "CALSCALE:GREGORIAN",
+    ] {
+        push_line(&mut ics, line);
+    }
+    let stamp = now.format("%Y%m%dT%H%M%SZ");
+    for (name, times) in schedule {
+        let summary = escape_text(&format!("Take {name}"));
+        for time in times {
+            let lines = [
+                "BEGIN:VEVENT".to_string(),
+                format!(
+                    "UID:med-{}-{}-{}@pdd-bot",
+                    chat_id.0,
+                    uid_slug(name),
+                    time.format("%H%M")
+                ),
+                format!("DTSTAMP:{stamp}"),
+                format!("DTSTART:{}", start.and_time(*time).format("%Y%m%dT%H%M%S")),
+                format!("DURATION:{EVENT_DURATION}"),
+                "RRULE:FREQ=DAILY".to_string(),
+                format!("SUMMARY:{summary}"),
+                "BEGIN:VALARM".to_string(),
+                "ACTION:DISPLAY".to_string(),
+                format!("DESCRIPTION:{summary}"),
+                "TRIGGER:PT0S".to_string(),
+                "END:VALARM".to_string(),
+                "END:VEVENT".to_string(),
+            ];
+            for line in &lines {
+                push_line(&mut ics, line);
+            }
+        }
+    }
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
@@ -0,0 +1,467 @@
+use super::mmol_l_to_mg_dl;
+use super::plot::{self, Plot, TimePlot};
+use super::records::{
+    self, CarbRecord, CsvRecord, GlucoseRecord, InsulinRecord, KetoneRecord, WeightRecord,
+};
+use super::settings::{GlucoseRange, GlucoseUnit, UserSettings};
+use chrono::{DateTime, Days, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, VPos};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+use teloxide::types::ChatId;
+
+pub(super) const CHART_DEFAULT_DAYS: i64 = 14;
+pub(super) const CHART_MAX_DAYS: i64 = 365;
+const GLUCOSE_AXIS_MMOL_L: Range<f64> = 0.0..20.0;
+const GLUCOSE_AXIS_MG_DL: Range<f64> = 0.0..360.0;
+/// Ketone axis reaches at least this high, so normal readings sit low on the chart.
+const KETONES_AXIS_MIN_TOP: f64 = 3.0;
+const DATE_FORMAT: &str = "%m-%d";
+const EMPTY: &str = "Nothing logged in this period.";
+/// Daily bars are labeled with their total up to this many days.
+const BAR_LABEL_MAX_DAYS: i64 = 31;
+const SERIES_COLOR: RGBColor = RGBColor(26, 51, 179);
+const BAR_COLOR: RGBColor = RGBColor(128, 51, 153);
+const TARGET_COLOR: RGBColor = RGBColor(190, 230, 190);
+const TARGET_MIDPOINT_COLOR: RGBColor = RGBColor(51, 153, 51);
+
+/// What `/chart` can draw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ChartMetric {
+    Glucose,
+    Weight,
+    Ketones,
+    Insulin,
+    Carbs,
+}
+
+impl ChartMetric {
+    const ALL: [ChartMetric; 5] = [
+        ChartMetric::Glucose,
+        ChartMetric::Weight,
+        ChartMetric::Ketones,
+        ChartMetric::Insulin,
+        ChartMetric::Carbs,
+    ];
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            ChartMetric::Glucose => "glucose",
+            ChartMetric::Weight => "weight",
+            ChartMetric::Ketones => "ketones",
+            ChartMetric::Insulin => "insulin",
+            ChartMetric::Carbs => "carbs",
+        }
+    }
+
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|metric| metric.name().eq_ignore_ascii_case(name))
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            ChartMetric::Glucose => "Glucose",
+            ChartMetric::Weight => "Weight",
+            ChartMetric::Ketones => "Ketones",
+            ChartMetric::Insulin => "Insulin",
+            ChartMetric::Carbs => "Carbs",
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            ChartMetric::Glucose | ChartMetric::Ketones => "mmol/L",
+            ChartMetric::Weight => "kg",
+            ChartMetric::Insulin => "U",
+            ChartMetric::Carbs => "g",
+        }
+    }
+}
+
+/// File type of `/chart` and `/today`. PNG is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum ChartFormat {
+    #[default]
+    Png,
+    /// Vector image for printing or editing; Telegram does not preview it.
+    Svg,
+}
+
+impl ChartFormat {
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(ChartFormat::Png),
+            "svg" => Some(ChartFormat::Svg),
+            _ => None,
+        }
+    }
+
+    pub(super) fn extension(self) -> &'static str {
+        match self {
+            ChartFormat::Png => "png",
+            ChartFormat::Svg => "svg",
+        }
+    }
+
+    /// Draws `plot` with the plotters backend of this format and returns the file.
+    pub(super) fn render(self, plot: &impl Plot) -> anyhow::Result<Vec<u8>> {
+        match self {
+            ChartFormat::Png => plot::to_png(plot),
+            ChartFormat::Svg => plot::to_svg(plot),
+        }
+    }
+}
+
+/// Fails for a NaN or infinite value, which a CSV row can hold but no chart can place.
+pub(super) fn ensure_finite(values: impl IntoIterator<Item = f64>) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        values.into_iter().all(f64::is_finite),
+        "a value to draw is not a finite number"
+    );
+    Ok(())
+}
+
+pub(super) fn usage() -> String {
+    let names = ChartMetric::ALL.map(ChartMetric::name).join("|");
+    format!("Usage: /chart <{names}> [1-{CHART_MAX_DAYS}] [png|svg], e.g. /chart weight 90")
+}
+
+/// Timestamp and value of every row of `metric` for the chat, from its typed reader.
+pub(super) fn read_points(
+    data_dir: &Path,
+    chat_id: ChatId,
+    metric: ChartMetric,
+) -> anyhow::Result<Vec<(DateTime<Utc>, f64)>> {
+    fn points<T: CsvRecord>(
+        data_dir: &Path,
+        chat_id: ChatId,
+        point: impl Fn(&T) -> (DateTime<Utc>, f64),
+    ) -> anyhow::Result<Vec<(DateTime<Utc>, f64)>> {
+        let rows = records::read_records::<T>(data_dir, chat_id)?.rows;
+        Ok(rows.iter().map(point).collect())
+    }
+    match metric {
+        ChartMetric::Glucose => points(data_dir, chat_id, |row: &GlucoseRecord| {
+            (row.timestamp, row.value)
+        }),
+        ChartMetric::Weight => points(data_dir, chat_id, |row: &WeightRecord| {
+            (row.timestamp, row.value)
+        }),
+        ChartMetric::Ketones => points(data_dir, chat_id, |row: &KetoneRecord| {
+            (row.timestamp, row.value)
+        }),
+        ChartMetric::Insulin => points(data_dir, chat_id, |row: &InsulinRecord| {
+            (row.timestamp, row.units)
+        }),
+        ChartMetric::Carbs => points(data_dir, chat_id, |row: &CarbRecord| {
+            (row.timestamp, row.grams)
+        }),
+    }
+}
+
+/// One metric over the last `days` local days, today included.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Chart {
+    pub(super) metric: ChartMetric,
+    pub(super) first_day: NaiveDate,
+    pub(super) last_day: NaiveDate,
+    /// Points on the shown days, in time order.
+    pub(super) points: Vec<(DateTime<Utc>, f64)>,
+}
+
+impl Chart {
+    pub(super) fn new(
+        metric: ChartMetric,
+        mut points: Vec<(DateTime<Utc>, f64)>,
+        tz: Tz,
+        now: DateTime<Utc>,
+        days: i64,
+    ) -> Self {
+        let last_day = now.with_timezone(&tz).date_naive();
+        let first_day = last_day - Days::new(days.max(1) as u64 - 1);
+        points.retain(|(timestamp, _)| {
+            (first_day..=last_day).contains(&timestamp.with_timezone(&tz).date_naive())
+        });
+        points.sort_by_key(|(timestamp, _)| *timestamp);
+        Chart {
+            metric,
+            first_day,
+            last_day,
+            points,
+        }
+    }
+
+    fn days(&self) -> i64 {
+        (self.last_day - self.first_day).num_days() + 1
+    }
+
+    fn daily_totals(&self, tz: Tz) -> BTreeMap<NaiveDate, f64> {
+        let mut totals = BTreeMap::new();
+        for (timestamp, value) in &self.points {
+            *totals
+                .entry(timestamp.with_timezone(&tz).date_naive())
+                .or_default() += value;
+        }
+        totals
+    }
+}
+
+pub(super) fn file_name(chart: &Chart, format: ChartFormat) -> String {
+    format!(
+        "chart-{}-{}.{}",
+        chart.metric.name(),
+        chart.last_day,
+        format.extension()
+    )
+}
+
+/// One-line summary used as the document caption.
+pub(super) fn caption(chart: &Chart, tz: Tz) -> String {
+    let metric = chart.metric;
+    let period = format!("{}, last {} days", metric.title(), chart.days());
+    let values = chart.points.iter().map(|(_, value)| *value);
+    let (Some(low), Some(high)) = (
+        values.clone().reduce(f64::min),
+        values.clone().reduce(f64::max),
+    ) else {
+        return format!("{period}: nothing logged.");
+    };
+    let count = chart.points.len();
+    let unit = metric.unit();
+    match metric {
+        ChartMetric::Glucose | ChartMetric::Weight | ChartMetric::Ketones => format!(
+            "{period}: avg {:.1} {unit} (readings: {count}, {low:.1}-{high:.1})",
+            values.sum::<f64>() / count as f64
+        ),
+        ChartMetric::Insulin | ChartMetric::Carbs => {
+            let totals = chart.daily_totals(tz);
+            format!(
+                "{period}: total {:.1} {unit}, {:.1} {unit} per day with entries (entries: {count})",
+                values.sum::<f64>(),
+                totals.values().sum::<f64>() / totals.len() as f64
+            )
+        }
+    }
+}
+
+/// Text form of the chart for when it cannot be drawn: the caption, then each reading,
+/// or each day's total for insulin and carbs.
+pub(super) fn numbers(chart: &Chart, tz: Tz) -> String {
+    let unit = chart.metric.unit();
+    let mut lines = vec![caption(chart, tz)];
+    match chart.metric {
+        ChartMetric::Glucose | ChartMetric::Weight | ChartMetric::Ketones => {
+            lines.extend(chart.points.iter().map(|(timestamp, value)| {
+                format!(
+                    "{}  {value} {unit}",
+                    timestamp.with_timezone(&tz).format("%Y-%m-%d %H:%M")
+                )
+            }));
+        }
+        ChartMetric::Insulin | ChartMetric::Carbs => {
+            lines.extend(
+                chart
+                    .daily_totals(tz)
+                    .iter()
+                    .map(|(day, total)| format!("{day}  {total:.1} {unit}")),
+            );
+        }
+    }
+    lines.join("\n")
+}
+
+/// Glucose value axis shared by every chart, in the chat's glucose unit: the same
+/// bounds, grid and target marks whichever report draws it. Values are passed in
+/// mmol/L, as records hold them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct GlucoseAxis(GlucoseUnit);
+
+impl GlucoseAxis {
+    /// Axis in the unit the chat stores glucose in, set with `/units`.
+    pub(super) fn for_settings(settings: &UserSettings) -> Self {
+        GlucoseAxis(settings.glucose_unit.unwrap_or_default())
+    }
+
+    pub(super) fn bounds(self) -> Range<f64> {
+        match self.0 {
+            GlucoseUnit::Mmol => GLUCOSE_AXIS_MMOL_L,
+            GlucoseUnit::Mgdl => GLUCOSE_AXIS_MG_DL,
+        }
+    }
+
+    fn value(self, value_mmol_l: f64) -> f64 {
+        match self.0 {
+            GlucoseUnit::Mmol => value_mmol_l,
+            GlucoseUnit::Mgdl => mmol_l_to_mg_dl(value_mmol_l),
+        }
+    }
+
+    pub(super) fn unit(self) -> &'static str {
+        self.0.csv_name()
+    }
+
+    /// Axis title, e.g. `Glucose (mg/dL)`.
+    pub(super) fn title(self) -> String {
+        format!("Glucose ({})", self.unit())
+    }
+
+    /// Place on the axis of a value in mmol/L; values off the axis sit on its edge.
+    pub(super) fn position(self, value_mmol_l: f64) -> f64 {
+        let Range { start, end } = self.bounds();
+        self.value(value_mmol_l).clamp(start, end)
+    }
+
+    /// Time plot with this axis up, its target band and a reference line at the middle
+    /// of the target range, labeled at its right end.
+    pub(super) fn plot<'a, DB: DrawingBackend>(
+        self,
+        area: &'a DrawingArea<DB, Shift>,
+        times: (NaiveDateTime, NaiveDateTime),
+        time_format: &str,
+        target: GlucoseRange,
+    ) -> DrawResult<TimePlot<'a, DB>, DB> {
+        let mut plot = plot::time_plot(area, times, self.bounds(), &self.title(), time_format)?;
+        let (start, end) = times;
+        plot.draw_series([Rectangle::new(
+            [
+                (start, self.position(target.low)),
+                (end, self.position(target.high)),
+            ],
+            TARGET_COLOR.mix(0.5).filled(),
+        )])?;
+        let midpoint = (target.low + target.high) / 2.0;
+        let mid = self.position(midpoint);
+        plot.draw_series(LineSeries::new(
+            [(start, mid), (end, mid)],
+            TARGET_MIDPOINT_COLOR.stroke_width(2),
+        ))?;
+        let label = match self.0 {
+            GlucoseUnit::Mmol => format!("{:.1}", self.value(midpoint)),
+            GlucoseUnit::Mgdl => format!("{:.0}", self.value(midpoint)),
+        };
+        plot.draw_series([Text::new(
+            label,
+            (end, mid),
+            plot::anchored(14.0, HPos::Right, VPos::Bottom),
+        )])?;
+        Ok(plot)
+    }
+}
+
+/// Renders the chart as an image in `format`. Glucose, weight and ketones are drawn as
+/// readings over time; insulin and carbs as daily totals. Glucose is shown on
+/// `glucose_axis`.
+pub(super) fn render(
+    chart: &Chart,
+    tz: Tz,
+    target: GlucoseRange,
+    glucose_axis: GlucoseAxis,
+    format: ChartFormat,
+) -> anyhow::Result<Vec<u8>> {
+    ensure_finite(chart.points.iter().map(|(_, value)| *value))?;
+    format.render(&ChartPlot {
+        chart,
+        tz,
+        target,
+        glucose_axis,
+    })
+}
+
+struct ChartPlot<'a> {
+    chart: &'a Chart,
+    tz: Tz,
+    target: GlucoseRange,
+    glucose_axis: GlucoseAxis,
+}
+
+impl Plot for ChartPlot<'_> {
+    const SIZE: (u32, u32) = (1200, 800);
+
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> DrawResult<(), DB> {
+        let ChartPlot {
+            chart,
+            tz,
+            target,
+            glucose_axis,
+        } = *self;
+        let area = plot::page(
+            root,
+            &format!(
+                "{}, {} to {}",
+                chart.metric.title(),
+                chart.first_day,
+                chart.last_day
+            ),
+            &caption(chart, tz),
+        )?;
+        let times = (
+            midnight(chart.first_day),
+            midnight(chart.last_day + Days::new(1)),
+        );
+        let title = format!("{} ({})", chart.metric.title(), chart.metric.unit());
+        let values = chart.points.iter().map(|(_, value)| *value);
+        let readings = chart
+            .points
+            .iter()
+            .map(|(timestamp, value)| (timestamp.with_timezone(&tz).naive_local(), *value));
+        match chart.metric {
+            ChartMetric::Glucose => {
+                let mut plot = glucose_axis.plot(&area, times, DATE_FORMAT, target)?;
+                let readings = readings.map(|(time, value)| (time, glucose_axis.position(value)));
+                plot_readings(&mut plot, readings.collect())
+            }
+            ChartMetric::Weight => {
+                let low = values.clone().reduce(f64::min).unwrap_or(0.0);
+                let high = values.reduce(f64::max).unwrap_or(0.0);
+                let axis = (low - 1.0).floor().max(0.0)..(high + 1.0).ceil();
+                let mut plot = plot::time_plot(&area, times, axis, &title, DATE_FORMAT)?;
+                plot_readings(&mut plot, readings.collect())
+            }
+            ChartMetric::Ketones => {
+                let axis = 0.0..values.fold(KETONES_AXIS_MIN_TOP, f64::max).ceil();
+                let mut plot = plot::time_plot(&area, times, axis, &title, DATE_FORMAT)?;
+                plot_readings(&mut plot, readings.collect())
+            }
+            ChartMetric::Insulin | ChartMetric::Carbs => {
+                let totals = chart.daily_totals(tz);
+                let max = totals.values().copied().fold(0.0, f64::max);
+                let axis = 0.0..if max > 0.0 { max * 1.15 } else { 1.0 };
+                let mut plot = plot::time_plot(&area, times, axis, &title, DATE_FORMAT)?;
+                if totals.is_empty() {
+                    return plot::draw_empty(&mut plot, EMPTY);
+                }
+                let labeled = chart.days() <= BAR_LABEL_MAX_DAYS;
+                let bars = totals
+                    .into_iter()
+                    .map(|(day, total)| {
+                        let noon = midnight(day) + Duration::hours(12);
+                        (noon, total, labeled.then(|| format!("{total:.0}")))
+                    })
+                    .collect::<Vec<_>>();
+                plot::draw_bars(&mut plot, &bars, Duration::hours(7), BAR_COLOR)
+            }
+        }
+    }
+}
+
+pub(super) fn midnight(day: NaiveDate) -> NaiveDateTime {
+    day.and_time(NaiveTime::MIN)
+}
+
+/// Readings as a line with a dot each, on the already drawn axis.
+fn plot_readings<DB: DrawingBackend>(
+    plot: &mut TimePlot<DB>,
+    readings: Vec<(NaiveDateTime, f64)>,
+) -> DrawResult<(), DB> {
+    if readings.is_empty() {
+        return plot::draw_empty(plot, EMPTY);
+    }
+    plot.draw_series(LineSeries::new(readings, SERIES_COLOR.stroke_width(2)).point_size(4))?;
+    Ok(())
+}
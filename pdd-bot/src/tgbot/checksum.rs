@@ -0,0 +1,18 @@
+use sha2::{Digest, Sha256};
+
+/// SHA-256 digest of `bytes` as 64 lowercase hex digits, as printed by `sha256sum`.
+pub(super) fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Name and content of the `.sha256` file sent after the export `file_name`, in the
+/// `sha256sum` format so `sha256sum -c` can check the downloaded file.
+pub(super) fn checksum_file(file_name: &str, bytes: &[u8]) -> (String, String) {
+    (
+        format!("{file_name}.sha256"),
+        format!("{}  {file_name}\n", sha256_hex(bytes)),
+    )
+}
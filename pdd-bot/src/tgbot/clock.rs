@@ -0,0 +1,29 @@
+use chrono::{DateTime, Utc};
+
+/// Source of the current time. Everything that reads "now" goes through
+/// [`InputOptions::clock`](super::InputOptions), so tests can pin it.
+pub(super) trait Clock: std::fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock that always reads the same instant.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+pub(super) struct FixedClock(pub(super) DateTime<Utc>);
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
@@ -0,0 +1,488 @@
+/// Group a command is listed under in `/commands`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CommandCategory {
+    Entry,
+    Reports,
+    Settings,
+    Admin,
+}
+
+impl CommandCategory {
+    const ALL: [CommandCategory; 4] = [
+        CommandCategory::Entry,
+        CommandCategory::Reports,
+        CommandCategory::Settings,
+        CommandCategory::Admin,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            CommandCategory::Entry => "Entry",
+            CommandCategory::Reports => "Reports",
+            CommandCategory::Settings => "Settings",
+            CommandCategory::Admin => "Admin",
+        }
+    }
+}
+
+/// Registry of slash commands; `/help` and `/commands` are generated from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BotCommand {
+    Menu,
+    MenuText,
+    Help,
+    Commands,
+    Privacy,
+    AddMed,
+    RenameMed,
+    MedTime,
+    AddGlucoseBefore,
+    AddGlucoseAfter,
+    AddKetone,
+    AddInsulin,
+    Meal,
+    Fast,
+    Food,
+    MealEffect,
+    MedEffect,
+    Month,
+    Trend,
+    Flags,
+    WeekdayAverages,
+    Predict,
+    Summary,
+    Goals,
+    Today,
+    Chart,
+    Iob,
+    Export,
+    Calendar,
+    Last,
+    History,
+    Del,
+    Note,
+    Tag,
+    SetRange,
+    InRange,
+    WeekStart,
+    DualUnits,
+    NoEmoji,
+    Verbose,
+    Checksums,
+    Precision,
+    SetLanguage,
+    Timezone,
+    Units,
+    Convert,
+    DefaultTag,
+    WeightGoal,
+    WeightAlert,
+    AverageGoal,
+    Nudge,
+    PostMeal,
+    Confirm,
+    SetBolus,
+    ResetSettings,
+    Bolus,
+    Share,
+    Revoke,
+    Merge,
+    Clear,
+}
+
+impl BotCommand {
+    /// All commands in `/help` order.
+    pub(super) const ALL: [BotCommand; 60] = [
+        BotCommand::Menu,
+        BotCommand::MenuText,
+        BotCommand::Help,
+        BotCommand::Commands,
+        BotCommand::Privacy,
+        BotCommand::AddMed,
+        BotCommand::RenameMed,
+        BotCommand::MedTime,
+        BotCommand::AddGlucoseBefore,
+        BotCommand::AddGlucoseAfter,
+        BotCommand::AddKetone,
+        BotCommand::AddInsulin,
+        BotCommand::Meal,
+        BotCommand::Fast,
+        BotCommand::Food,
+        BotCommand::MealEffect,
+        BotCommand::MedEffect,
+        BotCommand::Month,
+        BotCommand::Trend,
+        BotCommand::Flags,
+        BotCommand::WeekdayAverages,
+        BotCommand::Predict,
+        BotCommand::Summary,
+        BotCommand::Goals,
+        BotCommand::Today,
+        BotCommand::Chart,
+        BotCommand::Iob,
+        BotCommand::Export,
+        BotCommand::Calendar,
+        BotCommand::Last,
+        BotCommand::History,
+        BotCommand::Del,
+        BotCommand::Note,
+        BotCommand::Tag,
+        BotCommand::SetRange,
+        BotCommand::InRange,
+        BotCommand::WeekStart,
+        BotCommand::DualUnits,
+        BotCommand::NoEmoji,
+        BotCommand::Verbose,
+        BotCommand::Checksums,
+        BotCommand::Precision,
+        BotCommand::SetLanguage,
+        BotCommand::Timezone,
+        BotCommand::Units,
+        BotCommand::Convert,
+        BotCommand::DefaultTag,
+        BotCommand::WeightGoal,
+        BotCommand::WeightAlert,
+        BotCommand::AverageGoal,
+        BotCommand::Nudge,
+        BotCommand::PostMeal,
+        BotCommand::Confirm,
+        BotCommand::SetBolus,
+        BotCommand::ResetSettings,
+        BotCommand::Bolus,
+        BotCommand::Share,
+        BotCommand::Revoke,
+        BotCommand::Merge,
+        BotCommand::Clear,
+    ];
+
+    /// Whether the example only picks one word of the single choice in the usage, as
+    /// `/nudge on` does for `/nudge <on|off>`.
+    fn example_picks_a_choice(self) -> bool {
+        let choices = self
+            .usage()
+            .split_once(' ')
+            .and_then(|(_, argument)| argument.strip_prefix('<')?.strip_suffix('>'));
+        let picked = self.example().split_once(' ').map(|(_, argument)| argument);
+        match (choices, picked) {
+            (Some(choices), Some(picked)) => choices.split('|').any(|choice| choice == picked),
+            _ => false,
+        }
+    }
+
+    /// Command with its arguments, e.g. `/last [n]`.
+    pub(super) fn usage(self) -> &'static str {
+        match self {
+            BotCommand::Menu => "/menu",
+            BotCommand::MenuText => "/menutext",
+            BotCommand::Help => "/help",
+            BotCommand::Commands => "/commands",
+            BotCommand::Privacy => "/privacy",
+            BotCommand::AddMed => "/addmed <name>",
+            BotCommand::RenameMed => "/renamemed <old> -> <new>",
+            BotCommand::MedTime => "/medtime <name> <HH:MM>[,HH:MM...]|off",
+            BotCommand::AddGlucoseBefore => "/addgb <value> [date time] [@note]",
+            BotCommand::AddGlucoseAfter => "/addga <value> [date time] [@note]",
+            BotCommand::AddKetone => "/addketone <value> [date time] [@note]",
+            BotCommand::AddInsulin => "/addinsulin <units> [meal|correction] [date time] [@note]",
+            BotCommand::Meal => "/meal [date time] [@note]",
+            BotCommand::Fast => "/fast <start|end|status>",
+            BotCommand::Food => "/food <item> [grams g] [@note]",
+            BotCommand::MealEffect => "/mealeffect",
+            BotCommand::MedEffect => "/medeffect <medication> [days]",
+            BotCommand::Month => "/month",
+            BotCommand::Trend => "/trend [days]",
+            BotCommand::Flags => "/flags [days]",
+            BotCommand::WeekdayAverages => "/weekday [days]",
+            BotCommand::Predict => "/predict <before|after>",
+            BotCommand::Summary => "/summary",
+            BotCommand::Goals => "/goals",
+            BotCommand::Today => "/today [svg]",
+            BotCommand::Chart => "/chart <metric> [days] [svg]",
+            BotCommand::Iob => "/iob",
+            BotCommand::Export => "/export <file|health|long> [from to] [local|utc]",
+            BotCommand::Calendar => "/calendar",
+            BotCommand::Last => "/last [n]",
+            BotCommand::History => "/history",
+            BotCommand::Del => "/del <number>",
+            BotCommand::Note => "/note [number] <text>",
+            BotCommand::Tag => "/tag #<tag> <period>",
+            BotCommand::SetRange => "/setrange [before|after|HH:MM-HH:MM] <low> <high>",
+            BotCommand::InRange => "/inrange <on|off>",
+            BotCommand::WeekStart => "/weekstart <monday|sunday>",
+            BotCommand::DualUnits => "/dualunits <on|off>",
+            BotCommand::NoEmoji => "/noemoji <on|off>",
+            BotCommand::Verbose => "/verbose <on|off>",
+            BotCommand::Checksums => "/checksums <on|off>",
+            BotCommand::Precision => "/precision [<metric> <step>]",
+            BotCommand::SetLanguage => "/setlanguage <en|uk>",
+            BotCommand::Timezone => "/timezone <Area/City|off>",
+            BotCommand::Units => "/units <mmol|mgdl> [convert]",
+            BotCommand::Convert => "/convert <value> <mmol|mgdl>",
+            BotCommand::DefaultTag => "/defaulttag <before|after|off>",
+            BotCommand::WeightGoal => "/weightgoal <kg|off>",
+            BotCommand::WeightAlert => "/weightalert <kg|off>",
+            BotCommand::AverageGoal => "/avggoal <percent|off>",
+            BotCommand::Nudge => "/nudge <on|off>",
+            BotCommand::PostMeal => "/postmeal <on|off>",
+            BotCommand::Confirm => "/confirm [mark|reset|<metric> <short|detailed>]",
+            BotCommand::SetBolus => "/setbolus <g/U> <mmol/L per U> [target]",
+            BotCommand::ResetSettings => "/reset_settings",
+            BotCommand::Bolus => "/bolus <carbs g> <glucose>",
+            BotCommand::Share => "/share [days]",
+            BotCommand::Revoke => "/revoke",
+            BotCommand::Merge => "/merge <profile|chat id>",
+            BotCommand::Clear => "/clear <glucose|weight|ketones|insulin|meals|food|medlog>",
+        }
+    }
+
+    pub(super) fn description(self) -> &'static str {
+        match self {
+            BotCommand::Menu => "show menu buttons",
+            BotCommand::MenuText => "menu buttons as text, row by row",
+            BotCommand::Help => "show this help",
+            BotCommand::Commands => "commands by category with examples",
+            BotCommand::Privacy => "show how your data is stored",
+            BotCommand::AddMed => "add medication button",
+            BotCommand::RenameMed => "rename a medication everywhere",
+            BotCommand::MedTime => "medication times for /calendar",
+            BotCommand::AddGlucoseBefore => "add glucose before meal",
+            BotCommand::AddGlucoseAfter => "add glucose after meal",
+            BotCommand::AddKetone => "add blood ketones",
+            BotCommand::AddInsulin => "log a rapid insulin dose",
+            BotCommand::Meal => "mark a meal",
+            BotCommand::Fast => "start, end or show a fast",
+            BotCommand::Food => "log a food item",
+            BotCommand::MealEffect => "after-meal glucose per meal",
+            BotCommand::MedEffect => "glucose with vs without a med",
+            BotCommand::Month => "daily glucose for this month",
+            BotCommand::Trend => "glucose trend per day",
+            BotCommand::Flags => "out-of-range readings, worst first",
+            BotCommand::WeekdayAverages => "average glucose per weekday",
+            BotCommand::Predict => "guess the next reading",
+            BotCommand::Summary => "90-day PDF for clinic visits",
+            BotCommand::Goals => "glucose, weight and med goals",
+            BotCommand::Today => "today's glucose, insulin and meals",
+            BotCommand::Chart => "chart of one metric over days",
+            BotCommand::Iob => "insulin on board (not for dosing)",
+            BotCommand::Export => "CSV, Apple Health XML or long CSV",
+            BotCommand::Calendar => ".ics of daily med reminders",
+            BotCommand::Last => "list recent entries with numbers",
+            BotCommand::History => "edit or delete recent entries",
+            BotCommand::Del => "delete an entry listed by /last",
+            BotCommand::Note => "note the newest or a /last entry",
+            BotCommand::Tag => "tag glucose readings of a past period",
+            BotCommand::SetRange => "glucose range by meal tag or time",
+            BotCommand::InRange => "praise mid-range readings",
+            BotCommand::WeekStart => "first day of the week in /month",
+            BotCommand::DualUnits => "confirm glucose in both units",
+            BotCommand::NoEmoji => "plain text for screen readers",
+            BotCommand::Verbose => "show error details",
+            BotCommand::Checksums => "SHA-256 file with each /export",
+            BotCommand::Precision => "rounding of new entries",
+            BotCommand::SetLanguage => "language of the menu buttons",
+            BotCommand::Timezone => "timezone for times and days",
+            BotCommand::Units => "glucose unit for new or stored rows",
+            BotCommand::Convert => "convert a glucose value, not saved",
+            BotCommand::DefaultTag => "tag for a bare glucose number",
+            BotCommand::WeightGoal => "weight goal and progress",
+            BotCommand::WeightAlert => "flag big weekly weight changes",
+            BotCommand::AverageGoal => "lower the 7-day glucose average by %",
+            BotCommand::Nudge => "remind after days without entries",
+            BotCommand::PostMeal => "remind 2 h after before-meal",
+            BotCommand::Confirm => "how saved entries are confirmed",
+            BotCommand::SetBolus => "set carb ratio and correction factor",
+            BotCommand::ResetSettings => "settings back to defaults",
+            BotCommand::Bolus => "suggest a bolus (not medical advice)",
+            BotCommand::Share => "read-only link for a doctor",
+            BotCommand::Revoke => "turn off all /share links",
+            BotCommand::Merge => "merge another profile or chat",
+            BotCommand::Clear => "remove all entries of one file",
+        }
+    }
+
+    pub(super) fn example(self) -> &'static str {
+        match self {
+            BotCommand::Menu => "/menu",
+            BotCommand::MenuText => "/menutext",
+            BotCommand::Help => "/help",
+            BotCommand::Commands => "/commands",
+            BotCommand::Privacy => "/privacy",
+            BotCommand::AddMed => "/addmed Metformin",
+            BotCommand::RenameMed => "/renamemed Metformin -> Metformin 500",
+            BotCommand::MedTime => "/medtime Metformin 08:00,20:00",
+            BotCommand::AddGlucoseBefore => "/addgb 5.8 2/1 9:05 @before breakfast",
+            BotCommand::AddGlucoseAfter => "/addga 7.2",
+            BotCommand::AddKetone => "/addketone 0.4",
+            BotCommand::AddInsulin => "/addinsulin 4.5 @lunch",
+            BotCommand::Meal => "/meal 12:30 @lunch",
+            BotCommand::Fast => "/fast start",
+            BotCommand::Food => "/food oatmeal 45g @breakfast",
+            BotCommand::MealEffect => "/mealeffect",
+            BotCommand::MedEffect => "/medeffect metformin 60",
+            BotCommand::Month => "/month",
+            BotCommand::Trend => "/trend 30",
+            BotCommand::Flags => "/flags 30",
+            BotCommand::WeekdayAverages => "/weekday 56",
+            BotCommand::Predict => "/predict before",
+            BotCommand::Summary => "/summary",
+            BotCommand::Goals => "/goals",
+            BotCommand::Today => "/today",
+            BotCommand::Chart => "/chart weight 90",
+            BotCommand::Iob => "/iob",
+            BotCommand::Export => "/export glucose 2024-01-01 2024-01-31",
+            BotCommand::Calendar => "/calendar",
+            BotCommand::Last => "/last 5",
+            BotCommand::History => "/history",
+            BotCommand::Del => "/del 2",
+            BotCommand::Note => "/note stress",
+            BotCommand::Tag => "/tag #sick lastweek",
+            BotCommand::SetRange => "/setrange before 4.0 7.0",
+            BotCommand::InRange => "/inrange on",
+            BotCommand::WeekStart => "/weekstart sunday",
+            BotCommand::DualUnits => "/dualunits on",
+            BotCommand::NoEmoji => "/noemoji on",
+            BotCommand::Verbose => "/verbose on",
+            BotCommand::Checksums => "/checksums on",
+            BotCommand::Precision => "/precision insulin 0.5",
+            BotCommand::SetLanguage => "/setlanguage uk",
+            BotCommand::Timezone => "/timezone Europe/Kyiv",
+            BotCommand::Units => "/units mgdl convert",
+            BotCommand::Convert => "/convert 104 mgdl",
+            BotCommand::DefaultTag => "/defaulttag before",
+            BotCommand::WeightGoal => "/weightgoal 72",
+            BotCommand::WeightAlert => "/weightalert 2",
+            BotCommand::AverageGoal => "/avggoal 10",
+            BotCommand::Nudge => "/nudge on",
+            BotCommand::PostMeal => "/postmeal on",
+            BotCommand::Confirm => "/confirm 👍",
+            BotCommand::SetBolus => "/setbolus 10 2.5 6.0",
+            BotCommand::ResetSettings => "/reset_settings",
+            BotCommand::Bolus => "/bolus 60 8.2",
+            BotCommand::Share => "/share 30",
+            BotCommand::Revoke => "/revoke",
+            BotCommand::Merge => "/merge old",
+            BotCommand::Clear => "/clear ketones",
+        }
+    }
+
+    fn category(self) -> CommandCategory {
+        match self {
+            BotCommand::AddGlucoseBefore
+            | BotCommand::AddGlucoseAfter
+            | BotCommand::AddKetone
+            | BotCommand::AddInsulin
+            | BotCommand::Meal
+            | BotCommand::Fast
+            | BotCommand::Food => CommandCategory::Entry,
+            BotCommand::MealEffect
+            | BotCommand::MedEffect
+            | BotCommand::Month
+            | BotCommand::Trend
+            | BotCommand::Flags
+            | BotCommand::WeekdayAverages
+            | BotCommand::Predict
+            | BotCommand::Summary
+            | BotCommand::Goals
+            | BotCommand::Today
+            | BotCommand::Chart
+            | BotCommand::Iob
+            | BotCommand::Export
+            | BotCommand::Calendar
+            | BotCommand::Last
+            | BotCommand::Bolus => CommandCategory::Reports,
+            BotCommand::AddMed
+            | BotCommand::RenameMed
+            | BotCommand::MedTime
+            | BotCommand::SetRange
+            | BotCommand::InRange
+            | BotCommand::WeekStart
+            | BotCommand::DualUnits
+            | BotCommand::NoEmoji
+            | BotCommand::Verbose
+            | BotCommand::Checksums
+            | BotCommand::Precision
+            | BotCommand::SetLanguage
+            | BotCommand::Timezone
+            | BotCommand::Units
+            | BotCommand::DefaultTag
+            | BotCommand::Convert
+            | BotCommand::WeightGoal
+            | BotCommand::WeightAlert
+            | BotCommand::AverageGoal
+            | BotCommand::Nudge
+            | BotCommand::PostMeal
+            | BotCommand::Confirm
+            | BotCommand::SetBolus
+            | BotCommand::ResetSettings => CommandCategory::Settings,
+            BotCommand::Menu
+            | BotCommand::MenuText
+            | BotCommand::Help
+            | BotCommand::Commands
+            | BotCommand::Privacy
+            | BotCommand::History
+            | BotCommand::Del
+            | BotCommand::Note
+            | BotCommand::Tag
+            | BotCommand::Share
+            | BotCommand::Revoke
+            | BotCommand::Merge
+            | BotCommand::Clear => CommandCategory::Admin,
+        }
+    }
+}
+
+/// Commands accepted by [`super::routing::parse_command`] that `/help` does not list.
+pub(super) const UNLISTED_COMMANDS: [&str; 9] = [
+    "/start",
+    "/daystats",
+    "/debug",
+    "/status",
+    "/tail",
+    "/add_glucose_before",
+    "/add_glucose_after",
+    "/add_ketones",
+    "/add_medication",
+];
+
+/// Names of all slash commands without the `/`, e.g. `menu`.
+pub(super) fn command_words() -> impl Iterator<Item = &'static str> {
+    BotCommand::ALL
+        .iter()
+        .filter_map(|command| command.usage().split_whitespace().next())
+        .chain(UNLISTED_COMMANDS)
+        .map(|name| name.trim_start_matches('/'))
+}
+
+/// `usage - description` lines for `/help`.
+pub(super) fn help_lines() -> String {
+    BotCommand::ALL
+        .iter()
+        .map(|command| format!("{} - {}\n", command.usage(), command.description()))
+        .collect()
+}
+
+/// Reply to `/commands`: every command grouped by category, with a sample for those that
+/// take arguments.
+pub(super) fn commands_text() -> String {
+    let mut sections = Vec::new();
+    for category in CommandCategory::ALL {
+        let mut section = format!("{}:", category.title());
+        for command in BotCommand::ALL
+            .iter()
+            .filter(|command| command.category() == category)
+        {
+            section.push_str(&format!(
+                "\n{} - {}",
+                command.usage(),
+                command.description()
+            ));
+            // A command without arguments is its own example, and an example that only
+            // picks one word of a choice such as `<on|off>` adds nothing.
+            if command.example() != command.usage() && !command.example_picks_a_choice() {
+                section.push_str(&format!("\n  e.g. {}", command.example()));
+            }
+        }
+        sections.push(section);
+    }
+    sections.join("\n\n")
+}
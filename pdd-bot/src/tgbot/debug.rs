@@ -0,0 +1,190 @@
+use super::*;
+use std::fmt::Debug;
+
+fn outcome<T: Debug>(result: anyhow::Result<T>) -> String {
+    match result {
+        Ok(value) => format!("{value:?}"),
+        Err(err) => format!("error: {err}"),
+    }
+}
+
+/// Reply to the admin `/tail <file>`: the last line of one of this chat's data files as
+/// stored, with no parsing, so a row that reads wrong can be inspected. Invalid UTF-8
+/// shows as `�` and a Windows line ending is pointed out, since neither is visible.
+pub(super) fn tail_reply(
+    state: &AppState,
+    chat_id: ChatId,
+    target: &str,
+) -> anyhow::Result<String> {
+    let Some(target) = ClearTarget::parse(target) else {
+        let names = ClearTarget::ALL.map(ClearTarget::name).join("|");
+        return Ok(format!("Usage: /tail <{names}>"));
+    };
+    let path = user_data_dir(&state.data_dir, chat_id).join(target.file_name());
+    let content = if path.exists() {
+        fs_err::read(&path)?
+    } else {
+        Vec::new()
+    };
+    let content = String::from_utf8_lossy(&content);
+    let Some((number, line)) = content
+        .split('\n')
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .last()
+    else {
+        return Ok(format!("{} is empty.", target.file_name()));
+    };
+    let (line, ending) = match line.strip_suffix('\r') {
+        Some(line) => (line, ", CRLF"),
+        None => (line, ""),
+    };
+    Ok(format!(
+        "{} line {}{ending}:\n{line}",
+        target.file_name(),
+        number + 1
+    ))
+}
+
+/// Reply to `/debug <raw text>`: which branch of [`handle_text`] would handle `raw` for
+/// this chat and what its parser makes of it. Nothing is saved and no pending state
+/// changes.
+pub(super) async fn describe_route(state: &AppState, chat_id: ChatId, raw: &str) -> String {
+    let text = raw.trim();
+    let (route, parsed) = route(state, chat_id, text).await;
+    format!("Input: {text:?}\nRoute: {route}\nParsed: {parsed}")
+}
+
+async fn route(state: &AppState, chat_id: ChatId, text: &str) -> (String, String) {
+    let command = |name: &str, parsed: String| (format!("command {name}"), parsed);
+    let arguments = |payload: &str| format!("arguments {payload:?}");
+    match routing::parse_command(text) {
+        Command::Menu => ("show menu".to_string(), "-".to_string()),
+        Command::MoreMeds => ("show more medications".to_string(), "-".to_string()),
+        Command::Start { payload } => command("/start", arguments(payload)),
+        Command::Help => command("/help", "-".to_string()),
+        Command::Commands => command("/commands", "-".to_string()),
+        Command::MenuText => command("/menutext", "-".to_string()),
+        Command::Privacy => command("/privacy", "-".to_string()),
+        Command::Debug { raw } => command("/debug", arguments(raw)),
+        Command::Status => command("/status", "-".to_string()),
+        Command::Tail { target } => command("/tail", format!("{:?}", ClearTarget::parse(target))),
+        Command::AddGlucose { tag, payload } => (
+            format!("glucose add ({})", tag.as_csv_tag()),
+            outcome(parse_glucose_payload(payload, &state.input_for(chat_id))),
+        ),
+        Command::AddKetone { payload } => (
+            "ketones add".to_string(),
+            outcome(parse_ketone_payload(payload, &state.input_for(chat_id))),
+        ),
+        Command::AddInsulin { payload } => (
+            "insulin add".to_string(),
+            outcome(insulin::parse_insulin_payload(
+                payload,
+                &state.input_for(chat_id),
+            )),
+        ),
+        Command::Iob => command("/iob", "-".to_string()),
+        Command::Meal { payload } => (
+            "meal".to_string(),
+            outcome(parse_time_and_note(payload, &state.input_for(chat_id))),
+        ),
+        Command::Food { payload } => (
+            "food".to_string(),
+            outcome(parse_food_payload(payload, &state.input_for(chat_id))),
+        ),
+        Command::MealEffect => command("/mealeffect", "-".to_string()),
+        Command::MedEffect(args) => command("/medeffect", format!("{args:?}")),
+        Command::Month => command("/month", "-".to_string()),
+        Command::Trend(days) => command("/trend", format!("{days:?}")),
+        Command::Flags(days) => command("/flags", format!("{days:?}")),
+        Command::Chart(chart) => command("/chart", format!("{chart:?}")),
+        Command::WeekdayAverages(days) => command("/weekday", format!("{days:?}")),
+        Command::Predict(tag) => command("/predict", format!("{tag:?}")),
+        Command::Today(format) => command("/today", format!("{format:?}")),
+        Command::Summary => command("/summary", "-".to_string()),
+        Command::Goals => command("/goals", "-".to_string()),
+        Command::Export(range) => command("/export", format!("{range:?}")),
+        Command::Tag(request) => command("/tag", format!("{request:?}")),
+        Command::Share(days) => command("/share", format!("{days:?}")),
+        Command::Revoke => command("/revoke", "-".to_string()),
+        Command::Calendar => command("/calendar", "-".to_string()),
+        Command::Last(count) => command("/last", format!("{count:?}")),
+        Command::History => command("/history", "-".to_string()),
+        Command::Del { index } => command("/del", arguments(index)),
+        Command::Note { payload } => command("/note", arguments(payload)),
+        Command::Merge(source) => command("/merge", format!("{source:?}")),
+        Command::Clear { target } => command("/clear", format!("{:?}", ClearTarget::parse(target))),
+        Command::ResetSettings => command("/reset_settings", "-".to_string()),
+        Command::DualUnits { payload } => command("/dualunits", arguments(payload)),
+        Command::NoEmoji { payload } => command("/noemoji", arguments(payload)),
+        Command::Verbose { payload } => command("/verbose", arguments(payload)),
+        Command::Checksums { payload } => command("/checksums", arguments(payload)),
+        Command::Precision { payload } => command("/precision", arguments(payload)),
+        Command::SetLanguage { payload } => command("/setlanguage", arguments(payload)),
+        Command::Timezone { payload } => command("/timezone", arguments(payload)),
+        Command::Units(request) => command("/units", format!("{request:?}")),
+        Command::Convert(request) => command("/convert", format!("{request:?}")),
+        Command::WeightGoal { payload } => command("/weightgoal", arguments(payload)),
+        Command::DefaultTag { payload } => command("/defaulttag", arguments(payload)),
+        Command::WeightAlert { payload } => command("/weightalert", arguments(payload)),
+        Command::AverageGoal { payload } => command("/avggoal", arguments(payload)),
+        Command::Fast { payload } => command("/fast", arguments(payload)),
+        Command::Nudge { payload } => command("/nudge", arguments(payload)),
+        Command::PostMeal { payload } => command("/postmeal", arguments(payload)),
+        Command::InRange { payload } => command("/inrange", arguments(payload)),
+        Command::Confirm { payload } => command("/confirm", arguments(payload)),
+        Command::WeekStart { payload } => command("/weekstart", arguments(payload)),
+        Command::SetRange { payload } => command("/setrange", arguments(payload)),
+        Command::SetBolus { payload } => command("/setbolus", arguments(payload)),
+        Command::Bolus { payload } => command("/bolus", arguments(payload)),
+        Command::RenameMed { payload } => {
+            command("/renamemed", format!("{:?}", parse_rename_payload(payload)))
+        }
+        Command::MedTime(times) => command("/medtime", format!("{times:?}")),
+        Command::AddMed { name } => ("addmed".to_string(), format!("medication {name:?}")),
+        Command::EntryButton(button) => (format!("button {button:?}"), "-".to_string()),
+        Command::MedButton { name } => (
+            "medication button".to_string(),
+            format!("{:?}", match_medication(state, chat_id, name).await),
+        ),
+        Command::Plain { text } => plain_route(state, chat_id, text).await,
+    }
+}
+
+async fn plain_route(state: &AppState, chat_id: ChatId, text: &str) -> (String, String) {
+    if let Some(batch) = batch::parse_glucose_batch(text, &state.input_for(chat_id)) {
+        return (
+            "batch import".to_string(),
+            format!(
+                "{} readings, {} failed lines",
+                batch.inputs.len(),
+                batch.failures.len()
+            ),
+        );
+    }
+    if let Some(pending) = get_pending(state, chat_id).await {
+        let parsed = match pending {
+            PendingEntry::GlucoseBeforeMeal | PendingEntry::GlucoseAfterMeal => {
+                outcome(parse_glucose_payload(text, &state.input_for(chat_id)))
+            }
+            PendingEntry::Weight => format!("{:?}", parse_decimal(text)),
+            PendingEntry::Ketones => outcome(parse_ketone_payload(text, &state.input_for(chat_id))),
+            PendingEntry::Food => outcome(parse_food_payload(text, &state.input_for(chat_id))),
+            PendingEntry::ConfirmClear(_)
+            | PendingEntry::ConfirmMedication(_)
+            | PendingEntry::ConfirmImport(_)
+            | PendingEntry::ConfirmUnitConversion(_)
+            | PendingEntry::ConfirmResetSettings => format!("reply {:?}", text.to_lowercase()),
+            PendingEntry::EditRow(ref edit) => format!("new {:?} {text:?}", edit.field),
+        };
+        let name = match pending {
+            PendingEntry::ConfirmImport(inputs) => {
+                format!("ConfirmImport({} readings)", inputs.len())
+            }
+            other => format!("{other:?}"),
+        };
+        return (format!("pending {name}"), parsed);
+    }
+    ("fallback (menu hint)".to_string(), "-".to_string())
+}
@@ -0,0 +1,259 @@
+use super::*;
+use records::parse_csv_line;
+
+/// Applies an edited Telegram message to the rows it created, if any.
+pub(super) async fn handle_edited_message(
+    bot: Bot,
+    message: Message,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    let text = match message.text() {
+        Some(text) => text,
+        None => return Ok(()),
+    };
+    handle_edited_text(&bot, message.chat.id, message.id, text, state).await
+}
+
+pub(super) async fn handle_edited_text<R: Responder>(
+    bot: &R,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: &str,
+    state: Arc<AppState>,
+) -> anyhow::Result<()> {
+    if !state.is_allowed(chat_id) {
+        return Ok(());
+    }
+    let text = text.trim().to_string();
+
+    let reply = storage::blocking(&state, move |state| {
+        Ok(
+            match edit_glucose_rows(state, chat_id, message_id, &text)? {
+                Some(reply) => Some(reply),
+                None => match edit_weight_rows(state, chat_id, message_id, &text)? {
+                    Some(reply) => Some(reply),
+                    None => edit_ketone_rows(state, chat_id, message_id, &text)?,
+                },
+            },
+        )
+    })
+    .await?;
+    let Some(reply) = reply else {
+        tracing::debug!(
+            "ignoring edit of message {} in chat {}: no saved entry",
+            message_id.0,
+            chat_id.0
+        );
+        return Ok(());
+    };
+    bot.send_text(chat_id, reply, menu_keyboard(&state, chat_id).await)
+        .await
+}
+
+fn edit_glucose_rows(
+    state: &AppState,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: &str,
+) -> anyhow::Result<Option<String>> {
+    let path = user_data_dir(&state.data_dir, chat_id).join(GLUCOSE_FILE);
+    let rows = find_message_rows(&path, GLUCOSE_MESSAGE_ID_COLUMN, message_id)?;
+    let Some(first) = rows.first() else {
+        return Ok(None);
+    };
+
+    let (tag, payload) = match routing::parse_command(text) {
+        Command::AddGlucose { tag, payload } => (Some(tag), payload),
+        _ => (None, text),
+    };
+    let input = match parse_glucose_payload(payload, &state.input_for(chat_id)) {
+        Ok(input) => input,
+        Err(err) => return Ok(Some(format!("Edit not applied: {err}"))),
+    };
+    let tag = tag
+        .or_else(|| first.get(2).and_then(|tag| GlucoseTag::from_csv_tag(tag)))
+        .unwrap_or(GlucoseTag::BeforeMeal);
+    let timestamp = input
+        .timestamp
+        .clone()
+        .or_else(|| first.first().cloned())
+        .unwrap_or_else(|| state.input_for(chat_id).now_timestamp());
+    let user_settings = settings_for_saving(&state.data_dir, chat_id);
+    let unit = user_settings.glucose_unit.unwrap_or_default();
+    let lines = input
+        .values
+        .iter()
+        .map(|&value| {
+            glucose_csv_line(
+                &timestamp,
+                chat_id,
+                tag,
+                user_settings.round_glucose(value, unit),
+                &input,
+                Some(message_id),
+                unit,
+            )
+        })
+        .collect::<Vec<_>>();
+    replace_message_rows(
+        state,
+        chat_id,
+        &path,
+        GLUCOSE_MESSAGE_ID_COLUMN,
+        message_id,
+        &lines,
+    )?;
+    for line in &lines {
+        log_saved_row(
+            &state.input,
+            chat_id,
+            GLUCOSE_FILE,
+            line,
+            input.note.as_deref(),
+        );
+    }
+    Ok(Some("Glucose entry updated ✅".to_string()))
+}
+
+fn edit_weight_rows(
+    state: &AppState,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: &str,
+) -> anyhow::Result<Option<String>> {
+    let path = user_data_dir(&state.data_dir, chat_id).join(WEIGHT_FILE);
+    let rows = find_message_rows(&path, WEIGHT_MESSAGE_ID_COLUMN, message_id)?;
+    let Some(first) = rows.first() else {
+        return Ok(None);
+    };
+
+    let Some(value) = parse_decimal(text) else {
+        return Ok(Some(
+            "Edit not applied: could not parse number. Use format like 78.4 (dot or comma)."
+                .to_string(),
+        ));
+    };
+    let timestamp = first
+        .first()
+        .cloned()
+        .unwrap_or_else(|| state.input_for(chat_id).now_timestamp());
+    let value = round_for_chat(
+        &state.data_dir,
+        chat_id,
+        settings::PrecisionMetric::Weight,
+        value,
+    );
+    let line = weight_csv_line(&timestamp, chat_id, value, Some(message_id));
+    replace_message_rows(
+        state,
+        chat_id,
+        &path,
+        WEIGHT_MESSAGE_ID_COLUMN,
+        message_id,
+        std::slice::from_ref(&line),
+    )?;
+    log_saved_row(&state.input, chat_id, WEIGHT_FILE, &line, None);
+    Ok(Some("Weight entry updated ✅".to_string()))
+}
+
+fn edit_ketone_rows(
+    state: &AppState,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: &str,
+) -> anyhow::Result<Option<String>> {
+    let path = user_data_dir(&state.data_dir, chat_id).join(KETONES_FILE);
+    let rows = find_message_rows(&path, KETONES_MESSAGE_ID_COLUMN, message_id)?;
+    let Some(first) = rows.first() else {
+        return Ok(None);
+    };
+
+    let payload = match routing::parse_command(text) {
+        Command::AddKetone { payload } => payload,
+        _ => text,
+    };
+    let (value, timestamp, note) = match parse_ketone_payload(payload, &state.input_for(chat_id)) {
+        Ok(parsed) => parsed,
+        Err(err) => return Ok(Some(format!("Edit not applied: {err}"))),
+    };
+    let value = round_for_chat(
+        &state.data_dir,
+        chat_id,
+        settings::PrecisionMetric::Ketones,
+        value,
+    );
+    let timestamp = timestamp
+        .or_else(|| first.first().cloned())
+        .unwrap_or_else(|| state.input_for(chat_id).now_timestamp());
+    let line = ketones_csv_line(
+        &timestamp,
+        chat_id,
+        value,
+        note.as_deref(),
+        Some(message_id),
+    );
+    replace_message_rows(
+        state,
+        chat_id,
+        &path,
+        KETONES_MESSAGE_ID_COLUMN,
+        message_id,
+        std::slice::from_ref(&line),
+    )?;
+    log_saved_row(&state.input, chat_id, KETONES_FILE, &line, note.as_deref());
+    Ok(Some("Ketones entry updated ✅".to_string()))
+}
+
+fn row_matches(line: &str, column: usize, message_id: MessageId) -> bool {
+    parse_csv_line(line)
+        .get(column)
+        .is_some_and(|field| *field == message_id.0.to_string())
+}
+
+fn find_message_rows(
+    path: &Path,
+    column: usize,
+    message_id: MessageId,
+) -> anyhow::Result<Vec<Vec<String>>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs_err::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .skip(1)
+        .filter(|line| row_matches(line, column, message_id))
+        .map(parse_csv_line)
+        .collect())
+}
+
+/// Replaces all rows written for `message_id` with `lines`, placed where the first row was.
+fn replace_message_rows(
+    state: &AppState,
+    chat_id: ChatId,
+    path: &Path,
+    column: usize,
+    message_id: MessageId,
+    lines: &[String],
+) -> anyhow::Result<()> {
+    state.rewrite_files(chat_id, || {
+        let content = fs_err::read_to_string(path)?;
+        let mut result = String::with_capacity(content.len());
+        let mut replaced = false;
+        for (index, line) in content.lines().enumerate() {
+            if index > 0 && row_matches(line, column, message_id) {
+                if !replaced {
+                    for new_line in lines {
+                        result.push_str(new_line);
+                        result.push('\n');
+                    }
+                    replaced = true;
+                }
+                continue;
+            }
+            result.push_str(line);
+            result.push('\n');
+        }
+        write_file_atomically(path, &result, state.input.sync_writes)
+    })
+}
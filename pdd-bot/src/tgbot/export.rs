@@ -0,0 +1,385 @@
+use super::insulin::INSULIN_TYPE_COLUMN;
+use super::records::{
+    GlucoseRecord, RawRow, WeightRecord, glucose_unit_field, read_raw_rows, read_records,
+};
+use super::settings::GlucoseUnit;
+use super::*;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const HEALTH_TARGET: &str = "health";
+const LONG_TARGET: &str = "long";
+const LONG_HEADER: &str = "timestamp,metric,value,unit,tag,note";
+const HEALTH_SOURCE_NAME: &str = "Personal Diabetes Diary";
+/// Timestamp layout of Apple Health `export.xml`, e.g. `2024-02-01 09:00:00 +0100`.
+const HEALTH_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+/// Wall-clock layout of [`ExportTimestamps::Local`], e.g. `2024-02-01 09:00:00`.
+const LOCAL_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// How CSV exports write timestamps, chosen by an optional last word of `/export`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum ExportTimestamps {
+    /// As stored: the row's own text in file exports, UTC with `+00:00` in the long export.
+    #[default]
+    Stored,
+    /// `local`: wall time in the chat's timezone without an offset, for spreadsheets.
+    Local,
+    /// `utc`: UTC with a `Z` suffix.
+    Utc,
+}
+
+impl ExportTimestamps {
+    fn parse(word: &str) -> Option<Self> {
+        match word.to_lowercase().as_str() {
+            "local" => Some(ExportTimestamps::Local),
+            "utc" => Some(ExportTimestamps::Utc),
+            _ => None,
+        }
+    }
+
+    /// `timestamp` in this form; `None` for [`ExportTimestamps::Stored`].
+    fn format(self, timestamp: DateTime<Utc>, tz: Tz) -> Option<String> {
+        match self {
+            ExportTimestamps::Stored => None,
+            ExportTimestamps::Local => Some(
+                timestamp
+                    .with_timezone(&tz)
+                    .format(LOCAL_DATE_FORMAT)
+                    .to_string(),
+            ),
+            ExportTimestamps::Utc => Some(timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        }
+    }
+}
+
+/// What `/export` should produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ExportRequest {
+    File(ExportRange),
+    /// `/export health [<from> <to>]`: glucose and weight as Apple Health XML, all
+    /// entries when no range is given.
+    Health(Option<(NaiveDate, NaiveDate)>),
+    /// `/export long [<from> <to>] [local|utc]`: every measurement in one CSV, one value
+    /// per row.
+    Long(Option<(NaiveDate, NaiveDate)>, ExportTimestamps),
+}
+
+/// `/export <file> <from> <to> [local|utc]`: rows of one data file whose local date is
+/// within `from..=to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ExportRange {
+    pub(super) target: ClearTarget,
+    pub(super) from: NaiveDate,
+    pub(super) to: NaiveDate,
+    pub(super) timestamps: ExportTimestamps,
+}
+
+/// Filtered CSV ready to be sent as a document.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct ExportFile {
+    pub(super) file_name: String,
+    pub(super) content: String,
+    pub(super) rows: usize,
+}
+
+fn usage() -> String {
+    let names = ClearTarget::ALL.map(ClearTarget::name).join("|");
+    format!(
+        "Usage: /export <{names}> <from YYYY-MM-DD> <to YYYY-MM-DD> [local|utc]\n\
+or /export {HEALTH_TARGET} [<from YYYY-MM-DD> <to YYYY-MM-DD>]\n\
+or /export {LONG_TARGET} [<from YYYY-MM-DD> <to YYYY-MM-DD>] [local|utc]"
+    )
+}
+
+fn parse_date(raw: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(raw, DATE_FORMAT)
+        .map_err(|_| format!("Invalid date '{raw}'. Use YYYY-MM-DD, e.g. 2024-01-31."))
+}
+
+pub(super) fn parse_period(from: &str, to: &str) -> Result<(NaiveDate, NaiveDate), String> {
+    let (from, to) = (parse_date(from)?, parse_date(to)?);
+    if from > to {
+        return Err(format!("Start date {from} is after end date {to}."));
+    }
+    Ok((from, to))
+}
+
+pub(super) fn parse_export_args(args: &str) -> Result<ExportRequest, String> {
+    let mut parts = args.split_whitespace().collect::<Vec<_>>();
+    let timestamps = match parts.last().and_then(|last| ExportTimestamps::parse(last)) {
+        Some(timestamps) => {
+            parts.pop();
+            timestamps
+        }
+        None => ExportTimestamps::default(),
+    };
+    match parts.as_slice() {
+        [name, ..]
+            if name.eq_ignore_ascii_case(HEALTH_TARGET)
+                && timestamps != ExportTimestamps::Stored =>
+        {
+            Err(
+                "Apple Health XML always has local times with their offset; \
+local and utc apply to CSV exports."
+                    .to_string(),
+            )
+        }
+        [name] if name.eq_ignore_ascii_case(HEALTH_TARGET) => Ok(ExportRequest::Health(None)),
+        [name, from, to] if name.eq_ignore_ascii_case(HEALTH_TARGET) => {
+            Ok(ExportRequest::Health(Some(parse_period(from, to)?)))
+        }
+        [name] if name.eq_ignore_ascii_case(LONG_TARGET) => {
+            Ok(ExportRequest::Long(None, timestamps))
+        }
+        [name, from, to] if name.eq_ignore_ascii_case(LONG_TARGET) => Ok(ExportRequest::Long(
+            Some(parse_period(from, to)?),
+            timestamps,
+        )),
+        [name, from, to] => {
+            let target = ClearTarget::parse(name).ok_or_else(usage)?;
+            let (from, to) = parse_period(from, to)?;
+            Ok(ExportRequest::File(ExportRange {
+                target,
+                from,
+                to,
+                timestamps,
+            }))
+        }
+        _ => Err(usage()),
+    }
+}
+
+/// Builds the filtered CSV: the file header plus every row dated within the range in `tz`,
+/// with the first column rewritten unless timestamps are kept as stored.
+pub(super) fn export_range(
+    data_dir: &Path,
+    chat_id: ChatId,
+    tz: Tz,
+    range: ExportRange,
+) -> anyhow::Result<ExportFile> {
+    let path = user_data_dir(data_dir, chat_id).join(range.target.file_name());
+    let header = if path.exists() {
+        fs_err::read_to_string(&path)?
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    } else {
+        String::new()
+    };
+    let lines = read_raw_rows(&path)?
+        .into_iter()
+        .filter(|row| {
+            let day = row.timestamp.with_timezone(&tz).date_naive();
+            (range.from..=range.to).contains(&day)
+        })
+        .map(|row| match range.timestamps.format(row.timestamp, tz) {
+            // Timestamps never contain a comma, so the first one ends the column.
+            Some(timestamp) => match row.line.find(',') {
+                Some(comma) => format!("{timestamp}{}", &row.line[comma..]),
+                None => timestamp,
+            },
+            None => row.line,
+        })
+        .collect::<Vec<_>>();
+
+    let mut content = header;
+    for line in &lines {
+        content.push('\n');
+        content.push_str(line);
+    }
+    content.push('\n');
+    Ok(ExportFile {
+        file_name: format!("{}_{}_{}.csv", range.target.name(), range.from, range.to),
+        content,
+        rows: lines.len(),
+    })
+}
+
+/// Glucose and weight in the `HealthData` XML layout of Apple Health's `export.xml`.
+/// Glucose is converted to mg/dL, the unit Health records blood glucose in, and the
+/// meal tag becomes the `HKBloodGlucoseMealTime` metadata entry.
+pub(super) fn health_export(
+    data_dir: &Path,
+    chat_id: ChatId,
+    tz: Tz,
+    period: Option<(NaiveDate, NaiveDate)>,
+) -> anyhow::Result<ExportFile> {
+    let in_period = |timestamp: &DateTime<Utc>| {
+        let day = timestamp.with_timezone(&tz).date_naive();
+        period.is_none_or(|(from, to)| (from..=to).contains(&day))
+    };
+    let glucose = read_records::<GlucoseRecord>(data_dir, chat_id)?.rows;
+    let weights = read_records::<WeightRecord>(data_dir, chat_id)?.rows;
+
+    let mut content =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<HealthData locale=\"en_US\">\n");
+    let mut rows = 0;
+    for record in glucose.iter().filter(|record| in_period(&record.timestamp)) {
+        let value = GlucoseUnit::Mgdl.format_stored(record.value);
+        let meal_time = match GlucoseTag::from_csv_tag(&record.tag) {
+            Some(GlucoseTag::BeforeMeal) => Some(1),
+            Some(GlucoseTag::AfterMeal) => Some(2),
+            None => None,
+        };
+        content.push_str(&health_record(
+            "HKQuantityTypeIdentifierBloodGlucose",
+            "mg/dL",
+            &value,
+            record.timestamp,
+            tz,
+        ));
+        match meal_time {
+            Some(meal_time) => content.push_str(&format!(
+                ">\n  <MetadataEntry key=\"HKBloodGlucoseMealTime\" value=\"{meal_time}\"/>\n </Record>\n"
+            )),
+            None => content.push_str("/>\n"),
+        }
+        rows += 1;
+    }
+    for record in weights.iter().filter(|record| in_period(&record.timestamp)) {
+        content.push_str(&health_record(
+            "HKQuantityTypeIdentifierBodyMass",
+            "kg",
+            &record.value.to_string(),
+            record.timestamp,
+            tz,
+        ));
+        content.push_str("/>\n");
+        rows += 1;
+    }
+    content.push_str("</HealthData>\n");
+
+    let file_name = match period {
+        Some((from, to)) => format!("apple_health_{from}_{to}.xml"),
+        None => "apple_health.xml".to_string(),
+    };
+    Ok(ExportFile {
+        file_name,
+        content,
+        rows,
+    })
+}
+
+/// Opening `<Record` element without its closing `/>` or `>`.
+fn health_record(kind: &str, unit: &str, value: &str, timestamp: DateTime<Utc>, tz: Tz) -> String {
+    let date = timestamp.with_timezone(&tz).format(HEALTH_DATE_FORMAT);
+    format!(
+        " <Record type=\"{kind}\" sourceName=\"{HEALTH_SOURCE_NAME}\" unit=\"{unit}\" \
+creationDate=\"{date}\" startDate=\"{date}\" endDate=\"{date}\" value=\"{value}\""
+    )
+}
+
+/// Measurement file in the long export: where its value, tag and note are.
+struct LongSource {
+    file: &'static str,
+    metric: &'static str,
+    unit: &'static str,
+    value: usize,
+    tag: Option<usize>,
+    note: Option<usize>,
+}
+
+const LONG_SOURCES: [LongSource; 4] = [
+    LongSource {
+        file: GLUCOSE_FILE,
+        metric: "glucose",
+        unit: "mmol/L",
+        value: 3,
+        tag: Some(2),
+        note: Some(GLUCOSE_NOTE_COLUMN),
+    },
+    LongSource {
+        file: WEIGHT_FILE,
+        metric: "weight",
+        unit: "kg",
+        value: 2,
+        tag: None,
+        note: None,
+    },
+    LongSource {
+        file: KETONES_FILE,
+        metric: "ketones",
+        unit: "mmol/L",
+        value: 2,
+        tag: None,
+        note: Some(KETONES_NOTE_COLUMN),
+    },
+    LongSource {
+        file: INSULIN_FILE,
+        metric: "insulin",
+        unit: "U",
+        value: 2,
+        tag: Some(INSULIN_TYPE_COLUMN),
+        note: Some(3),
+    },
+];
+
+/// Value of `row` as exported: glucose stored in mg/dL is converted to mmol/L, other
+/// values are kept as written. `None` when the value is not a number.
+fn long_value(source: &LongSource, row: &RawRow) -> Option<String> {
+    let raw = row.fields.get(source.value)?;
+    let value = raw.parse::<f64>().ok()?;
+    if source.file == GLUCOSE_FILE {
+        let unit = glucose_unit_field(&row.fields).ok()?;
+        return Some(GlucoseUnit::Mmol.format_stored(unit.to_mmol_l(value)));
+    }
+    Some(raw.clone())
+}
+
+/// Glucose, weight, ketones and insulin in one long-format CSV
+/// (`timestamp,metric,value,unit,tag,note`) sorted by time, for pivoting in other tools.
+/// Timestamps are UTC unless `timestamps` asks for local time; missing files add no rows
+/// and rows without a numeric value are left out.
+pub(super) fn long_export(
+    data_dir: &Path,
+    chat_id: ChatId,
+    tz: Tz,
+    period: Option<(NaiveDate, NaiveDate)>,
+    timestamps: ExportTimestamps,
+) -> anyhow::Result<ExportFile> {
+    let dir = user_data_dir(data_dir, chat_id);
+    let mut rows = Vec::new();
+    for source in &LONG_SOURCES {
+        for row in read_raw_rows(&dir.join(source.file))? {
+            let day = row.timestamp.with_timezone(&tz).date_naive();
+            if period.is_some_and(|(from, to)| !(from..=to).contains(&day)) {
+                continue;
+            }
+            let Some(value) = long_value(source, &row) else {
+                continue;
+            };
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|column| row.fields.get(column))
+                    .map_or("", String::as_str)
+            };
+            let line = format!(
+                "{},{},{value},{},{},\"{}\"",
+                timestamps
+                    .format(row.timestamp, tz)
+                    .unwrap_or_else(|| row.timestamp.to_rfc3339_opts(SecondsFormat::Secs, false)),
+                source.metric,
+                source.unit,
+                field(source.tag),
+                csv_escape(field(source.note))
+            );
+            rows.push((row.timestamp, line));
+        }
+    }
+    rows.sort_by_key(|(timestamp, _)| *timestamp);
+
+    let mut content = format!("{LONG_HEADER}\n");
+    for (_, line) in &rows {
+        content.push_str(line);
+        content.push('\n');
+    }
+    let file_name = match period {
+        Some((from, to)) => format!("long_{from}_{to}.csv"),
+        None => "long.csv".to_string(),
+    };
+    Ok(ExportFile {
+        file_name,
+        content,
+        rows: rows.len(),
+    })
+}
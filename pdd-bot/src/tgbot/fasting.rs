@@ -0,0 +1,94 @@
+use super::records::{parse_timestamp, read_raw_rows};
+use super::*;
+
+const FASTING_HEADER: &str = "timestamp,chat_id,ended_at,duration_minutes";
+const FASTING_DURATION_COLUMN: usize = 3;
+pub(super) const FAST_USAGE: &str = "Usage: /fast <start|end|status>";
+
+/// Whole hours and minutes of `duration`, e.g. `16h 5m`.
+fn format_duration(duration: chrono::Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    format!("{}h {}m", minutes / 60, minutes % 60)
+}
+
+/// Reply to `/fast`. The start of an ongoing fast is kept in the chat's settings so it
+/// survives a restart; `/fast end` moves it to a row of [`FASTING_FILE`] with the
+/// duration in minutes.
+pub(super) fn fast_command(
+    state: &AppState,
+    chat_id: ChatId,
+    payload: &str,
+) -> anyhow::Result<String> {
+    let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+    let started = user_settings
+        .fast_started_at
+        .as_deref()
+        .and_then(parse_timestamp);
+    let options = state.input_for(chat_id);
+    let now = state.now();
+    let local = |time: DateTime<Utc>| time.with_timezone(&options.tz).format("%Y-%m-%d %H:%M");
+
+    let reply = match (payload.to_lowercase().as_str(), started) {
+        ("start", Some(started)) => format!(
+            "A fast is already running since {} ({}). End it with /fast end.",
+            local(started),
+            format_duration(now - started)
+        ),
+        ("start", None) => {
+            user_settings.fast_started_at = Some(now.to_rfc3339_opts(SecondsFormat::Secs, false));
+            settings::save_settings(state, chat_id, &user_settings)?;
+            format!("Fast started at {} ✅ End it with /fast end.", local(now))
+        }
+        ("end", None) => "No fast is running. Start one with /fast start.".to_string(),
+        ("end", Some(started)) => {
+            let file = user_data_dir(&state.data_dir, chat_id).join(FASTING_FILE);
+            append_line_if_needed(&file, FASTING_HEADER, options.sync_writes)?;
+            let minutes = (now - started).num_minutes().max(0);
+            append_csv_line(
+                &file,
+                &format!(
+                    "{},{},{},{}",
+                    options.format_timestamp(started),
+                    chat_id.0,
+                    options.format_timestamp(now),
+                    minutes
+                ),
+                options.sync_writes,
+            )?;
+            log_saved_entry(&options, chat_id, "fast", Some(minutes as f64), None, None);
+            user_settings.fast_started_at = None;
+            settings::save_settings(state, chat_id, &user_settings)?;
+            format!(
+                "Fast ended ✅ {} (since {}).",
+                format_duration(now - started),
+                local(started)
+            )
+        }
+        ("status" | "", Some(started)) => format!(
+            "Fasting for {} since {}.",
+            format_duration(now - started),
+            local(started)
+        ),
+        ("status" | "", None) => {
+            let file = user_data_dir(&state.data_dir, chat_id).join(FASTING_FILE);
+            let last = read_raw_rows(&file)?
+                .into_iter()
+                .max_by_key(|row| row.timestamp);
+            let minutes = last.as_ref().and_then(|row| {
+                row.fields
+                    .get(FASTING_DURATION_COLUMN)
+                    .and_then(|raw| raw.parse::<i64>().ok())
+            });
+            match (last, minutes) {
+                (Some(row), Some(minutes)) => format!(
+                    "No fast is running. Last fast: {} from {}.",
+                    format_duration(chrono::Duration::minutes(minutes)),
+                    local(row.timestamp)
+                ),
+                _ => "No fast is running. Start one with /fast start.".to_string(),
+            }
+        }
+        _ => FAST_USAGE.to_string(),
+    };
+    Ok(reply)
+}
@@ -0,0 +1,173 @@
+use super::records::{GlucoseRecord, WeightRecord};
+use super::settings::UserSettings;
+use super::{GlucoseTag, normalize_medication_name, reports};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+
+pub(super) const GOALS_DAYS: i64 = 7;
+pub(super) const AVERAGE_GOAL_MAX_PERCENT: f64 = 50.0;
+
+/// `/goals` reply: glucose time in range, weight goal progress and medication adherence
+/// over the last [`GOALS_DAYS`] days, each with a hint when it is not set up.
+/// `med_log` holds the time and medication name of each logged use.
+pub(super) fn goals_report(
+    settings: &UserSettings,
+    glucose: &[GlucoseRecord],
+    weights: &[WeightRecord],
+    medications: &[String],
+    med_log: &[(DateTime<Utc>, String)],
+    tz: Tz,
+    now: DateTime<Utc>,
+) -> String {
+    let since = now - Duration::days(GOALS_DAYS);
+    [
+        format!("Goals (last {GOALS_DAYS} days):"),
+        glucose_line(settings, glucose, tz, since, now),
+        average_line(settings, glucose, since, now),
+        weight_line(settings, weights),
+        medication_line(settings, medications, med_log, since, now),
+    ]
+    .join("\n")
+}
+
+fn glucose_line(
+    settings: &UserSettings,
+    glucose: &[GlucoseRecord],
+    tz: Tz,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> String {
+    let custom = settings.target_range.is_some()
+        || settings.before_meal_range.is_some()
+        || settings.after_meal_range.is_some()
+        || !settings.time_ranges.is_empty();
+    let hint = if custom {
+        String::new()
+    } else {
+        format!(
+            " Range not set, using {} mmol/L; set yours with /setrange.",
+            settings.general_range()
+        )
+    };
+    let checked = glucose
+        .iter()
+        .filter(|record| record.timestamp > since && record.timestamp <= now)
+        .filter_map(|record| {
+            let tag = GlucoseTag::from_csv_tag(&record.tag)?;
+            let (range, _) = settings.range_at(tag, record.timestamp.with_timezone(&tz).time());
+            Some((range.low..=range.high).contains(&record.value))
+        })
+        .collect::<Vec<_>>();
+    if checked.is_empty() {
+        return format!("🎯 Glucose in range: no readings.{hint}");
+    }
+    let in_range = checked.iter().filter(|in_range| **in_range).count();
+    format!(
+        "🎯 Glucose in range: {:.0}% of {} readings.{hint}",
+        in_range as f64 / checked.len() as f64 * 100.0,
+        checked.len()
+    )
+}
+
+/// Average glucose and reading count in `(since, now]`, or `None` without readings.
+pub(super) fn window_average(
+    glucose: &[GlucoseRecord],
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Option<(f64, usize)> {
+    let values = glucose
+        .iter()
+        .filter(|record| record.timestamp > since && record.timestamp <= now)
+        .map(|record| record.value)
+        .collect::<Vec<_>>();
+    (!values.is_empty()).then(|| {
+        (
+            values.iter().sum::<f64>() / values.len() as f64,
+            values.len(),
+        )
+    })
+}
+
+/// Current average against the `/avggoal` baseline, with the share of the way to the
+/// target; an average above the baseline counts as 0%.
+fn average_line(
+    settings: &UserSettings,
+    glucose: &[GlucoseRecord],
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> String {
+    let Some(goal) = &settings.average_goal else {
+        return "📉 Average: not set. Use /avggoal <percent>.".to_string();
+    };
+    let target = goal.target();
+    let Some((average, _)) = window_average(glucose, since, now) else {
+        return format!(
+            "📉 Average: goal -{:.0}% from {:.1} to {target:.1} mmol/L, no readings.",
+            goal.percent, goal.baseline
+        );
+    };
+    let change = (average - goal.baseline) / goal.baseline * 100.0;
+    let status = if average <= target {
+        "reached 🎉".to_string()
+    } else {
+        let progress = ((goal.baseline - average) / (goal.baseline - target)).clamp(0.0, 1.0);
+        format!("{:.0}% of the way", progress * 100.0)
+    };
+    format!(
+        "📉 Average: {average:.1} mmol/L, {change:+.1}% from {:.1}; goal -{:.0}% ({target:.1} mmol/L) {status}",
+        goal.baseline, goal.percent
+    )
+}
+
+fn weight_line(settings: &UserSettings, weights: &[WeightRecord]) -> String {
+    let Some(goal) = settings.weight_goal else {
+        return "⚖️ Weight: not set. Use /weightgoal <kg>.".to_string();
+    };
+    let start = weights.iter().min_by_key(|record| record.timestamp);
+    let current = weights.iter().max_by_key(|record| record.timestamp);
+    match (start, current) {
+        (Some(start), Some(current)) => format!(
+            "⚖️ Weight: {}",
+            reports::weight_goal_progress(start.value, current.value, goal)
+        ),
+        _ => format!("⚖️ Weight: goal {goal:.1} kg, no weight entries yet."),
+    }
+}
+
+/// Logged uses against the expected daily doses of every medication with `/medtime`
+/// times, capped at 100%.
+fn medication_line(
+    settings: &UserSettings,
+    medications: &[String],
+    med_log: &[(DateTime<Utc>, String)],
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> String {
+    let scheduled = medications
+        .iter()
+        .filter_map(|name| {
+            let doses = settings.med_times.get(name)?.len();
+            (doses > 0).then_some((name, doses))
+        })
+        .collect::<Vec<_>>();
+    if scheduled.is_empty() {
+        return "💊 Medications: not set. Use /medtime <name> <HH:MM> for daily doses.".to_string();
+    }
+    let parts = scheduled
+        .into_iter()
+        .map(|(name, doses)| {
+            let expected = doses * GOALS_DAYS as usize;
+            let taken = med_log
+                .iter()
+                .filter(|(timestamp, _)| *timestamp > since && *timestamp <= now)
+                .filter(|(_, logged)| normalize_medication_name(logged).eq_ignore_ascii_case(name))
+                .count()
+                .min(expected);
+            format!(
+                "{name} {taken}/{expected} ({:.0}%)",
+                taken as f64 / expected as f64 * 100.0
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("💊 Medications: {}", parts.join(", "))
+}
@@ -0,0 +1,685 @@
+use super::records::{RawRow, parse_csv_line, parse_timestamp, read_raw_rows, replace_field};
+use super::*;
+use std::time::Instant;
+use teloxide::types::InlineKeyboardButton;
+
+const LAST_DEFAULT_COUNT: usize = 10;
+const LAST_MAX_COUNT: usize = 50;
+const LAST_LISTING_TTL: Duration = Duration::from_secs(10 * 60);
+const LOWEST_MARK: &str = "🔻 lowest";
+const HIGHEST_MARK: &str = "🔺 highest";
+const HISTORY_COUNT: usize = 10;
+const STALE_ROW_REPLY: &str =
+    "That entry was changed or deleted since the list was shown. Send /history for a fresh list.";
+/// Hex digits of a line's SHA-256 kept in `/history` button data, which Telegram limits
+/// to 64 bytes.
+const ROW_HASH_LEN: usize = 16;
+
+/// Files listed by `/last`, newest entries first.
+const LISTED_FILES: [&str; 3] = [GLUCOSE_FILE, WEIGHT_FILE, KETONES_FILE];
+
+/// Row shown by the latest `/last`, identified by its file and exact line content.
+#[derive(Debug, Clone)]
+struct ListedRow {
+    file: &'static str,
+    line: String,
+    description: String,
+    /// Removed by `/del`; kept so the numbers of the other rows stay the same.
+    deleted: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct LastListing {
+    created: Instant,
+    rows: Vec<ListedRow>,
+}
+
+fn describe_row(file: &str, row: &RawRow, tz: Tz) -> String {
+    let when = row.timestamp.with_timezone(&tz).format("%m/%d %H:%M");
+    let field = |index: usize| row.fields.get(index).map_or("", String::as_str);
+    let with_note = |text: String, note: &str| {
+        if note.is_empty() {
+            text
+        } else {
+            format!("{text} @{note}")
+        }
+    };
+    match file {
+        GLUCOSE_FILE => {
+            let tag = field(2).replace('_', " ");
+            let unit = match field(GLUCOSE_UNIT_COLUMN) {
+                "mg/dL" => " mg/dL",
+                _ => "",
+            };
+            with_note(
+                format!("{when} glucose {}{unit} ({tag})", field(3)),
+                field(4),
+            )
+        }
+        WEIGHT_FILE => format!("{when} weight {} kg", field(2)),
+        KETONES_FILE => with_note(format!("{when} ketones {}", field(2)), field(3)),
+        _ => format!("{when} {}", row.line),
+    }
+}
+
+/// Glucose value of a `glucose.csv` row in mmol/L, whatever unit the row was stored in.
+fn glucose_mmol_l(row: &RawRow) -> Option<f64> {
+    let field = |index: usize| row.fields.get(index).map_or("", String::as_str);
+    let value = field(3).parse::<f64>().ok()?;
+    Some(settings::GlucoseUnit::from_csv_name(field(GLUCOSE_UNIT_COLUMN))?.to_mmol_l(value))
+}
+
+/// Positions of the lowest and highest glucose among `rows`, the first of equal values.
+/// `None` unless there are two different glucose values to compare.
+fn glucose_extremes(rows: &[(&str, RawRow)]) -> Option<(usize, usize)> {
+    let values = rows
+        .iter()
+        .enumerate()
+        .filter(|(_, (file, _))| *file == GLUCOSE_FILE)
+        .filter_map(|(index, (_, row))| glucose_mmol_l(row).map(|value| (index, value)))
+        .collect::<Vec<_>>();
+    let lowest = values
+        .iter()
+        .copied()
+        .reduce(|a, b| if b.1 < a.1 { b } else { a })?;
+    let highest = values
+        .iter()
+        .copied()
+        .reduce(|a, b| if b.1 > a.1 { b } else { a })?;
+    (lowest.1 < highest.1).then_some((lowest.0, highest.0))
+}
+
+pub(super) fn parse_last_args(args: &str) -> Result<usize, String> {
+    if args.is_empty() {
+        return Ok(LAST_DEFAULT_COUNT);
+    }
+    match args.parse::<usize>() {
+        Ok(count) if (1..=LAST_MAX_COUNT).contains(&count) => Ok(count),
+        _ => Err(format!("Usage: /last [1-{LAST_MAX_COUNT}]")),
+    }
+}
+
+/// The newest `count` rows of [`LISTED_FILES`], newest first.
+fn newest_rows(
+    state: &AppState,
+    chat_id: ChatId,
+    count: usize,
+) -> anyhow::Result<Vec<(&'static str, RawRow)>> {
+    let mut rows = Vec::new();
+    for file in LISTED_FILES {
+        let path = user_data_dir(&state.data_dir, chat_id).join(file);
+        rows.extend(read_raw_rows(&path)?.into_iter().map(|row| (file, row)));
+    }
+    rows.sort_by_key(|(_, row)| std::cmp::Reverse(row.timestamp));
+    rows.truncate(count);
+    Ok(rows)
+}
+
+/// Lists the newest `count` entries and remembers them for `/del`.
+pub(super) async fn last_entries(
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+    count: usize,
+) -> anyhow::Result<String> {
+    let rows = storage::blocking(state, move |state| newest_rows(state, chat_id, count)).await?;
+
+    if rows.is_empty() {
+        state.last_listing_by_chat.lock().await.remove(&chat_id);
+        return Ok("No entries yet.".to_string());
+    }
+
+    let extremes = glucose_extremes(&rows);
+    let listed = rows
+        .into_iter()
+        .map(|(file, row)| ListedRow {
+            file,
+            description: describe_row(file, &row, state.input_for(chat_id).tz),
+            line: row.line,
+            deleted: false,
+        })
+        .collect::<Vec<_>>();
+    let mut lines = vec![format!("Last {} entries:", listed.len())];
+    for (index, row) in listed.iter().enumerate() {
+        let mark = match extremes {
+            Some((lowest, _)) if lowest == index => format!(" {LOWEST_MARK}"),
+            Some((_, highest)) if highest == index => format!(" {HIGHEST_MARK}"),
+            _ => String::new(),
+        };
+        lines.push(format!("{}. {}{mark}", index + 1, row.description));
+    }
+    lines.push("Use /del <number> to delete an entry.".to_string());
+
+    state.last_listing_by_chat.lock().await.insert(
+        chat_id,
+        LastListing {
+            created: Instant::now(),
+            rows: listed,
+        },
+    );
+    Ok(lines.join("\n"))
+}
+
+/// Entry with 1-based `index` from the chat's latest `/last` listing, or the reply
+/// explaining why there is none; `command` names the command in that reply.
+async fn listed_row(
+    state: &AppState,
+    chat_id: ChatId,
+    index: usize,
+    command: &str,
+) -> Result<ListedRow, String> {
+    let mut lock = state.last_listing_by_chat.lock().await;
+    let Some(listing) = lock.get(&chat_id) else {
+        return Err(format!("Run /last first, then {command} <number>."));
+    };
+    if listing.created.elapsed() > LAST_LISTING_TTL {
+        lock.remove(&chat_id);
+        return Err("The /last listing is too old. Run /last again.".to_string());
+    }
+    match index.checked_sub(1).and_then(|i| listing.rows.get(i)) {
+        Some(row) if row.deleted => Err(format!("Entry {index} was already deleted.")),
+        Some(row) => Ok(row.clone()),
+        None => Err(format!(
+            "No entry number {index}. Choose 1-{}.",
+            listing.rows.len()
+        )),
+    }
+}
+
+/// Deletes the entry with 1-based `index` from the chat's latest `/last` listing.
+pub(super) async fn delete_listed_entry(
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+    index_raw: &str,
+) -> anyhow::Result<String> {
+    let Ok(index) = index_raw.parse::<usize>() else {
+        return Ok("Usage: /del <number from /last>".to_string());
+    };
+    let row = match listed_row(state, chat_id, index, "/del").await {
+        Ok(row) => row,
+        Err(reply) => return Ok(reply),
+    };
+
+    let (file, line) = (row.file, row.line.clone());
+    let removed = storage::blocking(state, move |state| {
+        let path = user_data_dir(&state.data_dir, chat_id).join(file);
+        remove_line(state, chat_id, &path, &line)
+    })
+    .await?;
+    if !removed {
+        return Ok("That entry no longer exists. Run /last again.".to_string());
+    }
+    if let Some(listing) = state.last_listing_by_chat.lock().await.get_mut(&chat_id)
+        && let Some(listed) = listing.rows.get_mut(index - 1)
+    {
+        // Rows are found by their line, so the other numbers stay valid.
+        listed.deleted = true;
+    }
+    tracing::info!("chat {} deleted a row from {}", chat_id.0, row.file);
+    Ok(format!("Deleted: {}", row.description))
+}
+
+fn note_column(file: &str) -> Option<usize> {
+    match file {
+        GLUCOSE_FILE => Some(GLUCOSE_NOTE_COLUMN),
+        KETONES_FILE => Some(KETONES_NOTE_COLUMN),
+        _ => None,
+    }
+}
+
+/// `/note [number] <text>`: sets the note of entry `number` of the latest `/last`
+/// listing, or of the newest glucose or ketone entry, replacing any earlier note. Only
+/// the note column of that row is rewritten.
+pub(super) async fn annotate_entry(
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+    payload: &str,
+) -> anyhow::Result<String> {
+    const USAGE: &str = "Usage: /note [number from /last] <text>";
+    let (index, note) = match payload.split_once(char::is_whitespace) {
+        Some((first, rest)) => match first.parse::<usize>() {
+            Ok(index) => (Some(index), rest.trim()),
+            Err(_) => (None, payload),
+        },
+        // A lone number is an index without text, not a note.
+        None if payload.parse::<usize>().is_ok() => return Ok(USAGE.to_string()),
+        None => (None, payload),
+    };
+    let note = note.strip_prefix('@').unwrap_or(note).trim();
+    if note.is_empty() {
+        return Ok(USAGE.to_string());
+    }
+    if let Err(err) = check_note_length(Some(note), state.input.note_max_len) {
+        return Ok(err.to_string());
+    }
+
+    let row = match index {
+        Some(index) => match listed_row(state, chat_id, index, "/note").await {
+            Ok(row) => row,
+            Err(reply) => return Ok(reply),
+        },
+        None => {
+            let newest = storage::blocking(state, move |state| {
+                let mut newest = None::<(&'static str, RawRow)>;
+                for file in [GLUCOSE_FILE, KETONES_FILE] {
+                    let path = user_data_dir(&state.data_dir, chat_id).join(file);
+                    for row in read_raw_rows(&path)? {
+                        if newest
+                            .as_ref()
+                            .is_none_or(|(_, latest)| row.timestamp >= latest.timestamp)
+                        {
+                            newest = Some((file, row));
+                        }
+                    }
+                }
+                Ok(newest)
+            })
+            .await?;
+            let Some((file, row)) = newest else {
+                return Ok("No glucose or ketone entries to annotate yet.".to_string());
+            };
+            ListedRow {
+                file,
+                description: String::new(),
+                line: row.line,
+                deleted: false,
+            }
+        }
+    };
+    let Some(column) = note_column(row.file) else {
+        return Ok("Only glucose and ketone entries have notes.".to_string());
+    };
+
+    let updated = replace_field(&row.line, column, &format!("\"{}\"", csv_escape(note)));
+    let (file, line, replacement) = (row.file, row.line.clone(), updated.clone());
+    let replaced = storage::blocking(state, move |state| {
+        let path = user_data_dir(&state.data_dir, chat_id).join(file);
+        replace_line(state, chat_id, &path, &line, &replacement)
+    })
+    .await?;
+    if !replaced {
+        return Ok("That entry no longer exists. Run /last again.".to_string());
+    }
+    log_saved_row(&state.input, chat_id, row.file, &updated, Some(note));
+    let fields = parse_csv_line(&updated);
+    let Some(timestamp) = fields.first().and_then(|raw| parse_timestamp(raw)) else {
+        anyhow::bail!("annotated row has no timestamp");
+    };
+    let description = describe_row(
+        row.file,
+        &RawRow {
+            timestamp,
+            line: updated.clone(),
+            fields,
+        },
+        state.input_for(chat_id).tz,
+    );
+    if let Some(listing) = state.last_listing_by_chat.lock().await.get_mut(&chat_id)
+        && let Some(listed) = listing
+            .rows
+            .iter_mut()
+            .find(|listed| listed.file == row.file && listed.line == row.line)
+    {
+        // Indices do not shift, so the listing stays usable with the new line.
+        listed.line = updated;
+        listed.description = description.clone();
+    }
+    tracing::info!("chat {} annotated a row in {}", chat_id.0, row.file);
+    Ok(format!("Note updated: {description}"))
+}
+
+/// What a `/history` button does with its row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RowAction {
+    /// Shows the other actions for the row.
+    Open,
+    Value,
+    Time,
+    Delete,
+}
+
+impl RowAction {
+    const ALL: [RowAction; 4] = [
+        RowAction::Open,
+        RowAction::Value,
+        RowAction::Time,
+        RowAction::Delete,
+    ];
+
+    fn code(self) -> &'static str {
+        match self {
+            RowAction::Open => "o",
+            RowAction::Value => "v",
+            RowAction::Time => "t",
+            RowAction::Delete => "d",
+        }
+    }
+}
+
+/// Short names of [`LISTED_FILES`] in button data.
+const FILE_CODES: [(&str, &str); 3] =
+    [("g", GLUCOSE_FILE), ("w", WEIGHT_FILE), ("k", KETONES_FILE)];
+
+/// Data row identified by its file and a hash of its exact line, so a button keeps
+/// pointing at the same entry while other rows are added or removed, and stops matching
+/// once the row itself changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct RowRef {
+    file: &'static str,
+    hash: String,
+}
+
+impl RowRef {
+    fn new(file: &'static str, line: &str) -> Self {
+        RowRef {
+            file,
+            hash: line_hash(line),
+        }
+    }
+
+    /// Button data such as `h:o:g:0123456789abcdef`.
+    fn callback_data(&self, action: RowAction) -> String {
+        let file = FILE_CODES
+            .iter()
+            .find(|(_, file)| *file == self.file)
+            .map_or("", |(code, _)| *code);
+        format!("h:{}:{file}:{}", action.code(), self.hash)
+    }
+
+    fn parse(data: &str) -> Option<(RowAction, RowRef)> {
+        let mut parts = data.split(':');
+        if parts.next()? != "h" {
+            return None;
+        }
+        let action_code = parts.next()?;
+        let action = RowAction::ALL
+            .into_iter()
+            .find(|action| action.code() == action_code)?;
+        let file_code = parts.next()?;
+        let file = FILE_CODES
+            .iter()
+            .find(|(code, _)| *code == file_code)
+            .map(|(_, file)| *file)?;
+        let hash = parts.next()?;
+        if parts.next().is_some() || hash.len() != ROW_HASH_LEN {
+            return None;
+        }
+        Some((
+            action,
+            RowRef {
+                file,
+                hash: hash.to_string(),
+            },
+        ))
+    }
+
+    /// Current row, or `None` once no line of the file has this hash.
+    fn find(&self, state: &AppState, chat_id: ChatId) -> anyhow::Result<Option<RawRow>> {
+        let path = user_data_dir(&state.data_dir, chat_id).join(self.file);
+        Ok(read_raw_rows(&path)?
+            .into_iter()
+            .find(|row| line_hash(&row.line) == self.hash))
+    }
+}
+
+fn line_hash(line: &str) -> String {
+    checksum::sha256_hex(line.as_bytes())[..ROW_HASH_LEN].to_string()
+}
+
+/// Column a `/history` edit rewrites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RowField {
+    Value,
+    Timestamp,
+}
+
+/// `/history` edit waiting for the new value or time as the next message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct RowEdit {
+    row: RowRef,
+    pub(super) field: RowField,
+}
+
+fn value_column(file: &str) -> usize {
+    match file {
+        GLUCOSE_FILE => 3,
+        _ => 2,
+    }
+}
+
+/// `/history`: the newest entries as inline buttons, or `None` when there are none.
+pub(super) fn history_listing(
+    state: &AppState,
+    chat_id: ChatId,
+) -> anyhow::Result<Option<(String, InlineKeyboardMarkup)>> {
+    let rows = newest_rows(state, chat_id, HISTORY_COUNT)?;
+    if rows.is_empty() {
+        return Ok(None);
+    }
+    let tz = state.input_for(chat_id).tz;
+    let buttons = rows
+        .iter()
+        .map(|(file, row)| {
+            vec![InlineKeyboardButton::callback(
+                describe_row(file, row, tz),
+                RowRef::new(file, &row.line).callback_data(RowAction::Open),
+            )]
+        })
+        .collect::<Vec<_>>();
+    let text = format!(
+        "Last {} entries. Tap one to change its value or time, or to delete it.",
+        rows.len()
+    );
+    Ok(Some((text, InlineKeyboardMarkup::new(buttons))))
+}
+
+/// Reply to a tap on a `/history` button, with the action buttons after
+/// [`RowAction::Open`]. Every action looks the row up again, so a row changed or deleted
+/// since the buttons were sent is reported instead of touched.
+pub(super) async fn history_callback(
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+    data: &str,
+) -> anyhow::Result<(String, Option<InlineKeyboardMarkup>)> {
+    let Some((action, row_ref)) = RowRef::parse(data) else {
+        return Ok((
+            "This button is no longer valid. Send /history again.".to_string(),
+            None,
+        ));
+    };
+    let found = row_ref.clone();
+    let row = storage::blocking(state, move |state| found.find(state, chat_id)).await?;
+    let Some(row) = row else {
+        return Ok((STALE_ROW_REPLY.to_string(), None));
+    };
+    let description = describe_row(row_ref.file, &row, state.input_for(chat_id).tz);
+    let edit = |field| {
+        PendingEntry::EditRow(RowEdit {
+            row: row_ref.clone(),
+            field,
+        })
+    };
+    let reply = match action {
+        RowAction::Open => {
+            let button = |label: &str, action| {
+                InlineKeyboardButton::callback(label, row_ref.callback_data(action))
+            };
+            let buttons = InlineKeyboardMarkup::new([[
+                button("✏️ Value", RowAction::Value),
+                button("🕒 Time", RowAction::Time),
+                button("🗑 Delete", RowAction::Delete),
+            ]]);
+            return Ok((description, Some(buttons)));
+        }
+        RowAction::Value => {
+            set_pending(state, chat_id, edit(RowField::Value)).await;
+            let example = match (row_ref.file, state.input_for(chat_id).glucose_unit) {
+                (WEIGHT_FILE, _) => "78.4",
+                (KETONES_FILE, _) => "0.6",
+                (_, settings::GlucoseUnit::Mgdl) => "104 (mg/dL)",
+                (_, settings::GlucoseUnit::Mmol) => "5.8 (mmol/L)",
+            };
+            format!("Send the new value for: {description}\nFor example: {example}")
+        }
+        RowAction::Time => {
+            set_pending(state, chat_id, edit(RowField::Timestamp)).await;
+            format!(
+                "Send the new date and time for: {description}\nFor example: 2/1 9:05 or yesterday 9:05"
+            )
+        }
+        RowAction::Delete => {
+            let (file, line) = (row_ref.file, row.line);
+            let removed = storage::blocking(state, move |state| {
+                let path = user_data_dir(&state.data_dir, chat_id).join(file);
+                remove_line(state, chat_id, &path, &line)
+            })
+            .await?;
+            if !removed {
+                return Ok((STALE_ROW_REPLY.to_string(), None));
+            }
+            tracing::info!("chat {} deleted a row from {}", chat_id.0, row_ref.file);
+            format!("Deleted: {description}")
+        }
+    };
+    Ok((reply, None))
+}
+
+/// Applies `text`, the reply to a `/history` value or time prompt, to the row of `edit`.
+/// Only that column of the row is rewritten.
+pub(super) fn apply_row_edit(
+    state: &AppState,
+    chat_id: ChatId,
+    edit: &RowEdit,
+    text: &str,
+) -> anyhow::Result<String> {
+    let Some(row) = edit.row.find(state, chat_id)? else {
+        return Ok(STALE_ROW_REPLY.to_string());
+    };
+    let file = edit.row.file;
+    let options = state.input_for(chat_id);
+    let (column, value) = match edit.field {
+        RowField::Value => {
+            let parsed = match file {
+                GLUCOSE_FILE => {
+                    parse_entered_glucose(text.trim(), options.glucose_unit).map(|value| {
+                        let unit = row
+                            .fields
+                            .get(GLUCOSE_UNIT_COLUMN)
+                            .and_then(|name| settings::GlucoseUnit::from_csv_name(name))
+                            .unwrap_or_default();
+                        let value = settings_for_saving(&state.data_dir, chat_id)
+                            .round_glucose(value, unit);
+                        unit.format_stored(value)
+                    })
+                }
+                WEIGHT_FILE | KETONES_FILE => {
+                    let metric = match file {
+                        WEIGHT_FILE => settings::PrecisionMetric::Weight,
+                        _ => settings::PrecisionMetric::Ketones,
+                    };
+                    parse_decimal(text)
+                        .map(|value| {
+                            round_for_chat(&state.data_dir, chat_id, metric, value).to_string()
+                        })
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "could not parse number. Use format like 78.4 (dot or comma)."
+                            )
+                        })
+                }
+                _ => anyhow::bail!("no value column in {file}"),
+            };
+            match parsed {
+                Ok(value) => (value_column(file), value),
+                Err(err) => return Ok(format!("Edit not applied: {err}")),
+            }
+        }
+        RowField::Timestamp => match parse_flexible_datetime(text, &options) {
+            Some(dt) => (0, options.format_timestamp(dt)),
+            None => {
+                return Ok(
+                    "Edit not applied: invalid date/time. Examples: 2/1 9:05, yesterday 9:05"
+                        .to_string(),
+                );
+            }
+        },
+    };
+
+    let updated = replace_field(&row.line, column, &value);
+    let path = user_data_dir(&state.data_dir, chat_id).join(file);
+    if !replace_line(state, chat_id, &path, &row.line, &updated)? {
+        return Ok(STALE_ROW_REPLY.to_string());
+    }
+    log_saved_row(&options, chat_id, file, &updated, None);
+    let fields = parse_csv_line(&updated);
+    let Some(timestamp) = fields.first().and_then(|raw| parse_timestamp(raw)) else {
+        anyhow::bail!("edited row has no timestamp");
+    };
+    let description = describe_row(
+        file,
+        &RawRow {
+            timestamp,
+            line: updated,
+            fields,
+        },
+        options.tz,
+    );
+    tracing::info!("chat {} edited a row in {file}", chat_id.0);
+    Ok(format!("Entry updated ✅ {description}"))
+}
+
+/// Replaces the first data line equal to `line` with `replacement`, holding the chat's
+/// file lock. Returns `false` if it is not present.
+fn replace_line(
+    state: &AppState,
+    chat_id: ChatId,
+    path: &Path,
+    line: &str,
+    replacement: &str,
+) -> anyhow::Result<bool> {
+    state.rewrite_files(chat_id, || {
+        if !path.exists() {
+            return Ok(false);
+        }
+        let content = fs_err::read_to_string(path)?;
+        let mut result = String::with_capacity(content.len() + replacement.len());
+        let mut replaced = false;
+        for (index, existing) in content.lines().enumerate() {
+            if index > 0 && !replaced && existing == line {
+                replaced = true;
+                result.push_str(replacement);
+            } else {
+                result.push_str(existing);
+            }
+            result.push('\n');
+        }
+        if replaced {
+            write_file_atomically(path, &result, state.input.sync_writes)?;
+        }
+        Ok(replaced)
+    })
+}
+
+/// Removes the first data line equal to `line`, holding the chat's file lock. Returns
+/// `false` if it is not present.
+fn remove_line(state: &AppState, chat_id: ChatId, path: &Path, line: &str) -> anyhow::Result<bool> {
+    state.rewrite_files(chat_id, || {
+        if !path.exists() {
+            return Ok(false);
+        }
+        let content = fs_err::read_to_string(path)?;
+        let mut result = String::with_capacity(content.len());
+        let mut removed = false;
+        for (index, existing) in content.lines().enumerate() {
+            if index > 0 && !removed && existing == line {
+                removed = true;
+                continue;
+            }
+            result.push_str(existing);
+            result.push('\n');
+        }
+        if removed {
+            write_file_atomically(path, &result, state.input.sync_writes)?;
+        }
+        Ok(removed)
+    })
+}
@@ -0,0 +1,285 @@
+use super::records::{InsulinRecord, read_records};
+use super::*;
+
+pub(super) const INSULIN_HEADER: &str = "timestamp,chat_id,units,note,message_id,type";
+pub(super) const INSULIN_TYPE_COLUMN: usize = 5;
+pub(super) const DEFAULT_IOB_DURATION_MINUTES: u64 = 240;
+const IOB_DURATION_MIN_MINUTES: u64 = 60;
+const IOB_DURATION_MAX_MINUTES: u64 = 720;
+const INSULIN_MAX_UNITS: f64 = 100.0;
+/// Peak of the bilinear activity curve: 75 minutes of a 180-minute action, scaled.
+const BILINEAR_PEAK_FRACTION: f64 = 75.0 / 180.0;
+const DISCLAIMER: &str =
+    "⚠️ Estimate only, not for dosing decisions. Absorption varies with site, dose and activity.";
+
+/// Why a rapid insulin dose was taken. Rows written before the `type` column are meal
+/// doses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum InsulinKind {
+    #[default]
+    Meal,
+    Correction,
+}
+
+impl InsulinKind {
+    const ALL: [InsulinKind; 2] = [InsulinKind::Meal, InsulinKind::Correction];
+
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Word used in commands, replies and the `type` column.
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            InsulinKind::Meal => "meal",
+            InsulinKind::Correction => "correction",
+        }
+    }
+
+    pub(super) fn from_csv_name(name: &str) -> Option<Self> {
+        match name {
+            "" => Some(InsulinKind::Meal),
+            name => Self::ALL.into_iter().find(|kind| kind.name() == name),
+        }
+    }
+}
+
+/// Parsed `/addinsulin` payload.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct InsulinDose {
+    pub(super) units: f64,
+    pub(super) kind: InsulinKind,
+    pub(super) timestamp: Option<String>,
+    pub(super) note: Option<String>,
+}
+
+/// How the activity of a rapid insulin dose is assumed to fade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(super) enum IobModel {
+    /// Constant activity until the end of the duration of action.
+    Linear,
+    /// Activity rises to a peak and falls back to zero, as in the OpenAPS bilinear curve.
+    #[default]
+    Bilinear,
+}
+
+impl IobModel {
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "linear" => Some(IobModel::Linear),
+            "bilinear" => Some(IobModel::Bilinear),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            IobModel::Linear => "linear",
+            IobModel::Bilinear => "bilinear",
+        }
+    }
+}
+
+/// Insulin-on-board model set by `iob_model` and `iob_duration_minutes`.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct IobOptions {
+    pub(super) model: IobModel,
+    /// Duration of insulin action; a dose older than this has no insulin left.
+    pub(super) duration_minutes: u64,
+}
+
+impl Default for IobOptions {
+    fn default() -> Self {
+        IobOptions {
+            model: IobModel::default(),
+            duration_minutes: DEFAULT_IOB_DURATION_MINUTES,
+        }
+    }
+}
+
+impl IobOptions {
+    pub(super) fn validate(&self) -> anyhow::Result<()> {
+        if !(IOB_DURATION_MIN_MINUTES..=IOB_DURATION_MAX_MINUTES).contains(&self.duration_minutes) {
+            anyhow::bail!(
+                "iob_duration_minutes must be between {IOB_DURATION_MIN_MINUTES} and {IOB_DURATION_MAX_MINUTES}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Share of a dose still active `elapsed` after it was taken, from 1.0 down to 0.0.
+    pub(super) fn remaining_fraction(&self, elapsed: chrono::Duration) -> f64 {
+        let duration = self.duration_minutes as f64;
+        let t = elapsed.num_seconds() as f64 / 60.0;
+        if t <= 0.0 {
+            return 1.0;
+        }
+        if t >= duration {
+            return 0.0;
+        }
+        match self.model {
+            IobModel::Linear => 1.0 - t / duration,
+            IobModel::Bilinear => {
+                // The activity triangle has unit area; the remainder is the area after `t`.
+                let peak = duration * BILINEAR_PEAK_FRACTION;
+                if t <= peak {
+                    1.0 - t * t / (peak * duration)
+                } else {
+                    (duration - t).powi(2) / (duration * (duration - peak))
+                }
+            }
+        }
+    }
+}
+
+/// Parses `<units> [meal|correction] [date time] [@note]` for a rapid insulin dose. A word
+/// after the units that is neither a type nor a date word such as `yesterday` is
+/// rejected rather than read as a date.
+pub(super) fn parse_insulin_payload(
+    payload: &str,
+    options: &InputOptions,
+) -> anyhow::Result<InsulinDose> {
+    let payload = payload.trim();
+    let (units_raw, rest) = payload
+        .split_once(char::is_whitespace)
+        .unwrap_or((payload, ""));
+    if units_raw.is_empty() || units_raw.starts_with('@') {
+        return Err(anyhow::anyhow!("Missing insulin units"));
+    }
+    let units_raw = units_raw.strip_suffix(['u', 'U']).unwrap_or(units_raw);
+    let units = parse_decimal(units_raw)
+        .ok_or_else(|| anyhow::anyhow!("Invalid insulin units '{units_raw}'. Example: 4.5"))?;
+    if !(units > 0.0 && units <= INSULIN_MAX_UNITS) {
+        return Err(anyhow::anyhow!(
+            "Insulin units must be above 0 and at most {INSULIN_MAX_UNITS:.0} U"
+        ));
+    }
+    let rest = rest.trim_start();
+    let (word, after_word) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let (kind, rest) = match InsulinKind::parse(word) {
+        Some(kind) => (kind, after_word),
+        None if !word.is_empty()
+            && word.chars().all(char::is_alphabetic)
+            && relative_day_offset(word).is_none() =>
+        {
+            return Err(anyhow::anyhow!(
+                "Unknown insulin type '{word}'. Use {}.",
+                InsulinKind::ALL.map(InsulinKind::name).join(" or ")
+            ));
+        }
+        None => (InsulinKind::default(), rest),
+    };
+    let (timestamp, note) = parse_time_and_note(rest, options)?;
+    Ok(InsulinDose {
+        units,
+        kind,
+        timestamp,
+        note,
+    })
+}
+
+/// Saves a parsed rapid insulin dose to `insulin.csv` and returns the confirmation text.
+pub(super) fn save_insulin(
+    state: &AppState,
+    chat_id: ChatId,
+    message_id: MessageId,
+    InsulinDose {
+        units,
+        kind,
+        timestamp,
+        note,
+    }: InsulinDose,
+) -> anyhow::Result<String> {
+    let units = round_for_chat(
+        &state.data_dir,
+        chat_id,
+        settings::PrecisionMetric::Insulin,
+        units,
+    );
+    let file = user_data_dir(&state.data_dir, chat_id).join(INSULIN_FILE);
+    append_line_if_needed(&file, INSULIN_HEADER, state.input.sync_writes)?;
+    let ts = timestamp.unwrap_or_else(|| state.input_for(chat_id).now_timestamp());
+    append_csv_line(
+        &file,
+        &format!(
+            "{ts},{},{units},\"{}\",{},{}",
+            chat_id.0,
+            csv_escape(note.as_deref().unwrap_or("")),
+            message_id_field(Some(message_id)),
+            kind.name()
+        ),
+        state.input.sync_writes,
+    )?;
+    log_saved_entry(
+        &state.input,
+        chat_id,
+        "insulin",
+        Some(units),
+        Some(kind.name()),
+        note.as_deref(),
+    );
+    Ok(
+        save_confirmation(state, chat_id, settings::ConfirmMetric::Insulin).text(
+            |mark| match kind {
+                InsulinKind::Meal => format!("Insulin saved {mark} {units} U"),
+                InsulinKind::Correction => format!("Insulin saved {mark} {units} U correction"),
+            },
+        ),
+    )
+}
+
+/// `/iob` reply: the estimated insulin on board at `now` with one line per active dose.
+pub(super) fn iob_report(
+    state: &AppState,
+    chat_id: ChatId,
+    now: DateTime<Utc>,
+) -> anyhow::Result<String> {
+    let options = &state.iob;
+    let doses = read_records::<InsulinRecord>(&state.data_dir, chat_id)?.rows;
+    let active = doses
+        .iter()
+        .filter(|dose| dose.timestamp <= now)
+        .map(|dose| {
+            (
+                dose,
+                dose.units * options.remaining_fraction(now - dose.timestamp),
+            )
+        })
+        .filter(|(_, left)| *left > 0.0)
+        .collect::<Vec<_>>();
+    let total = active.iter().fold(0.0, |total, (_, left)| total + left);
+    let correction = active
+        .iter()
+        .filter(|(dose, _)| dose.kind == InsulinKind::Correction)
+        .fold(0.0, |total, (_, left)| total + left);
+    let hours = options.duration_minutes as f64 / 60.0;
+    let mut lines = vec![if correction > 0.0 {
+        format!("Insulin on board: {total:.1} U (estimate), of it correction: {correction:.1} U")
+    } else {
+        format!("Insulin on board: {total:.1} U (estimate)")
+    }];
+    if active.is_empty() {
+        lines.push(format!("No insulin doses in the last {hours} h."));
+    }
+    for (dose, left) in &active {
+        let kind = match dose.kind {
+            InsulinKind::Meal => "",
+            InsulinKind::Correction => " correction",
+        };
+        lines.push(format!(
+            "- {} U{kind} at {}: {left:.1} U left",
+            dose.units,
+            dose.timestamp
+                .with_timezone(&state.input_for(chat_id).tz)
+                .format("%m/%d %H:%M")
+        ));
+    }
+    lines.push(format!(
+        "Model: {}, {hours} h duration of action.",
+        options.model.name()
+    ));
+    lines.push(DISCLAIMER.to_string());
+    Ok(lines.join("\n"))
+}
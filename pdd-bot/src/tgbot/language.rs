@@ -0,0 +1,48 @@
+use super::plain::strip_emoji;
+use super::settings::Language;
+use super::{
+    BTN_FOOD, BTN_GLUCOSE_AFTER_MEAL, BTN_GLUCOSE_BEFORE_MEAL, BTN_KETONES, BTN_MORE_MEDS,
+    BTN_SHOW_MENU, BTN_WEIGHT,
+};
+
+/// Menu button labels in Ukrainian by their English label. Medication buttons only add
+/// an emoji to the name, so they are the same in every language.
+const UKRAINIAN_BUTTON_LABELS: [(&str, &str); 7] = [
+    (BTN_GLUCOSE_BEFORE_MEAL, "🩸 Глюкоза: до їжі"),
+    (BTN_GLUCOSE_AFTER_MEAL, "🩸 Глюкоза: після їжі"),
+    (BTN_WEIGHT, "⚖️ Вага"),
+    (BTN_KETONES, "🧪 Кетони"),
+    (BTN_FOOD, "🍽 Їжа"),
+    (BTN_SHOW_MENU, "📋 Показати меню"),
+    (BTN_MORE_MEDS, "📂 Ще ліки ▾"),
+];
+
+/// `label`, an English menu button label, as shown in `language`. Other labels are
+/// kept as they are.
+pub(super) fn localized_label(label: &str, language: Language) -> String {
+    let translated = match language {
+        Language::English => None,
+        Language::Ukrainian => UKRAINIAN_BUTTON_LABELS
+            .iter()
+            .find(|(english, _)| *english == label)
+            .map(|(_, translated)| *translated),
+    };
+    translated.unwrap_or(label).to_string()
+}
+
+/// English label of a translated menu button, with or without its emoji. Labels of every
+/// language are accepted whatever the chat chose, so a tap on a keyboard the client
+/// still shows from before `/setlanguage` is not lost.
+pub(super) fn button_label(text: &str) -> Option<&'static str> {
+    UKRAINIAN_BUTTON_LABELS
+        .iter()
+        .find(|(_, translated)| *translated == text || strip_emoji(translated) == text)
+        .map(|(english, _)| *english)
+}
+
+/// Translated menu button labels, which medication names must not repeat.
+pub(super) fn translated_labels() -> impl Iterator<Item = &'static str> {
+    UKRAINIAN_BUTTON_LABELS
+        .iter()
+        .map(|(_, translated)| *translated)
+}
@@ -0,0 +1,311 @@
+use super::records::{parse_csv_line, parse_timestamp, replace_field};
+use super::*;
+
+const STAGED_SUFFIX: &str = ".merge.tmp";
+
+/// Where `/merge` takes rows from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum MergeSource {
+    /// This chat's directory in the `data_dir` of another profile of the config file.
+    Profile(String),
+    /// Another chat's directory in this bot's `data_dir`; admins only.
+    Chat(ChatId),
+}
+
+pub(super) fn parse_merge_args(args: &str) -> Result<MergeSource, String> {
+    let parts = args.split_whitespace().collect::<Vec<_>>();
+    match parts.as_slice() {
+        [name] => Ok(match name.parse::<i64>() {
+            Ok(id) => MergeSource::Chat(ChatId(id)),
+            Err(_) => MergeSource::Profile(name.to_string()),
+        }),
+        _ => Err("Usage: /merge <profile|chat id>".to_string()),
+    }
+}
+
+/// Rows merged into one data file.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct FileMerge {
+    pub(super) target: ClearTarget,
+    pub(super) added: usize,
+    pub(super) duplicates: usize,
+    /// Copy of the file made before merging, in the user's data directory.
+    pub(super) backup: Option<String>,
+}
+
+/// `/merge` reply: checks who may merge from where, merges and summarizes the counts.
+pub(super) fn merge_reply(
+    state: &AppState,
+    chat_id: ChatId,
+    source: &MergeSource,
+    now: DateTime<Utc>,
+) -> anyhow::Result<String> {
+    let (source_dir, label) = match source {
+        MergeSource::Chat(source_chat) => {
+            if !state.admin_chat_ids.contains(&chat_id) {
+                return Ok("Only admins can merge another chat's data.".to_string());
+            }
+            (
+                user_data_dir(&state.data_dir, *source_chat),
+                format!("chat {}", source_chat.0),
+            )
+        }
+        MergeSource::Profile(name) => {
+            let Some(config_path) = &state.config_path else {
+                return Ok("Profiles are not available.".to_string());
+            };
+            let data_dir = match args::AppConfig::from_file_with_profile(config_path, Some(name)) {
+                Ok(config) => config
+                    .data_dir
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR)),
+                Err(err) => return Ok(format!("Cannot read profile '{name}': {err}")),
+            };
+            (
+                user_data_dir(&data_dir, chat_id),
+                format!("profile '{name}'"),
+            )
+        }
+    };
+    let target_dir = user_data_dir(&state.data_dir, chat_id);
+    if same_dir(&source_dir, &target_dir) {
+        return Ok(format!("Nothing to merge: {label} uses this chat's data."));
+    }
+    if !source_dir.is_dir() {
+        return Ok(format!(
+            "Nothing to merge: {label} has no data for this chat."
+        ));
+    }
+    let merges = match merge_dirs(
+        &source_dir,
+        &target_dir,
+        chat_id,
+        now,
+        state.input.sync_writes,
+    ) {
+        Ok(merges) => merges,
+        Err(err) => {
+            tracing::error!("merge into chat {} failed: {err:#}", chat_id.0);
+            return Ok(format!("Merge failed: {err}"));
+        }
+    };
+    if merges.is_empty() {
+        return Ok(format!("Nothing to merge: {label} has no entries."));
+    }
+    let added = merges.iter().map(|merge| merge.added).sum::<usize>();
+    let duplicates = merges.iter().map(|merge| merge.duplicates).sum::<usize>();
+    let mut lines = vec![format!(
+        "Merged from {label} ✅ {added} rows added, {duplicates} duplicates skipped."
+    )];
+    for merge in &merges {
+        lines.push(format!(
+            "- {}: {} added, {} duplicates",
+            merge.target.name(),
+            merge.added,
+            merge.duplicates
+        ));
+    }
+    let backups = merges
+        .iter()
+        .filter_map(|merge| merge.backup.as_deref())
+        .collect::<Vec<_>>();
+    if !backups.is_empty() {
+        lines.push(format!("Backups: {}", backups.join(", ")));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (fs_err::canonicalize(a), fs_err::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Merges the data files of `source_dir` into `target_dir`. Every merged file is
+/// computed first and each changed file is copied to `<file>.<timestamp>.bak`; the new
+/// contents are then staged next to their files and only renamed into place once all
+/// are written, so a failure before the renames leaves the data unchanged. The source
+/// is not modified.
+pub(super) fn merge_dirs(
+    source_dir: &Path,
+    target_dir: &Path,
+    chat_id: ChatId,
+    now: DateTime<Utc>,
+    sync: bool,
+) -> anyhow::Result<Vec<FileMerge>> {
+    let mut merges = Vec::new();
+    let mut writes = Vec::new();
+    for target in ClearTarget::ALL {
+        let source_path = source_dir.join(target.file_name());
+        if !source_path.exists() {
+            continue;
+        }
+        // Either side may come from an older version, so both are compared with the
+        // current header.
+        let upgrade = |content: String| upgraded_csv(&content, target.header()).unwrap_or(content);
+        let source = upgrade(fs_err::read_to_string(&source_path)?);
+        let path = target_dir.join(target.file_name());
+        let existing = if path.exists() {
+            Some(upgrade(fs_err::read_to_string(&path)?))
+        } else {
+            None
+        };
+        let Some((content, added, duplicates)) =
+            merge_csv(existing.as_deref(), &source, chat_id)
+                .map_err(|err| anyhow::anyhow!("{}: {err}", target.file_name()))?
+        else {
+            continue;
+        };
+        let backup = (existing.is_some() && added > 0).then(|| {
+            format!(
+                "{}.{}.bak",
+                target.file_name(),
+                now.format("%Y%m%dT%H%M%SZ")
+            )
+        });
+        merges.push(FileMerge {
+            target,
+            added,
+            duplicates,
+            backup,
+        });
+        if added > 0 {
+            writes.push((path, content));
+        }
+    }
+
+    fs_err::create_dir_all(target_dir)?;
+    for merge in &merges {
+        if let Some(backup) = &merge.backup {
+            fs_err::copy(
+                target_dir.join(merge.target.file_name()),
+                target_dir.join(backup),
+            )?;
+        }
+    }
+    let staged = writes
+        .iter()
+        .map(|(path, _)| {
+            let mut staged = path.as_os_str().to_owned();
+            staged.push(STAGED_SUFFIX);
+            PathBuf::from(staged)
+        })
+        .collect::<Vec<_>>();
+    let staging = writes
+        .iter()
+        .zip(&staged)
+        .try_for_each(|((_, content), staged)| write_staged(staged, content, sync));
+    if let Err(err) = staging {
+        for staged in &staged {
+            let _ = fs_err::remove_file(staged);
+        }
+        return Err(err);
+    }
+    for ((path, _), staged) in writes.iter().zip(&staged) {
+        fs_err::rename(staged, path)?;
+    }
+    if let Some((path, _)) = writes.first() {
+        sync_parent_dir(path, sync)?;
+    }
+    tracing::info!(
+        "chat {} merged {} rows from {}",
+        chat_id.0,
+        merges.iter().map(|merge| merge.added).sum::<usize>(),
+        source_dir.display()
+    );
+    Ok(merges)
+}
+
+fn write_staged(path: &Path, content: &str, sync: bool) -> anyhow::Result<()> {
+    use std::io::Write;
+    let mut file = fs_err::File::create(path)?;
+    file.write_all(content.as_bytes())?;
+    sync_if_enabled(&file, sync)?;
+    Ok(())
+}
+
+/// Merges the rows of `source` into `existing` and sorts them by timestamp. Source rows
+/// get this chat's id and no message id, since those belonged to the other chat or bot;
+/// one that then equals a row already present is skipped as a duplicate. Returns `None`
+/// when `source` has no rows, otherwise the new content with the added and skipped counts.
+fn merge_csv(
+    existing: Option<&str>,
+    source: &str,
+    chat_id: ChatId,
+) -> Result<Option<(String, usize, usize)>, String> {
+    let source_header = source.lines().next().unwrap_or("");
+    let source_rows = data_rows(source);
+    if source_rows.is_empty() {
+        return Ok(None);
+    }
+    let existing_header = existing.and_then(|content| content.lines().next());
+    let header = match existing_header {
+        None => source_header,
+        Some(header) if header.starts_with(source_header) => header,
+        Some(header) if source_header.starts_with(header) => source_header,
+        Some(header) => {
+            return Err(format!("columns differ ({header:?} and {source_header:?})"));
+        }
+    };
+    let columns = header.split(',').collect::<Vec<_>>();
+    let chat_column = columns.iter().position(|column| *column == "chat_id");
+    let message_column = columns.iter().position(|column| *column == "message_id");
+    let pad = |line: &str, from: &str| {
+        format!(
+            "{line}{}",
+            ",".repeat(header.matches(',').count() - from.matches(',').count())
+        )
+    };
+    let key = |line: &str| {
+        let mut fields = parse_csv_line(line);
+        if let Some(column) = message_column.filter(|&column| column < fields.len()) {
+            fields[column].clear();
+        }
+        fields
+    };
+
+    let mut rows = existing
+        .map(|content| {
+            data_rows(content)
+                .into_iter()
+                .map(|line| pad(line, existing_header.unwrap_or(header)))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let mut seen = rows.iter().map(|line| key(line)).collect::<HashSet<_>>();
+    let (mut added, mut duplicates) = (0, 0);
+    for line in source_rows {
+        let mut line = pad(line, source_header);
+        if let Some(column) = chat_column {
+            line = replace_field(&line, column, &chat_id.0.to_string());
+        }
+        if let Some(column) = message_column {
+            line = replace_field(&line, column, "");
+        }
+        if seen.insert(key(&line)) {
+            rows.push(line);
+            added += 1;
+        } else {
+            duplicates += 1;
+        }
+    }
+    rows.sort_by_cached_key(|line| {
+        let timestamp = line.split(',').next().and_then(parse_timestamp);
+        (timestamp.is_none(), timestamp)
+    });
+    let mut content = format!("{header}\n");
+    for line in rows {
+        content.push_str(&line);
+        content.push('\n');
+    }
+    Ok(Some((content, added, duplicates)))
+}
+
+fn data_rows(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .collect()
+}
@@ -0,0 +1,156 @@
+use super::records::{parse_timestamp, read_raw_rows};
+use super::settings::window_contains;
+use super::*;
+
+pub(super) const DEFAULT_NUDGE_AFTER_DAYS: u64 = 3;
+pub(super) const DEFAULT_NUDGE_MIN_INTERVAL_DAYS: u64 = 7;
+pub(super) const DEFAULT_NUDGE_QUIET_HOURS: &str = "22:00-08:00";
+pub(super) const NUDGE_CHECK_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// When chats that turned nudges on with `/nudge on` are reminded to log.
+#[derive(Debug, Clone)]
+pub(super) struct NudgeOptions {
+    /// Days without any entry before a nudge is sent.
+    pub(super) after_days: u64,
+    /// Least number of days between two nudges to the same chat.
+    pub(super) min_interval_days: u64,
+    /// Local `HH:MM-HH:MM` window in which no nudge is sent.
+    pub(super) quiet_hours: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl Default for NudgeOptions {
+    fn default() -> Self {
+        NudgeOptions {
+            after_days: DEFAULT_NUDGE_AFTER_DAYS,
+            min_interval_days: DEFAULT_NUDGE_MIN_INTERVAL_DAYS,
+            quiet_hours: settings::parse_time_window(DEFAULT_NUDGE_QUIET_HOURS),
+        }
+    }
+}
+
+pub(super) fn nudge_text(days: i64) -> String {
+    format!(
+        "It's been {days} days since your last entry. A quick reading keeps your reports \
+useful whenever you're ready. Send /nudge off to stop these reminders."
+    )
+}
+
+/// Newest timestamp in any of the chat's data files.
+pub(super) fn last_entry_at(
+    data_dir: &Path,
+    chat_id: ChatId,
+) -> anyhow::Result<Option<DateTime<Utc>>> {
+    let dir = user_data_dir(data_dir, chat_id);
+    let mut last = None;
+    for target in ClearTarget::ALL {
+        let newest = read_raw_rows(&dir.join(target.file_name()))?
+            .into_iter()
+            .map(|row| row.timestamp)
+            .max();
+        last = last.max(newest);
+    }
+    Ok(last)
+}
+
+/// Whether a nudge is due: the last entry is at least `after_days` old, no nudge was sent
+/// since that entry (so one quiet spell gets one nudge), the previous nudge is at least
+/// `min_interval_days` old, and `now` is outside quiet hours.
+pub(super) fn nudge_due(
+    options: &NudgeOptions,
+    last_entry: DateTime<Utc>,
+    last_nudge: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    tz: Tz,
+) -> bool {
+    let stale = now - last_entry >= chrono::Duration::days(options.after_days as i64);
+    let already_nudged = last_nudge.is_some_and(|nudged| {
+        nudged >= last_entry
+            || now - nudged < chrono::Duration::days(options.min_interval_days as i64)
+    });
+    stale && !already_nudged && !in_quiet_hours(options, now, tz)
+}
+
+/// Whether local `now` is inside `nudge_quiet_hours`, when nudges and `/postmeal`
+/// reminders are held back.
+pub(super) fn in_quiet_hours(options: &NudgeOptions, now: DateTime<Utc>, tz: Tz) -> bool {
+    options
+        .quiet_hours
+        .is_some_and(|window| window_contains(window, now.with_timezone(&tz).time()))
+}
+
+/// Sends due nudges to every allowed chat that enabled them and records when. A failing
+/// chat is logged and does not stop the others. Returns the number of nudges sent.
+pub(super) async fn send_due_nudges<R: Responder>(
+    bot: &R,
+    state: &Arc<AppState>,
+    now: DateTime<Utc>,
+) -> usize {
+    let mut sent = 0;
+    for chat_id in state.allowed_chats() {
+        match nudge_chat(bot, state, chat_id, now).await {
+            Ok(true) => sent += 1,
+            Ok(false) => {}
+            Err(err) => tracing::error!("nudge for chat {} failed: {err}", chat_id.0),
+        }
+    }
+    sent
+}
+
+async fn nudge_chat<R: Responder>(
+    bot: &R,
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+    now: DateTime<Utc>,
+) -> anyhow::Result<bool> {
+    let due = storage::blocking(state, move |state| {
+        let user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+        if !user_settings.nudge.unwrap_or(false) {
+            return Ok(None);
+        }
+        let Some(last_entry) = last_entry_at(&state.data_dir, chat_id)? else {
+            return Ok(None);
+        };
+        let last_nudge = user_settings.nudged_at.as_deref().and_then(parse_timestamp);
+        Ok(nudge_due(
+            &state.nudge,
+            last_entry,
+            last_nudge,
+            now,
+            state.input_for(chat_id).tz,
+        )
+        .then_some(last_entry))
+    })
+    .await?;
+    let Some(last_entry) = due else {
+        return Ok(false);
+    };
+    let days = (now - last_entry).num_days();
+    bot.send_text(
+        chat_id,
+        nudge_text(days),
+        menu_keyboard(state, chat_id).await,
+    )
+    .await?;
+    storage::blocking(state, move |state| {
+        let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+        user_settings.nudged_at = Some(now.to_rfc3339_opts(SecondsFormat::Secs, false));
+        settings::save_settings(state, chat_id, &user_settings)
+    })
+    .await?;
+    tracing::info!(
+        "chat {} nudged after {days} days without entries",
+        chat_id.0
+    );
+    Ok(true)
+}
+
+/// Checks for due nudges every [`NUDGE_CHECK_INTERVAL`].
+pub(super) fn spawn_nudge_task<R: Responder>(bot: R, state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(NUDGE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            send_due_nudges(&bot, &state, state.now()).await;
+        }
+    });
+}
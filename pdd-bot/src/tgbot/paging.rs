@@ -0,0 +1,142 @@
+use super::*;
+
+/// Telegram rejects messages longer than this, counted in UTF-16 code units.
+pub(super) const MESSAGE_MAX_LEN: usize = 4096;
+const CODE_FENCE: &str = "```";
+
+/// Sends `text` as one message, or as several split at line boundaries when it is too
+/// long for Telegram. Every part carries the keyboard.
+pub(super) async fn send_long_message<R: Responder>(
+    bot: &R,
+    chat_id: ChatId,
+    text: impl Into<String>,
+    keyboard: KeyboardMarkup,
+) -> anyhow::Result<()> {
+    for part in split_message(&text.into(), MESSAGE_MAX_LEN) {
+        bot.send_text(chat_id, part, keyboard.clone()).await?;
+    }
+    Ok(())
+}
+
+/// [`send_long_message`] for monospace output; each part is its own preformatted block.
+pub(super) async fn send_long_preformatted<R: Responder>(
+    bot: &R,
+    chat_id: ChatId,
+    text: impl Into<String>,
+    keyboard: KeyboardMarkup,
+) -> anyhow::Result<()> {
+    for part in split_message(&text.into(), MESSAGE_MAX_LEN) {
+        bot.send_preformatted(chat_id, part, keyboard.clone())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Splits `text` into parts of at most `limit` UTF-16 units. Parts end at line
+/// boundaries and a ```` ``` ```` block is kept in one part; only a block or a line that
+/// alone exceeds `limit` is cut, and a cut block is closed and reopened in each part.
+pub(super) fn split_message(text: &str, limit: usize) -> Vec<String> {
+    if text_len(text) <= limit {
+        return vec![text.to_string()];
+    }
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for block in blocks(text) {
+        if !current.is_empty() && text_len(&current) + 1 + text_len(&block) > limit {
+            parts.push(std::mem::take(&mut current));
+        }
+        if text_len(&block) <= limit {
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(&block);
+            continue;
+        }
+        parts.extend(split_oversized_block(&block, limit));
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+fn text_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Lines of `text`, with each fenced code block joined into a single entry.
+fn blocks(text: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut fenced: Option<String> = None;
+    for line in text.lines() {
+        let is_fence = line.trim_start().starts_with(CODE_FENCE);
+        match fenced.as_mut() {
+            Some(block) => {
+                block.push('\n');
+                block.push_str(line);
+                if is_fence {
+                    blocks.extend(fenced.take());
+                }
+            }
+            None if is_fence => fenced = Some(line.to_string()),
+            None => blocks.push(line.to_string()),
+        }
+    }
+    blocks.extend(fenced);
+    blocks
+}
+
+fn split_oversized_block(block: &str, limit: usize) -> Vec<String> {
+    let mut lines = block.lines().collect::<Vec<_>>();
+    let fence = lines
+        .first()
+        .filter(|line| line.trim_start().starts_with(CODE_FENCE) && lines.len() > 1)
+        .map(|line| line.to_string());
+    let Some(opening) = fence else {
+        return split_line(block, limit);
+    };
+    if lines.last().is_some_and(|line| line.trim() == CODE_FENCE) {
+        lines.pop();
+    }
+    // Room for the reopened fence line and the closing fence in every part.
+    let inner_limit = limit.saturating_sub(text_len(&opening) + text_len(CODE_FENCE) + 2);
+    if inner_limit == 0 {
+        return split_line(block, limit);
+    }
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for line in &lines[1..] {
+        for piece in split_line(line, inner_limit) {
+            if !current.is_empty() && text_len(&current) + 1 + text_len(&piece) > inner_limit {
+                parts.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(&piece);
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+    parts
+        .into_iter()
+        .map(|part| format!("{opening}\n{part}\n{CODE_FENCE}"))
+        .collect()
+}
+
+/// Cuts a single line that is longer than `limit` at character boundaries.
+fn split_line(line: &str, limit: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    for ch in line.chars() {
+        if !current.is_empty() && text_len(&current) + ch.len_utf16() > limit {
+            parts.push(std::mem::take(&mut current));
+        }
+        current.push(ch);
+    }
+    if !current.is_empty() || parts.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
@@ -0,0 +1,92 @@
+use printpdf::{
+    ColorBits, ColorSpace, Image, ImageTransform, ImageXObject, IndirectFontRef, Mm, PdfDocument,
+    PdfDocumentReference, PdfLayerReference, Pt, Px,
+};
+
+/// A4 page size in points.
+pub(super) const PAGE_WIDTH: f64 = 595.0;
+pub(super) const PAGE_HEIGHT: f64 = 842.0;
+
+/// DejaVu Sans, bundled so text in any script, such as Ukrainian notes and labels, is
+/// drawn as written in PDFs and charts without depending on the fonts of the host. See
+/// `assets/fonts/LICENSE`.
+pub(super) const FONT_REGULAR: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans.ttf");
+pub(super) const FONT_BOLD: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf");
+
+/// Footer of every chart and report.
+pub(super) const DISCLAIMER: &str =
+    "Generated by pdd-bot from self-reported data. Informational only, not medical advice.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Font {
+    Regular,
+    Bold,
+}
+
+/// Single A4 page written with `printpdf`, its text set in the embedded [`FONT_REGULAR`]
+/// and [`FONT_BOLD`]. Coordinates are in points from the bottom-left corner.
+pub(super) struct PdfPage {
+    document: PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+}
+
+impl PdfPage {
+    pub(super) fn new(title: &str) -> anyhow::Result<Self> {
+        let (document, page, layer) =
+            PdfDocument::new(title, mm(PAGE_WIDTH), mm(PAGE_HEIGHT), "page");
+        let regular = document.add_external_font(FONT_REGULAR)?;
+        let bold = document.add_external_font(FONT_BOLD)?;
+        let layer = document.get_page(page).get_layer(layer);
+        Ok(PdfPage {
+            document,
+            layer,
+            regular,
+            bold,
+        })
+    }
+
+    pub(super) fn text(&mut self, x: f64, y: f64, font: Font, size: f64, text: &str) {
+        let font = match font {
+            Font::Regular => &self.regular,
+            Font::Bold => &self.bold,
+        };
+        self.layer.use_text(text, size as f32, mm(x), mm(y), font);
+    }
+
+    /// Places an image of 8-bit RGB `pixels`, such as [`super::plot::to_rgb`] draws,
+    /// with its bottom-left corner at `x`, `y`, scaled to `width` points.
+    pub(super) fn image(&mut self, x: f64, y: f64, width: f64, size: (u32, u32), pixels: Vec<u8>) {
+        let image = Image::from(ImageXObject {
+            width: Px(size.0 as usize),
+            height: Px(size.1 as usize),
+            color_space: ColorSpace::Rgb,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data: pixels,
+            image_filter: None,
+            smask: None,
+            clipping_bbox: None,
+        });
+        image.add_to_layer(
+            self.layer.clone(),
+            ImageTransform {
+                translate_x: Some(mm(x)),
+                translate_y: Some(mm(y)),
+                // One pixel is 72 / dpi points.
+                dpi: Some((f64::from(size.0) * 72.0 / width) as f32),
+                ..ImageTransform::default()
+            },
+        );
+    }
+
+    /// Serializes the page as a complete PDF document.
+    pub(super) fn into_pdf(self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.document.save_to_bytes()?)
+    }
+}
+
+fn mm(points: f64) -> Mm {
+    Pt(points as f32).into()
+}
@@ -0,0 +1,180 @@
+use super::{
+    AppState, BTN_FOOD, BTN_GLUCOSE_AFTER_MEAL, BTN_GLUCOSE_BEFORE_MEAL, BTN_KETONES,
+    BTN_MORE_MEDS, BTN_SHOW_MENU, BTN_WEIGHT, MED_BUTTON_PREFIX, Responder, language,
+    settings_or_default,
+};
+use std::sync::Arc;
+use teloxide::types::{ChatId, InlineKeyboardMarkup, KeyboardMarkup};
+
+/// Medication button prefix used instead of [`MED_BUTTON_PREFIX`] in emoji-free mode.
+pub(super) const PLAIN_MED_BUTTON_PREFIX: &str = "Med: ";
+
+/// Menu button labels and their emoji-free forms. Both forms are accepted as taps, so a
+/// keyboard sent before `/noemoji` was changed keeps working.
+const PLAIN_BUTTON_LABELS: [(&str, &str); 7] = [
+    (BTN_GLUCOSE_BEFORE_MEAL, "Glucose: Before meal"),
+    (BTN_GLUCOSE_AFTER_MEAL, "Glucose: After meal"),
+    (BTN_WEIGHT, "Weight"),
+    (BTN_KETONES, "Ketones"),
+    (BTN_FOOD, "Food"),
+    (BTN_SHOW_MENU, "Show menu"),
+    (BTN_MORE_MEDS, "More meds"),
+];
+
+/// Menu button label for its emoji-free form, e.g. `⚖️ Weight` for `Weight`.
+pub(super) fn button_label(text: &str) -> Option<&'static str> {
+    PLAIN_BUTTON_LABELS
+        .iter()
+        .find(|(_, plain)| *plain == text)
+        .map(|(label, _)| *label)
+}
+
+/// Emoji-free form of a button label, e.g. `Med: Insulin` for `💊 Insulin`.
+pub(super) fn plain_label(label: &str) -> String {
+    if let Some((_, plain)) = PLAIN_BUTTON_LABELS
+        .iter()
+        .find(|(emoji, _)| *emoji == label)
+    {
+        return plain.to_string();
+    }
+    match label.strip_prefix(MED_BUTTON_PREFIX) {
+        Some(name) => format!("{PLAIN_MED_BUTTON_PREFIX}{name}"),
+        None => strip_emoji(label),
+    }
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1F000}'..='\u{1FAFF}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2B00}'..='\u{2BFF}'
+            | '\u{231A}'..='\u{23FF}'
+            | '\u{E0020}'..='\u{E007F}'
+            | '\u{FE0F}'
+            | '\u{200D}'
+            | '\u{20E3}'
+    )
+}
+
+/// Removes emoji from `text`. Lines that held one get their spaces collapsed and trimmed,
+/// e.g. `Saved ✅` becomes `Saved`; other lines, such as table rows, are kept as they are.
+pub(super) fn strip_emoji(text: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            if !line.chars().any(is_emoji) {
+                return line.to_string();
+            }
+            line.chars()
+                .filter(|c| !is_emoji(*c))
+                .collect::<String>()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Sends replies with the menu buttons in the chat's `/setlanguage`, and without emoji
+/// and with plain button labels when the chat turned on `/noemoji`. The settings are read
+/// on every send, so the reply to either command already follows it.
+#[derive(Clone)]
+pub(super) struct ChatResponder<R> {
+    inner: R,
+    state: Arc<AppState>,
+}
+
+impl<R: Responder> ChatResponder<R> {
+    pub(super) fn new(inner: R, state: Arc<AppState>) -> Self {
+        ChatResponder { inner, state }
+    }
+
+    fn render(
+        &self,
+        chat_id: ChatId,
+        text: String,
+        mut keyboard: KeyboardMarkup,
+    ) -> (String, KeyboardMarkup) {
+        let user_settings = settings_or_default(&self.state, chat_id);
+        let language = user_settings.language.unwrap_or_default();
+        let plain = user_settings.no_emoji.unwrap_or(false);
+        for button in keyboard.keyboard.iter_mut().flatten() {
+            button.text = language::localized_label(&button.text, language);
+            if plain {
+                button.text = plain_label(&button.text);
+            }
+        }
+        if plain {
+            (strip_emoji(&text), keyboard)
+        } else {
+            (text, keyboard)
+        }
+    }
+}
+
+impl<R: Responder> Responder for ChatResponder<R> {
+    fn send_text(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let (text, keyboard) = self.render(chat_id, text.into(), keyboard);
+        self.inner.send_text(chat_id, text, keyboard)
+    }
+
+    fn send_preformatted(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let (text, keyboard) = self.render(chat_id, text.into(), keyboard);
+        self.inner.send_preformatted(chat_id, text, keyboard)
+    }
+
+    fn send_document(
+        &self,
+        chat_id: ChatId,
+        file_name: &str,
+        bytes: Vec<u8>,
+        caption: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let (caption, keyboard) = self.render(chat_id, caption.into(), keyboard);
+        self.inner
+            .send_document(chat_id, file_name, bytes, caption, keyboard)
+    }
+
+    fn send_photo(
+        &self,
+        chat_id: ChatId,
+        file_name: &str,
+        bytes: Vec<u8>,
+        caption: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let (caption, keyboard) = self.render(chat_id, caption.into(), keyboard);
+        self.inner
+            .send_photo(chat_id, file_name, bytes, caption, keyboard)
+    }
+
+    fn send_inline(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        mut buttons: InlineKeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let (text, _) = self.render(chat_id, text.into(), KeyboardMarkup::default());
+        if settings_or_default(&self.state, chat_id)
+            .no_emoji
+            .unwrap_or(false)
+        {
+            for button in buttons.inline_keyboard.iter_mut().flatten() {
+                button.text = strip_emoji(&button.text);
+            }
+        }
+        self.inner.send_inline(chat_id, text, buttons)
+    }
+}
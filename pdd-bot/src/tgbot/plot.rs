@@ -0,0 +1,184 @@
+use super::pdf::{DISCLAIMER, FONT_BOLD, FONT_REGULAR};
+use chrono::{Duration, NaiveDateTime};
+use plotters::coord::Shift;
+use plotters::coord::types::RangedCoordf64;
+use plotters::prelude::*;
+use plotters::style::register_font;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Family the bundled fonts are registered under for plotters.
+const FONT_FAMILY: &str = "sans-serif";
+const GRID_LABELS: usize = 5;
+const TIME_LABELS: usize = 13;
+
+/// Plot with local time across and values up, the shape of every chart of the bot.
+pub(super) type TimePlot<'a, DB> =
+    ChartContext<'a, DB, Cartesian2d<RangedDateTime<NaiveDateTime>, RangedCoordf64>>;
+
+/// Image drawn with plotters on any backend, so the PNG and SVG files and the chart in
+/// the summary PDF share one drawing code.
+pub(super) trait Plot {
+    /// Width and height in pixels.
+    const SIZE: (u32, u32);
+
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> DrawResult<(), DB>;
+}
+
+/// Registers the bundled DejaVu Sans with plotters once, so charts never depend on the
+/// fonts of the host.
+fn register_fonts() -> anyhow::Result<()> {
+    static REGISTERED: OnceLock<bool> = OnceLock::new();
+    let registered = *REGISTERED.get_or_init(|| {
+        [
+            (FontStyle::Normal, FONT_REGULAR),
+            (FontStyle::Bold, FONT_BOLD),
+        ]
+        .into_iter()
+        .all(|(style, bytes)| register_font(FONT_FAMILY, style, bytes).is_ok())
+    });
+    anyhow::ensure!(registered, "the bundled chart font cannot be loaded");
+    Ok(())
+}
+
+/// Draws `plot` into 8-bit RGB pixels, row by row from the top.
+pub(super) fn to_rgb<P: Plot>(plot: &P) -> anyhow::Result<Vec<u8>> {
+    register_fonts()?;
+    let (width, height) = P::SIZE;
+    let mut pixels = vec![0; width as usize * height as usize * 3];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, P::SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        plot.draw(&root)?;
+        root.present()?;
+    }
+    Ok(pixels)
+}
+
+pub(super) fn to_png<P: Plot>(plot: &P) -> anyhow::Result<Vec<u8>> {
+    let pixels = to_rgb(plot)?;
+    let (width, height) = P::SIZE;
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(png)
+}
+
+pub(super) fn to_svg<P: Plot>(plot: &P) -> anyhow::Result<Vec<u8>> {
+    register_fonts()?;
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, P::SIZE).into_drawing_area();
+        root.fill(&WHITE)?;
+        plot.draw(&root)?;
+        root.present()?;
+    }
+    Ok(svg.into_bytes())
+}
+
+pub(super) fn font(size: f64) -> FontDesc<'static> {
+    (FONT_FAMILY, size).into_font()
+}
+
+pub(super) fn bold(size: f64) -> FontDesc<'static> {
+    font(size).style(FontStyle::Bold)
+}
+
+/// Text of `size` whose `h` and `v` sides sit on the position it is drawn at.
+pub(super) fn anchored(size: f64, h: HPos, v: VPos) -> TextStyle<'static> {
+    TextStyle::from(font(size)).pos(Pos::new(h, v))
+}
+
+/// Title and caption at the top of `root` and the disclaimer at its bottom; returns the
+/// area between them.
+pub(super) fn page<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    title: &str,
+    caption: &str,
+) -> DrawResult<DrawingArea<DB, Shift>, DB> {
+    let (_, height) = root.dim_in_pixel();
+    root.draw_text(title, &bold(32.0).into(), (40, 30))?;
+    root.draw_text(caption, &font(18.0).into(), (40, 78))?;
+    root.draw_text(DISCLAIMER, &font(14.0).into(), (40, height as i32 - 36))?;
+    Ok(root.margin(110, 50, 20, 30))
+}
+
+/// Plot over local times from `start` to `end` and `values`, with a grid, the value
+/// axis titled `title` and times labeled with the chrono `time_format`.
+pub(super) fn time_plot<'a, DB: DrawingBackend>(
+    area: &'a DrawingArea<DB, Shift>,
+    (start, end): (NaiveDateTime, NaiveDateTime),
+    values: Range<f64>,
+    title: &str,
+    time_format: &str,
+) -> DrawResult<TimePlot<'a, DB>, DB> {
+    let whole = values.end - values.start >= 10.0;
+    let mut plot = ChartBuilder::on(area)
+        .margin(10)
+        .x_label_area_size(34)
+        .y_label_area_size(70)
+        .build_cartesian_2d(RangedDateTime::from(start..end), values)?;
+    plot.configure_mesh()
+        .disable_x_mesh()
+        .light_line_style(TRANSPARENT)
+        .x_labels(TIME_LABELS)
+        .y_labels(GRID_LABELS)
+        .x_label_formatter(&|time| time.format(time_format).to_string())
+        .y_label_formatter(&|value| {
+            if whole {
+                format!("{value:.0}")
+            } else {
+                format!("{value:.1}")
+            }
+        })
+        .y_desc(title)
+        .label_style(font(15.0))
+        .axis_desc_style(bold(17.0))
+        .draw()?;
+    Ok(plot)
+}
+
+/// `text` in the middle of the plot, for a period without entries.
+pub(super) fn draw_empty<DB: DrawingBackend>(
+    plot: &mut TimePlot<DB>,
+    text: &str,
+) -> DrawResult<(), DB> {
+    let Range { start, end } = plot.x_range();
+    let values = plot.y_range();
+    let middle = (start + (end - start) / 2, (values.start + values.end) / 2.0);
+    plot.draw_series([Text::new(
+        text.to_string(),
+        middle,
+        anchored(18.0, HPos::Center, VPos::Center),
+    )])?;
+    Ok(())
+}
+
+/// Bars from the bottom of the plot, `half_width` to each side of their time, each
+/// labeled above when it has a label.
+pub(super) fn draw_bars<DB: DrawingBackend>(
+    plot: &mut TimePlot<DB>,
+    bars: &[(NaiveDateTime, f64, Option<String>)],
+    half_width: Duration,
+    color: RGBColor,
+) -> DrawResult<(), DB> {
+    plot.draw_series(bars.iter().map(|(time, value, _)| {
+        Rectangle::new(
+            [(*time - half_width, 0.0), (*time + half_width, *value)],
+            color.filled(),
+        )
+    }))?;
+    plot.draw_series(bars.iter().filter_map(|(time, value, label)| {
+        Some(Text::new(
+            label.clone()?,
+            (*time, *value),
+            anchored(13.0, HPos::Center, VPos::Bottom),
+        ))
+    }))?;
+    Ok(())
+}
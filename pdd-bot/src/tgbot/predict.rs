@@ -0,0 +1,90 @@
+use super::GlucoseTag;
+use super::records::GlucoseRecord;
+use chrono::{DateTime, Duration, NaiveTime, Timelike, Utc};
+use chrono_tz::Tz;
+
+const PREDICT_DAYS: i64 = 30;
+/// Readings within this many minutes of the current local time of day are compared.
+const PREDICT_WINDOW_MINUTES: i64 = 120;
+const PREDICT_MIN_READINGS: usize = 7;
+/// From this many readings on the band is called moderate instead of low confidence.
+const PREDICT_MODERATE_READINGS: usize = 20;
+const MINUTES_PER_DAY: i64 = 24 * 60;
+const DISCLAIMER: &str = "⚠️ A statistical guess from your past readings only, not a forecast \
+and not medical advice. Always measure.";
+
+pub(super) fn parse_predict_args(args: &str) -> Result<GlucoseTag, String> {
+    match args.to_lowercase().as_str() {
+        "before" => Ok(GlucoseTag::BeforeMeal),
+        "after" => Ok(GlucoseTag::AfterMeal),
+        _ => Err("Usage: /predict <before|after>".to_string()),
+    }
+}
+
+/// Minutes between two times of day, going the short way around midnight.
+fn time_of_day_distance(a: NaiveTime, b: NaiveTime) -> i64 {
+    let minutes = |time: NaiveTime| i64::from(time.num_seconds_from_midnight() / 60);
+    let diff = (minutes(a) - minutes(b)).rem_euclid(MINUTES_PER_DAY);
+    diff.min(MINUTES_PER_DAY - diff)
+}
+
+/// Value at quantile `q` (0.0-1.0) of sorted values, interpolating between neighbours.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let (low, high) = (position.floor() as usize, position.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (position - low as f64)
+}
+
+/// `/predict` reply: the median and interquartile range of the last [`PREDICT_DAYS`] days
+/// of `tag` readings taken within [`PREDICT_WINDOW_MINUTES`] of the local time of `now`.
+pub(super) fn predict_report(
+    glucose: &[GlucoseRecord],
+    tag: GlucoseTag,
+    tz: Tz,
+    now: DateTime<Utc>,
+) -> String {
+    let label = tag.as_csv_tag().replace('_', "-");
+    let time = now.with_timezone(&tz).time();
+    let since = now - Duration::days(PREDICT_DAYS);
+    let mut values = glucose
+        .iter()
+        .filter(|record| record.tag == tag.as_csv_tag())
+        .filter(|record| record.timestamp >= since && record.timestamp <= now)
+        .filter(|record| {
+            time_of_day_distance(record.timestamp.with_timezone(&tz).time(), time)
+                <= PREDICT_WINDOW_MINUTES
+        })
+        .map(|record| record.value)
+        .collect::<Vec<_>>();
+    let window = format!(
+        "within {} h of {} in the last {PREDICT_DAYS} days",
+        PREDICT_WINDOW_MINUTES / 60,
+        time.format("%H:%M")
+    );
+    if values.len() < PREDICT_MIN_READINGS {
+        return format!(
+            "Not enough data to guess the next {label} reading: {} readings {window} \
+(need at least {PREDICT_MIN_READINGS}).",
+            values.len()
+        );
+    }
+    values.sort_by(f64::total_cmp);
+    let confidence = if values.len() >= PREDICT_MODERATE_READINGS {
+        "moderate"
+    } else {
+        "low"
+    };
+    [
+        format!("Next {label} reading, statistical guess:"),
+        format!(
+            "around {:.1} mmol/L, likely {:.1}-{:.1} (middle half of {} readings {window})",
+            quantile(&values, 0.5),
+            quantile(&values, 0.25),
+            quantile(&values, 0.75),
+            values.len()
+        ),
+        format!("Confidence: {confidence}."),
+        DISCLAIMER.to_string(),
+    ]
+    .join("\n")
+}
@@ -0,0 +1,382 @@
+use super::bolus::BOLUS_FILE;
+use super::insulin::InsulinKind;
+use super::settings::GlucoseUnit;
+use super::{
+    DEFAULT_SOURCE, GLUCOSE_FILE, GLUCOSE_UNIT_COLUMN, INSULIN_FILE, KETONES_FILE, MEALS_FILE,
+    WEIGHT_FILE, user_data_dir,
+};
+use chrono::{DateTime, Utc};
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
+use teloxide::types::ChatId;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "GlucoseRow")]
+pub(super) struct GlucoseRecord {
+    pub(super) timestamp: DateTime<Utc>,
+    pub(super) tag: String,
+    pub(super) value: f64,
+    pub(super) note: String,
+    pub(super) source: String,
+}
+
+/// `glucose.csv` row as written, before its value is converted to mmol/L.
+#[derive(Deserialize)]
+struct GlucoseRow {
+    #[serde(deserialize_with = "timestamp")]
+    timestamp: DateTime<Utc>,
+    tag: String,
+    #[serde(alias = "value_mmol_l")]
+    value: f64,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    source: String,
+    #[serde(default, deserialize_with = "glucose_unit")]
+    unit: GlucoseUnit,
+}
+
+impl From<GlucoseRow> for GlucoseRecord {
+    fn from(row: GlucoseRow) -> Self {
+        GlucoseRecord {
+            timestamp: row.timestamp,
+            tag: row.tag,
+            value: row.unit.to_mmol_l(row.value),
+            note: row.note,
+            source: match row.source.is_empty() {
+                true => DEFAULT_SOURCE.to_string(),
+                false => row.source,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(super) struct MealRecord {
+    #[serde(deserialize_with = "timestamp")]
+    pub(super) timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub(super) note: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(super) struct WeightRecord {
+    #[serde(deserialize_with = "timestamp")]
+    pub(super) timestamp: DateTime<Utc>,
+    #[serde(rename = "value_kg")]
+    pub(super) value: f64,
+}
+
+/// Blood ketone reading from `ketones.csv`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(super) struct KetoneRecord {
+    #[serde(deserialize_with = "timestamp")]
+    pub(super) timestamp: DateTime<Utc>,
+    #[serde(rename = "value_mmol_l")]
+    pub(super) value: f64,
+}
+
+/// Carbohydrates entered with `/bolus`, from `bolus_suggestions.csv`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(super) struct CarbRecord {
+    #[serde(deserialize_with = "timestamp")]
+    pub(super) timestamp: DateTime<Utc>,
+    #[serde(rename = "carbs_g")]
+    pub(super) grams: f64,
+}
+
+/// Rapid insulin dose from `insulin.csv`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(super) struct InsulinRecord {
+    #[serde(deserialize_with = "timestamp")]
+    pub(super) timestamp: DateTime<Utc>,
+    pub(super) units: f64,
+    #[serde(rename = "type", default, deserialize_with = "insulin_kind")]
+    pub(super) kind: InsulinKind,
+}
+
+impl CsvRecord for GlucoseRecord {
+    const FILE: &'static str = GLUCOSE_FILE;
+}
+
+impl CsvRecord for MealRecord {
+    const FILE: &'static str = MEALS_FILE;
+}
+
+impl CsvRecord for WeightRecord {
+    const FILE: &'static str = WEIGHT_FILE;
+}
+
+impl CsvRecord for KetoneRecord {
+    const FILE: &'static str = KETONES_FILE;
+}
+
+impl CsvRecord for CarbRecord {
+    const FILE: &'static str = BOLUS_FILE;
+}
+
+impl CsvRecord for InsulinRecord {
+    const FILE: &'static str = INSULIN_FILE;
+}
+
+pub(super) fn read_glucose_records(
+    data_dir: &Path,
+    chat_id: ChatId,
+) -> anyhow::Result<Vec<GlucoseRecord>> {
+    Ok(read_records(data_dir, chat_id)?.rows)
+}
+
+pub(super) fn read_weight_records(
+    data_dir: &Path,
+    chat_id: ChatId,
+) -> anyhow::Result<Vec<WeightRecord>> {
+    Ok(read_records(data_dir, chat_id)?.rows)
+}
+
+/// Row type deserialized by column name from its CSV file.
+pub(super) trait CsvRecord: DeserializeOwned {
+    /// File in the user's data directory holding these rows.
+    const FILE: &'static str;
+}
+
+/// Line that could not be parsed; `line` is 1-based and counts the header.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct RowError {
+    pub(super) line: usize,
+    pub(super) reason: String,
+}
+
+/// Parsed rows of one CSV file plus the lines that were skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Records<T> {
+    pub(super) rows: Vec<T>,
+    pub(super) errors: Vec<RowError>,
+}
+
+impl<T> Records<T> {
+    pub(super) fn skipped(&self) -> usize {
+        self.errors.len()
+    }
+}
+
+/// Reads the typed rows of `T::FILE` in file order. Malformed lines, including ones that
+/// are not valid UTF-8, are collected in [`Records::errors`] and logged instead of failing
+/// the whole read; only I/O errors fail it.
+pub(super) fn read_records<T: CsvRecord>(
+    data_dir: &Path,
+    chat_id: ChatId,
+) -> anyhow::Result<Records<T>> {
+    let path = user_data_dir(data_dir, chat_id).join(T::FILE);
+    let mut records = Records {
+        rows: Vec::new(),
+        errors: Vec::new(),
+    };
+    if !path.exists() {
+        return Ok(records);
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(fs_err::File::open(&path)?);
+    let headers = reader.headers()?.clone();
+    let mut skip = |line: usize, reason: String| {
+        tracing::warn!(
+            "skipping malformed row {line} in {}: {reason}",
+            path.display()
+        );
+        records.errors.push(RowError { line, reason });
+    };
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) if !err.is_io_error() => {
+                let line = err
+                    .position()
+                    .map_or(0, |position| position.line() as usize);
+                skip(line, read_error_reason(&err, &headers));
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        let line = record
+            .position()
+            .map_or(0, |position| position.line() as usize);
+        match record.deserialize(Some(&headers)) {
+            Ok(row) => rows.push(row),
+            Err(err) => skip(line, row_error_reason(&err, &headers, &record)),
+        }
+    }
+    records.rows = rows;
+    Ok(records)
+}
+
+/// Names the column of a line the CSV reader could not split into text fields.
+fn read_error_reason(err: &csv::Error, headers: &csv::StringRecord) -> String {
+    match err.kind() {
+        csv::ErrorKind::Utf8 { err, .. } => format!(
+            "invalid UTF-8 in {}",
+            headers.get(err.field()).unwrap_or("field")
+        ),
+        _ => err.to_string(),
+    }
+}
+
+/// Names the column and value a row failed on when the error points at one field.
+fn row_error_reason(
+    err: &csv::Error,
+    headers: &csv::StringRecord,
+    record: &csv::StringRecord,
+) -> String {
+    let csv::ErrorKind::Deserialize { err, .. } = err.kind() else {
+        return err.to_string();
+    };
+    match err.field().map(|index| index as usize) {
+        Some(index) => format!(
+            "invalid {} {:?}",
+            headers.get(index).unwrap_or("field"),
+            record.get(index).unwrap_or_default()
+        ),
+        None => err.kind().to_string(),
+    }
+}
+
+fn timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    parse_timestamp(&raw).ok_or_else(|| de::Error::custom(format!("invalid timestamp {raw:?}")))
+}
+
+fn glucose_unit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<GlucoseUnit, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    GlucoseUnit::from_csv_name(&raw)
+        .ok_or_else(|| de::Error::custom(format!("unknown unit {raw:?}")))
+}
+
+fn insulin_kind<'de, D: Deserializer<'de>>(deserializer: D) -> Result<InsulinKind, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    InsulinKind::from_csv_name(&raw)
+        .ok_or_else(|| de::Error::custom(format!("unknown insulin type {raw:?}")))
+}
+
+/// Unit of a glucose row; see [`GlucoseUnit::from_csv_name`].
+pub(super) fn glucose_unit_field(fields: &[String]) -> Result<GlucoseUnit, String> {
+    let raw = fields.get(GLUCOSE_UNIT_COLUMN).map_or("", String::as_str);
+    GlucoseUnit::from_csv_name(raw).ok_or_else(|| format!("unknown unit {raw:?}"))
+}
+
+/// Data row of any CSV file together with its raw line, for rewriting the file later.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct RawRow {
+    pub(super) timestamp: DateTime<Utc>,
+    pub(super) line: String,
+    pub(super) fields: Vec<String>,
+}
+
+/// Reads rows whose first column is a timestamp.
+pub(super) fn read_raw_rows(path: &Path) -> anyhow::Result<Vec<RawRow>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs_err::read_to_string(path)?;
+    let mut result = Vec::new();
+    for line in content.lines().skip(1) {
+        let fields = parse_csv_line(line);
+        if let Some(timestamp) = fields.first().and_then(|raw| parse_timestamp(raw)) {
+            result.push(RawRow {
+                timestamp,
+                line: line.to_string(),
+                fields,
+            });
+        }
+    }
+    Ok(result)
+}
+
+/// Bytes read from the end of a file at first by [`last_data_line`]; doubled until a
+/// whole line fits.
+const TAIL_CHUNK: u64 = 4096;
+
+/// Last non-empty line after the header, read from the end of the file so checking the
+/// newest row costs the same however long the file is. `None` when there is no such line.
+pub(super) fn last_data_line(path: &Path) -> anyhow::Result<Option<String>> {
+    use std::io::{Read, Seek, SeekFrom};
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut file = fs_err::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut chunk = TAIL_CHUNK;
+    loop {
+        let start = len.saturating_sub(chunk);
+        file.seek(SeekFrom::Start(start))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+        // Unless the chunk starts the file, its first line may be cut off.
+        let complete = match start {
+            0 => &tail[..],
+            _ => match tail.iter().position(|&byte| byte == b'\n') {
+                Some(newline) => &tail[newline + 1..],
+                None => &[],
+            },
+        };
+        let text = std::str::from_utf8(complete)?;
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        if start == 0 {
+            lines.next();
+        }
+        if let Some(last) = lines.next_back() {
+            return Ok(Some(last.to_string()));
+        }
+        if start == 0 {
+            return Ok(None);
+        }
+        chunk *= 2;
+    }
+}
+
+/// Replaces the raw text of field `index` of a CSV line, leaving the other fields as
+/// written. Commas inside quoted fields do not separate fields.
+pub(super) fn replace_field(line: &str, index: usize, value: &str) -> String {
+    let mut field = 0;
+    let mut start = 0;
+    let mut in_quotes = false;
+    for (offset, ch) in line.char_indices() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                if field == index {
+                    return format!("{}{value}{}", &line[..start], &line[offset..]);
+                }
+                field += 1;
+                start = offset + 1;
+            }
+            _ => {}
+        }
+    }
+    if field == index {
+        format!("{}{value}", &line[..start])
+    } else {
+        line.to_string()
+    }
+}
+
+pub(super) fn parse_timestamp(raw: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(raw)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Splits one CSV line into its unquoted fields; an empty line is one empty field.
+pub(super) fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(line.as_bytes());
+    match reader.records().next() {
+        Some(Ok(record)) => record.iter().map(str::to_string).collect(),
+        _ => vec![String::new()],
+    }
+}
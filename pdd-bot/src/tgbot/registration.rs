@@ -0,0 +1,56 @@
+use super::*;
+
+/// Chats registered through a deep link, one id per line in the data directory.
+const REGISTERED_FILE: &str = "registered_chats.txt";
+
+pub(super) fn load_registered(data_dir: &Path) -> anyhow::Result<HashSet<ChatId>> {
+    let path = data_dir.join(REGISTERED_FILE);
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    fs_err::read_to_string(&path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse::<i64>()
+                .map(ChatId)
+                .map_err(|e| anyhow::anyhow!("invalid chat id '{line}' in {}: {e}", path.display()))
+        })
+        .collect()
+}
+
+/// Registers `chat_id` when `payload` is `register_<registration_token>` and returns
+/// whether it did. Other payloads, a wrong token, a group or registration being off
+/// return `false`, so the chat is treated like any unknown chat.
+pub(super) fn register(state: &AppState, chat_id: ChatId, payload: &str) -> anyhow::Result<bool> {
+    if !chat_id.is_user() {
+        return Ok(false);
+    }
+    let (Some(expected), Some(given)) = (
+        state.registration_token.as_deref(),
+        payload.strip_prefix(args::REGISTER_PAYLOAD_PREFIX),
+    ) else {
+        return Ok(false);
+    };
+    if !same_token(expected, given) {
+        tracing::warn!("chat {} sent a wrong registration token", chat_id.0);
+        return Ok(false);
+    }
+    let mut registered = state
+        .registered_chat_ids
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if registered.insert(chat_id) {
+        let mut ids = registered.iter().map(|id| id.0).collect::<Vec<_>>();
+        ids.sort_unstable();
+        let content = ids.iter().map(|id| format!("{id}\n")).collect::<String>();
+        let path = state.data_dir.join(REGISTERED_FILE);
+        if let Err(err) = write_file_atomically(&path, &content, state.input.sync_writes) {
+            registered.remove(&chat_id);
+            return Err(err);
+        }
+        tracing::info!("registered chat {}", chat_id.0);
+    }
+    Ok(true)
+}
@@ -0,0 +1,562 @@
+use super::records::{GlucoseRecord, MealRecord};
+use super::settings::UserSettings;
+use super::{GlucoseTag, normalize_medication_name};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+
+const MEAL_EFFECT_DAYS: i64 = 30;
+const MEAL_EFFECT_WINDOW_MINUTES: i64 = 240;
+const MEAL_EFFECT_MAX_UNMATCHED: usize = 5;
+const POST_MEAL_NORMAL_MAX: f64 = 7.8;
+const POST_MEAL_ELEVATED_MAX: f64 = 10.0;
+
+/// Lowest and highest reading, the earlier of equal values; `None` without readings.
+pub(super) fn glucose_extremes<'a>(
+    readings: &[&'a GlucoseRecord],
+) -> Option<(&'a GlucoseRecord, &'a GlucoseRecord)> {
+    let pick = |better: fn(f64, f64) -> bool| {
+        readings.iter().copied().reduce(|kept, next| {
+            if better(next.value, kept.value)
+                || (next.value == kept.value && next.timestamp < kept.timestamp)
+            {
+                next
+            } else {
+                kept
+            }
+        })
+    };
+    Some((pick(|a, b| a < b)?, pick(|a, b| a > b)?))
+}
+
+/// Reading value with its local time, e.g. `7.0 (02/01 10:00)`.
+fn value_at(record: &GlucoseRecord, tz: Tz) -> String {
+    format!(
+        "{:.1} ({})",
+        record.value,
+        record.timestamp.with_timezone(&tz).format("%m/%d %H:%M")
+    )
+}
+
+/// Pairs every meal of the last [`MEAL_EFFECT_DAYS`] days with the first after-meal
+/// reading that follows it within [`MEAL_EFFECT_WINDOW_MINUTES`].
+pub(super) fn meal_effect_report(
+    glucose: &[GlucoseRecord],
+    meals: &[MealRecord],
+    tz: Tz,
+    now: DateTime<Utc>,
+) -> String {
+    let since = now - Duration::days(MEAL_EFFECT_DAYS);
+    let window = Duration::minutes(MEAL_EFFECT_WINDOW_MINUTES);
+    let mut after_meal = glucose
+        .iter()
+        .filter(|record| record.tag == GlucoseTag::AfterMeal.as_csv_tag())
+        .collect::<Vec<_>>();
+    after_meal.sort_by_key(|record| record.timestamp);
+    let mut meals = meals
+        .iter()
+        .filter(|meal| meal.timestamp >= since && meal.timestamp <= now)
+        .collect::<Vec<_>>();
+    meals.sort_by_key(|meal| meal.timestamp);
+
+    if meals.is_empty() {
+        return format!("No meals logged in the last {MEAL_EFFECT_DAYS} days. Use /meal [@note].");
+    }
+
+    let mut matched = Vec::new();
+    let mut unmatched = Vec::new();
+    for meal in &meals {
+        let reading = after_meal.iter().find(|record| {
+            record.timestamp >= meal.timestamp && record.timestamp <= meal.timestamp + window
+        });
+        match reading {
+            Some(reading) => matched.push((*meal, *reading)),
+            None => unmatched.push(*meal),
+        }
+    }
+
+    let mut lines = vec![
+        format!(
+            "Meal effect (last {MEAL_EFFECT_DAYS} days, after-meal reading within {}h):",
+            MEAL_EFFECT_WINDOW_MINUTES / 60
+        ),
+        format!(
+            "Meals: {}, with reading: {}, without: {}",
+            meals.len(),
+            matched.len(),
+            unmatched.len()
+        ),
+    ];
+
+    if !matched.is_empty() {
+        let mut values = matched
+            .iter()
+            .map(|(_, reading)| reading.value)
+            .collect::<Vec<_>>();
+        values.sort_by(f64::total_cmp);
+        let median = if values.len() % 2 == 0 {
+            (values[values.len() / 2 - 1] + values[values.len() / 2]) / 2.0
+        } else {
+            values[values.len() / 2]
+        };
+        let readings = matched
+            .iter()
+            .map(|(_, reading)| *reading)
+            .collect::<Vec<_>>();
+        if let Some((lowest, highest)) = glucose_extremes(&readings) {
+            lines.push(format!(
+                "Post-meal glucose: min {}, median {median:.1}, max {} mmol/L",
+                value_at(lowest, tz),
+                value_at(highest, tz)
+            ));
+        }
+        let normal = values
+            .iter()
+            .filter(|value| **value < POST_MEAL_NORMAL_MAX)
+            .count();
+        let high = values
+            .iter()
+            .filter(|value| **value > POST_MEAL_ELEVATED_MAX)
+            .count();
+        lines.push(format!("- below {POST_MEAL_NORMAL_MAX:.1}: {normal}"));
+        lines.push(format!(
+            "- {POST_MEAL_NORMAL_MAX:.1}-{POST_MEAL_ELEVATED_MAX:.1}: {}",
+            values.len() - normal - high
+        ));
+        lines.push(format!("- above {POST_MEAL_ELEVATED_MAX:.1}: {high}"));
+
+        let mut by_note: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for (meal, reading) in &matched {
+            let note = if meal.note.trim().is_empty() {
+                "(no note)".to_string()
+            } else {
+                meal.note.trim().to_lowercase()
+            };
+            by_note.entry(note).or_default().push(reading.value);
+        }
+        let mut by_note = by_note
+            .into_iter()
+            .map(|(note, values)| {
+                let average = values.iter().sum::<f64>() / values.len() as f64;
+                (note, average, values.len())
+            })
+            .collect::<Vec<_>>();
+        by_note.sort_by(|a, b| b.1.total_cmp(&a.1));
+        lines.push("By meal note (highest average first):".to_string());
+        for (note, average, count) in by_note {
+            lines.push(format!("- {note}: {average:.1} ({count})"));
+        }
+    }
+
+    if !unmatched.is_empty() {
+        lines.push("Without after-meal reading:".to_string());
+        for meal in unmatched.iter().rev().take(MEAL_EFFECT_MAX_UNMATCHED) {
+            let local = meal.timestamp.with_timezone(&tz).format("%m/%d %H:%M");
+            if meal.note.is_empty() {
+                lines.push(format!("- {local}"));
+            } else {
+                lines.push(format!("- {local} {}", meal.note));
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+const GLUCOSE_LOW: f64 = 3.9;
+const GLUCOSE_TARGET_MAX: f64 = 7.8;
+const GLUCOSE_HIGH: f64 = 10.0;
+const MARK_LOW: char = 'L';
+const MARK_IN_RANGE: char = 'o';
+const MARK_ELEVATED: char = '+';
+const MARK_HIGH: char = 'H';
+const MARK_NO_DATA: char = '.';
+const WEEKDAY_NAMES: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+
+fn day_mark(average: f64) -> char {
+    if average < GLUCOSE_LOW {
+        MARK_LOW
+    } else if average <= GLUCOSE_TARGET_MAX {
+        MARK_IN_RANGE
+    } else if average <= GLUCOSE_HIGH {
+        MARK_ELEVATED
+    } else {
+        MARK_HIGH
+    }
+}
+
+/// Calendar grid of `today`'s month with one mark per day for the daily glucose average.
+/// Days are bucketed in `tz`; days after `today` are left blank. Weeks start on `week_start`.
+pub(super) fn month_overview(
+    glucose: &[GlucoseRecord],
+    tz: Tz,
+    today: NaiveDate,
+    week_start: Weekday,
+) -> String {
+    let first = today.with_day(1).unwrap_or(today);
+    let days_in_month = first
+        .checked_add_months(Months::new(1))
+        .map_or(31, |next| next.signed_duration_since(first).num_days())
+        as u32;
+
+    let mut sums: BTreeMap<u32, (f64, usize)> = BTreeMap::new();
+    for record in glucose {
+        let local = record.timestamp.with_timezone(&tz).date_naive();
+        if local.year() == first.year() && local.month() == first.month() {
+            let entry = sums.entry(local.day()).or_insert((0.0, 0));
+            entry.0 += record.value;
+            entry.1 += 1;
+        }
+    }
+
+    let mut lines = vec![
+        format!("Glucose {} (daily average)", first.format("%B %Y")),
+        (0..7)
+            .map(|offset| {
+                let weekday = week_start.num_days_from_monday() + offset;
+                format!("{:>3}", WEEKDAY_NAMES[weekday as usize % 7])
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+    ];
+    let mut cells = vec!["   ".to_string(); first.weekday().days_since(week_start) as usize];
+    let mut in_range_days = 0;
+    for day in 1..=days_in_month {
+        let mark = match sums.get(&day) {
+            Some((sum, count)) => {
+                let mark = day_mark(sum / *count as f64);
+                if mark == MARK_IN_RANGE {
+                    in_range_days += 1;
+                }
+                mark
+            }
+            None if day > today.day() => ' ',
+            None => MARK_NO_DATA,
+        };
+        cells.push(format!("{day:>2}{mark}"));
+        if cells.len() == 7 {
+            lines.push(cells.join(" ").trim_end().to_string());
+            cells.clear();
+        }
+    }
+    if !cells.is_empty() {
+        lines.push(cells.join(" ").trim_end().to_string());
+    }
+
+    lines.push(String::new());
+    lines.push(format!(
+        "{MARK_LOW} <{GLUCOSE_LOW:.1}  {MARK_IN_RANGE} {GLUCOSE_LOW:.1}-{GLUCOSE_TARGET_MAX:.1}  \
+{MARK_ELEVATED} {GLUCOSE_TARGET_MAX:.1}-{GLUCOSE_HIGH:.1}  {MARK_HIGH} >{GLUCOSE_HIGH:.1}  {MARK_NO_DATA} no data"
+    ));
+    lines.push(format!(
+        "Days with data: {}/{}, in range: {in_range_days}",
+        sums.len(),
+        today.day()
+    ));
+    lines.join("\n")
+}
+
+pub(super) const WEEKDAY_DEFAULT_DAYS: i64 = 28;
+pub(super) const WEEKDAY_MAX_DAYS: i64 = 365;
+
+/// Average glucose per day of the week over the last `days` days, one row per weekday
+/// starting on `week_start`. Readings are bucketed by their day in `tz`.
+pub(super) fn weekday_report(
+    glucose: &[GlucoseRecord],
+    tz: Tz,
+    now: DateTime<Utc>,
+    days: i64,
+    week_start: Weekday,
+) -> String {
+    let since = now - Duration::days(days);
+    let mut sums = [(0.0, 0usize); 7];
+    for record in glucose
+        .iter()
+        .filter(|record| record.timestamp >= since && record.timestamp <= now)
+    {
+        let weekday = record.timestamp.with_timezone(&tz).weekday();
+        let entry = &mut sums[weekday.num_days_from_monday() as usize];
+        entry.0 += record.value;
+        entry.1 += 1;
+    }
+    let mut lines = vec![
+        format!("Glucose by weekday (last {days} days, mmol/L):"),
+        "Day   Avg  Readings".to_string(),
+    ];
+    for offset in 0..7 {
+        let index = (week_start.num_days_from_monday() + offset) as usize % 7;
+        let name = WEEKDAY_NAMES[index];
+        lines.push(match sums[index] {
+            (_, 0) => format!("{name}      -  no data"),
+            (sum, count) => format!("{name}  {:>5.1}  {count:>8}", sum / count as f64),
+        });
+    }
+    lines.join("\n")
+}
+
+pub(super) const MED_EFFECT_DEFAULT_DAYS: i64 = 30;
+pub(super) const MED_EFFECT_MAX_DAYS: i64 = 365;
+/// Days with readings each group needs before the averages are compared.
+const MED_EFFECT_MIN_DAYS: usize = 3;
+
+/// Average glucose of the last `days` days on local days in `tz` when `medication` was
+/// logged in `med_log` against days when it was not. Only days with readings count.
+pub(super) fn medication_effect_report(
+    glucose: &[GlucoseRecord],
+    med_log: &[(DateTime<Utc>, String)],
+    medication: &str,
+    tz: Tz,
+    now: DateTime<Utc>,
+    days: i64,
+) -> String {
+    let since = now - Duration::days(days);
+    let in_window = |timestamp: &DateTime<Utc>| *timestamp >= since && *timestamp <= now;
+    let taken_days = med_log
+        .iter()
+        .filter(|(timestamp, _)| in_window(timestamp))
+        .filter(|(_, logged)| normalize_medication_name(logged).eq_ignore_ascii_case(medication))
+        .map(|(timestamp, _)| timestamp.with_timezone(&tz).date_naive())
+        .collect::<std::collections::BTreeSet<_>>();
+    if taken_days.is_empty() {
+        return format!("No {medication} uses logged in the last {days} days.");
+    }
+    let mut groups: [BTreeMap<NaiveDate, (f64, usize)>; 2] = Default::default();
+    for record in glucose.iter().filter(|record| in_window(&record.timestamp)) {
+        let day = record.timestamp.with_timezone(&tz).date_naive();
+        let entry = groups[usize::from(taken_days.contains(&day))]
+            .entry(day)
+            .or_default();
+        entry.0 += record.value;
+        entry.1 += 1;
+    }
+    let average = |group: &BTreeMap<NaiveDate, (f64, usize)>| {
+        let (sum, count) = group
+            .values()
+            .fold((0.0, 0), |(sum, count), day| (sum + day.0, count + day.1));
+        (count > 0).then(|| (sum / count as f64, count, group.len()))
+    };
+    let line = |label: &str, group| match average(group) {
+        Some((average, readings, days)) => {
+            format!("{label}: avg {average:.1}, readings: {readings}, days: {days}")
+        }
+        None => format!("{label}: no readings"),
+    };
+    let [without, with] = &groups;
+    let mut lines = vec![
+        format!("Glucose with vs without {medication} (last {days} days, mmol/L):"),
+        line(&format!("Days with {medication}"), with),
+        line("Days without", without),
+    ];
+    match (average(with), average(without)) {
+        (Some((with_avg, _, with_days)), Some((without_avg, _, without_days)))
+            if with_days >= MED_EFFECT_MIN_DAYS && without_days >= MED_EFFECT_MIN_DAYS =>
+        {
+            lines.push(format!(
+                "Difference: {:+.1} mmol/L on days with {medication}",
+                with_avg - without_avg
+            ));
+        }
+        _ => lines.push(format!(
+            "Not enough data to compare: each group needs readings on at least \
+{MED_EFFECT_MIN_DAYS} days."
+        )),
+    }
+    lines.push(
+        "ℹ️ This shows a correlation, not that the medication caused it: food, activity, \
+illness and dose timing also differ between days. Talk to your doctor before changing a medication."
+            .to_string(),
+    );
+    lines.join("\n")
+}
+
+pub(super) const FLAGS_DEFAULT_DAYS: i64 = 14;
+pub(super) const FLAGS_MAX_DAYS: i64 = 365;
+
+/// Glucose readings of the last `days` days outside their target range: the range of
+/// their time window or meal tag as in save confirmations, else the general one. The
+/// furthest from their range come first; equally far ones newest first.
+pub(super) fn flags_report(
+    glucose: &[GlucoseRecord],
+    settings: &UserSettings,
+    tz: Tz,
+    now: DateTime<Utc>,
+    days: i64,
+) -> String {
+    let since = now - Duration::days(days);
+    let readings = glucose
+        .iter()
+        .filter(|record| record.timestamp >= since && record.timestamp <= now)
+        .collect::<Vec<_>>();
+    if readings.is_empty() {
+        return format!("No glucose readings in the last {days} days.");
+    }
+    let mut flags = readings
+        .iter()
+        .filter_map(|record| {
+            let local = record.timestamp.with_timezone(&tz);
+            let (range, label) = match GlucoseTag::from_csv_tag(&record.tag) {
+                Some(tag) => settings.range_at(tag, local.time()),
+                None => (settings.general_range(), "general".to_string()),
+            };
+            let (distance, side) = if record.value < range.low {
+                (range.low - record.value, "below")
+            } else if record.value > range.high {
+                (record.value - range.high, "above")
+            } else {
+                return None;
+            };
+            let note = if record.note.is_empty() {
+                String::new()
+            } else {
+                format!(" @{}", record.note)
+            };
+            let line = format!(
+                "{}  {:.1}  {distance:.1} {side} {label} {range}{note}",
+                local.format("%Y-%m-%d %H:%M"),
+                record.value
+            );
+            Some((distance, record.timestamp, line))
+        })
+        .collect::<Vec<_>>();
+    if flags.is_empty() {
+        return format!(
+            "All in range 🎉 (readings in the last {days} days: {})",
+            readings.len()
+        );
+    }
+    flags.sort_by(|a, b| b.0.total_cmp(&a.0).then(b.1.cmp(&a.1)));
+    let mut lines = vec![format!(
+        "Out of range in the last {days} days: {} of {} readings, furthest first",
+        flags.len(),
+        readings.len()
+    )];
+    lines.extend(flags.into_iter().map(|(_, _, line)| line));
+    lines.join("\n")
+}
+
+pub(super) const TREND_DEFAULT_DAYS: i64 = 14;
+pub(super) const TREND_MAX_DAYS: i64 = 365;
+const TREND_MIN_READINGS: usize = 5;
+const TREND_MIN_DAYS_WITH_DATA: usize = 3;
+/// Slopes smaller than this (mmol/L per day) are reported as stable.
+const TREND_STABLE_SLOPE: f64 = 0.05;
+const TREND_WEAK_R2: f64 = 0.3;
+const TREND_STRONG_R2: f64 = 0.7;
+
+/// Least-squares line through `(x, y)` points: slope and R².
+/// R² is `None` when all `y` are equal; the slope is `None` when all `x` are equal.
+pub(super) fn linear_regression(points: &[(f64, f64)]) -> Option<(f64, Option<f64>)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx = points
+        .iter()
+        .map(|(x, _)| (x - mean_x).powi(2))
+        .sum::<f64>();
+    let sxy = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>();
+    let syy = points
+        .iter()
+        .map(|(_, y)| (y - mean_y).powi(2))
+        .sum::<f64>();
+    if sxx <= f64::EPSILON {
+        return None;
+    }
+    let slope = sxy / sxx;
+    let r2 = (syy > f64::EPSILON).then(|| (sxy * sxy) / (sxx * syy));
+    Some((slope, r2))
+}
+
+/// Linear trend of glucose readings over the last `days` days.
+pub(super) fn trend_report(
+    glucose: &[GlucoseRecord],
+    tz: Tz,
+    now: DateTime<Utc>,
+    days: i64,
+) -> String {
+    let since = now - Duration::days(days);
+    let readings = glucose
+        .iter()
+        .filter(|record| record.timestamp >= since && record.timestamp <= now)
+        .collect::<Vec<_>>();
+    let days_with_data = readings
+        .iter()
+        .map(|record| record.timestamp.with_timezone(&tz).date_naive())
+        .collect::<std::collections::BTreeSet<_>>()
+        .len();
+    if readings.len() < TREND_MIN_READINGS || days_with_data < TREND_MIN_DAYS_WITH_DATA {
+        return format!(
+            "Not enough data for a trend in the last {days} days: {} readings on {days_with_data} days \
+(need at least {TREND_MIN_READINGS} readings on {TREND_MIN_DAYS_WITH_DATA} days).",
+            readings.len()
+        );
+    }
+
+    let points = readings
+        .iter()
+        .map(|record| {
+            let x = (record.timestamp - since).num_seconds() as f64 / 86_400.0;
+            (x, record.value)
+        })
+        .collect::<Vec<_>>();
+    let Some((slope, r2)) = linear_regression(&points) else {
+        return "Not enough spread in reading times for a trend.".to_string();
+    };
+    let direction = if slope.abs() < TREND_STABLE_SLOPE {
+        "→ stable"
+    } else if slope < 0.0 {
+        "↘ improving"
+    } else {
+        "↗ worsening"
+    };
+    let fit = match r2 {
+        Some(r2) if r2 < TREND_WEAK_R2 => format!("R² = {r2:.2} (weak fit, readings vary a lot)"),
+        Some(r2) if r2 < TREND_STRONG_R2 => format!("R² = {r2:.2} (moderate fit)"),
+        Some(r2) => format!("R² = {r2:.2} (strong fit)"),
+        None => "R² n/a (all readings are equal)".to_string(),
+    };
+    let mut lines = vec![
+        format!(
+            "Glucose trend (last {days} days, {} readings on {days_with_data} days):",
+            readings.len()
+        ),
+        format!("{direction}: {slope:+.2} mmol/L per day"),
+        fit,
+    ];
+    if let Some((lowest, highest)) = glucose_extremes(&readings)
+        && lowest.value < highest.value
+    {
+        lines.push(format!(
+            "Lowest {}, highest {} mmol/L",
+            value_at(lowest, tz),
+            value_at(highest, tz)
+        ));
+    }
+    lines.join("\n")
+}
+
+/// Progress toward a weight goal from the first recorded weight, for loss and gain goals alike.
+pub(super) fn weight_goal_progress(start: f64, current: f64, goal: f64) -> String {
+    let remaining = goal - current;
+    let reached = if goal < start {
+        current <= goal
+    } else if goal > start {
+        current >= goal
+    } else {
+        remaining.abs() < 0.05
+    };
+    if reached {
+        return format!("Goal of {goal:.1} kg reached 🎉");
+    }
+    let percent = if goal == start {
+        0.0
+    } else {
+        ((start - current) / (start - goal) * 100.0).clamp(0.0, 100.0)
+    };
+    format!(
+        "Goal {goal:.1} kg: {:.1} kg to go ({percent:.0}% of the way from {start:.1} kg)",
+        remaining.abs()
+    )
+}
@@ -0,0 +1,397 @@
+use super::calendar::{MedTimes, parse_medtime_args};
+use super::chart::{self, ChartFormat, ChartMetric};
+use super::export::{ExportRequest, parse_export_args};
+use super::history::parse_last_args;
+use super::merge::{MergeSource, parse_merge_args};
+use super::predict::parse_predict_args;
+use super::settings::GlucoseUnit;
+use super::tagging::{TagRequest, parse_tag_args};
+use super::units::{UnitsRequest, parse_convert_args, parse_units_args};
+use super::*;
+
+/// Menu buttons that ask for a value; the answer is handled as a pending entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum EntryButton {
+    GlucoseBeforeMeal,
+    GlucoseAfterMeal,
+    Weight,
+    Food,
+    Ketones,
+}
+
+impl EntryButton {
+    pub(super) fn pending(self) -> PendingEntry {
+        match self {
+            EntryButton::GlucoseBeforeMeal => PendingEntry::GlucoseBeforeMeal,
+            EntryButton::GlucoseAfterMeal => PendingEntry::GlucoseAfterMeal,
+            EntryButton::Weight => PendingEntry::Weight,
+            EntryButton::Food => PendingEntry::Food,
+            EntryButton::Ketones => PendingEntry::Ketones,
+        }
+    }
+
+    pub(super) fn prompt(self) -> &'static str {
+        match self {
+            EntryButton::GlucoseBeforeMeal => {
+                "Enter glucose: <value> [date time] [@note], e.g. 5.8 2/1 9:05 @before breakfast"
+            }
+            EntryButton::GlucoseAfterMeal => {
+                "Enter glucose: <value> [date time] [@note], e.g. 7.2 2/1 11:00 @after lunch"
+            }
+            EntryButton::Weight => "Enter weight value (kg), for example: 78.4",
+            EntryButton::Food => {
+                "Enter food: <item> [grams g] [@note], e.g. oatmeal 45g @breakfast"
+            }
+            EntryButton::Ketones => "Enter ketones: <value> [date time] [@note], e.g. 0.6 2/1 9:05",
+        }
+    }
+}
+
+/// What a text message asks for. Arguments are trimmed; commands that take none only
+/// match without arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Command<'a> {
+    Menu,
+    /// [`BTN_MORE_MEDS`]: the medications that do not fit on the menu keyboard.
+    MoreMeds,
+    /// `/start <payload>` from a deep link such as `t.me/<bot>?start=register_<token>`.
+    Start {
+        payload: &'a str,
+    },
+    Help,
+    Commands,
+    MenuText,
+    Privacy,
+    /// `/debug <raw text>`; from a chat that is not an admin it is handled as plain text.
+    Debug {
+        raw: &'a str,
+    },
+    /// `/status`; from a chat that is not an admin it is handled as plain text.
+    Status,
+    /// `/tail <file>`; from a chat that is not an admin it is handled as plain text.
+    Tail {
+        target: &'a str,
+    },
+    AddGlucose {
+        tag: GlucoseTag,
+        payload: &'a str,
+    },
+    AddKetone {
+        payload: &'a str,
+    },
+    AddInsulin {
+        payload: &'a str,
+    },
+    Iob,
+    Meal {
+        payload: &'a str,
+    },
+    Food {
+        payload: &'a str,
+    },
+    MealEffect,
+    /// `/medeffect <medication> [days]`, or the usage text for invalid arguments.
+    MedEffect(Result<(&'a str, i64), String>),
+    Month,
+    Trend(Result<i64, String>),
+    Flags(Result<i64, String>),
+    /// `/chart <metric> [days] [png|svg]`, or the usage text for invalid arguments.
+    Chart(Result<(ChartMetric, i64, ChartFormat), String>),
+    WeekdayAverages(Result<i64, String>),
+    Predict(Result<GlucoseTag, String>),
+    Summary,
+    Goals,
+    /// `/today [png|svg]`.
+    Today(Result<ChartFormat, String>),
+    Export(Result<ExportRequest, String>),
+    Tag(Result<TagRequest, String>),
+    Share(Result<i64, String>),
+    Revoke,
+    Calendar,
+    Last(Result<usize, String>),
+    History,
+    Del {
+        index: &'a str,
+    },
+    Note {
+        payload: &'a str,
+    },
+    Clear {
+        target: &'a str,
+    },
+    ResetSettings,
+    Merge(Result<MergeSource, String>),
+    DualUnits {
+        payload: &'a str,
+    },
+    NoEmoji {
+        payload: &'a str,
+    },
+    Verbose {
+        payload: &'a str,
+    },
+    Checksums {
+        payload: &'a str,
+    },
+    Precision {
+        payload: &'a str,
+    },
+    SetLanguage {
+        payload: &'a str,
+    },
+    Timezone {
+        payload: &'a str,
+    },
+    Units(Result<UnitsRequest, String>),
+    Convert(Result<(f64, GlucoseUnit), String>),
+    WeightGoal {
+        payload: &'a str,
+    },
+    DefaultTag {
+        payload: &'a str,
+    },
+    WeightAlert {
+        payload: &'a str,
+    },
+    AverageGoal {
+        payload: &'a str,
+    },
+    Fast {
+        payload: &'a str,
+    },
+    Nudge {
+        payload: &'a str,
+    },
+    PostMeal {
+        payload: &'a str,
+    },
+    InRange {
+        payload: &'a str,
+    },
+    Confirm {
+        payload: &'a str,
+    },
+    WeekStart {
+        payload: &'a str,
+    },
+    SetRange {
+        payload: &'a str,
+    },
+    SetBolus {
+        payload: &'a str,
+    },
+    Bolus {
+        payload: &'a str,
+    },
+    RenameMed {
+        payload: &'a str,
+    },
+    MedTime(Result<MedTimes, String>),
+    AddMed {
+        name: &'a str,
+    },
+    EntryButton(EntryButton),
+    MedButton {
+        name: &'a str,
+    },
+    /// Anything else: a batch import, the answer to a pending entry or unknown text.
+    Plain {
+        text: &'a str,
+    },
+}
+
+/// Decides what `text` asks for. Menu buttons come first, with or without their emoji, so
+/// a medication button is always a medication whatever its name; then slash commands by
+/// their exact name.
+/// Multi-line text is never a button, so a pasted batch stays a batch.
+pub(super) fn parse_command(text: &str) -> Command<'_> {
+    let text = text.trim();
+    let button = match plain::button_label(text)
+        .or_else(|| language::button_label(text))
+        .unwrap_or(text)
+    {
+        BTN_SHOW_MENU => Some(Command::Menu),
+        BTN_MORE_MEDS => Some(Command::MoreMeds),
+        BTN_GLUCOSE_BEFORE_MEAL => Some(Command::EntryButton(EntryButton::GlucoseBeforeMeal)),
+        BTN_GLUCOSE_AFTER_MEAL => Some(Command::EntryButton(EntryButton::GlucoseAfterMeal)),
+        BTN_WEIGHT => Some(Command::EntryButton(EntryButton::Weight)),
+        BTN_FOOD => Some(Command::EntryButton(EntryButton::Food)),
+        BTN_KETONES => Some(Command::EntryButton(EntryButton::Ketones)),
+        _ => None,
+    };
+    if let Some(button) = button {
+        return button;
+    }
+    if let Some(name) = text
+        .strip_prefix(MED_BUTTON_PREFIX)
+        .or_else(|| text.strip_prefix(plain::PLAIN_MED_BUTTON_PREFIX))
+        && !name.contains('\n')
+    {
+        return Command::MedButton { name: name.trim() };
+    }
+
+    let plain = Command::Plain { text };
+    if !text.starts_with('/') {
+        return plain;
+    }
+    let (name, args) = match text.split_once(' ') {
+        Some((name, args)) => (name, args.trim()),
+        None => (text, ""),
+    };
+    let bare = args.is_empty();
+    match name {
+        "/start" | "/menu" if bare => Command::Menu,
+        "/start" => Command::Start { payload: args },
+        "/help" if bare => Command::Help,
+        "/commands" if bare => Command::Commands,
+        "/menutext" if bare => Command::MenuText,
+        "/privacy" if bare => Command::Privacy,
+        "/debug" => Command::Debug { raw: args },
+        "/status" if bare => Command::Status,
+        "/tail" => Command::Tail { target: args },
+        "/addgb" | "/add_glucose_before" => Command::AddGlucose {
+            tag: GlucoseTag::BeforeMeal,
+            payload: args,
+        },
+        "/addga" | "/add_glucose_after" => Command::AddGlucose {
+            tag: GlucoseTag::AfterMeal,
+            payload: args,
+        },
+        "/addketone" | "/add_ketones" => Command::AddKetone { payload: args },
+        "/addinsulin" => Command::AddInsulin { payload: args },
+        "/iob" if bare => Command::Iob,
+        "/meal" => Command::Meal { payload: args },
+        "/fast" => Command::Fast { payload: args },
+        "/food" => Command::Food { payload: args },
+        "/mealeffect" if bare => Command::MealEffect,
+        "/medeffect" => Command::MedEffect(parse_medeffect_args(args)),
+        "/month" | "/daystats" if bare => Command::Month,
+        "/trend" => Command::Trend(parse_trend_args(args)),
+        "/flags" => Command::Flags(parse_flags_args(args)),
+        "/chart" => Command::Chart(parse_chart_args(args)),
+        "/weekday" => Command::WeekdayAverages(parse_weekday_args(args)),
+        "/predict" => Command::Predict(parse_predict_args(args)),
+        "/summary" if bare => Command::Summary,
+        "/goals" if bare => Command::Goals,
+        "/today" => Command::Today(parse_today_args(args)),
+        "/export" => Command::Export(parse_export_args(args)),
+        "/tag" => Command::Tag(parse_tag_args(args)),
+        "/share" => Command::Share(share::parse_share_args(args)),
+        "/revoke" if bare => Command::Revoke,
+        "/calendar" if bare => Command::Calendar,
+        "/last" => Command::Last(parse_last_args(args)),
+        "/history" if bare => Command::History,
+        "/del" => Command::Del { index: args },
+        "/note" => Command::Note { payload: args },
+        "/clear" => Command::Clear { target: args },
+        "/reset_settings" if bare => Command::ResetSettings,
+        "/merge" => Command::Merge(parse_merge_args(args)),
+        "/dualunits" => Command::DualUnits { payload: args },
+        "/noemoji" => Command::NoEmoji { payload: args },
+        "/verbose" => Command::Verbose { payload: args },
+        "/checksums" => Command::Checksums { payload: args },
+        "/precision" => Command::Precision { payload: args },
+        "/setlanguage" => Command::SetLanguage { payload: args },
+        "/timezone" => Command::Timezone { payload: args },
+        "/units" => Command::Units(parse_units_args(args)),
+        "/convert" => Command::Convert(parse_convert_args(args)),
+        "/weightgoal" => Command::WeightGoal { payload: args },
+        "/defaulttag" => Command::DefaultTag { payload: args },
+        "/weightalert" => Command::WeightAlert { payload: args },
+        "/avggoal" => Command::AverageGoal { payload: args },
+        "/nudge" => Command::Nudge { payload: args },
+        "/postmeal" => Command::PostMeal { payload: args },
+        "/inrange" => Command::InRange { payload: args },
+        "/confirm" => Command::Confirm { payload: args },
+        "/weekstart" => Command::WeekStart { payload: args },
+        "/setrange" => Command::SetRange { payload: args },
+        "/setbolus" => Command::SetBolus { payload: args },
+        "/bolus" => Command::Bolus { payload: args },
+        "/renamemed" => Command::RenameMed { payload: args },
+        "/medtime" => Command::MedTime(parse_medtime_args(args)),
+        "/addmed" | "/add_medication" => Command::AddMed { name: args },
+        _ => plain,
+    }
+}
+
+/// Parses `<medication> [days]`; a trailing number from 1 to the maximum is the
+/// number of days, anything else belongs to the medication name.
+fn parse_medeffect_args(args: &str) -> Result<(&str, i64), String> {
+    let (name, days) = match args.rsplit_once(char::is_whitespace) {
+        Some((name, days)) => match days.parse::<i64>() {
+            Ok(days) if (1..=reports::MED_EFFECT_MAX_DAYS).contains(&days) => (name.trim(), days),
+            _ => (args, reports::MED_EFFECT_DEFAULT_DAYS),
+        },
+        None => (args, reports::MED_EFFECT_DEFAULT_DAYS),
+    };
+    if name.is_empty() {
+        return Err(format!(
+            "Usage: /medeffect <medication> [1-{}]",
+            reports::MED_EFFECT_MAX_DAYS
+        ));
+    }
+    Ok((name, days))
+}
+
+fn parse_weekday_args(args: &str) -> Result<i64, String> {
+    if args.is_empty() {
+        return Ok(reports::WEEKDAY_DEFAULT_DAYS);
+    }
+    match args.parse::<i64>() {
+        Ok(days) if (1..=reports::WEEKDAY_MAX_DAYS).contains(&days) => Ok(days),
+        _ => Err(format!("Usage: /weekday [1-{}]", reports::WEEKDAY_MAX_DAYS)),
+    }
+}
+
+fn parse_chart_args(args: &str) -> Result<(ChartMetric, i64, ChartFormat), String> {
+    let mut parts = args.split_whitespace().collect::<Vec<_>>();
+    let format = match parts.last().and_then(|last| ChartFormat::parse(last)) {
+        Some(format) => {
+            parts.pop();
+            format
+        }
+        None => ChartFormat::default(),
+    };
+    let mut parts = parts.into_iter();
+    let metric = parts
+        .next()
+        .and_then(ChartMetric::parse)
+        .ok_or_else(chart::usage)?;
+    let days = match (parts.next(), parts.next()) {
+        (None, _) => chart::CHART_DEFAULT_DAYS,
+        (Some(days), None) => days
+            .parse::<i64>()
+            .ok()
+            .filter(|days| (1..=chart::CHART_MAX_DAYS).contains(days))
+            .ok_or_else(chart::usage)?,
+        _ => return Err(chart::usage()),
+    };
+    Ok((metric, days, format))
+}
+
+fn parse_today_args(args: &str) -> Result<ChartFormat, String> {
+    if args.is_empty() {
+        return Ok(ChartFormat::default());
+    }
+    ChartFormat::parse(args).ok_or_else(|| "Usage: /today [png|svg]".to_string())
+}
+
+fn parse_flags_args(args: &str) -> Result<i64, String> {
+    if args.is_empty() {
+        return Ok(reports::FLAGS_DEFAULT_DAYS);
+    }
+    match args.parse::<i64>() {
+        Ok(days) if (1..=reports::FLAGS_MAX_DAYS).contains(&days) => Ok(days),
+        _ => Err(format!("Usage: /flags [1-{}]", reports::FLAGS_MAX_DAYS)),
+    }
+}
+
+fn parse_trend_args(args: &str) -> Result<i64, String> {
+    if args.is_empty() {
+        return Ok(reports::TREND_DEFAULT_DAYS);
+    }
+    match args.parse::<i64>() {
+        Ok(days) if (1..=reports::TREND_MAX_DAYS).contains(&days) => Ok(days),
+        _ => Err(format!("Usage: /trend [1-{}]", reports::TREND_MAX_DAYS)),
+    }
+}
@@ -0,0 +1,736 @@
+use super::{
+    AppState, GlucoseTag, MG_DL_PER_MMOL_L, storage, user_data_dir, write_file_atomically,
+};
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use teloxide::types::ChatId;
+
+const SETTINGS_FILE: &str = "settings.toml";
+
+/// Mark ending save confirmations until the user picks another with `/confirm`.
+pub(super) const DEFAULT_CONFIRMATION: &str = "✅";
+const CONFIRMATION_MAX_CHARS: usize = 16;
+
+/// Range used when the user has not set any target range.
+pub(super) const DEFAULT_TARGET_RANGE: GlucoseRange = GlucoseRange {
+    low: 3.9,
+    high: 10.0,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(super) struct GlucoseRange {
+    pub(super) low: f64,
+    pub(super) high: f64,
+}
+
+impl GlucoseRange {
+    /// Whether `value` lies in the middle half of the range, a quarter of its width away
+    /// from both limits.
+    pub(super) fn comfortably_contains(&self, value: f64) -> bool {
+        let margin = (self.high - self.low) / 4.0;
+        self.low + margin <= value && value <= self.high - margin
+    }
+
+    /// The range in `unit`, e.g. `3.9-10.0` or `70-180`.
+    pub(super) fn format_in(&self, unit: GlucoseUnit) -> String {
+        match unit {
+            GlucoseUnit::Mmol => self.to_string(),
+            GlucoseUnit::Mgdl => format!(
+                "{:.0}-{:.0}",
+                self.low * MG_DL_PER_MMOL_L,
+                self.high * MG_DL_PER_MMOL_L
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for GlucoseRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.1}-{:.1}", self.low, self.high)
+    }
+}
+
+/// Target range for readings taken within a local time window such as `22:00-06:00`.
+/// The window includes its start and excludes its end; it wraps past midnight when the
+/// end is not after the start.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct TimeRange {
+    pub(super) window: String,
+    pub(super) low: f64,
+    pub(super) high: f64,
+}
+
+/// Whether `time` falls in a window from [`parse_time_window`].
+pub(super) fn window_contains((start, end): (NaiveTime, NaiveTime), time: NaiveTime) -> bool {
+    if start < end {
+        start <= time && time < end
+    } else {
+        time >= start || time < end
+    }
+}
+
+/// Validates a `/confirm` mark: one line of at most [`CONFIRMATION_MAX_CHARS`] characters.
+pub(super) fn parse_confirmation(mark: &str) -> Result<String, String> {
+    let mark = mark.trim();
+    if mark.is_empty() {
+        return Err("The confirmation cannot be empty.".to_string());
+    }
+    if mark.contains('\n') || mark.chars().count() > CONFIRMATION_MAX_CHARS {
+        return Err(format!(
+            "The confirmation must be one line of at most {CONFIRMATION_MAX_CHARS} characters."
+        ));
+    }
+    Ok(mark.to_string())
+}
+
+/// Kind of entry whose save confirmation can be set to short or detailed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum ConfirmMetric {
+    Glucose,
+    Weight,
+    Ketones,
+    Insulin,
+    Meal,
+    Food,
+    Med,
+}
+
+impl ConfirmMetric {
+    pub(super) const ALL: [ConfirmMetric; 7] = [
+        ConfirmMetric::Glucose,
+        ConfirmMetric::Weight,
+        ConfirmMetric::Ketones,
+        ConfirmMetric::Insulin,
+        ConfirmMetric::Meal,
+        ConfirmMetric::Food,
+        ConfirmMetric::Med,
+    ];
+
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "glucose" => Some(ConfirmMetric::Glucose),
+            "weight" => Some(ConfirmMetric::Weight),
+            "ketones" | "ketone" => Some(ConfirmMetric::Ketones),
+            "insulin" => Some(ConfirmMetric::Insulin),
+            "meal" | "meals" => Some(ConfirmMetric::Meal),
+            "food" => Some(ConfirmMetric::Food),
+            "med" | "meds" | "medication" => Some(ConfirmMetric::Med),
+            _ => None,
+        }
+    }
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            ConfirmMetric::Glucose => "glucose",
+            ConfirmMetric::Weight => "weight",
+            ConfirmMetric::Ketones => "ketones",
+            ConfirmMetric::Insulin => "insulin",
+            ConfirmMetric::Meal => "meal",
+            ConfirmMetric::Food => "food",
+            ConfirmMetric::Med => "med",
+        }
+    }
+}
+
+/// How much a save confirmation says. Short is the mark alone; warnings are still added.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum ConfirmStyle {
+    Short,
+    #[default]
+    Detailed,
+}
+
+impl ConfirmStyle {
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "short" => Some(ConfirmStyle::Short),
+            "detailed" => Some(ConfirmStyle::Detailed),
+            _ => None,
+        }
+    }
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            ConfirmStyle::Short => "short",
+            ConfirmStyle::Detailed => "detailed",
+        }
+    }
+}
+
+/// Parses `/confirm <metric> <short|detailed>`; anything else is not a style change.
+pub(super) fn parse_confirm_style(payload: &str) -> Option<(ConfirmMetric, ConfirmStyle)> {
+    let (metric, style) = payload.split_once(char::is_whitespace)?;
+    Some((
+        ConfirmMetric::parse(metric)?,
+        ConfirmStyle::parse(style.trim())?,
+    ))
+}
+
+/// Parses `<metric> <step>` for `/precision`.
+pub(super) fn parse_precision(payload: &str) -> Option<(PrecisionMetric, f64)> {
+    let (metric, step) = payload.split_once(char::is_whitespace)?;
+    Some((PrecisionMetric::parse(metric)?, parse_precision_step(step)?))
+}
+
+/// Rounding steps `/precision` accepts, coarsest first.
+pub(super) const PRECISION_STEPS: [f64; 5] = [1.0, 0.5, 0.1, 0.05, 0.01];
+/// Glucose steps in mmol/L: anything coarser than 0.1 would store a 3.6 hypo as 4.
+const MMOL_GLUCOSE_STEPS: [f64; 3] = [0.1, 0.05, 0.01];
+/// Glucose steps in mg/dL, where whole numbers are what meters show.
+const MGDL_GLUCOSE_STEPS: [f64; 3] = [1.0, 0.5, 0.1];
+
+/// Metric whose entered values are rounded to a `/precision` step before they are saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum PrecisionMetric {
+    Glucose,
+    Weight,
+    Ketones,
+    Insulin,
+    Carbs,
+}
+
+impl PrecisionMetric {
+    pub(super) const ALL: [PrecisionMetric; 5] = [
+        PrecisionMetric::Glucose,
+        PrecisionMetric::Weight,
+        PrecisionMetric::Ketones,
+        PrecisionMetric::Insulin,
+        PrecisionMetric::Carbs,
+    ];
+
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "glucose" => Some(PrecisionMetric::Glucose),
+            "weight" => Some(PrecisionMetric::Weight),
+            "ketones" | "ketone" => Some(PrecisionMetric::Ketones),
+            "insulin" => Some(PrecisionMetric::Insulin),
+            "carbs" | "carb" => Some(PrecisionMetric::Carbs),
+            _ => None,
+        }
+    }
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            PrecisionMetric::Glucose => "glucose",
+            PrecisionMetric::Weight => "weight",
+            PrecisionMetric::Ketones => "ketones",
+            PrecisionMetric::Insulin => "insulin",
+            PrecisionMetric::Carbs => "carbs",
+        }
+    }
+
+    /// Unit of the step; glucose steps are in the unit its rows are stored in.
+    pub(super) fn unit(self, glucose_unit: GlucoseUnit) -> &'static str {
+        match self {
+            PrecisionMetric::Glucose => glucose_unit.csv_name(),
+            PrecisionMetric::Ketones => "mmol/L",
+            PrecisionMetric::Weight => "kg",
+            PrecisionMetric::Insulin => "U",
+            PrecisionMetric::Carbs => "g",
+        }
+    }
+
+    /// Steps `/precision` accepts for this metric, coarsest first.
+    pub(super) fn steps(self, glucose_unit: GlucoseUnit) -> &'static [f64] {
+        match (self, glucose_unit) {
+            (PrecisionMetric::Glucose, GlucoseUnit::Mmol) => &MMOL_GLUCOSE_STEPS,
+            (PrecisionMetric::Glucose, GlucoseUnit::Mgdl) => &MGDL_GLUCOSE_STEPS,
+            _ => &PRECISION_STEPS,
+        }
+    }
+
+    /// Step used until `/precision` sets one. Glucose keeps the two decimals mmol/L rows
+    /// always had and the one decimal of mg/dL rows, so converted readings round-trip;
+    /// insulin allows pump steps.
+    pub(super) fn default_step(self, glucose_unit: GlucoseUnit) -> f64 {
+        match (self, glucose_unit) {
+            (PrecisionMetric::Glucose, GlucoseUnit::Mmol) => 0.01,
+            (PrecisionMetric::Glucose, GlucoseUnit::Mgdl) => 0.1,
+            (PrecisionMetric::Weight | PrecisionMetric::Ketones, _) => 0.1,
+            (PrecisionMetric::Insulin, _) => 0.05,
+            (PrecisionMetric::Carbs, _) => 1.0,
+        }
+    }
+}
+
+/// Parses a `/precision` step: one of [`PRECISION_STEPS`], with `.` or `,` as separator.
+pub(super) fn parse_precision_step(step: &str) -> Option<f64> {
+    let step = step.trim().replace(',', ".").parse::<f64>().ok()?;
+    PRECISION_STEPS
+        .into_iter()
+        .find(|allowed| (allowed - step).abs() < 1e-9)
+}
+
+/// `value` rounded to the nearest multiple of `step`, half away from zero. Scaling by the
+/// whole number `1 / step` keeps results such as 5.6 free of binary noise.
+pub(super) fn round_to_step(value: f64, step: f64) -> f64 {
+    let scale = (1.0 / step).round();
+    (value * scale).round() / scale
+}
+
+/// Parses `HH:MM-HH:MM` into its start and end; equal times are rejected.
+pub(super) fn parse_time_window(window: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = window.split_once('-')?;
+    let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+    (start != end).then_some((start, end))
+}
+
+impl TimeRange {
+    pub(super) fn range(&self) -> GlucoseRange {
+        GlucoseRange {
+            low: self.low,
+            high: self.high,
+        }
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        parse_time_window(&self.window).is_some_and(|window| window_contains(window, time))
+    }
+}
+
+/// Carb counting parameters used by `/bolus`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(super) struct BolusSettings {
+    /// Grams of carbohydrate covered by one unit of insulin.
+    pub(super) carb_ratio: f64,
+    /// Glucose drop in mmol/L per unit of insulin.
+    pub(super) correction_factor: f64,
+    /// Glucose the correction aims for, in mmol/L.
+    pub(super) target: f64,
+}
+
+/// Read-only link made by `/share`. Only a hash of the token is kept, so the settings
+/// file itself does not grant access.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct ShareLink {
+    /// SHA-256 of the token as hex.
+    pub(super) token_sha256: String,
+    /// Days of data before each request that the link returns.
+    pub(super) days: i64,
+    /// RFC 3339 time the link stops working.
+    pub(super) expires_at: String,
+}
+
+/// `/avggoal` target: bring the average glucose `percent` below `baseline`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct AverageGoal {
+    /// Average glucose in mmol/L over the window before the goal was set.
+    pub(super) baseline: f64,
+    /// Wanted reduction in percent, e.g. `10.0`.
+    pub(super) percent: f64,
+    /// RFC 3339 time the baseline was captured.
+    pub(super) set_at: String,
+}
+
+impl AverageGoal {
+    /// Average glucose in mmol/L that meets the goal.
+    pub(super) fn target(&self) -> f64 {
+        self.baseline * (1.0 - self.percent / 100.0)
+    }
+}
+
+/// First day of the week for weekly layouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum WeekStart {
+    #[default]
+    Monday,
+    Sunday,
+}
+
+impl WeekStart {
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "monday" | "mon" => Some(WeekStart::Monday),
+            "sunday" | "sun" => Some(WeekStart::Sunday),
+            _ => None,
+        }
+    }
+
+    pub(super) fn weekday(self) -> Weekday {
+        match self {
+            WeekStart::Monday => Weekday::Mon,
+            WeekStart::Sunday => Weekday::Sun,
+        }
+    }
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            WeekStart::Monday => "Monday",
+            WeekStart::Sunday => "Sunday",
+        }
+    }
+}
+
+/// Unit of the values stored in `glucose.csv`; each row records its own unit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum GlucoseUnit {
+    #[default]
+    Mmol,
+    Mgdl,
+}
+
+impl GlucoseUnit {
+    pub(super) fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "mmol" | "mmol/l" => Some(GlucoseUnit::Mmol),
+            "mgdl" | "mg/dl" => Some(GlucoseUnit::Mgdl),
+            _ => None,
+        }
+    }
+
+    /// Argument of `/units` selecting this unit.
+    pub(super) fn command_name(self) -> &'static str {
+        match self {
+            GlucoseUnit::Mmol => "mmol",
+            GlucoseUnit::Mgdl => "mgdl",
+        }
+    }
+
+    /// Value of the `unit` column; rows written before the column existed are mmol/L.
+    pub(super) fn csv_name(self) -> &'static str {
+        match self {
+            GlucoseUnit::Mmol => "mmol/L",
+            GlucoseUnit::Mgdl => "mg/dL",
+        }
+    }
+
+    pub(super) fn from_csv_name(name: &str) -> Option<Self> {
+        match name {
+            "" | "mmol/L" => Some(GlucoseUnit::Mmol),
+            "mg/dL" => Some(GlucoseUnit::Mgdl),
+            _ => None,
+        }
+    }
+
+    /// Stored value in mmol/L, rounded to the two decimals mmol/L rows keep.
+    pub(super) fn to_mmol_l(self, value: f64) -> f64 {
+        match self {
+            GlucoseUnit::Mmol => value,
+            GlucoseUnit::Mgdl => (value / MG_DL_PER_MMOL_L * 100.0).round() / 100.0,
+        }
+    }
+
+    /// Stored form of a value given in mmol/L: mmol/L keeps two decimals and mg/dL one,
+    /// so converting back and forth returns the original reading.
+    pub(super) fn format_stored(self, value_mmol_l: f64) -> String {
+        let (value, scale) = match self {
+            GlucoseUnit::Mmol => (value_mmol_l, 100.0),
+            GlucoseUnit::Mgdl => (value_mmol_l * MG_DL_PER_MMOL_L, 10.0),
+        };
+        ((value * scale).round() / scale).to_string()
+    }
+}
+
+/// Language of the menu button labels; replies are in English either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "uk")]
+    Ukrainian,
+}
+
+impl Language {
+    pub(super) const ALL: [Language; 2] = [Language::English, Language::Ukrainian];
+
+    /// Argument of `/setlanguage` and value in the settings file.
+    pub(super) fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Ukrainian => "uk",
+        }
+    }
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Ukrainian => "Ukrainian",
+        }
+    }
+
+    pub(super) fn parse(code: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|language| language.code().eq_ignore_ascii_case(code.trim()))
+    }
+}
+
+/// Per-user preferences stored as `data/<user_id>/settings.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(super) struct UserSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) target_range: Option<GlucoseRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) before_meal_range: Option<GlucoseRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) after_meal_range: Option<GlucoseRange>,
+    /// Time-of-day ranges; they take precedence over the meal ranges.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) time_ranges: Vec<TimeRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) bolus: Option<BolusSettings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) week_start: Option<WeekStart>,
+    /// Show confirmations in mmol/L and mg/dL; rows are still stored in `glucose_unit`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) dual_units: Option<bool>,
+    /// Tag of a glucose reading sent as a bare number; such a message is not saved when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) default_glucose_tag: Option<GlucoseTag>,
+    /// Plain-text button labels and replies without emoji, for screen readers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) no_emoji: Option<bool>,
+    /// Append the underlying error to the reply when handling a message fails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) verbose_errors: Option<bool>,
+    /// Language of the menu button labels, English when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) language: Option<Language>,
+    /// Send a `.sha256` file after each `/export` file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) export_checksums: Option<bool>,
+    /// Target weight in kg; progress is reported after each weight entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) weight_goal: Option<f64>,
+    /// Percent reduction of the average glucose from a baseline captured by `/avggoal`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) average_goal: Option<AverageGoal>,
+    /// Weight change in kg since the previous entry that gets a note, off when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) weight_alert: Option<f64>,
+    /// Unit new glucose rows are stored in; with mg/dL, whole numbers are entered in mg/dL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) glucose_unit: Option<GlucoseUnit>,
+    /// IANA name of this chat's timezone; `input_timezone` is used when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) timezone: Option<String>,
+    /// Set once this chat was asked to choose a timezone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) timezone_prompted: Option<bool>,
+    /// Set once the plaintext storage notice has been shown to this chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) privacy_acknowledged: Option<bool>,
+    /// Remind this chat after days without entries; off unless turned on with `/nudge`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) nudge: Option<bool>,
+    /// RFC 3339 time of the last nudge sent to this chat.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) nudged_at: Option<String>,
+    /// One reminder two hours after a before-meal reading in place of the configured
+    /// after-meal series; off unless turned on with `/postmeal`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) postmeal_reminder: Option<bool>,
+    /// Add a short note to the confirmation when every saved glucose value is well inside
+    /// the applied range; off unless turned on with `/inrange`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) in_range_note: Option<bool>,
+    /// RFC 3339 start of the ongoing `/fast`, unset when no fast is running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) fast_started_at: Option<String>,
+    /// Links from `/share` until `/revoke`; expired ones are dropped on the next `/share`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) shares: Vec<ShareLink>,
+    /// Mark that replaces [`DEFAULT_CONFIRMATION`] in save confirmations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) confirmation: Option<String>,
+    /// Metrics confirmed other than in detail; metrics not listed are detailed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(super) confirm_styles: BTreeMap<ConfirmMetric, ConfirmStyle>,
+    /// Rounding step by metric; metrics not listed use their default step.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(super) precision: BTreeMap<PrecisionMetric, f64>,
+    /// Daily `HH:MM` times by medication name, exported by `/calendar`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(super) med_times: BTreeMap<String, Vec<String>>,
+}
+
+impl UserSettings {
+    /// Copy with every preference back at its default. Bookkeeping is kept: the privacy
+    /// notice and timezone prompt are not shown again, the last nudge and an ongoing fast
+    /// are remembered, and `/share` links stay until `/revoke`.
+    pub(super) fn reset(&self) -> UserSettings {
+        UserSettings {
+            timezone_prompted: self.timezone_prompted,
+            privacy_acknowledged: self.privacy_acknowledged,
+            nudged_at: self.nudged_at.clone(),
+            fast_started_at: self.fast_started_at.clone(),
+            shares: self.shares.clone(),
+            ..UserSettings::default()
+        }
+    }
+
+    /// Names of the preferences [`UserSettings::reset`] would change, as spelled in
+    /// `settings.toml` with spaces for underscores, e.g. `glucose unit`.
+    pub(super) fn changed_from_defaults(&self) -> anyhow::Result<Vec<String>> {
+        let current = toml::Table::try_from(self)?;
+        let reset = toml::Table::try_from(self.reset())?;
+        Ok(current
+            .iter()
+            .filter(|(key, value)| reset.get(*key) != Some(*value))
+            .map(|(key, _)| key.replace('_', " "))
+            .collect())
+    }
+
+    pub(super) fn confirm_style(&self, metric: ConfirmMetric) -> ConfirmStyle {
+        self.confirm_styles
+            .get(&metric)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Rounding step for `metric`, with glucose in this chat's `/units`.
+    pub(super) fn precision_step(&self, metric: PrecisionMetric) -> f64 {
+        self.step_for(metric, self.glucose_unit.unwrap_or_default())
+    }
+
+    /// Rounding step for `metric` with glucose in `glucose_unit`. A step not allowed for
+    /// it, edited by hand or set before `/units` changed, falls back to the default.
+    fn step_for(&self, metric: PrecisionMetric, glucose_unit: GlucoseUnit) -> f64 {
+        self.precision
+            .get(&metric)
+            .copied()
+            .filter(|step| {
+                metric
+                    .steps(glucose_unit)
+                    .iter()
+                    .any(|allowed| (allowed - step).abs() < 1e-9)
+            })
+            .unwrap_or_else(|| metric.default_step(glucose_unit))
+    }
+
+    /// `value` of `metric` rounded to this chat's step. Values are rounded once, when
+    /// saved, so every reply and export shows the stored form, e.g. `4.5` or `4`.
+    pub(super) fn round_value(&self, metric: PrecisionMetric, value: f64) -> f64 {
+        round_to_step(value, self.precision_step(metric))
+    }
+
+    /// Glucose `value_mmol_l` as saved in a row of `unit`, rounded to the glucose step in
+    /// that unit: mg/dL rows are rounded in mg/dL, so 105 mg/dL is not saved as 104.9.
+    pub(super) fn round_glucose(&self, value_mmol_l: f64, unit: GlucoseUnit) -> f64 {
+        let step = self.step_for(PrecisionMetric::Glucose, unit);
+        match unit {
+            GlucoseUnit::Mmol => round_to_step(value_mmol_l, step),
+            GlucoseUnit::Mgdl => {
+                round_to_step(value_mmol_l * MG_DL_PER_MMOL_L, step) / MG_DL_PER_MMOL_L
+            }
+        }
+    }
+
+    /// This chat's `/timezone`, or `None` when unset or no longer a known zone.
+    pub(super) fn tz(&self) -> Option<Tz> {
+        self.timezone.as_deref().and_then(|name| name.parse().ok())
+    }
+
+    /// General target range, falling back to [`DEFAULT_TARGET_RANGE`].
+    pub(super) fn general_range(&self) -> GlucoseRange {
+        self.target_range.unwrap_or(DEFAULT_TARGET_RANGE)
+    }
+
+    /// Range for a reading with `tag`: the tag-specific range if set, else the general one.
+    pub(super) fn range_for(&self, tag: GlucoseTag) -> GlucoseRange {
+        let specific = match tag {
+            GlucoseTag::BeforeMeal => self.before_meal_range,
+            GlucoseTag::AfterMeal => self.after_meal_range,
+        };
+        specific.unwrap_or_else(|| self.general_range())
+    }
+
+    /// Range for a reading with `tag` taken at local `time`, with the name of the band
+    /// applied: the first time window containing `time`, else the range for `tag`.
+    pub(super) fn range_at(&self, tag: GlucoseTag, time: NaiveTime) -> (GlucoseRange, String) {
+        if let Some(time_range) = self.time_ranges.iter().find(|range| range.contains(time)) {
+            return (time_range.range(), time_range.window.clone());
+        }
+        let label = match tag {
+            GlucoseTag::BeforeMeal => "before-meal",
+            GlucoseTag::AfterMeal => "after-meal",
+        };
+        (self.range_for(tag), label.to_string())
+    }
+}
+
+/// A chat setting turned on or off by a command such as `/postmeal`; off until set.
+pub(super) struct Toggle {
+    /// Usage line, e.g. `/postmeal <on|off>`.
+    pub(super) usage: &'static str,
+    /// Name of the setting in replies, e.g. `Post-meal reminder`.
+    pub(super) name: &'static str,
+    /// `is` or `are`, to agree with `name` when the state is shown.
+    pub(super) verb: &'static str,
+    pub(super) flag: fn(&mut UserSettings) -> &mut Option<bool>,
+}
+
+impl Toggle {
+    /// Reply to the command: the current state for an empty `payload`, otherwise saves `on`
+    /// or `off` and confirms it, followed by `on_detail` when turned on. The settings file
+    /// is read and written on the blocking pool.
+    pub(super) async fn apply(
+        self,
+        state: &Arc<AppState>,
+        chat_id: ChatId,
+        payload: &str,
+        on_detail: &str,
+    ) -> anyhow::Result<String> {
+        let (payload, on_detail) = (payload.to_lowercase(), on_detail.to_string());
+        storage::blocking(state, move |state| {
+            let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+            let mut user_settings = load_settings(&state.data_dir, chat_id)?;
+            let flag = (self.flag)(&mut user_settings);
+            let reply = match payload.as_str() {
+                "" => format!(
+                    "{} {} {}.",
+                    self.name,
+                    self.verb,
+                    on_off(flag.unwrap_or(false))
+                ),
+                "on" | "off" => {
+                    let enabled = payload == "on";
+                    *flag = Some(enabled);
+                    save_settings(state, chat_id, &user_settings)?;
+                    let mut reply = format!("{} {} ✅", self.name, on_off(enabled));
+                    if enabled && !on_detail.is_empty() {
+                        reply.push(' ');
+                        reply.push_str(&on_detail);
+                    }
+                    reply
+                }
+                _ => format!("Usage: {}", self.usage),
+            };
+            Ok(reply)
+        })
+        .await
+    }
+}
+
+pub(super) fn load_settings(data_dir: &Path, chat_id: ChatId) -> anyhow::Result<UserSettings> {
+    let path = user_data_dir(data_dir, chat_id).join(SETTINGS_FILE);
+    if !path.exists() {
+        return Ok(UserSettings::default());
+    }
+    let content = fs_err::read_to_string(&path)?;
+    toml::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("invalid settings file {}: {e}", path.display()))
+}
+
+pub(super) fn save_settings(
+    state: &AppState,
+    chat_id: ChatId,
+    settings: &UserSettings,
+) -> anyhow::Result<()> {
+    let path = user_data_dir(&state.data_dir, chat_id).join(SETTINGS_FILE);
+    if let Some(parent) = path.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+    write_file_atomically(&path, &toml::to_string(settings)?, state.input.sync_writes)
+}
@@ -0,0 +1,129 @@
+use super::records::parse_timestamp;
+use super::settings::ShareLink;
+use super::*;
+
+const SHARE_DEFAULT_DAYS: i64 = 14;
+const SHARE_MAX_DAYS: i64 = 90;
+const SHARE_LINK_VALID_DAYS: i64 = 7;
+/// Random bytes of a token, sent as twice as many hex digits.
+const SHARE_TOKEN_BYTES: usize = 24;
+/// Path of shared data on the read API, followed by the token.
+pub(super) const SHARE_PATH: &str = "/api/share/";
+
+/// Start of `/share` links when the read API listens on `api_listen`.
+pub(super) fn share_url(listen: Option<&str>, public_url: Option<&str>) -> Option<String> {
+    let listen = listen.map(str::trim).filter(|listen| !listen.is_empty())?;
+    Some(
+        match public_url.map(str::trim).filter(|url| !url.is_empty()) {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => format!("http://{listen}"),
+        },
+    )
+}
+
+pub(super) fn parse_share_args(args: &str) -> Result<i64, String> {
+    if args.is_empty() {
+        return Ok(SHARE_DEFAULT_DAYS);
+    }
+    match args.parse::<i64>() {
+        Ok(days) if (1..=SHARE_MAX_DAYS).contains(&days) => Ok(days),
+        _ => Err(format!("Usage: /share [1-{SHARE_MAX_DAYS} days]")),
+    }
+}
+
+/// Token of a new link: random bytes from the operating system as hex, so a link cannot
+/// be guessed from another one or from the time it was made.
+fn new_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; SHARE_TOKEN_BYTES];
+    getrandom::fill(&mut bytes)
+        .map_err(|e| anyhow::anyhow!("cannot get random bytes for a share token: {e}"))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn token_hash(token: &str) -> String {
+    checksum::sha256_hex(token.as_bytes())
+}
+
+fn is_active(link: &ShareLink, now: DateTime<Utc>) -> bool {
+    parse_timestamp(&link.expires_at).is_some_and(|expires| expires > now)
+}
+
+/// `/share [days]`: makes a link valid for [`SHARE_LINK_VALID_DAYS`] that returns this
+/// chat's last `days` days of data, and drops the chat's expired links.
+pub(super) fn share_command(
+    state: &AppState,
+    chat_id: ChatId,
+    days: i64,
+) -> anyhow::Result<String> {
+    let Some(base) = state.share_url.as_deref() else {
+        return Ok(
+            "Sharing needs the read API: build the bot with --features api and set api_listen."
+                .to_string(),
+        );
+    };
+    let token = new_token()?;
+    let now = state.now();
+    let expires = now + chrono::Duration::days(SHARE_LINK_VALID_DAYS);
+    let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+    user_settings.shares.retain(|link| is_active(link, now));
+    user_settings.shares.push(ShareLink {
+        token_sha256: token_hash(&token),
+        days,
+        expires_at: expires.to_rfc3339_opts(SecondsFormat::Secs, false),
+    });
+    settings::save_settings(state, chat_id, &user_settings)?;
+    tracing::info!("chat {} created a share link for {days} days", chat_id.0);
+    let until = expires
+        .with_timezone(&state.input_for(chat_id).tz)
+        .format("%Y-%m-%d %H:%M");
+    Ok(format!(
+        "Read-only link to your last {days} days of glucose, weight, insulin and meals, \
+valid until {until}:\n{base}{SHARE_PATH}{token}\n\
+Anyone with the link can read this data. /revoke turns off all your links."
+    ))
+}
+
+/// `/revoke`: turns off every link of this chat.
+pub(super) fn revoke_command(state: &AppState, chat_id: ChatId) -> anyhow::Result<String> {
+    let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+    let now = state.now();
+    let active = user_settings
+        .shares
+        .iter()
+        .filter(|link| is_active(link, now))
+        .count();
+    if user_settings.shares.is_empty() {
+        return Ok("You have no share links.".to_string());
+    }
+    user_settings.shares.clear();
+    settings::save_settings(state, chat_id, &user_settings)?;
+    tracing::info!("chat {} revoked its share links", chat_id.0);
+    Ok(match active {
+        0 => "Your share links had already expired; they are removed.".to_string(),
+        1 => "Share link revoked ✅ It no longer works.".to_string(),
+        n => format!("{n} share links revoked ✅ They no longer work."),
+    })
+}
+
+/// Chat and window in days of the active link with `token`, if any. Only chats that may
+/// still use the bot are searched, so a link stops working once its chat is removed.
+/// Reads a settings file per chat; run it through [`storage::blocking`].
+#[cfg(feature = "api")]
+pub(super) fn find_share(state: &AppState, token: &str) -> anyhow::Result<Option<(ChatId, i64)>> {
+    if token.len() != SHARE_TOKEN_BYTES * 2 || !token.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    let hash = token_hash(token);
+    let now = state.now();
+    for chat_id in state.allowed_chats() {
+        let user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+        if let Some(link) = user_settings
+            .shares
+            .iter()
+            .find(|link| same_token(&link.token_sha256, &hash) && is_active(link, now))
+        {
+            return Ok(Some((chat_id, link.days)));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,79 @@
+use super::records::read_raw_rows;
+use super::*;
+
+pub(super) const STATS_FILE: &str = "stats.csv";
+const STATS_HEADER: &str = "date,entries,active_users";
+pub(super) const STATS_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Entries and active users per day in `tz`, over the data files of every user under
+/// `data_dir`. A user is active on a day with at least one entry. Only row timestamps
+/// are read; values, notes and chat ids do not leave this function.
+pub(super) fn daily_counts(
+    data_dir: &Path,
+    tz: Tz,
+) -> anyhow::Result<BTreeMap<NaiveDate, (usize, usize)>> {
+    let mut counts: BTreeMap<NaiveDate, (usize, usize)> = BTreeMap::new();
+    if !data_dir.exists() {
+        return Ok(counts);
+    }
+    for entry in fs_err::read_dir(data_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !entry.file_type()?.is_dir() || name.parse::<i64>().is_err() {
+            continue;
+        }
+        let mut user_days = BTreeMap::<NaiveDate, usize>::new();
+        for target in ClearTarget::ALL {
+            for row in read_raw_rows(&entry.path().join(target.file_name()))? {
+                *user_days
+                    .entry(row.timestamp.with_timezone(&tz).date_naive())
+                    .or_default() += 1;
+            }
+        }
+        for (date, entries) in user_days {
+            let day = counts.entry(date).or_default();
+            day.0 += entries;
+            day.1 += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Rewrites `stats.csv` in `data_dir` with one row per day before `today`, so a day is
+/// only reported once it is over.
+pub(super) fn write_stats(
+    data_dir: &Path,
+    tz: Tz,
+    today: NaiveDate,
+    sync: bool,
+) -> anyhow::Result<()> {
+    let mut content = format!("{STATS_HEADER}\n");
+    for (date, (entries, users)) in daily_counts(data_dir, tz)?.range(..today) {
+        content.push_str(&format!("{date},{entries},{users}\n"));
+    }
+    write_file_atomically(&data_dir.join(STATS_FILE), &content, sync)
+}
+
+/// Runs [`write_stats`] at startup and then once a day when `operator_stats` is on.
+pub(super) fn spawn_stats_task(state: Arc<AppState>) {
+    if !state.operator_stats {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STATS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let data_dir = state.data_dir.clone();
+            let tz = state.input.tz;
+            let today = state.now().with_timezone(&tz).date_naive();
+            let sync = state.input.sync_writes;
+            let result =
+                tokio::task::spawn_blocking(move || write_stats(&data_dir, tz, today, sync)).await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => tracing::error!("writing usage stats failed: {err}"),
+                Err(err) => tracing::error!("usage stats task panicked: {err}"),
+            }
+        }
+    });
+}
@@ -0,0 +1,73 @@
+use super::*;
+
+/// Next run of a task that ticked at `started_at` and then every `period`, as the
+/// `tokio::time::interval` loops of the background tasks do.
+pub(super) fn next_run(
+    started_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+    period: Duration,
+) -> DateTime<Utc> {
+    let period = chrono::Duration::from_std(period).unwrap_or(chrono::Duration::days(1));
+    let elapsed = (now - started_at).max(chrono::Duration::zero());
+    let runs = elapsed.num_seconds() / period.num_seconds().max(1) + 1;
+    started_at + period * runs as i32
+}
+
+/// Whole days, hours and minutes of `duration`, e.g. `2d 3h 5m`.
+fn format_uptime(duration: chrono::Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{days}d {hours}h {minutes}m")
+    } else {
+        format!("{hours}h {minutes}m")
+    }
+}
+
+/// Reply to the admin `/status`: uptime, chats, today's entries over all users, the next
+/// run of each background task and where entries are stored. Only counts are read from
+/// the data files, as for `stats.csv`.
+pub(super) fn status_report(state: &AppState) -> anyhow::Result<String> {
+    let now = state.now();
+    let tz = state.input.tz;
+    let today = now.with_timezone(&tz).date_naive();
+    let (entries_today, active_today) = stats::daily_counts(&state.data_dir, tz)?
+        .get(&today)
+        .copied()
+        .unwrap_or_default();
+    let registered = state
+        .registered_chat_ids
+        .read()
+        .map_err(|_| anyhow::anyhow!("registered chats lock poisoned"))?
+        .len();
+    let local = |time: DateTime<Utc>| time.with_timezone(&tz).format("%Y-%m-%d %H:%M");
+    let task = |enabled: bool, period: Duration| {
+        if enabled {
+            local(next_run(state.started_at, now, period)).to_string()
+        } else {
+            "off".to_string()
+        }
+    };
+    Ok(format!(
+        "Uptime: {} (since {})\n\
+Chats: {} configured, {registered} registered, {active_today} active today\n\
+Entries today: {entries_today}\n\
+Next runs ({tz}):\n\
+- backup: {}\n\
+- usage stats: {}\n\
+- nudge check: {}\n\
+Storage: CSV files in {}{}",
+        format_uptime(now - state.started_at),
+        local(state.started_at),
+        state.allowed_chat_ids.len(),
+        task(state.backup.is_some(), backup::BACKUP_INTERVAL),
+        task(state.operator_stats, stats::STATS_INTERVAL),
+        task(true, nudge::NUDGE_CHECK_INTERVAL),
+        state.data_dir.display(),
+        if state.input.sync_writes {
+            ", synced writes"
+        } else {
+            ""
+        },
+    ))
+}
@@ -0,0 +1,102 @@
+use super::*;
+
+/// Runs synchronous file work on tokio's blocking thread pool, so a slow disk holds up
+/// only the chat that is writing and not the reminders and other chats sharing the
+/// runtime worker. `work` gets the shared state and logs to the caller's subscriber; a
+/// panic in it is returned as an error.
+pub(super) async fn blocking<T, F>(state: &Arc<AppState>, work: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&AppState) -> anyhow::Result<T> + Send + 'static,
+{
+    let state = Arc::clone(state);
+    let dispatch = tracing::dispatcher::get_default(Clone::clone);
+    tokio::task::spawn_blocking(move || {
+        tracing::dispatcher::with_default(&dispatch, || work(&state))
+    })
+    .await?
+}
+
+/// Like [`blocking`], holding `chat_id`'s file lock around `work` so its appends cannot
+/// land between another task's read and rewrite of the same file.
+pub(super) async fn locked<T, F>(
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+    work: F,
+) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&AppState) -> anyhow::Result<T> + Send + 'static,
+{
+    blocking(state, move |state| {
+        state.rewrite_files(chat_id, || work(state))
+    })
+    .await
+}
+
+/// Upgrades the headers of every chat's data files written by an older version, once
+/// at startup, so appends do not have to re-read a file to check its header.
+pub(super) fn upgrade_headers(data_dir: &Path, sync: bool) -> anyhow::Result<()> {
+    for entry in fs_err::read_dir(data_dir)? {
+        let dir = entry?.path();
+        let is_chat_dir = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.parse::<i64>().is_ok());
+        if !is_chat_dir || !dir.is_dir() {
+            continue;
+        }
+        for target in ClearTarget::ALL {
+            let path = dir.join(target.file_name());
+            if path.exists() {
+                upgrade_csv_header(&path, target.header(), sync)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+const SELF_TEST_FILE: &str = ".startup-selftest.csv";
+/// Non-ASCII note of the self-test row, so an encoding problem shows up too.
+const SELF_TEST_NOTE: &str = "self-test, \"Глюкоза\" 🩸";
+
+/// Startup self-test enabled by `startup_selftest`: writes a row to a scratch file in
+/// `data_dir` with the writers entries use, reads it back, deletes the file and checks
+/// the row came back unchanged. The scratch file is removed even when a step fails.
+pub(super) fn self_test(data_dir: &Path, clock: &dyn clock::Clock) -> anyhow::Result<()> {
+    let path = data_dir.join(SELF_TEST_FILE);
+    let result = self_test_round_trip(&path, clock);
+    let removed = if path.exists() {
+        fs_err::remove_file(&path).map_err(anyhow::Error::from)
+    } else {
+        Ok(())
+    };
+    result.and(removed).map_err(|err| {
+        anyhow::anyhow!(
+            "startup self-test failed in {}: {err}. Check that the bot can write UTF-8 \
+files there",
+            data_dir.display()
+        )
+    })
+}
+
+fn self_test_round_trip(path: &Path, clock: &dyn clock::Clock) -> anyhow::Result<()> {
+    let timestamp = clock.now().to_rfc3339_opts(SecondsFormat::Secs, false);
+    append_line_if_needed(path, "timestamp,note", false)?;
+    append_csv_line(
+        path,
+        &format!("{timestamp},\"{}\"", csv_escape(SELF_TEST_NOTE)),
+        false,
+    )?;
+    let rows = records::read_raw_rows(path)?;
+    let [row] = rows.as_slice() else {
+        anyhow::bail!("expected 1 row back, read {}", rows.len());
+    };
+    let expected = [timestamp.as_str(), SELF_TEST_NOTE];
+    anyhow::ensure!(
+        row.fields == expected,
+        "row read back as {:?}, expected {expected:?}",
+        row.fields
+    );
+    Ok(())
+}
@@ -0,0 +1,268 @@
+use super::chart::{GlucoseAxis, ensure_finite, midnight};
+use super::pdf::{DISCLAIMER, Font, PAGE_HEIGHT, PAGE_WIDTH, PdfPage};
+use super::plot::{self, Plot};
+use super::records::{GlucoseRecord, WeightRecord};
+use super::reports::linear_regression;
+use super::settings::GlucoseRange;
+use super::{GlucoseTag, mmol_l_to_mg_dl};
+use chrono::{DateTime, Days, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+pub(super) const SUMMARY_DAYS: i64 = 90;
+pub(super) const SUMMARY_FILE_NAME: &str = "glucose-summary.pdf";
+/// Below either threshold the summary carries a limited-data note.
+const LIMITED_MIN_READINGS: usize = 30;
+const LIMITED_MIN_DAYS: usize = 14;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct WeightSummary {
+    first: f64,
+    last: f64,
+    /// Linear trend in kg per week, if the entries span more than one point in time.
+    per_week: Option<f64>,
+}
+
+/// Figures shown on the clinic summary.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct ClinicSummary {
+    from: NaiveDate,
+    to: NaiveDate,
+    readings: usize,
+    days_with_data: usize,
+    average: Option<f64>,
+    /// Label, average and number of readings per tag.
+    by_tag: Vec<(&'static str, f64, usize)>,
+    weight: Option<WeightSummary>,
+    /// Daily glucose averages in the input timezone.
+    daily: Vec<(NaiveDate, f64)>,
+    target: GlucoseRange,
+}
+
+fn average(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Estimated A1c (%) from average glucose using the ADAG formula.
+pub(super) fn estimated_a1c(average_mmol_l: f64) -> f64 {
+    (mmol_l_to_mg_dl(average_mmol_l) + 46.7) / 28.7
+}
+
+/// Collects the last [`SUMMARY_DAYS`] days of glucose and weight data.
+pub(super) fn clinic_summary(
+    glucose: &[GlucoseRecord],
+    weights: &[WeightRecord],
+    target: GlucoseRange,
+    tz: Tz,
+    now: DateTime<Utc>,
+) -> ClinicSummary {
+    let since = now - Duration::days(SUMMARY_DAYS);
+    let readings = glucose
+        .iter()
+        .filter(|record| record.timestamp > since && record.timestamp <= now)
+        .collect::<Vec<_>>();
+    let values = readings
+        .iter()
+        .map(|record| record.value)
+        .collect::<Vec<_>>();
+
+    let by_tag = [GlucoseTag::BeforeMeal, GlucoseTag::AfterMeal]
+        .into_iter()
+        .filter_map(|tag| {
+            let values = readings
+                .iter()
+                .filter(|record| record.tag == tag.as_csv_tag())
+                .map(|record| record.value)
+                .collect::<Vec<_>>();
+            let label = match tag {
+                GlucoseTag::BeforeMeal => "Before meal",
+                GlucoseTag::AfterMeal => "After meal",
+            };
+            average(&values).map(|avg| (label, avg, values.len()))
+        })
+        .collect();
+
+    let mut by_day: BTreeMap<NaiveDate, Vec<f64>> = BTreeMap::new();
+    for record in &readings {
+        let day = record.timestamp.with_timezone(&tz).date_naive();
+        by_day.entry(day).or_default().push(record.value);
+    }
+    let daily = by_day
+        .iter()
+        .filter_map(|(day, values)| average(values).map(|avg| (*day, avg)))
+        .collect::<Vec<_>>();
+
+    let mut weights = weights
+        .iter()
+        .filter(|record| record.timestamp > since && record.timestamp <= now)
+        .collect::<Vec<_>>();
+    weights.sort_by_key(|record| record.timestamp);
+    let weight = match (weights.first(), weights.last()) {
+        (Some(first), Some(last)) if weights.len() > 1 => {
+            let points = weights
+                .iter()
+                .map(|record| {
+                    let days = (record.timestamp - since).num_seconds() as f64 / 86_400.0;
+                    (days, record.value)
+                })
+                .collect::<Vec<_>>();
+            Some(WeightSummary {
+                first: first.value,
+                last: last.value,
+                per_week: linear_regression(&points).map(|(slope, _)| slope * 7.0),
+            })
+        }
+        _ => None,
+    };
+
+    ClinicSummary {
+        from: since.with_timezone(&tz).date_naive() + Duration::days(1),
+        to: now.with_timezone(&tz).date_naive(),
+        readings: readings.len(),
+        days_with_data: readings
+            .iter()
+            .map(|record| record.timestamp.with_timezone(&tz).date_naive())
+            .collect::<BTreeSet<_>>()
+            .len(),
+        average: average(&values),
+        by_tag,
+        weight,
+        daily,
+        target,
+    }
+}
+
+/// Text lines of the summary, in page order.
+pub(super) fn summary_lines(summary: &ClinicSummary) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Period: {} to {} (last {SUMMARY_DAYS} days)",
+        summary.from, summary.to
+    )];
+    let Some(avg) = summary.average else {
+        lines.push("No glucose readings in this period.".to_string());
+        lines.extend(weight_line(summary));
+        return lines;
+    };
+    lines.push(format!(
+        "Glucose readings: {} on {} days",
+        summary.readings, summary.days_with_data
+    ));
+    if summary.readings < LIMITED_MIN_READINGS || summary.days_with_data < LIMITED_MIN_DAYS {
+        lines.push(format!(
+            "Limited data: fewer than {LIMITED_MIN_READINGS} readings or {LIMITED_MIN_DAYS} days. \
+Figures may not be representative."
+        ));
+    }
+    lines.push(format!(
+        "Average glucose: {avg:.1} mmol/L ({:.0} mg/dL)",
+        mmol_l_to_mg_dl(avg)
+    ));
+    for (label, tag_avg, count) in &summary.by_tag {
+        lines.push(format!("{label}: {tag_avg:.1} mmol/L ({count} readings)"));
+    }
+    lines.push(format!(
+        "Estimated A1c: {:.1} % (from average glucose, not a lab result)",
+        estimated_a1c(avg)
+    ));
+    lines.push(format!("Target range: {} mmol/L", summary.target));
+    lines.extend(weight_line(summary));
+    lines
+}
+
+/// Text form of the summary for when the PDF cannot be drawn: [`summary_lines`] and the
+/// daily averages the chart plots.
+pub(super) fn numbers(summary: &ClinicSummary) -> String {
+    let mut lines = summary_lines(summary);
+    if !summary.daily.is_empty() {
+        lines.push("Daily averages (mmol/L):".to_string());
+        lines.extend(
+            summary
+                .daily
+                .iter()
+                .map(|(day, avg)| format!("{day}  {avg:.1}")),
+        );
+    }
+    lines.join("\n")
+}
+
+fn weight_line(summary: &ClinicSummary) -> Option<String> {
+    let Some(weight) = &summary.weight else {
+        return Some("Weight: not enough entries for a trend.".to_string());
+    };
+    let mut line = format!(
+        "Weight: {:.1} kg -> {:.1} kg ({:+.1} kg)",
+        weight.first,
+        weight.last,
+        weight.last - weight.first
+    );
+    if let Some(per_week) = weight.per_week {
+        line.push_str(&format!(", trend {per_week:+.2} kg per week"));
+    }
+    Some(line)
+}
+
+/// Renders the summary as a one-page A4 PDF with a daily-average glucose chart on `axis`.
+pub(super) fn render_pdf(summary: &ClinicSummary, axis: GlucoseAxis) -> anyhow::Result<Vec<u8>> {
+    ensure_finite(summary.daily.iter().map(|(_, avg)| *avg))?;
+    let mut page = PdfPage::new("Glucose summary")?;
+    let left = 60.0;
+    let mut y = PAGE_HEIGHT - 70.0;
+    page.text(left, y, Font::Bold, 18.0, "Glucose summary");
+    y -= 30.0;
+    for line in summary_lines(summary) {
+        page.text(left, y, Font::Regular, 11.0, &line);
+        y -= 18.0;
+    }
+
+    y -= 12.0;
+    page.text(
+        left,
+        y,
+        Font::Bold,
+        11.0,
+        &format!("Daily average glucose ({})", axis.unit()),
+    );
+    let chart_width = PAGE_WIDTH - 2.0 * left;
+    let (width, height) = DailyPlot::SIZE;
+    let chart_height = chart_width * f64::from(height) / f64::from(width);
+    let pixels = plot::to_rgb(&DailyPlot { summary, axis })?;
+    page.image(
+        left,
+        y - 8.0 - chart_height,
+        chart_width,
+        DailyPlot::SIZE,
+        pixels,
+    );
+
+    page.text(left, 40.0, Font::Regular, 8.0, DISCLAIMER);
+    page.into_pdf()
+}
+
+/// Daily averages over the summary period, drawn into the PDF as an image.
+struct DailyPlot<'a> {
+    summary: &'a ClinicSummary,
+    axis: GlucoseAxis,
+}
+
+impl Plot for DailyPlot<'_> {
+    const SIZE: (u32, u32) = (1200, 560);
+
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> DrawResult<(), DB> {
+        let DailyPlot { summary, axis } = *self;
+        let times = (midnight(summary.from), midnight(summary.to + Days::new(1)));
+        let mut plot = axis.plot(root, times, "%Y-%m-%d", summary.target)?;
+        if summary.daily.is_empty() {
+            return plot::draw_empty(&mut plot, "No data to chart.");
+        }
+        let averages = summary
+            .daily
+            .iter()
+            .map(|(day, avg)| (midnight(*day) + Duration::hours(12), axis.position(*avg)));
+        plot.draw_series(
+            LineSeries::new(averages, RGBColor(26, 51, 179).stroke_width(2)).point_size(3),
+        )?;
+        Ok(())
+    }
+}
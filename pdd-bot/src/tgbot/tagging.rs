@@ -0,0 +1,170 @@
+use super::export::parse_period;
+use super::records::{parse_csv_line, parse_timestamp, replace_field};
+use super::settings::WeekStart;
+use super::*;
+use chrono::Days;
+
+const TAG_MAX_LEN: usize = 30;
+
+/// Days whose glucose readings `/tag` labels, in the chat's timezone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TagWindow {
+    Today,
+    Yesterday,
+    /// From the first day of the current week to today.
+    ThisWeek,
+    /// The whole week before the current one.
+    LastWeek,
+    Dates(NaiveDate, NaiveDate),
+}
+
+impl TagWindow {
+    /// First and last day of the window as of `today`, weeks starting on `week_start`.
+    pub(super) fn days(self, today: NaiveDate, week_start: WeekStart) -> (NaiveDate, NaiveDate) {
+        let since_week_start = today.weekday().days_since(week_start.weekday());
+        let week_first = today - Days::new(u64::from(since_week_start));
+        match self {
+            TagWindow::Today => (today, today),
+            TagWindow::Yesterday => (today - Days::new(1), today - Days::new(1)),
+            TagWindow::ThisWeek => (week_first, today),
+            TagWindow::LastWeek => (week_first - Days::new(7), week_first - Days::new(1)),
+            TagWindow::Dates(from, to) => (from, to),
+        }
+    }
+}
+
+/// `/tag #<tag> <window>`: label the glucose readings of a past period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct TagRequest {
+    /// Lowercase, with the leading `#`.
+    pub(super) tag: String,
+    pub(super) window: TagWindow,
+}
+
+fn usage() -> String {
+    "Usage: /tag #<tag> <today|yesterday|thisweek|lastweek>\n\
+or /tag #<tag> <from YYYY-MM-DD> <to YYYY-MM-DD>, e.g. /tag #sick lastweek"
+        .to_string()
+}
+
+pub(super) fn parse_tag_args(args: &str) -> Result<TagRequest, String> {
+    let parts = args.split_whitespace().collect::<Vec<_>>();
+    let (tag, window) = match parts.as_slice() {
+        [tag, window] => {
+            let window = match window.to_lowercase().as_str() {
+                "today" => TagWindow::Today,
+                "yesterday" => TagWindow::Yesterday,
+                "thisweek" => TagWindow::ThisWeek,
+                "lastweek" => TagWindow::LastWeek,
+                _ => return Err(usage()),
+            };
+            (tag, window)
+        }
+        [tag, from, to] => {
+            let (from, to) = parse_period(from, to)?;
+            (tag, TagWindow::Dates(from, to))
+        }
+        _ => return Err(usage()),
+    };
+    Ok(TagRequest {
+        tag: parse_tag(tag)?,
+        window,
+    })
+}
+
+/// A `#` followed by letters, digits, `-` or `_`; stored lowercase so tags compare as
+/// typed in any case.
+fn parse_tag(raw: &str) -> Result<String, String> {
+    let valid = raw.strip_prefix('#').filter(|name| {
+        !name.is_empty()
+            && name.chars().count() <= TAG_MAX_LEN
+            && name
+                .chars()
+                .all(|ch| ch.is_alphanumeric() || ch == '-' || ch == '_')
+    });
+    let Some(name) = valid else {
+        return Err(format!(
+            "Invalid tag '{raw}'. Use # and up to {TAG_MAX_LEN} letters, digits, - or _, e.g. #sick."
+        ));
+    };
+    let tag = format!("#{}", name.to_lowercase());
+    if tag == CONFIRMED_TAG {
+        return Err(format!(
+            "{CONFIRMED_TAG} is set when a reading is entered, with a trailing !."
+        ));
+    }
+    Ok(tag)
+}
+
+/// Rows counted by [`tag_glucose`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct Tagged {
+    pub(super) tagged: usize,
+    /// Rows in the window that already carried the tag.
+    pub(super) already: usize,
+}
+
+/// Appends `tag` to the space-separated tags column of every glucose row dated
+/// `from..=to` in `tz` and rewrites the file atomically under the chat's file lock.
+/// Rows that already carry the tag are counted and left as they are.
+pub(super) fn tag_glucose(
+    state: &AppState,
+    chat_id: ChatId,
+    tag: &str,
+    (from, to): (NaiveDate, NaiveDate),
+    tz: Tz,
+) -> anyhow::Result<Tagged> {
+    state.rewrite_files(chat_id, || {
+        let path = user_data_dir(&state.data_dir, chat_id).join(GLUCOSE_FILE);
+        let mut result = Tagged::default();
+        if !path.exists() {
+            return Ok(result);
+        }
+        upgrade_csv_header(&path, GLUCOSE_HEADER, state.input.sync_writes)?;
+        let content = fs_err::read_to_string(&path)?;
+        let mut lines = content.lines();
+        let mut rewritten = format!("{}\n", lines.next().unwrap_or(GLUCOSE_HEADER));
+        let mut tagged_lines = Vec::new();
+        for line in lines {
+            let fields = parse_csv_line(line);
+            let in_window = fields
+                .first()
+                .and_then(|raw| parse_timestamp(raw))
+                .is_some_and(|timestamp| {
+                    (from..=to).contains(&timestamp.with_timezone(&tz).date_naive())
+                });
+            let tags = fields
+                .get(GLUCOSE_TAGS_COLUMN)
+                .map(String::as_str)
+                .unwrap_or_default();
+            if !in_window {
+                rewritten.push_str(line);
+            } else if tags.split_whitespace().any(|existing| existing == tag) {
+                result.already += 1;
+                rewritten.push_str(line);
+            } else {
+                // Rows edited by hand may lack trailing columns.
+                let missing = (GLUCOSE_TAGS_COLUMN + 1).saturating_sub(fields.len());
+                let padded = format!("{line}{}", ",".repeat(missing));
+                let tags = if tags.is_empty() {
+                    tag.to_string()
+                } else {
+                    format!("{tags} {tag}")
+                };
+                let tagged = replace_field(&padded, GLUCOSE_TAGS_COLUMN, &tags);
+                rewritten.push_str(&tagged);
+                tagged_lines.push(tagged);
+                result.tagged += 1;
+            }
+            rewritten.push('\n');
+        }
+        if result.tagged > 0 {
+            write_file_atomically(&path, &rewritten, state.input.sync_writes)?;
+            for line in &tagged_lines {
+                log_saved_row(&state.input, chat_id, GLUCOSE_FILE, line, Some(tag));
+            }
+            tracing::info!("chat {} tagged {} glucose rows", chat_id.0, result.tagged);
+        }
+        Ok(result)
+    })
+}
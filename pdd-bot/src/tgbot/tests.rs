@@ -0,0 +1,5314 @@
+use super::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const CHAT: ChatId = ChatId(1);
+
+#[derive(Debug, Clone)]
+struct SentMessage {
+    chat_id: ChatId,
+    text: String,
+    keyboard: KeyboardMarkup,
+    preformatted: bool,
+    /// File name and contents, for documents and photos.
+    document: Option<(String, Vec<u8>)>,
+    /// Whether the file was sent as a photo shown in the chat.
+    photo: bool,
+    /// Labels and data of the buttons attached to the message.
+    inline: Vec<(String, String)>,
+}
+
+impl SentMessage {
+    fn button_labels(&self) -> Vec<String> {
+        self.keyboard
+            .keyboard
+            .iter()
+            .flatten()
+            .map(|button| button.text.clone())
+            .collect()
+    }
+
+    /// Data of the attached button labelled `label`.
+    fn inline_data(&self, label: &str) -> String {
+        self.inline
+            .iter()
+            .find(|(text, _)| text == label)
+            .map(|(_, data)| data.clone())
+            .unwrap_or_else(|| panic!("no button {label:?} in {:?}", self.inline))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct RecordingResponder {
+    sent: Arc<std::sync::Mutex<Vec<SentMessage>>>,
+}
+
+impl RecordingResponder {
+    fn take(&self) -> Vec<SentMessage> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+
+    fn take_texts(&self) -> Vec<String> {
+        self.take().into_iter().map(|sent| sent.text).collect()
+    }
+}
+
+impl Responder for RecordingResponder {
+    fn send_text(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        self.sent.lock().unwrap().push(SentMessage {
+            chat_id,
+            text: text.into(),
+            keyboard,
+            preformatted: false,
+            document: None,
+            photo: false,
+            inline: Vec::new(),
+        });
+        async { Ok(()) }
+    }
+
+    fn send_preformatted(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        self.sent.lock().unwrap().push(SentMessage {
+            chat_id,
+            text: text.into(),
+            keyboard,
+            preformatted: true,
+            document: None,
+            photo: false,
+            inline: Vec::new(),
+        });
+        async { Ok(()) }
+    }
+
+    fn send_document(
+        &self,
+        chat_id: ChatId,
+        file_name: &str,
+        bytes: Vec<u8>,
+        caption: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        self.sent.lock().unwrap().push(SentMessage {
+            chat_id,
+            text: caption.into(),
+            keyboard,
+            preformatted: false,
+            document: Some((file_name.to_string(), bytes)),
+            photo: false,
+            inline: Vec::new(),
+        });
+        async { Ok(()) }
+    }
+
+    fn send_photo(
+        &self,
+        chat_id: ChatId,
+        file_name: &str,
+        bytes: Vec<u8>,
+        caption: impl Into<String>,
+        keyboard: KeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        self.sent.lock().unwrap().push(SentMessage {
+            chat_id,
+            text: caption.into(),
+            keyboard,
+            preformatted: false,
+            document: Some((file_name.to_string(), bytes)),
+            photo: true,
+            inline: Vec::new(),
+        });
+        async { Ok(()) }
+    }
+
+    fn send_inline(
+        &self,
+        chat_id: ChatId,
+        text: impl Into<String>,
+        buttons: InlineKeyboardMarkup,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        let inline = buttons
+            .inline_keyboard
+            .into_iter()
+            .flatten()
+            .map(|button| {
+                let data = match button.kind {
+                    teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => data,
+                    other => format!("{other:?}"),
+                };
+                (button.text, data)
+            })
+            .collect();
+        self.sent.lock().unwrap().push(SentMessage {
+            chat_id,
+            text: text.into(),
+            keyboard: KeyboardMarkup::default(),
+            preformatted: false,
+            document: None,
+            photo: false,
+            inline,
+        });
+        async { Ok(()) }
+    }
+}
+
+/// Temporary data directory removed on drop.
+struct TestDir(PathBuf);
+
+impl TestDir {
+    fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "pdd-bot-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs_err::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+
+    fn read(&self, file: &str) -> String {
+        fs_err::read_to_string(user_data_dir(&self.0, CHAT).join(file)).unwrap()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+struct Harness {
+    dir: TestDir,
+    bot: RecordingResponder,
+    state: Arc<AppState>,
+    next_message_id: AtomicUsize,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let dir = TestDir::new();
+        let state = AppState {
+            pending_by_chat: Arc::new(Mutex::new(HashMap::new())),
+            last_listing_by_chat: Arc::new(Mutex::new(HashMap::new())),
+            after_meal_reminder_generations: Arc::new(Mutex::new(HashMap::new())),
+            allowed_chat_ids: HashSet::from([CHAT]),
+            registered_chat_ids: Arc::new(std::sync::RwLock::new(HashSet::new())),
+            registration_token: None,
+            unauthorized_message: None,
+            told_unauthorized: Arc::new(Mutex::new(HashMap::new())),
+            file_locks: Arc::default(),
+            admin_chat_ids: HashSet::new(),
+            started_at: Utc::now(),
+            data_dir: dir.0.clone(),
+            config_path: None,
+            input: InputOptions {
+                tz: Tz::UTC,
+                note_max_len: DEFAULT_NOTE_MAX_LENGTH,
+                timestamp_precision: TimestampPrecision::Minute,
+                clock: Arc::new(clock::RealClock),
+                glucose_unit: settings::GlucoseUnit::Mmol,
+                sync_writes: false,
+                log_entry_notes: false,
+            },
+            duplicates: DuplicateCheck {
+                window_seconds: DEFAULT_DUPLICATE_WINDOW_SECONDS,
+                action: DuplicateAction::Warn,
+            },
+            glucose_after_meal_reminder_minutes: 0,
+            glucose_after_meal_reminder_count: 0,
+            glucose_after_meal_reminder_interval_minutes: 0,
+            transcriber: None,
+            privacy_notice: false,
+            timezone_prompt: false,
+            backup: None,
+            operator_stats: false,
+            nudge: nudge::NudgeOptions::default(),
+            iob: insulin::IobOptions::default(),
+            share_url: None,
+        };
+        Harness {
+            dir,
+            bot: RecordingResponder::default(),
+            state: Arc::new(state),
+            next_message_id: AtomicUsize::new(1),
+        }
+    }
+
+    /// Sends a voice message whose audio downloads successfully.
+    async fn send_voice(&self) {
+        let message_id = MessageId(self.next_message_id.fetch_add(1, Ordering::Relaxed) as i32);
+        handle_voice(
+            &self.bot,
+            CHAT,
+            message_id,
+            async { Ok(b"audio".to_vec()) },
+            Arc::clone(&self.state),
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Uploads a document named `file_name` holding `content`.
+    async fn send_document(&self, file_name: &str, content: &str) {
+        let content = content.as_bytes().to_vec();
+        handle_document(
+            &self.bot,
+            CHAT,
+            file_name,
+            content.len() as u32,
+            async { Ok(content) },
+            Arc::clone(&self.state),
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn send(&self, text: &str) -> MessageId {
+        self.send_from(CHAT, text).await
+    }
+
+    /// Taps an inline button carrying `data`.
+    async fn tap(&self, data: &str) {
+        handle_callback(&self.bot, CHAT, data, Arc::clone(&self.state))
+            .await
+            .unwrap();
+    }
+
+    /// Sends a message and returns its id.
+    async fn send_from(&self, chat_id: ChatId, text: &str) -> MessageId {
+        let message_id = MessageId(self.next_message_id.fetch_add(1, Ordering::Relaxed) as i32);
+        handle_text(
+            &self.bot,
+            chat_id,
+            message_id,
+            text,
+            Arc::clone(&self.state),
+        )
+        .await
+        .unwrap();
+        message_id
+    }
+
+    async fn edit(&self, message_id: MessageId, text: &str) {
+        edits::handle_edited_text(&self.bot, CHAT, message_id, text, Arc::clone(&self.state))
+            .await
+            .unwrap();
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Contents of the text elements of an SVG drawn by plotters, which writes each
+/// between the lines of its tags, still escaped.
+fn svg_texts(svg: &str) -> Vec<&str> {
+    let lines = svg.lines().collect::<Vec<_>>();
+    lines
+        .windows(3)
+        .filter(|tag| tag[0].starts_with("<text ") && tag[2] == "</text>")
+        .map(|tag| tag[1])
+        .collect()
+}
+
+fn data_lines(content: &str) -> Vec<&str> {
+    content.lines().skip(1).collect()
+}
+
+/// Text drawn on the pages of a PDF, one line per text operation. The glyph ids of the
+/// embedded fonts are mapped back to characters through each font's ToUnicode map.
+fn pdf_text(pdf: &[u8]) -> String {
+    use printpdf::lopdf::{Document, Object, content::Content};
+
+    let document = Document::load_mem(pdf).unwrap();
+    let mut lines = Vec::new();
+    for page in document.get_pages().into_values() {
+        // printpdf refers to the font dictionary, which `Document::get_page_fonts` skips.
+        let fonts: HashMap<&[u8], HashMap<u16, char>> = document
+            .get_dictionary(page)
+            .and_then(|page| page.get_deref(b"Resources", &document))
+            .and_then(Object::as_dict)
+            .and_then(|resources| resources.get_deref(b"Font", &document))
+            .and_then(Object::as_dict)
+            .unwrap()
+            .iter()
+            .map(|(name, font)| {
+                let cmap = document
+                    .dereference(font)
+                    .and_then(|(_, font)| font.as_dict())
+                    .and_then(|font| font.get_deref(b"ToUnicode", &document))
+                    .and_then(Object::as_stream)
+                    .unwrap();
+                let cmap = cmap
+                    .decompressed_content()
+                    .unwrap_or_else(|_| cmap.content.clone());
+                let glyphs = String::from_utf8(cmap)
+                    .unwrap()
+                    .lines()
+                    .filter_map(|line| {
+                        let (glyph, unicode) = line.strip_prefix('<')?.split_once("> <")?;
+                        let unicode = u32::from_str_radix(unicode.strip_suffix('>')?, 16).ok()?;
+                        Some((
+                            u16::from_str_radix(glyph, 16).ok()?,
+                            char::from_u32(unicode)?,
+                        ))
+                    })
+                    .collect();
+                (name.as_slice(), glyphs)
+            })
+            .collect();
+        let content = Content::decode(&document.get_page_content(page).unwrap()).unwrap();
+        let mut font = &HashMap::new();
+        for operation in content.operations {
+            match operation.operator.as_str() {
+                "Tf" => font = &fonts[operation.operands[0].as_name().unwrap()],
+                "Tj" => lines.push(
+                    operation.operands[0]
+                        .as_str()
+                        .unwrap()
+                        .chunks(2)
+                        .map(|glyph| font[&u16::from_be_bytes([glyph[0], glyph[1]])])
+                        .collect::<String>(),
+                ),
+                _ => {}
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+#[tokio::test]
+async fn fixed_clock_stamps_rows_and_fills_in_the_year() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2031-06-15T10:30:45+00:00")));
+    h.send("/addgb 5.8 2/1 9:05").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("78,4").await;
+
+    assert_eq!(
+        records::parse_csv_line(data_lines(&h.dir.read(GLUCOSE_FILE))[0])[0],
+        "2031-02-01T09:05:00+00:00"
+    );
+    assert_eq!(
+        data_lines(&h.dir.read(WEIGHT_FILE)),
+        ["2031-06-15T10:30:00+00:00,1,78.4,3"]
+    );
+}
+
+#[tokio::test]
+async fn weight_button_then_value_saves_weight() {
+    let h = Harness::new();
+    h.send(BTN_WEIGHT).await;
+    h.send("78,4").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|message| message.chat_id == CHAT));
+    assert_eq!(sent[0].text, "Enter weight value (kg), for example: 78.4");
+    assert_eq!(sent[1].text, "Saved ✅");
+
+    let content = h.dir.read("weight.csv");
+    assert!(content.starts_with("timestamp,chat_id,value_kg,message_id\n"));
+    let rows = data_lines(&content);
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].ends_with(",1,78.4,2"));
+}
+
+#[tokio::test]
+async fn invalid_weight_keeps_pending_entry() {
+    let h = Harness::new();
+    h.send(BTN_WEIGHT).await;
+    h.send("heavy").await;
+    h.send("80").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[1],
+        "Could not parse number. Use format like 78.4 (dot or comma)."
+    );
+    assert_eq!(texts[2], "Saved ✅");
+    assert_eq!(data_lines(&h.dir.read("weight.csv")).len(), 1);
+}
+
+#[tokio::test]
+async fn glucose_button_flow_saves_tag_and_note() {
+    let h = Harness::new();
+    h.send(BTN_GLUCOSE_AFTER_MEAL).await;
+    h.send("7,2 2024/2/1 11:00 @after lunch").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts.last().unwrap(), "Saved ✅");
+    let content = h.dir.read("glucose.csv");
+    assert_eq!(
+        data_lines(&content),
+        vec!["2024-02-01T11:00:00+00:00,1,after_meal,7.2,\"after lunch\",manual,2,mmol/L,,,"]
+    );
+}
+
+#[tokio::test]
+async fn addgb_command_saves_glucose() {
+    let h = Harness::new();
+    h.send("/addgb 5.8 @fasting").await;
+
+    assert_eq!(h.bot.take_texts(), vec!["Glucose entry saved ✅"]);
+    let content = h.dir.read("glucose.csv");
+    let rows = data_lines(&content);
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].ends_with(",1,before_meal,5.8,\"fasting\",manual,1,mmol/L,,,"));
+}
+
+#[tokio::test]
+async fn bare_number_is_saved_with_the_default_tag_only_when_set() {
+    let mut h = Harness::new();
+    h.send("5.8").await;
+    h.send("/defaulttag after").await;
+    h.send("7,2").await;
+    h.send("/defaulttag").await;
+    h.send("/defaulttag sometimes").await;
+    h.send("/defaulttag off").await;
+    h.send("6.1").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Choose an action from menu. Type /menu to show buttons or /addmed <name>."
+    );
+    assert_eq!(
+        texts[1],
+        "Default tag set ✅ a bare number is saved as after-meal glucose"
+    );
+    assert_eq!(
+        texts[2],
+        "Glucose entry saved ✅\n(Saved as after-meal glucose, your /defaulttag.)"
+    );
+    assert_eq!(texts[3], "A bare number is saved as after-meal glucose.");
+    assert_eq!(texts[4], "Usage: /defaulttag <before|after|off>");
+    assert_eq!(texts[5], "Default tag cleared ✅");
+    assert_eq!(
+        texts[6],
+        "Choose an action from menu. Type /menu to show buttons or /addmed <name>."
+    );
+    let content = h.dir.read(GLUCOSE_FILE);
+    let rows = data_lines(&content);
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].contains(",1,after_meal,7.2,"), "{}", rows[0]);
+
+    Arc::get_mut(&mut h.state).unwrap().duplicates.action = DuplicateAction::Skip;
+    h.send("/defaulttag after").await;
+    h.bot.take();
+    h.send("7.2").await;
+    h.send("99").await;
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], DUPLICATE_SKIPPED);
+    assert!(!texts[1].contains("/defaulttag"), "{}", texts[1]);
+    assert_eq!(data_lines(&h.dir.read(GLUCOSE_FILE)).len(), 1);
+}
+
+#[test]
+fn iso_dates_are_parsed_next_to_slash_dates() {
+    let options = &InputOptions {
+        tz: chrono_tz::Europe::Kyiv,
+        note_max_len: 0,
+        timestamp_precision: TimestampPrecision::Minute,
+        clock: Arc::new(clock::FixedClock(utc("2024-06-01T12:00:00+00:00"))),
+        glucose_unit: settings::GlucoseUnit::Mmol,
+        sync_writes: false,
+        log_entry_notes: false,
+    };
+    let parse = |input: &str| parse_flexible_datetime(input, options);
+    let local = Some(utc("2024-02-01T07:05:00+00:00"));
+    assert_eq!(parse("2024-02-01 09:05"), local);
+    assert_eq!(parse("2024-02-01T09:05"), local);
+    assert_eq!(parse("2024/2/1 9:05"), local);
+    assert_eq!(parse("2/1 9:05"), local);
+    assert_eq!(
+        parse("2024-02-01T09:05:30"),
+        Some(utc("2024-02-01T07:05:30+00:00"))
+    );
+    assert_eq!(
+        parse("2024-02-01T09:05:00Z"),
+        Some(utc("2024-02-01T09:05:00+00:00"))
+    );
+    assert_eq!(
+        parse("2024-02-01T09:05:00+01:00"),
+        Some(utc("2024-02-01T08:05:00+00:00"))
+    );
+    assert_eq!(parse("2024-02-01T25:05"), None);
+    assert_eq!(parse("2/1T09:05"), None);
+}
+
+#[test]
+fn relative_day_words_are_parsed_in_english_and_ukrainian() {
+    let options = &InputOptions {
+        tz: chrono_tz::Europe::Kyiv,
+        note_max_len: 0,
+        timestamp_precision: TimestampPrecision::Minute,
+        // Already June 2 in Kyiv.
+        clock: Arc::new(clock::FixedClock(utc("2024-06-01T22:30:00+00:00"))),
+        glucose_unit: settings::GlucoseUnit::Mmol,
+        sync_writes: false,
+        log_entry_notes: false,
+    };
+    let parse = |input: &str| parse_flexible_datetime(input, options);
+    let today = Some(utc("2024-06-02T06:05:00+00:00"));
+    let yesterday = Some(utc("2024-06-01T06:05:00+00:00"));
+    assert_eq!(parse("today 9:05"), today);
+    assert_eq!(parse("Today 09:05"), today);
+    assert_eq!(parse("сьогодні 9:05"), today);
+    assert_eq!(parse("yesterday 9:05"), yesterday);
+    assert_eq!(parse("вчора 9:05"), yesterday);
+    assert_eq!(parse("Учора 9:05"), yesterday);
+    assert_eq!(parse("yesterday"), None);
+    assert_eq!(parse("tomorrow 9:05"), None);
+}
+
+#[tokio::test]
+async fn timezone_prompt_on_first_message_sets_the_chat_timezone() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().timezone_prompt = true;
+    h.send("/addgb 5.8 2024-02-01T09:05").await;
+    h.send("/addgb 6.0 2024-02-01T10:00").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent.len(), 3);
+    assert_eq!(sent[0].text, "Glucose entry saved ✅");
+    assert!(
+        sent[1]
+            .text
+            .starts_with("Your timezone is not set, so dates and times you enter are read as UTC")
+    );
+    let labels = sent[1].button_labels();
+    assert_eq!(labels[0], "/timezone Europe/Kyiv");
+    assert_eq!(labels.last().unwrap(), BTN_SHOW_MENU);
+
+    h.send(&labels[0]).await;
+    h.send("/addgb 6.2 2024-02-01T09:05").await;
+    h.send("/timezone mars/base").await;
+    h.send("/timezone").await;
+    let texts = h.bot.take_texts();
+    assert!(texts[0].starts_with("Timezone set to Europe/Kyiv ✅ Your local time is "));
+    assert!(texts[2].starts_with("Unknown timezone 'mars/base'."));
+    assert!(texts[3].starts_with("Your timezone is Europe/Kyiv."));
+    let saved = settings::load_settings(&h.dir.0, CHAT).unwrap();
+    assert_eq!(saved.timezone.as_deref(), Some("Europe/Kyiv"));
+    let content = h.dir.read(GLUCOSE_FILE);
+    let rows = data_lines(&content);
+    assert!(rows[0].starts_with("2024-02-01T09:05:00+00:00,1,before_meal,5.8,"));
+    assert!(rows[2].starts_with("2024-02-01T09:05:00+02:00,1,before_meal,6.2,"));
+
+    h.send("/timezone OFF").await;
+    assert_eq!(
+        h.bot.take_texts(),
+        vec!["Timezone reset ✅ Dates and times are read as UTC."]
+    );
+    assert_eq!(
+        settings::load_settings(&h.dir.0, CHAT).unwrap().timezone,
+        None
+    );
+}
+
+#[tokio::test]
+async fn timezone_prompt_is_skipped_when_choosing_one_first() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().timezone_prompt = true;
+    h.send("/timezone utc").await;
+    h.send("/menu").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts.len(), 2);
+    assert!(texts[0].starts_with("Timezone set to UTC ✅"));
+}
+
+#[tokio::test]
+async fn addgb_accepts_iso_timestamp() {
+    let h = Harness::new();
+    h.send("/addgb 5.8 2024-02-01T09:05 @fasting").await;
+
+    assert_eq!(h.bot.take_texts(), vec!["Glucose entry saved ✅"]);
+    let content = h.dir.read(GLUCOSE_FILE);
+    assert!(data_lines(&content)[0].starts_with("2024-02-01T09:05:00+00:00,1,before_meal,5.8,"));
+}
+
+#[tokio::test]
+async fn addgb_without_payload_shows_usage() {
+    let h = Harness::new();
+    h.send("/addgb").await;
+
+    let texts = h.bot.take_texts();
+    assert!(texts[0].starts_with("Usage:\n/addgb"));
+    assert!(!user_data_dir(&h.dir.0, CHAT).join("glucose.csv").exists());
+}
+
+#[tokio::test]
+async fn goals_combine_range_weight_and_medication_status() {
+    let h = Harness::new();
+    h.send("/goals").await;
+    h.send("/addgb 5.8").await;
+    h.send("/addga 12.0").await;
+    h.send("/setrange 4 11").await;
+    h.send("/weightgoal 75").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("80").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("78").await;
+    h.send("/addmed Metformin").await;
+    h.send("/medtime metformin 08:00").await;
+    h.send("💊 Metformin").await;
+    h.send("💊 Metformin").await;
+    h.send("/goals").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Goals (last 7 days):\n\
+🎯 Glucose in range: no readings. Range not set, using 3.9-10.0 mmol/L; set yours with /setrange.\n\
+📉 Average: not set. Use /avggoal <percent>.\n\
+⚖️ Weight: not set. Use /weightgoal <kg>.\n\
+💊 Medications: not set. Use /medtime <name> <HH:MM> for daily doses."
+    );
+    assert_eq!(
+        texts.last().unwrap(),
+        "Goals (last 7 days):\n\
+🎯 Glucose in range: 50% of 2 readings.\n\
+📉 Average: not set. Use /avggoal <percent>.\n\
+⚖️ Weight: Goal 75.0 kg: 3.0 kg to go (40% of the way from 80.0 kg)\n\
+💊 Medications: Metformin 2/7 (29%)"
+    );
+}
+
+#[tokio::test]
+async fn average_goal_captures_baseline_and_reports_progress() {
+    let mut h = Harness::new();
+    let set_clock = |h: &mut Harness, at: &str| {
+        Arc::get_mut(&mut h.state).unwrap().input.clock = Arc::new(clock::FixedClock(utc(at)));
+    };
+    set_clock(&mut h, "2024-03-01T12:00:00+00:00");
+    h.send("/avggoal 10").await;
+    h.send("/addgb 8.0").await;
+    h.send("/addga 10.0").await;
+    h.send("/avggoal 60").await;
+    h.send("/avggoal 10%").await;
+    h.send("/avggoal").await;
+    set_clock(&mut h, "2024-03-09T12:00:00+00:00");
+    h.send("/goals").await;
+    h.send("/addgb 8.5").await;
+    h.send("/goals").await;
+    h.send("/addgb 7.0").await;
+    h.send("/goals").await;
+    h.send("/avggoal off").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "No glucose readings in the last 7 days to use as the baseline."
+    );
+    assert_eq!(texts[3], "Usage: /avggoal <percent|off>, percent up to 50");
+    assert_eq!(
+        texts[4],
+        "Average goal set ✅ -10% from 9.0 mmol/L (last 7 days) to 8.1 mmol/L"
+    );
+    assert_eq!(texts[5], "Average goal: -10% from 9.0 to 8.1 mmol/L.");
+    let average = |text: &str| text.lines().nth(2).unwrap().to_string();
+    assert_eq!(
+        average(&texts[6]),
+        "📉 Average: goal -10% from 9.0 to 8.1 mmol/L, no readings."
+    );
+    assert_eq!(
+        average(&texts[8]),
+        "📉 Average: 8.5 mmol/L, -5.6% from 9.0; goal -10% (8.1 mmol/L) 56% of the way"
+    );
+    assert_eq!(
+        average(&texts[10]),
+        "📉 Average: 7.8 mmol/L, -13.9% from 9.0; goal -10% (8.1 mmol/L) reached 🎉"
+    );
+    assert_eq!(texts[11], "Average goal cleared ✅");
+}
+
+#[tokio::test]
+async fn adversarial_medication_names_are_cleaned_and_quoted() {
+    let h = Harness::new();
+    h.send("/addmed 💊 💊Aspirin").await;
+    h.send("/addmed Vit\u{0}amin\u{7}\tD\u{200B}\u{202E}").await;
+    h.send("/addmed Insulin, \"fast\"").await;
+    h.send(&format!("/addmed {}", "x".repeat(41))).await;
+    h.send("/addmed \u{1}\u{200F}").await;
+    h.send("💊 Insulin, \"fast\"").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Medication added: Aspirin");
+    assert_eq!(texts[1], "Medication added: Vit amin D");
+    assert_eq!(texts[2], "Medication added: Insulin, \"fast\"");
+    assert_eq!(texts[3], "Medication name must be at most 40 characters.");
+    assert_eq!(texts[4], "Usage: /addmed <medication name>");
+    assert_eq!(texts[5], "Medication usage saved ✅ (Insulin, \"fast\")");
+    assert_eq!(
+        h.dir.read(MEDICATIONS_FILE),
+        "Aspirin\nVit amin D\nInsulin, \"fast\"\n"
+    );
+    let log = h.dir.read(MEDICATION_LOG_FILE);
+    let row = data_lines(&log)[0];
+    assert!(row.ends_with(",1,\"Insulin, \"\"fast\"\"\""), "{row}");
+    assert_eq!(records::parse_csv_line(row)[2], "Insulin, \"fast\"");
+}
+
+#[tokio::test]
+async fn added_medication_appears_in_keyboard_and_logs_usage() {
+    let h = Harness::new();
+    h.send("/addmed  Metformin ").await;
+    h.send("/addmed metformin").await;
+    h.send("💊 Metformin").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent[0].text, "Medication added: Metformin");
+    assert_eq!(sent[1].text, "Medication already exists: metformin");
+    assert_eq!(sent[2].text, "Medication usage saved ✅ (Metformin)");
+    assert!(
+        sent[2]
+            .button_labels()
+            .contains(&"💊 Metformin".to_string())
+    );
+
+    let content = h.dir.read(MEDICATION_LOG_FILE);
+    let rows = data_lines(&content);
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].ends_with(",1,\"Metformin\""));
+}
+
+#[tokio::test]
+async fn no_emoji_mode_sends_plain_labels_that_still_work() {
+    let h = Harness::new();
+    h.send("/addmed Metformin").await;
+    h.send("/noemoji on").await;
+    h.send("Weight").await;
+    h.send("80").await;
+    h.send("Med: Metformin").await;
+    h.send("💊 Metformin").await;
+    h.send("/noemoji maybe").await;
+    h.send("/noemoji off").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent[1].text, "Emoji-free mode on");
+    assert_eq!(
+        sent[1].button_labels(),
+        [
+            "Glucose: Before meal",
+            "Glucose: After meal",
+            "Weight",
+            "Ketones",
+            "Food",
+            "Show menu",
+            "Med: Metformin",
+        ]
+    );
+    assert_eq!(sent[2].text, "Enter weight value (kg), for example: 78.4");
+    assert_eq!(sent[3].text, "Saved");
+    assert_eq!(sent[4].text, "Medication usage saved (Metformin)");
+    assert_eq!(sent[5].text, "Medication usage saved (Metformin)");
+    assert_eq!(sent[6].text, "Usage: /noemoji <on|off>");
+    assert_eq!(sent[7].text, "Emoji-free mode off ✅");
+    assert!(
+        sent[7]
+            .button_labels()
+            .contains(&"💊 Metformin".to_string())
+    );
+    assert_eq!(data_lines(&h.dir.read(MEDICATION_LOG_FILE)).len(), 2);
+    assert_eq!(data_lines(&h.dir.read(WEIGHT_FILE)).len(), 1);
+}
+
+#[tokio::test]
+async fn setlanguage_translates_buttons_and_keeps_old_labels_working() {
+    let h = Harness::new();
+    h.send("/addmed Metformin").await;
+    h.send("/setlanguage uk").await;
+    // A tap on the English keyboard the client still shows right after switching.
+    h.send(BTN_WEIGHT).await;
+    h.send("78,4").await;
+    h.send("⚖️ Вага").await;
+    h.send("79").await;
+    h.send("/noemoji on").await;
+    h.send("Кетони").await;
+    h.send("/noemoji off").await;
+    h.send("/setlanguage en").await;
+    h.send("📋 Показати меню").await;
+    h.send("/setlanguage fr").await;
+    h.send("/addmed вага").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent[1].text, "Menu buttons are now in Ukrainian ✅");
+    assert_eq!(
+        sent[1].button_labels(),
+        [
+            "🩸 Глюкоза: до їжі",
+            "🩸 Глюкоза: після їжі",
+            "⚖️ Вага",
+            "🧪 Кетони",
+            "🍽 Їжа",
+            "📋 Показати меню",
+            "💊 Metformin",
+        ]
+    );
+    assert_eq!(sent[2].text, "Enter weight value (kg), for example: 78.4");
+    assert_eq!(sent[4].text, "Enter weight value (kg), for example: 78.4");
+    assert!(sent[6].button_labels().contains(&"Кетони".to_string()));
+    assert!(sent[7].text.starts_with("Enter ketones:"));
+    assert_eq!(sent[9].text, "Menu buttons are now in English ✅");
+    assert!(sent[9].button_labels().contains(&BTN_WEIGHT.to_string()));
+    assert!(sent[10].text.starts_with("Diabetes diary menu:"));
+    assert_eq!(sent[11].text, "Usage: /setlanguage <en|uk>");
+    assert!(
+        sent[12]
+            .text
+            .starts_with("\"вага\" is reserved for a menu button")
+    );
+    assert_eq!(data_lines(&h.dir.read(WEIGHT_FILE)).len(), 2);
+}
+
+#[tokio::test]
+async fn menutext_lists_keyboard_rows() {
+    let h = Harness::new();
+    h.send("/addmed Metformin").await;
+    h.send("/menutext").await;
+    h.send("/noemoji on").await;
+    h.send("/menutext").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[1],
+        "Menu buttons:\n\
+Row 1: 🩸 Glucose: Before meal | 🩸 Glucose: After meal\n\
+Row 2: ⚖️ Weight | 🧪 Ketones\n\
+Row 3: 🍽 Food | 📋 Show menu\n\
+Row 4: 💊 Metformin"
+    );
+    assert_eq!(
+        texts[3],
+        "Menu buttons:\n\
+Row 1: Glucose: Before meal | Glucose: After meal\n\
+Row 2: Weight | Ketones\n\
+Row 3: Food | Show menu\n\
+Row 4: Med: Metformin"
+    );
+
+    h.send("/noemoji off").await;
+    h.send("/setlanguage uk").await;
+    h.send("/menutext").await;
+    assert_eq!(
+        h.bot.take_texts()[2],
+        "Menu buttons:\n\
+Row 1: 🩸 Глюкоза: до їжі | 🩸 Глюкоза: після їжі\n\
+Row 2: ⚖️ Вага | 🧪 Кетони\n\
+Row 3: 🍽 Їжа | 📋 Показати меню\n\
+Row 4: 💊 Metformin"
+    );
+}
+
+#[test]
+fn strip_emoji_keeps_lines_without_emoji() {
+    assert_eq!(
+        plain::strip_emoji(
+            "🎯 Glucose in range: 80%\nMo    7.0         2\nWeight goal set ✅ (72.0 kg)"
+        ),
+        "Glucose in range: 80%\nMo    7.0         2\nWeight goal set (72.0 kg)"
+    );
+    assert_eq!(plain::strip_emoji("⚠️ Low → stable"), "Low → stable");
+}
+
+#[tokio::test]
+async fn unknown_medication_button_is_rejected() {
+    let h = Harness::new();
+    h.send("💊 Aspirin").await;
+
+    assert_eq!(
+        h.bot.take_texts(),
+        vec!["Unknown medication. Use /addmed <name> first."]
+    );
+}
+
+#[tokio::test]
+async fn menu_keyboard_has_fixed_rows() {
+    let h = Harness::new();
+    h.send("/start").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(
+        sent[0].button_labels(),
+        vec![
+            BTN_GLUCOSE_BEFORE_MEAL,
+            BTN_GLUCOSE_AFTER_MEAL,
+            BTN_WEIGHT,
+            BTN_KETONES,
+            BTN_FOOD,
+            BTN_SHOW_MENU
+        ]
+    );
+}
+
+#[tokio::test]
+async fn messages_from_unknown_chats_are_ignored() {
+    let h = Harness::new();
+    h.send_from(ChatId(2), "/start").await;
+    h.send_from(ChatId(2), "/addgb 5.8").await;
+
+    assert!(h.bot.take().is_empty());
+    assert!(!user_data_dir(&h.dir.0, ChatId(2)).exists());
+}
+
+#[tokio::test]
+async fn group_messages_are_refused_once_and_never_saved() {
+    let mut h = Harness::new();
+    let group = ChatId(-100_123);
+    let state = Arc::get_mut(&mut h.state).unwrap();
+    state.allowed_chat_ids.insert(group);
+    state.registration_token = Some("s3cret-token_0123".to_string());
+    h.send_from(group, "/addgb 5.8").await;
+    h.send_from(group, "/start register_s3cret-token_0123")
+        .await;
+    h.send_from(group, BTN_WEIGHT).await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].chat_id, group);
+    assert_eq!(sent[0].text, GROUP_MESSAGE);
+    assert!(!user_data_dir(&h.dir.0, group).exists());
+    assert!(!h.state.is_allowed(group));
+}
+
+#[tokio::test]
+async fn start_link_with_the_registration_token_registers_the_chat() {
+    let mut h = Harness::new();
+    let state = Arc::get_mut(&mut h.state).unwrap();
+    state.registration_token = Some("s3cret-token_0123".to_string());
+    state.unauthorized_message = Some("Not allowed: {chat_id}".to_string());
+    h.send_from(ChatId(2), "/start register_wrong-token_0123")
+        .await;
+    h.send_from(ChatId(3), "/start register_s3cret-token_0123")
+        .await;
+    h.send_from(ChatId(3), "/addgb 5.8").await;
+    h.send("/start register_s3cret-token_0123").await;
+    h.send("/start promo").await;
+
+    let sent = h.bot.take();
+    let texts = sent
+        .iter()
+        .map(|sent| (sent.chat_id, sent.text.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(texts.len(), 6);
+    assert_eq!(texts[0], (ChatId(2), "Not allowed: 2"));
+    assert_eq!(
+        texts[1],
+        (
+            ChatId(3),
+            "This chat is registered. Welcome to your diabetes diary!"
+        )
+    );
+    assert!(texts[2].1.starts_with("Diabetes diary menu:"));
+    assert_eq!(texts[3], (ChatId(3), "Glucose entry saved ✅"));
+    assert!(texts[4].1.starts_with("Diabetes diary menu:"));
+    assert!(texts[5].1.starts_with("Diabetes diary menu:"));
+    assert_eq!(
+        fs_err::read_to_string(h.dir.0.join("registered_chats.txt")).unwrap(),
+        "3\n"
+    );
+    assert_eq!(
+        registration::load_registered(&h.dir.0).unwrap(),
+        HashSet::from([ChatId(3)])
+    );
+}
+
+#[tokio::test]
+async fn unknown_chats_get_the_unauthorized_message_once() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().unauthorized_message =
+        Some("Ask the admin to add your id: {chat_id}".to_string());
+    h.send_from(ChatId(2), "/start").await;
+    h.send_from(ChatId(2), "/addgb 5.8").await;
+    h.send_from(ChatId(3), "hello").await;
+    h.send("/addgb 5.8").await;
+
+    let sent = h.bot.take();
+    let texts = sent
+        .iter()
+        .map(|sent| (sent.chat_id, sent.text.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        texts,
+        vec![
+            (ChatId(2), "Ask the admin to add your id: 2"),
+            (ChatId(3), "Ask the admin to add your id: 3"),
+            (CHAT, "Glucose entry saved ✅"),
+        ]
+    );
+    assert!(!user_data_dir(&h.dir.0, ChatId(2)).exists());
+}
+
+#[tokio::test]
+async fn told_unauthorized_chats_expire_and_stay_bounded() {
+    let h = Harness::new();
+    let start = Instant::now();
+    assert!(mark_told_unauthorized(&h.state, ChatId(2), start).await);
+    assert!(!mark_told_unauthorized(&h.state, ChatId(2), start).await);
+    let later = start + TOLD_UNAUTHORIZED_EXPIRY;
+    assert!(mark_told_unauthorized(&h.state, ChatId(2), later).await);
+
+    for id in 0..TOLD_UNAUTHORIZED_MAX as i64 {
+        mark_told_unauthorized(&h.state, ChatId(100 + id), later + Duration::from_secs(1)).await;
+    }
+    let told = h.state.told_unauthorized.lock().await;
+    assert_eq!(told.len(), TOLD_UNAUTHORIZED_MAX);
+    assert!(!told.contains_key(&ChatId(2)));
+}
+
+#[tokio::test]
+async fn free_text_without_pending_entry_shows_hint() {
+    let h = Harness::new();
+    h.send("hello").await;
+
+    assert_eq!(
+        h.bot.take_texts(),
+        vec!["Choose an action from menu. Type /menu to show buttons or /addmed <name>."]
+    );
+}
+
+#[tokio::test]
+async fn addgb_with_several_values_saves_one_row_each() {
+    let h = Harness::new();
+    h.send("/addgb 5.8, 6,1; 5.9 2024/2/1 9:05 @fasting").await;
+
+    assert_eq!(h.bot.take_texts(), vec!["Glucose entries saved ✅ (3)"]);
+    let content = h.dir.read("glucose.csv");
+    assert_eq!(
+        data_lines(&content),
+        vec![
+            "2024-02-01T09:05:00+00:00,1,before_meal,5.8,\"fasting\",manual,1,mmol/L,,,",
+            "2024-02-01T09:05:00+00:00,1,before_meal,6.1,\"fasting\",manual,1,mmol/L,,,",
+            "2024-02-01T09:05:00+00:00,1,before_meal,5.9,\"fasting\",manual,1,mmol/L,,,",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn several_values_without_time_share_one_timestamp() {
+    let h = Harness::new();
+    h.send(BTN_GLUCOSE_BEFORE_MEAL).await;
+    h.send("5.8 , 6.1").await;
+
+    let content = h.dir.read("glucose.csv");
+    let timestamps = data_lines(&content)
+        .iter()
+        .map(|row| row.split(',').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(timestamps.len(), 2);
+    assert_eq!(timestamps[0], timestamps[1]);
+}
+
+#[tokio::test]
+async fn out_of_range_value_rejects_whole_entry() {
+    let h = Harness::new();
+    h.send("/addgb 5.8, 58").await;
+
+    assert_eq!(
+        h.bot.take_texts(),
+        vec!["Glucose value 58 is out of range (1.0-35.0 mmol/L)"]
+    );
+    assert!(!user_data_dir(&h.dir.0, CHAT).join("glucose.csv").exists());
+}
+
+#[test]
+fn parse_glucose_payload_handles_separators() {
+    let tz = &InputOptions {
+        tz: Tz::UTC,
+        note_max_len: 0,
+        timestamp_precision: TimestampPrecision::Minute,
+        clock: Arc::new(clock::RealClock),
+        glucose_unit: settings::GlucoseUnit::Mmol,
+        sync_writes: false,
+        log_entry_notes: false,
+    };
+    assert_eq!(parse_glucose_payload("5,8", tz).unwrap().values, vec![5.8]);
+    assert_eq!(
+        parse_glucose_payload("5.8, 6.1 @a, b", tz).unwrap(),
+        GlucoseInput {
+            values: vec![5.8, 6.1],
+            timestamp: None,
+            note: Some("a, b".to_string()),
+            source: None,
+            confirmed: false,
+            trend: None,
+        }
+    );
+    assert!(parse_glucose_payload("5.8,", tz).is_err());
+    assert!(parse_glucose_payload("5.8,6.1", tz).is_err());
+}
+
+#[test]
+fn parse_csv_line_handles_quotes() {
+    assert_eq!(
+        records::parse_csv_line("a,1,\"x, \"\"y\"\"\",,"),
+        vec!["a", "1", "x, \"y\"", "", ""]
+    );
+}
+
+fn utc(raw: &str) -> chrono::DateTime<Utc> {
+    chrono::DateTime::parse_from_rfc3339(raw)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+/// Before-meal glucose reading at the RFC 3339 `timestamp`, for report tests.
+fn glucose_record(timestamp: &str, value: f64) -> records::GlucoseRecord {
+    tagged_glucose_record(timestamp, GlucoseTag::BeforeMeal, value)
+}
+
+fn tagged_glucose_record(timestamp: &str, tag: GlucoseTag, value: f64) -> records::GlucoseRecord {
+    records::GlucoseRecord {
+        timestamp: utc(timestamp),
+        tag: tag.as_csv_tag().to_string(),
+        value,
+        note: String::new(),
+        source: DEFAULT_SOURCE.to_string(),
+    }
+}
+
+#[test]
+fn meal_effect_pairs_meals_with_following_after_meal_reading() {
+    let meal = |ts: &str, note: &str| records::MealRecord {
+        timestamp: utc(ts),
+        note: note.to_string(),
+    };
+    let glucose_records = vec![
+        tagged_glucose_record("2024-02-01T09:00:00+00:00", GlucoseTag::BeforeMeal, 5.5),
+        tagged_glucose_record("2024-02-01T10:00:00+00:00", GlucoseTag::AfterMeal, 7.0),
+        tagged_glucose_record("2024-02-01T11:30:00+00:00", GlucoseTag::AfterMeal, 7.5),
+        tagged_glucose_record("2024-02-02T14:30:00+00:00", GlucoseTag::AfterMeal, 11.2),
+        tagged_glucose_record("2024-02-03T20:00:00+00:00", GlucoseTag::AfterMeal, 9.0),
+    ];
+    let meals = vec![
+        meal("2024-02-01T08:00:00+00:00", "Oatmeal"),
+        meal("2024-02-02T13:00:00+00:00", "pizza"),
+        meal("2024-02-03T12:00:00+00:00", "pasta"),
+        meal("2023-12-01T12:00:00+00:00", "too old"),
+    ];
+
+    let report = reports::meal_effect_report(
+        &glucose_records,
+        &meals,
+        Tz::UTC,
+        utc("2024-02-04T00:00:00+00:00"),
+    );
+    assert_eq!(
+        report,
+        "Meal effect (last 30 days, after-meal reading within 4h):\n\
+Meals: 3, with reading: 2, without: 1\n\
+Post-meal glucose: min 7.0 (02/01 10:00), median 9.1, max 11.2 (02/02 14:30) mmol/L\n\
+- below 7.8: 1\n\
+- 7.8-10.0: 0\n\
+- above 10.0: 1\n\
+By meal note (highest average first):\n\
+- pizza: 11.2 (1)\n\
+- oatmeal: 7.0 (1)\n\
+Without after-meal reading:\n\
+- 02/03 12:00 pasta"
+    );
+}
+
+#[tokio::test]
+async fn meal_command_is_saved_and_reported() {
+    let h = Harness::new();
+    h.send("/meal @toast").await;
+    h.send("/addga 8.4").await;
+    h.send("/mealeffect").await;
+    h.send("/meal 2/30 9:00").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Meal saved ✅");
+    assert!(texts[2].contains("Meals: 1, with reading: 1, without: 0"));
+    assert!(texts[2].contains("- toast: 8.4 (1)"));
+    assert!(texts[3].starts_with("Invalid date/time."));
+    let content = h.dir.read(MEALS_FILE);
+    let rows = data_lines(&content);
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].ends_with(",1,\"toast\""));
+}
+
+#[tokio::test]
+async fn fast_is_kept_in_settings_until_it_ends() {
+    let mut h = Harness::new();
+    let set_clock = |h: &mut Harness, now: &str| {
+        Arc::get_mut(&mut h.state).unwrap().input.clock = Arc::new(clock::FixedClock(utc(now)));
+    };
+    set_clock(&mut h, "2024-06-01T20:00:00+00:00");
+    h.send("/fast end").await;
+    h.send("/fast").await;
+    h.send("/fast start").await;
+    assert!(
+        h.dir
+            .read("settings.toml")
+            .contains("fast_started_at = \"2024-06-01T20:00:00+00:00\"")
+    );
+    set_clock(&mut h, "2024-06-02T12:30:00+00:00");
+    h.send("/fast start").await;
+    h.send("/fast status").await;
+    h.send("/fast end").await;
+    h.send("/fast").await;
+    h.send("/fast later").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "No fast is running. Start one with /fast start.");
+    assert_eq!(texts[1], texts[0]);
+    assert_eq!(
+        texts[2],
+        "Fast started at 2024-06-01 20:00 ✅ End it with /fast end."
+    );
+    assert_eq!(
+        texts[3],
+        "A fast is already running since 2024-06-01 20:00 (16h 30m). End it with /fast end."
+    );
+    assert_eq!(texts[4], "Fasting for 16h 30m since 2024-06-01 20:00.");
+    assert_eq!(texts[5], "Fast ended ✅ 16h 30m (since 2024-06-01 20:00).");
+    assert_eq!(
+        texts[6],
+        "No fast is running. Last fast: 16h 30m from 2024-06-01 20:00."
+    );
+    assert_eq!(texts[7], "Usage: /fast <start|end|status>");
+    assert_eq!(
+        data_lines(&h.dir.read(FASTING_FILE)),
+        ["2024-06-01T20:00:00+00:00,1,2024-06-02T12:30:00+00:00,990"]
+    );
+    assert!(!h.dir.read("settings.toml").contains("fast_started_at"));
+}
+
+#[tokio::test]
+async fn failed_save_is_reported_with_details_when_verbose() {
+    let h = Harness::new();
+    // A directory where the glucose file should be makes every save fail.
+    fs_err::create_dir_all(user_data_dir(&h.dir.0, CHAT).join(GLUCOSE_FILE)).unwrap();
+    let send =
+        |text: &'static str| handle_text(&h.bot, CHAT, MessageId(1), text, Arc::clone(&h.state));
+    assert!(send("/addgb 5.8").await.is_err());
+    h.send("/verbose on").await;
+    assert!(send("/addgb 5.8").await.is_err());
+    h.send("/verbose").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], FAILURE_REPLY);
+    assert_eq!(texts[1], "Error details on ✅");
+    assert!(texts[2].starts_with(&format!("{FAILURE_REPLY}\nError: ")));
+    assert!(texts[2].contains(GLUCOSE_FILE));
+    assert_eq!(texts[3], "Error details are on.");
+}
+
+#[tokio::test]
+async fn failed_saves_are_reported_while_bad_input_gets_its_error() {
+    let h = Harness::new();
+    for file in [KETONES_FILE, INSULIN_FILE, FOOD_FILE] {
+        fs_err::create_dir_all(user_data_dir(&h.dir.0, CHAT).join(file)).unwrap();
+    }
+    let send =
+        |text: &'static str| handle_text(&h.bot, CHAT, MessageId(1), text, Arc::clone(&h.state));
+    assert!(send("/addketone 0.4").await.is_err());
+    assert!(send("/addinsulin 4").await.is_err());
+    assert!(send("/food apple").await.is_err());
+    assert!(send("/addketone abc").await.is_ok());
+    assert!(send("/addinsulin 4 lunch").await.is_ok());
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[..3], [FAILURE_REPLY; 3]);
+    assert!(texts[3].starts_with("Invalid"), "{}", texts[3]);
+    assert_eq!(
+        texts[4],
+        "Unknown insulin type 'lunch'. Use meal or correction."
+    );
+}
+
+#[tokio::test]
+async fn reset_settings_restores_defaults_after_confirmation() {
+    let h = Harness::new();
+    h.send("/addgb 5.8").await;
+    h.send("/units mgdl").await;
+    h.send("/setlanguage uk").await;
+    h.send("/precision insulin 0.5").await;
+    h.send("/fast start").await;
+    h.bot.take();
+    h.send("/reset_settings").await;
+    h.send("no").await;
+    h.send("/reset_settings").await;
+    h.send("yes").await;
+    h.send("/reset_settings").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "This will reset these settings to their defaults: glucose unit, language, precision. \
+Logged data and medications are kept. Reply \"yes\" to confirm, anything else cancels."
+    );
+    assert_eq!(texts[1], "Reset cancelled.");
+    assert_eq!(
+        texts[3],
+        "Settings reset to defaults ✅ glucose unit, language, precision"
+    );
+    assert_eq!(texts[4], "All settings are already at their defaults.");
+    let settings = h.dir.read("settings.toml");
+    assert!(!settings.contains("glucose_unit") && !settings.contains("language"));
+    assert!(settings.contains("fast_started_at"));
+    assert_eq!(data_lines(&h.dir.read(GLUCOSE_FILE)).len(), 1);
+}
+
+#[test]
+fn mg_dl_integers_round_trip_through_storage() {
+    let defaults = settings::UserSettings::default();
+    for mg_dl in 20..=600 {
+        let value = parse_entered_glucose(&mg_dl.to_string(), settings::GlucoseUnit::Mgdl).unwrap();
+        let stored = settings::GlucoseUnit::Mgdl
+            .format_stored(defaults.round_glucose(value, settings::GlucoseUnit::Mgdl));
+        assert_eq!(stored, mg_dl.to_string());
+        assert_eq!(format!("{:.0}", mmol_l_to_mg_dl(value)), mg_dl.to_string());
+    }
+}
+
+#[tokio::test]
+async fn mg_dl_chat_logs_whole_numbers_as_mg_dl() {
+    let h = Harness::new();
+    h.send("/units mgdl").await;
+    h.send("/dualunits on").await;
+    h.bot.take();
+    h.send("/addgb 105").await;
+    h.send("/addga 5.8").await;
+    h.send("/addgb 6").await;
+    h.send("/addgb 250").await;
+    h.send("/addgb 50").await;
+    h.send("/units").await;
+
+    let texts = h.bot.take_texts();
+    assert!(texts[0].contains("5.8 mmol/L (105 mg/dL)"), "{}", texts[0]);
+    assert_eq!(
+        texts[2],
+        "Glucose value 6 mg/dL is out of range (18-630 mg/dL). For mmol/L, type a decimal such as 6.0"
+    );
+    assert!(
+        texts[3].contains("\n⚠️ 250 is above your before-meal target (70-180)"),
+        "{}",
+        texts[3]
+    );
+    assert!(
+        texts[4].contains("\n⚠️ 50 is below your before-meal target (70-180)"),
+        "{}",
+        texts[4]
+    );
+    assert_eq!(
+        texts[5],
+        "New glucose entries are stored in mg/dL. Values are entered in mg/dL as whole numbers \
+such as 104; a decimal such as 5.8 is read as mmol/L.\nUse /units <mmol|mgdl> [convert]."
+    );
+    let values = data_lines(&h.dir.read(GLUCOSE_FILE))
+        .iter()
+        .map(|line| records::parse_csv_line(line)[3].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(values, ["105", "104.4", "250", "50"]);
+}
+
+#[tokio::test]
+async fn tag_labels_readings_of_a_past_period() {
+    let mut h = Harness::new();
+    // Wednesday; last week is 2024-02-05 to 2024-02-11.
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-14T12:00:00Z")));
+    for timestamp in [
+        "2024-02-04T20:00",
+        "2024-02-05T08:00",
+        "2024-02-11T20:00",
+        "2024-02-12T08:00",
+    ] {
+        h.send(&format!("/addgb 5.8 {timestamp}")).await;
+    }
+    h.bot.take();
+    h.send("/tag #Sick lastweek").await;
+    h.send("/tag #sick lastweek").await;
+    h.send("/tag #travel 2024-02-11 2024-02-12").await;
+    h.send("/tag #sick today").await;
+    h.send("/tag sick lastweek").await;
+    h.send("/tag #sick").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Tagged 2 glucose readings from 2024-02-05 to 2024-02-11 with #sick ✅"
+    );
+    assert_eq!(
+        texts[1],
+        "All 2 glucose readings from 2024-02-05 to 2024-02-11 already have #sick."
+    );
+    assert_eq!(
+        texts[2],
+        "Tagged 2 glucose readings from 2024-02-11 to 2024-02-12 with #travel ✅"
+    );
+    assert_eq!(
+        texts[3],
+        "No glucose readings from 2024-02-14 to 2024-02-14."
+    );
+    assert!(texts[4].starts_with("Invalid tag 'sick'."));
+    assert!(texts[5].starts_with("Usage: /tag #<tag>"));
+    let tags = data_lines(&h.dir.read(GLUCOSE_FILE))
+        .iter()
+        .map(|line| records::parse_csv_line(line)[GLUCOSE_TAGS_COLUMN].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(tags, ["", "#sick", "#sick #travel", "#travel"]);
+}
+
+#[tokio::test]
+async fn concurrent_tags_of_a_chat_keep_every_tag() {
+    let h = Harness::new();
+    for minute in 0..20 {
+        h.send(&format!("/addgb 5.8 2024-02-01T08:{minute:02}"))
+            .await;
+    }
+    let day = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+    std::thread::scope(|scope| {
+        for tag in ["#a", "#b", "#c", "#d", "#e", "#f"] {
+            let state = &h.state;
+            scope.spawn(move || {
+                tagging::tag_glucose(state, CHAT, tag, (day, day), Tz::UTC).unwrap()
+            });
+        }
+    });
+
+    for line in data_lines(&h.dir.read(GLUCOSE_FILE)) {
+        let tags = &records::parse_csv_line(line)[GLUCOSE_TAGS_COLUMN];
+        assert_eq!(tags.split_whitespace().count(), 6, "{line}");
+    }
+}
+
+#[tokio::test]
+async fn source_marker_is_stored() {
+    let h = Harness::new();
+    h.send("/addga 9.1 !CGM 2024/2/1 13:00 @lunch !not a source")
+        .await;
+    h.send("/addga 9.1 !cgm !meter").await;
+    h.send("/addga 9.1 !c,g").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Glucose entry saved ✅");
+    assert_eq!(texts[1], "Only one !source marker is allowed");
+    assert!(texts[2].starts_with("Invalid source '!c,g'."));
+    let content = h.dir.read("glucose.csv");
+    assert_eq!(
+        data_lines(&content),
+        vec!["2024-02-01T13:00:00+00:00,1,after_meal,9.1,\"lunch !not a source\",cgm,1,mmol/L,,,"]
+    );
+    let records = records::read_glucose_records(&h.dir.0, CHAT).unwrap();
+    assert_eq!(records[0].source, "cgm");
+}
+
+#[tokio::test]
+async fn old_glucose_file_header_is_upgraded() {
+    let h = Harness::new();
+    let path = user_data_dir(&h.dir.0, CHAT).join(GLUCOSE_FILE);
+    fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+    fs_err::write(
+        &path,
+        "timestamp,chat_id,tag,value_mmol_l,note\n\
+2024-02-01T09:00:00+00:00,1,before_meal,5.5,\"old\"\n",
+    )
+    .unwrap();
+    storage::upgrade_headers(&h.dir.0, false).unwrap();
+    h.send("/addgb 6.0 2024/2/2 9:00 !meter").await;
+
+    assert_eq!(
+        h.dir.read(GLUCOSE_FILE),
+        "timestamp,chat_id,tag,value,note,source,message_id,unit,confirmed,trend,tags\n\
+2024-02-01T09:00:00+00:00,1,before_meal,5.5,\"old\",,,,,,\n\
+2024-02-02T09:00:00+00:00,1,before_meal,6,\"\",meter,1,mmol/L,,,\n"
+    );
+    let sources = records::read_glucose_records(&h.dir.0, CHAT)
+        .unwrap()
+        .into_iter()
+        .map(|record| record.source)
+        .collect::<Vec<_>>();
+    assert_eq!(sources, vec!["manual", "meter"]);
+}
+
+#[tokio::test]
+async fn clear_truncates_file_after_confirmation() {
+    let h = Harness::new();
+    h.send("/addgb 5.8, 6.1").await;
+    h.send("/meal @soup").await;
+    h.send("/clear glucose").await;
+    h.send("YES").await;
+    h.send("/clear glucose").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[2],
+        "This will remove all 2 glucose entries. Reply \"yes\" to confirm, anything else cancels."
+    );
+    assert_eq!(texts[3], "Cleared glucose: 2 rows removed.");
+    assert_eq!(texts[4], "Nothing to clear: glucose has no entries.");
+    assert_eq!(h.dir.read(GLUCOSE_FILE), format!("{GLUCOSE_HEADER}\n"));
+    assert_eq!(data_lines(&h.dir.read(MEALS_FILE)).len(), 1);
+}
+
+#[tokio::test]
+async fn clear_is_cancelled_by_other_reply() {
+    let h = Harness::new();
+    h.send(BTN_WEIGHT).await;
+    h.send("80").await;
+    h.send("/clear weight").await;
+    h.send("no").await;
+    h.send("/clear everything").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[3], "Clear cancelled.");
+    assert_eq!(
+        texts[4],
+        "Usage: /clear <glucose|weight|ketones|insulin|meals|food|medlog>"
+    );
+    assert_eq!(data_lines(&h.dir.read(WEIGHT_FILE)).len(), 1);
+}
+
+#[tokio::test]
+async fn edited_glucose_message_updates_its_rows() {
+    let h = Harness::new();
+    h.send("/addgb 5.8 @fasting").await;
+    let edited = h.send("/addgb 6.1, 6.3 @first").await;
+    h.edit(edited, "/addga 7.1 2024/2/1 9:00 @fixed").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts.last().unwrap(), "Glucose entry updated ✅");
+    let content = h.dir.read(GLUCOSE_FILE);
+    let rows = data_lines(&content);
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].ends_with(",1,before_meal,5.8,\"fasting\",manual,1,mmol/L,,,"));
+    assert_eq!(
+        rows[1],
+        "2024-02-01T09:00:00+00:00,1,after_meal,7.1,\"fixed\",manual,2,mmol/L,,,"
+    );
+}
+
+#[tokio::test]
+async fn edited_pending_flow_value_keeps_tag_and_timestamp() {
+    let h = Harness::new();
+    h.send(BTN_GLUCOSE_AFTER_MEAL).await;
+    let edited = h.send("7,2 2024/2/1 11:00").await;
+    h.edit(edited, "8.2").await;
+    h.edit(edited, "eight").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[2], "Glucose entry updated ✅");
+    assert!(texts[3].starts_with("Edit not applied: Invalid glucose value"));
+    assert_eq!(
+        data_lines(&h.dir.read(GLUCOSE_FILE)),
+        vec!["2024-02-01T11:00:00+00:00,1,after_meal,8.2,\"\",manual,2,mmol/L,,,"]
+    );
+}
+
+#[tokio::test]
+async fn edited_weight_message_updates_value() {
+    let h = Harness::new();
+    h.send(BTN_WEIGHT).await;
+    let edited = h.send("78,4").await;
+    h.edit(edited, "79.1").await;
+
+    assert_eq!(
+        h.bot.take_texts().last().unwrap(),
+        "Weight entry updated ✅"
+    );
+    let content = h.dir.read(WEIGHT_FILE);
+    let rows = data_lines(&content);
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].ends_with(",1,79.1,2"));
+}
+
+#[tokio::test]
+async fn edits_of_unknown_messages_are_ignored() {
+    let h = Harness::new();
+    let button = h.send(BTN_WEIGHT).await;
+    h.send("80").await;
+    h.edit(button, "81").await;
+    h.edit(MessageId(99), "/addgb 5.0").await;
+
+    assert_eq!(h.bot.take().len(), 2);
+    assert!(h.dir.read(WEIGHT_FILE).contains(",1,80,2"));
+}
+
+#[test]
+fn find_medication_tolerates_common_typos() {
+    let meds = vec![
+        "Metformin".to_string(),
+        "Insulin Glargine".to_string(),
+        "Aspirin".to_string(),
+        "Aspiron".to_string(),
+    ];
+    let exact = |name: &str| MedicationMatch::Exact(name.to_string());
+    let fuzzy = |name: &str| MedicationMatch::Fuzzy(name.to_string());
+
+    assert_eq!(find_medication(&meds, "metformin"), exact("Metformin"));
+    assert_eq!(
+        find_medication(&meds, " insulin   glargine "),
+        exact("Insulin Glargine")
+    );
+    assert_eq!(find_medication(&meds, "Metfromin"), fuzzy("Metformin"));
+    assert_eq!(find_medication(&meds, "Metformn"), fuzzy("Metformin"));
+    assert_eq!(find_medication(&meds, "metformine"), fuzzy("Metformin"));
+    assert_eq!(
+        find_medication(&meds, "Insulin Glargin"),
+        fuzzy("Insulin Glargine")
+    );
+    // Equally close to two medications: ambiguous.
+    assert_eq!(find_medication(&meds, "Aspirun"), MedicationMatch::None);
+    // Too far away or too short to guess.
+    assert_eq!(find_medication(&meds, "Metoprolol"), MedicationMatch::None);
+    assert_eq!(
+        find_medication(&["Abc".to_string()], "Abd"),
+        MedicationMatch::None
+    );
+}
+
+#[test]
+fn levenshtein_counts_edits() {
+    assert_eq!(levenshtein("", "abc"), 3);
+    assert_eq!(levenshtein("kitten", "sitting"), 3);
+    assert_eq!(levenshtein("metformin", "metformin"), 0);
+    assert_eq!(levenshtein("ліки", "лiки"), 1);
+}
+
+#[tokio::test]
+async fn fuzzy_medication_button_asks_for_confirmation() {
+    let h = Harness::new();
+    h.send("/addmed Metformin").await;
+    h.send("💊 metfromin").await;
+    h.send("yes").await;
+    h.send("💊 Metformn").await;
+    h.send("no").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[1],
+        "Did you mean Metformin? Reply \"yes\" to log it, anything else cancels."
+    );
+    assert_eq!(texts[2], "Medication usage saved ✅ (Metformin)");
+    assert_eq!(texts[4], "Medication not logged.");
+    let content = h.dir.read(MEDICATION_LOG_FILE);
+    let rows = data_lines(&content);
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].ends_with(",1,\"Metformin\""));
+}
+
+#[tokio::test]
+async fn over_length_note_is_rejected() {
+    let h = Harness::new();
+    let long_note = "a".repeat(DEFAULT_NOTE_MAX_LENGTH + 1);
+    h.send(&format!("/addgb 5.8 @{long_note}")).await;
+    h.send(&format!("/meal @{long_note}")).await;
+    h.send(&format!("/addgb 5.8 @{}", &long_note[1..])).await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Note is too long (201 characters, max 200). Please shorten it."
+    );
+    assert_eq!(texts[1], texts[0]);
+    assert_eq!(texts[2], "Glucose entry saved ✅");
+    assert_eq!(data_lines(&h.dir.read(GLUCOSE_FILE)).len(), 1);
+}
+
+#[test]
+fn note_length_zero_means_unlimited() {
+    let long_note = "й".repeat(1000);
+    assert!(check_note_length(Some(&long_note), 0).is_ok());
+    assert!(check_note_length(Some(&long_note), 1000).is_ok());
+    assert!(check_note_length(Some(&long_note), 999).is_err());
+    assert!(check_note_length(None, 1).is_ok());
+}
+
+#[test]
+fn month_overview_buckets_days_in_input_timezone() {
+    let glucose = vec![
+        // 2024-02-01 23:30 UTC is already 2 February in Kyiv.
+        glucose_record("2024-02-01T23:30:00+00:00", 12.0),
+        glucose_record("2024-02-01T08:00:00+00:00", 5.0),
+        glucose_record("2024-02-01T12:00:00+00:00", 6.0),
+        glucose_record("2024-02-05T08:00:00+00:00", 3.0),
+        glucose_record("2024-02-06T08:00:00+00:00", 9.0),
+        glucose_record("2024-01-31T08:00:00+00:00", 20.0),
+    ];
+    let today = NaiveDate::from_ymd_opt(2024, 2, 7).unwrap();
+
+    let overview = reports::month_overview(
+        &glucose,
+        chrono_tz::Europe::Kyiv,
+        today,
+        chrono::Weekday::Mon,
+    );
+    let expected = [
+        "Glucose February 2024 (daily average)",
+        " Mo  Tu  We  Th  Fr  Sa  Su",
+        "             1o  2H  3.  4.",
+        " 5L  6+  7.  8   9  10  11",
+        "12  13  14  15  16  17  18",
+        "19  20  21  22  23  24  25",
+        "26  27  28  29",
+        "",
+        "L <3.9  o 3.9-7.8  + 7.8-10.0  H >10.0  . no data",
+        "Days with data: 4/7, in range: 1",
+    ];
+    assert_eq!(overview, expected.join("\n"));
+}
+
+#[test]
+fn month_overview_respects_week_start() {
+    let glucose = [
+        ("2024-02-04T08:00:00+00:00", 5.0),
+        ("2024-02-05T08:00:00+00:00", 12.0),
+    ]
+    .iter()
+    .map(|(ts, value)| glucose_record(ts, *value))
+    .collect::<Vec<_>>();
+    let today = NaiveDate::from_ymd_opt(2024, 2, 5).unwrap();
+    let grid = |week_start| {
+        reports::month_overview(&glucose, Tz::UTC, today, week_start)
+            .lines()
+            .skip(1)
+            .take(3)
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        grid(chrono::Weekday::Mon),
+        [
+            " Mo  Tu  We  Th  Fr  Sa  Su",
+            "             1.  2.  3.  4o",
+            " 5H  6   7   8   9  10  11",
+        ]
+    );
+    assert_eq!(
+        grid(chrono::Weekday::Sun),
+        [
+            " Su  Mo  Tu  We  Th  Fr  Sa",
+            "                 1.  2.  3.",
+            " 4o  5H  6   7   8   9  10",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn weekstart_setting_is_saved_and_used_by_month() {
+    let h = Harness::new();
+    h.send("/weekstart").await;
+    h.send("/weekstart sunday").await;
+    h.send("/weekstart friday").await;
+    h.send("/month").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent[0].text, "Week starts on Monday.");
+    assert_eq!(sent[1].text, "Week start updated ✅ (Sunday)");
+    assert_eq!(sent[2].text, "Usage: /weekstart <monday|sunday>");
+    assert_eq!(
+        sent[3].text.lines().nth(1),
+        Some(" Su  Mo  Tu  We  Th  Fr  Sa")
+    );
+    assert!(
+        h.dir
+            .read("settings.toml")
+            .contains("week_start = \"sunday\"")
+    );
+}
+
+#[tokio::test]
+async fn month_command_sends_preformatted_grid() {
+    let h = Harness::new();
+    h.send("/addgb 5.8").await;
+    h.send("/month").await;
+
+    let sent = h.bot.take();
+    assert!(sent[1].preformatted);
+    assert!(sent[1].text.contains("Days with data: 1/"));
+}
+
+#[tokio::test]
+async fn addketone_saves_and_warns_when_elevated() {
+    let h = Harness::new();
+    h.send("/addketone 0,6 2024/2/1 9:05 @morning").await;
+    h.send("/addketone 1.5").await;
+    h.send("/addketone 12").await;
+    h.send("/addketone @oops").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Ketones saved ✅");
+    assert_eq!(
+        texts[1],
+        "Ketones saved ✅\n⚠️ elevated ketones (1.5 mmol/L, warning at 1.5)"
+    );
+    assert_eq!(
+        texts[2],
+        "Ketone value 12 is out of range (0.0-10.0 mmol/L)"
+    );
+    assert_eq!(texts[3], "Missing ketone value");
+    let content = h.dir.read(KETONES_FILE);
+    let rows = data_lines(&content);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0], "2024-02-01T09:05:00+00:00,1,0.6,\"morning\",1");
+    assert!(rows[1].ends_with(",1,1.5,\"\",2"));
+}
+
+#[tokio::test]
+async fn ketones_button_flow_and_edit() {
+    let h = Harness::new();
+    h.send(BTN_KETONES).await;
+    h.send("abc").await;
+    let saved = h.send("0.4").await;
+    h.edit(saved, "2.1").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Enter ketones: <value> [date time] [@note], e.g. 0.6 2/1 9:05"
+    );
+    assert_eq!(texts[1], "Invalid ketone value 'abc'. Example: 0.6");
+    assert_eq!(texts[2], "Ketones saved ✅");
+    assert_eq!(texts[3], "Ketones entry updated ✅");
+    let content = h.dir.read(KETONES_FILE);
+    assert!(data_lines(&content)[0].ends_with(",1,2.1,\"\",3"));
+}
+
+#[tokio::test]
+async fn setrange_updates_tag_specific_ranges() {
+    let h = Harness::new();
+    h.send("/setrange").await;
+    h.send("/setrange 4 9").await;
+    h.send("/setrange after 5,0 11").await;
+    h.send("/setrange before 6 5").await;
+    h.send("/setrange after off").await;
+    h.send("/setrange sideways").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Target ranges (mmol/L):\n- general: 3.9-10.0 (default)\n- before meal: general\n- after meal: general"
+    );
+    assert_eq!(
+        texts[2],
+        "Target ranges updated ✅\nTarget ranges (mmol/L):\n- general: 4.0-9.0\n- before meal: general\n- after meal: 5.0-11.0"
+    );
+    assert!(texts[3].starts_with("Invalid range."));
+    assert!(texts[4].ends_with("- after meal: general"));
+    assert!(texts[5].starts_with("Usage: /setrange"));
+    let saved = settings::load_settings(&h.dir.0, CHAT).unwrap();
+    assert_eq!(
+        saved.target_range,
+        Some(settings::GlucoseRange {
+            low: 4.0,
+            high: 9.0
+        })
+    );
+    assert_eq!(saved.after_meal_range, None);
+}
+
+#[tokio::test]
+async fn save_warning_uses_range_for_reading_tag() {
+    let h = Harness::new();
+    h.send("/setrange before 4 7").await;
+    h.send("/addgb 7.5").await;
+    h.send("/addga 7.5, 10.5").await;
+    h.send(BTN_GLUCOSE_BEFORE_MEAL).await;
+    h.send("3,2").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[1],
+        "Glucose entry saved ✅\n⚠️ 7.5 is above your before-meal target (4.0-7.0)"
+    );
+    assert_eq!(
+        texts[2],
+        "Glucose entries saved ✅ (2)\n⚠️ 10.5 is above your after-meal target (3.9-10.0)"
+    );
+    assert_eq!(
+        texts[4],
+        "Saved ✅\n⚠️ 3.2 is below your before-meal target (4.0-7.0)"
+    );
+}
+
+#[test]
+fn medication_calendar_has_a_daily_event_per_time() {
+    let at = |time: &str| NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+    let ics = calendar::medication_calendar(
+        CHAT,
+        &[
+            ("Metformin 500".to_string(), vec![at("08:00"), at("20:00")]),
+            ("Vitamin D, drops".to_string(), vec![at("09:30")]),
+        ],
+        NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(),
+        utc("2024-02-01T06:00:00+00:00"),
+    );
+    assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+    assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 3);
+    assert_eq!(ics.matches("RRULE:FREQ=DAILY\r\n").count(), 3);
+    assert!(ics.contains(
+        "UID:med-1-metformin-500-0800@pdd-bot\r\n\
+DTSTAMP:20240201T060000Z\r\n\
+DTSTART:20240201T080000\r\n"
+    ));
+    assert!(ics.contains("DTSTART:20240201T200000\r\n"));
+    assert!(ics.contains("SUMMARY:Take Vitamin D\\, drops\r\n"));
+    assert!(ics.lines().all(|line| line.len() <= 75));
+    assert!(ics.split("\r\n").all(|line| !line.contains('\n')));
+}
+
+#[tokio::test]
+async fn medtime_sets_times_that_calendar_exports() {
+    let h = Harness::new();
+    h.send("/calendar").await;
+    h.send("/addmed Metformin").await;
+    h.send("/medtime aspirin 08:00").await;
+    h.send("/medtime metformin 20:00,8:00").await;
+    h.send("/medtime metformin 25:00").await;
+    h.send("/calendar").await;
+    h.send("/renamemed Metformin -> Glucophage").await;
+    h.send("/medtime glucophage off").await;
+    h.send("/calendar").await;
+
+    let sent = h.bot.take();
+    let texts = sent
+        .iter()
+        .map(|sent| sent.text.as_str())
+        .collect::<Vec<_>>();
+    let none =
+        "No scheduled medications. Set daily times with /medtime <name> <HH:MM>[,HH:MM...] first.";
+    assert_eq!(texts[0], none);
+    assert_eq!(texts[2], "Medication not found: aspirin");
+    assert_eq!(
+        texts[3],
+        "Daily times of Metformin: 08:00, 20:00. Use /calendar to export them."
+    );
+    assert!(texts[4].starts_with("Usage: /medtime"));
+    assert_eq!(
+        texts[5],
+        "Medication reminders: 2 daily events. Open the file to add them to your calendar."
+    );
+    let (name, bytes) = sent[5].document.clone().unwrap();
+    assert_eq!(name, "medications.ics");
+    assert!(
+        String::from_utf8(bytes)
+            .unwrap()
+            .contains("SUMMARY:Take Metformin\r\n")
+    );
+    assert_eq!(texts[7], "Daily times of Glucophage removed.");
+    assert_eq!(texts[8], none);
+}
+
+#[tokio::test]
+async fn last_marks_lowest_and_highest_glucose_in_mmol_l() {
+    let h = Harness::new();
+    let path = user_data_dir(&h.dir.0, CHAT).join(GLUCOSE_FILE);
+    fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+    fs_err::write(
+        &path,
+        format!(
+            "{GLUCOSE_HEADER}\n\
+2024-02-01T08:00:00+00:00,1,before_meal,90,\"\",manual,1,mg/dL,,,\n\
+2024-02-01T09:00:00+00:00,1,after_meal,7.2,\"\",manual,2,mmol/L,,,\n\
+2024-02-01T10:00:00+00:00,1,before_meal,4.8,\"\",manual,3,mmol/L,,,\n"
+        ),
+    )
+    .unwrap();
+    h.send("/last").await;
+    h.send("/last 1").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Last 3 entries:\n\
+1. 02/01 10:00 glucose 4.8 (before meal) 🔻 lowest\n\
+2. 02/01 09:00 glucose 7.2 (after meal) 🔺 highest\n\
+3. 02/01 08:00 glucose 90 mg/dL (before meal)\n\
+Use /del <number> to delete an entry."
+    );
+    assert!(!texts[1].contains("lowest"));
+}
+
+#[tokio::test]
+async fn last_lists_entries_and_del_removes_one() {
+    let h = Harness::new();
+    h.send("/addgb 5.8 2024/2/1 8:00 @fasting").await;
+    h.send("/addketone 0.4 2024/2/1 8:05").await;
+    h.send("/addga 9.1 2024/2/1 10:00").await;
+    h.send("/last").await;
+    h.send("/del 2").await;
+    h.send("/del 2").await;
+    h.send("/del 1").await;
+    h.send("/last 2").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[3],
+        "Last 3 entries:\n\
+1. 02/01 10:00 glucose 9.1 (after meal) 🔺 highest\n\
+2. 02/01 08:05 ketones 0.4\n\
+3. 02/01 08:00 glucose 5.8 (before meal) @fasting 🔻 lowest\n\
+Use /del <number> to delete an entry."
+    );
+    assert_eq!(texts[4], "Deleted: 02/01 08:05 ketones 0.4");
+    assert_eq!(texts[5], "Entry 2 was already deleted.");
+    assert_eq!(texts[6], "Deleted: 02/01 10:00 glucose 9.1 (after meal)");
+    assert!(texts[7].starts_with("Last 1 entries:\n1. 02/01 08:00 glucose 5.8"));
+    assert_eq!(h.dir.read(KETONES_FILE), format!("{KETONES_HEADER}\n"));
+    assert_eq!(data_lines(&h.dir.read(GLUCOSE_FILE)).len(), 1);
+}
+
+#[tokio::test]
+async fn del_refuses_without_listing_or_with_stale_row() {
+    let h = Harness::new();
+    h.send("/del 1").await;
+    h.send("/addgb 5.8").await;
+    h.send("/last").await;
+    h.send("/clear glucose").await;
+    h.send("yes").await;
+    h.send("/del 1").await;
+    h.send("/del x").await;
+    h.send("/last 0").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Run /last first, then /del <number>.");
+    assert_eq!(texts[5], "That entry no longer exists. Run /last again.");
+    assert_eq!(texts[6], "Usage: /del <number from /last>");
+    assert_eq!(texts[7], "Usage: /last [1-50]");
+}
+
+#[tokio::test]
+async fn history_buttons_edit_retime_and_delete_rows() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-01T09:00:00+00:00")));
+    h.send("/history").await;
+    assert_eq!(h.bot.take_texts(), ["No entries yet."]);
+    h.send("/addgb 5.8 2024/2/1 8:00 @fasting").await;
+    h.send("/addketone 0.4 2024/2/1 8:05").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("78.4").await;
+    h.bot.take();
+
+    h.send("/history").await;
+    let listing = h.bot.take().pop().unwrap();
+    assert!(listing.text.starts_with("Last 3 entries."));
+    let glucose = listing.inline_data("02/01 08:00 glucose 5.8 (before meal) @fasting");
+    let ketones = listing.inline_data("02/01 08:05 ketones 0.4");
+    assert!(glucose.len() <= 64);
+
+    h.tap(&glucose).await;
+    let actions = h.bot.take().pop().unwrap();
+    assert_eq!(
+        actions.text,
+        "02/01 08:00 glucose 5.8 (before meal) @fasting"
+    );
+    h.tap(&actions.inline_data("✏️ Value")).await;
+    h.send("6,2").await;
+    // The row changed, so buttons sent for its old line no longer match it.
+    h.tap(&actions.inline_data("🗑 Delete")).await;
+    assert_eq!(
+        h.bot.take_texts(),
+        [
+            "Send the new value for: 02/01 08:00 glucose 5.8 (before meal) @fasting\n\
+For example: 5.8 (mmol/L)",
+            "Entry updated ✅ 02/01 08:00 glucose 6.2 (before meal) @fasting",
+            "That entry was changed or deleted since the list was shown. Send /history for a fresh list.",
+        ]
+    );
+
+    h.tap(&ketones).await;
+    let actions = h.bot.take().pop().unwrap();
+    h.tap(&actions.inline_data("🕒 Time")).await;
+    h.send("2024/2/1 7:55").await;
+    assert_eq!(
+        h.bot.take_texts(),
+        [
+            "Send the new date and time for: 02/01 08:05 ketones 0.4\n\
+For example: 2/1 9:05 or yesterday 9:05",
+            "Entry updated ✅ 02/01 07:55 ketones 0.4",
+        ]
+    );
+
+    h.send("/history").await;
+    let listing = h.bot.take().pop().unwrap();
+    assert_eq!(listing.inline[0].0, "02/01 09:00 weight 78.4 kg");
+    h.tap(&listing.inline[0].1.replacen(":o:", ":d:", 1)).await;
+    h.tap("h:x:g:0").await;
+    assert_eq!(
+        h.bot.take_texts(),
+        [
+            "Deleted: 02/01 09:00 weight 78.4 kg",
+            "This button is no longer valid. Send /history again.",
+        ]
+    );
+    assert_eq!(
+        data_lines(&h.dir.read(KETONES_FILE)),
+        ["2024-02-01T07:55:00+00:00,1,0.4,\"\",3"]
+    );
+    assert_eq!(
+        data_lines(&h.dir.read(GLUCOSE_FILE)),
+        ["2024-02-01T08:00:00+00:00,1,before_meal,6.2,\"fasting\",manual,2,mmol/L,,,"]
+    );
+    assert_eq!(data_lines(&h.dir.read(WEIGHT_FILE)).len(), 0);
+}
+
+#[tokio::test]
+async fn note_annotates_the_newest_or_a_listed_entry() {
+    let h = Harness::new();
+    h.send("/note stress").await;
+    h.send("/addgb 5.8 2024/2/1 8:00 @fasting").await;
+    h.send("/addketone 0.4 2024/2/1 8:05").await;
+    h.send("/addga 9.1 2024/2/1 10:00").await;
+    h.send("/note that high was from \"stress\"").await;
+    h.send("/note 3 before the run").await;
+    h.send("/last").await;
+    h.send("/note 2 @after coffee").await;
+    h.send("/note 3 replaced").await;
+    h.send("/note 2").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "No glucose or ketone entries to annotate yet.");
+    assert_eq!(
+        texts[4],
+        "Note updated: 02/01 10:00 glucose 9.1 (after meal) @that high was from \"stress\""
+    );
+    assert_eq!(texts[5], "Run /last first, then /note <number>.");
+    assert_eq!(
+        texts[7],
+        "Note updated: 02/01 08:05 ketones 0.4 @after coffee"
+    );
+    assert_eq!(
+        texts[8],
+        "Note updated: 02/01 08:00 glucose 5.8 (before meal) @replaced"
+    );
+    assert_eq!(texts[9], "Usage: /note [number from /last] <text>");
+
+    assert_eq!(
+        data_lines(&h.dir.read(GLUCOSE_FILE)),
+        [
+            "2024-02-01T08:00:00+00:00,1,before_meal,5.8,\"replaced\",manual,2,mmol/L,,,",
+            "2024-02-01T10:00:00+00:00,1,after_meal,9.1,\"that high was from \"\"stress\"\"\",manual,4,mmol/L,,,",
+        ]
+    );
+    assert_eq!(
+        data_lines(&h.dir.read(KETONES_FILE)),
+        ["2024-02-01T08:05:00+00:00,1,0.4,\"after coffee\",3"]
+    );
+}
+
+#[test]
+fn bot_token_sources_follow_precedence() {
+    let dir = TestDir::new();
+    let token_file = dir.0.join("token");
+    fs_err::write(&token_file, "file-token\n").unwrap();
+    let with_file = |extra: &str| {
+        args::AppConfig::from_str(format!(
+            "tg_bot_token_file = {:?}\n{extra}",
+            token_file.display().to_string()
+        ))
+        .unwrap()
+    };
+
+    let explicit = with_file("tg_bot_token = \"config-token\"");
+    assert_eq!(
+        explicit
+            .bot_token_with_env(Some("env-token".into()))
+            .unwrap(),
+        "config-token"
+    );
+    let from_file = with_file("");
+    assert_eq!(
+        from_file
+            .bot_token_with_env(Some("env-token".into()))
+            .unwrap(),
+        "file-token"
+    );
+    let env_only = args::AppConfig::default();
+    assert_eq!(
+        env_only
+            .bot_token_with_env(Some("env-token".into()))
+            .unwrap(),
+        "env-token"
+    );
+
+    let missing = env_only.bot_token_with_env(None).unwrap_err().to_string();
+    assert!(missing.contains("TG_BOT_TOKEN"));
+    fs_err::write(&token_file, "  \n").unwrap();
+    let empty = from_file.bot_token_with_env(None).unwrap_err().to_string();
+    assert_eq!(empty, "tg_bot_token_file is empty");
+}
+
+#[test]
+fn config_profile_overrides_top_level_keys() {
+    let content = r#"
+tg_bot_token = "shared"
+data_dir = "data"
+input_timezone = "UTC"
+
+[profiles.kyiv]
+data_dir = "data-kyiv"
+input_timezone = "Europe/Kyiv"
+
+[profiles.empty]
+"#;
+    let base = args::AppConfig::from_str_with_profile(content, None).unwrap();
+    assert_eq!(base.data_dir.as_deref(), Some("data"));
+
+    let kyiv = args::AppConfig::from_str_with_profile(content, Some("kyiv")).unwrap();
+    assert_eq!(kyiv.data_dir.as_deref(), Some("data-kyiv"));
+    assert_eq!(kyiv.input_timezone.as_deref(), Some("Europe/Kyiv"));
+    assert_eq!(kyiv.tg_bot_token.as_deref(), Some("shared"));
+
+    let empty = args::AppConfig::from_str_with_profile(content, Some("empty")).unwrap();
+    assert_eq!(empty.data_dir.as_deref(), Some("data"));
+
+    let err = args::AppConfig::from_str_with_profile(content, Some("missing")).unwrap_err();
+    assert_eq!(err.to_string(), "profile 'missing' not found in config");
+}
+
+#[test]
+fn registration_token_must_fit_a_start_link() {
+    let token = |value: &str| {
+        args::AppConfig::from_str(format!("registration_token = \"{value}\""))
+            .unwrap()
+            .registration_token()
+    };
+    assert_eq!(token("").unwrap(), None);
+    assert_eq!(
+        token(" abcdefgh-12345678 ").unwrap(),
+        Some("abcdefgh-12345678".to_string())
+    );
+    assert!(token("too-short").is_err());
+    assert!(token("has spaces in the token").is_err());
+    assert!(token(&"x".repeat(56)).is_err());
+}
+
+#[test]
+fn tg_chat_id_entries_are_validated() {
+    let chat_ids = |list: &str| {
+        args::AppConfig::from_str(format!("tg_chat_id = {list}"))
+            .unwrap()
+            .chat_ids()
+    };
+    assert_eq!(chat_ids(r#"["1", " 2 "]"#).unwrap(), (vec![1, 2], vec![]));
+    assert_eq!(
+        chat_ids(r#"["1", "-100"]"#).unwrap(),
+        (
+            vec![1],
+            vec![
+                "tg_chat_id entry 2 (-100) is a group or channel and is ignored; only private chats are served"
+                    .to_string()
+            ]
+        )
+    );
+    assert_eq!(
+        chat_ids(r#"["1", "2", "1"]"#).unwrap(),
+        (
+            vec![1, 2],
+            vec!["tg_chat_id entry 3 repeats entry 1 (1)".to_string()]
+        )
+    );
+    assert_eq!(
+        chat_ids(r#"["1", "  "]"#).unwrap_err().to_string(),
+        "tg_chat_id entry 2 is empty"
+    );
+    assert!(
+        chat_ids(r#"["1", "12a"]"#)
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid tg_chat_id entry 2 '12a'")
+    );
+    assert_eq!(
+        args::AppConfig::default()
+            .chat_ids()
+            .unwrap_err()
+            .to_string(),
+        "tg_chat_id is required in config"
+    );
+}
+
+#[tokio::test]
+async fn bolus_requires_settings_and_logs_suggestion_separately() {
+    let h = Harness::new();
+    h.send("/bolus 60 8.5").await;
+    h.send("/setbolus 10 2").await;
+    h.send("/bolus 60 8.5").await;
+    h.send("/bolus 10 4.0").await;
+    h.send("/setbolus 0 2").await;
+
+    let texts = h.bot.take_texts();
+    assert!(texts[0].starts_with("⚠️ Informational only, not medical advice."));
+    assert!(texts[0].contains("Set your ratios first"));
+    assert!(texts[1].contains("- target: 6.0 mmol/L"));
+    assert_eq!(
+        texts[2],
+        "⚠️ Informational only, not medical advice. Check the dose yourself before injecting.\n\
+Suggested bolus: 7.3 U\n\
+- carbs: 60 g / 10.0 g/U = 6.0 U\n\
+- correction: (8.5 - 6.0) / 2.0 = +1.3 U\n\
+This is not logged as a dose."
+    );
+    assert!(texts[3].contains("Suggested bolus: 0.0 U"));
+    assert_eq!(texts[4], "Carb ratio must be above 0 and at most 100 g/U");
+
+    let log = h.dir.read("bolus_suggestions.csv");
+    let rows = data_lines(&log);
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].ends_with(",1,60,8.5,10,2,6,7.3"));
+    assert!(
+        !user_data_dir(&h.dir.0, CHAT)
+            .join(MEDICATION_LOG_FILE)
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn pasted_meter_lines_are_imported_after_confirmation() {
+    let h = Harness::new();
+    h.send(
+        "2024-02-01 08:00 5.8\n\n2024/02/01 12:30 7,4 mmol/L\nbroken line\n9.1 2/1 18:00 @dinner",
+    )
+    .await;
+    h.send("after").await;
+    h.send("2024/02/02 08:00 6.0\n2024/02/02 09:00 6.2").await;
+    h.send("no").await;
+
+    let texts = h.bot.take_texts();
+    let year = Utc::now().year();
+    assert_eq!(
+        texts[0],
+        "Found 3 readings to import:\n\
+- 02/01 08:00 5.8\n\
+- 02/01 12:30 7.4\n\
+- 02/01 18:00 9.1\n\
+Skipped 1 lines:\n\
+- line 4: Invalid glucose value 'line'. Example: 5.8 (separate several readings with ', ')\n\
+Reply 'before' or 'after' to import them with that meal tag, anything else cancels."
+    );
+    assert_eq!(texts[1], "Imported 3 readings ✅ (after meal)");
+    assert_eq!(texts[3], "Import cancelled.");
+
+    let glucose = h.dir.read(GLUCOSE_FILE);
+    assert_eq!(
+        data_lines(&glucose),
+        vec![
+            "2024-02-01T08:00:00+00:00,1,after_meal,5.8,\"\",import,,mmol/L,,,".to_string(),
+            "2024-02-01T12:30:00+00:00,1,after_meal,7.4,\"\",import,,mmol/L,,,".to_string(),
+            format!("{year}-02-01T18:00:00+00:00,1,after_meal,9.1,\"dinner\",import,,mmol/L,,,"),
+        ]
+    );
+}
+
+#[test]
+fn predict_uses_same_tag_readings_near_this_time_of_day() {
+    let mut glucose = (1..=8)
+        .map(|day| {
+            tagged_glucose_record(
+                &format!("2024-02-{day:02}T08:00:00Z"),
+                GlucoseTag::BeforeMeal,
+                4.5 + 0.5 * day as f64,
+            )
+        })
+        .collect::<Vec<_>>();
+    glucose.push(tagged_glucose_record(
+        "2024-02-09T08:00:00Z",
+        GlucoseTag::AfterMeal,
+        12.0,
+    ));
+    glucose.push(tagged_glucose_record(
+        "2024-02-09T15:00:00Z",
+        GlucoseTag::BeforeMeal,
+        12.0,
+    ));
+    glucose.push(tagged_glucose_record(
+        "2023-12-01T08:00:00Z",
+        GlucoseTag::BeforeMeal,
+        12.0,
+    ));
+    let tz = chrono_tz::UTC;
+
+    assert_eq!(
+        predict::predict_report(
+            &glucose,
+            GlucoseTag::BeforeMeal,
+            tz,
+            utc("2024-02-15T08:30:00Z")
+        ),
+        "Next before-meal reading, statistical guess:\n\
+around 6.8 mmol/L, likely 5.9-7.6 (middle half of 8 readings within 2 h of 08:30 in the last 30 days)\n\
+Confidence: low.\n\
+⚠️ A statistical guess from your past readings only, not a forecast and not medical advice. Always measure."
+    );
+    assert_eq!(
+        predict::predict_report(
+            &glucose,
+            GlucoseTag::AfterMeal,
+            tz,
+            utc("2024-02-15T08:30:00Z")
+        ),
+        "Not enough data to guess the next after-meal reading: 1 readings within 2 h of 08:30 \
+in the last 30 days (need at least 7)."
+    );
+}
+
+#[tokio::test]
+async fn predict_command_needs_a_tag() {
+    let h = Harness::new();
+    h.send("/predict").await;
+    h.send("/predict before").await;
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Usage: /predict <before|after>");
+    assert!(
+        texts[1].starts_with("Not enough data to guess the next before-meal reading: 0 readings")
+    );
+}
+
+#[tokio::test]
+async fn multi_line_text_without_readings_is_not_a_batch() {
+    let h = Harness::new();
+    h.send("hello\nthere").await;
+    assert_eq!(
+        h.bot.take_texts(),
+        vec!["Choose an action from menu. Type /menu to show buttons or /addmed <name>."]
+    );
+}
+
+#[test]
+fn trend_report_fits_slope_and_handles_sparse_data() {
+    let record =
+        |day: u32, value: f64| glucose_record(&format!("2024-02-{day:02}T08:00:00+00:00"), value);
+    let now = utc("2024-02-15T12:00:00+00:00");
+    let tz = chrono_tz::UTC;
+
+    let falling = (2..=11)
+        .map(|day| record(day, 10.0 - 0.5 * day as f64))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reports::trend_report(&falling, tz, now, 14),
+        "Glucose trend (last 14 days, 10 readings on 10 days):\n\
+↘ improving: -0.50 mmol/L per day\n\
+R² = 1.00 (strong fit)\n\
+Lowest 4.5 (02/11 08:00), highest 9.0 (02/02 08:00) mmol/L"
+    );
+
+    let flat = (1..=6).map(|day| record(day, 6.0)).collect::<Vec<_>>();
+    assert!(
+        reports::trend_report(&flat, tz, now, 14)
+            .ends_with("→ stable: +0.00 mmol/L per day\nR² n/a (all readings are equal)")
+    );
+
+    let noisy = [5.0, 9.0, 5.5, 8.5, 6.0, 9.5]
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| record(i as u32 + 5, value))
+        .collect::<Vec<_>>();
+    assert!(reports::trend_report(&noisy, tz, now, 14).contains("(weak fit"));
+
+    let sparse = vec![
+        record(14, 5.0),
+        record(14, 6.0),
+        record(13, 7.0),
+        record(1, 9.0),
+    ];
+    assert_eq!(
+        reports::trend_report(&sparse, tz, now, 7),
+        "Not enough data for a trend in the last 7 days: 3 readings on 2 days \
+(need at least 5 readings on 3 days)."
+    );
+}
+
+#[test]
+fn weekday_report_averages_by_local_day_of_week() {
+    let glucose = vec![
+        glucose_record("2024-02-12T08:00:00+00:00", 6.0),
+        // Sunday 23:30 UTC is already Monday in Kyiv.
+        glucose_record("2024-02-11T23:30:00+00:00", 8.0),
+        glucose_record("2024-02-10T10:00:00+00:00", 9.0),
+        glucose_record("2024-02-01T08:00:00+00:00", 12.0),
+    ];
+    let now = utc("2024-02-15T12:00:00+00:00");
+    assert_eq!(
+        reports::weekday_report(
+            &glucose,
+            chrono_tz::Europe::Kyiv,
+            now,
+            14,
+            chrono::Weekday::Mon
+        ),
+        "Glucose by weekday (last 14 days, mmol/L):\n\
+Day   Avg  Readings\n\
+Mo    7.0         2\n\
+Tu      -  no data\n\
+We      -  no data\n\
+Th      -  no data\n\
+Fr      -  no data\n\
+Sa    9.0         1\n\
+Su      -  no data"
+    );
+    assert!(
+        reports::weekday_report(&glucose, chrono_tz::UTC, now, 14, chrono::Weekday::Sun)
+            .contains("Day   Avg  Readings\nSu    8.0         1\nMo    6.0         1\n")
+    );
+}
+
+#[tokio::test]
+async fn weekday_command_validates_days() {
+    let h = Harness::new();
+    h.send("/weekday 400").await;
+    h.send("/weekday").await;
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Usage: /weekday [1-365]");
+    assert!(texts[1].contains("Glucose by weekday (last 28 days, mmol/L):"));
+}
+
+#[tokio::test]
+async fn trend_command_validates_days() {
+    let h = Harness::new();
+    h.send("/trend 0").await;
+    h.send("/trend").await;
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Usage: /trend [1-365]");
+    assert!(texts[1].starts_with("Not enough data for a trend in the last 14 days"));
+}
+
+#[test]
+fn stored_timestamps_are_truncated_to_precision() {
+    let dt = utc("2024-02-01T08:15:42.123456789+00:00");
+    assert_eq!(
+        TimestampPrecision::Minute.format(dt, chrono_tz::UTC),
+        "2024-02-01T08:15:00+00:00"
+    );
+    assert_eq!(
+        TimestampPrecision::Second.format(dt, chrono_tz::UTC),
+        "2024-02-01T08:15:42+00:00"
+    );
+    assert_eq!(
+        TimestampPrecision::Second.format(dt, chrono_tz::Europe::Kyiv),
+        "2024-02-01T10:15:42+02:00"
+    );
+    assert_eq!(
+        TimestampPrecision::parse(" Second "),
+        Some(TimestampPrecision::Second)
+    );
+    assert_eq!(TimestampPrecision::parse("hour"), None);
+}
+
+#[tokio::test]
+async fn stored_timestamps_carry_the_input_timezone_offset() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.tz = chrono_tz::Europe::Kyiv;
+    h.send("/addgb 5.8 2024/2/1 9:00").await;
+    h.send("/addgb 6.1 2024/7/1 9:00").await;
+    h.send("/addketone 0.4").await;
+
+    assert_eq!(
+        data_lines(&h.dir.read(GLUCOSE_FILE))
+            .iter()
+            .map(|line| line.split(',').next().unwrap())
+            .collect::<Vec<_>>(),
+        ["2024-02-01T09:00:00+02:00", "2024-07-01T09:00:00+03:00"]
+    );
+    let ketones = h.dir.read(KETONES_FILE);
+    let stamped = data_lines(&ketones)[0].split(',').next().unwrap();
+    assert!(
+        stamped.ends_with("+02:00") || stamped.ends_with("+03:00"),
+        "{stamped}"
+    );
+}
+
+#[tokio::test]
+async fn now_timestamps_are_stored_without_seconds() {
+    let h = Harness::new();
+    h.send("/addgb 5.8").await;
+    h.send("/meal @toast").await;
+
+    for file in [GLUCOSE_FILE, MEALS_FILE] {
+        let content = h.dir.read(file);
+        let row = data_lines(&content)[0];
+        let timestamp = row.split(',').next().unwrap();
+        assert!(timestamp.ends_with(":00+00:00"), "{file}: {timestamp}");
+        assert_eq!(timestamp.len(), "2024-02-01T08:15:00+00:00".len());
+    }
+}
+
+#[test]
+fn last_data_line_reads_only_the_end_of_the_file() {
+    let dir = TestDir::new();
+    let path = dir.0.join("rows.csv");
+    assert_eq!(records::last_data_line(&path).unwrap(), None);
+    fs_err::write(&path, "timestamp,note\n").unwrap();
+    assert_eq!(records::last_data_line(&path).unwrap(), None);
+
+    let mut content = "timestamp,note\r\n".to_string();
+    for row in 0..500 {
+        content.push_str(&format!("2024-02-01T08:00:00+00:00,\"row {row}\"\r\n"));
+    }
+    let long = format!("2024-02-02T08:00:00+00:00,\"{}\"", "Глюкоза ".repeat(1000));
+    content.push_str(&format!("{long}\n\n"));
+    fs_err::write(&path, &content).unwrap();
+    assert_eq!(records::last_data_line(&path).unwrap(), Some(long));
+
+    fs_err::write(&path, "timestamp,note\n2024-02-01T08:00:00+00:00,\"only\"").unwrap();
+    assert_eq!(
+        records::last_data_line(&path).unwrap().as_deref(),
+        Some("2024-02-01T08:00:00+00:00,\"only\"")
+    );
+}
+
+#[tokio::test]
+async fn repeated_glucose_entry_is_flagged_as_duplicate() {
+    let h = Harness::new();
+    h.send("/addgb 5.8 2024/2/1 8:00").await;
+    h.send("/addgb 5.8 2024/2/1 8:01").await;
+    h.send("/addga 5.8 2024/2/1 8:01").await;
+    h.send("/addga 5.8 2024/2/1 8:05").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Glucose entry saved ✅");
+    assert_eq!(
+        texts[1],
+        "Glucose entry saved ✅\n⚠️ Looks like a duplicate of your last reading — saved anyway."
+    );
+    // Different tag, then outside the one-minute window.
+    assert_eq!(texts[2], "Glucose entry saved ✅");
+    assert_eq!(texts[3], "Glucose entry saved ✅");
+    assert_eq!(data_lines(&h.dir.read(GLUCOSE_FILE)).len(), 4);
+}
+
+#[tokio::test]
+async fn untimed_duplicates_are_measured_against_the_clock() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-01T08:00:30Z")));
+    h.send("/addgb 5.8 2024/2/1 8:00").await;
+    h.send("/addgb 5.8").await;
+    h.send("/addgb 6.2 2024/2/1 7:00").await;
+    h.send("/addgb 6.2").await;
+
+    let texts = h.bot.take_texts();
+    assert!(texts[1].ends_with(DUPLICATE_WARNING), "{}", texts[1]);
+    // An hour after the last reading by the clock, so not a duplicate.
+    assert_eq!(texts[3], "Glucose entry saved ✅");
+}
+
+#[tokio::test]
+async fn duplicate_is_skipped_when_configured() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().duplicates.action = DuplicateAction::Skip;
+    h.send(BTN_GLUCOSE_BEFORE_MEAL).await;
+    h.send("6.1").await;
+    h.send(BTN_GLUCOSE_BEFORE_MEAL).await;
+    h.send("6.1").await;
+    h.send("6.1").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[3],
+        "Looks like a duplicate of your last reading — not saved."
+    );
+    // The pending entry is cleared, so the third message is not a reading.
+    assert!(texts[4].starts_with("Choose an action from menu."));
+    assert_eq!(data_lines(&h.dir.read(GLUCOSE_FILE)).len(), 1);
+}
+
+#[test]
+fn parse_rename_payload_accepts_arrow_and_two_words() {
+    assert_eq!(
+        parse_rename_payload("Vitamin  D -> Vitamin D3 --keep-log"),
+        Some(MedicationRename {
+            old: "Vitamin D".to_string(),
+            new: "Vitamin D3".to_string(),
+            migrate_log: false,
+        })
+    );
+    assert_eq!(
+        parse_rename_payload("metformin Glucophage"),
+        Some(MedicationRename {
+            old: "metformin".to_string(),
+            new: "Glucophage".to_string(),
+            migrate_log: true,
+        })
+    );
+    assert_eq!(parse_rename_payload("a b c"), None);
+    assert_eq!(parse_rename_payload("-> new"), None);
+}
+
+#[tokio::test]
+async fn renamemed_renames_button_and_migrates_log() {
+    let h = Harness::new();
+    h.send("/addmed Metformin").await;
+    h.send("/addmed Insulin").await;
+    h.send("💊 Metformin").await;
+    h.send("💊 Insulin").await;
+    h.send("💊 Metformin").await;
+    h.bot.take();
+
+    h.send("/renamemed metformin -> Glucophage").await;
+    h.send("/renamemed Glucophage -> insulin --keep-log").await;
+    h.send("/renamemed Aspirin -> Other").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Medication renamed: Metformin → Glucophage ✅\nLog rows updated: 2"
+    );
+    assert_eq!(
+        texts[1],
+        "Medication Glucophage merged into Insulin ✅\nLog rows kept with the old name."
+    );
+    assert_eq!(texts[2], "Medication not found: Aspirin");
+    assert_eq!(h.dir.read(MEDICATIONS_FILE), "Insulin\n");
+    let log = h.dir.read(MEDICATION_LOG_FILE);
+    let names = data_lines(&log)
+        .iter()
+        .map(|line| records::parse_csv_line(line)[2].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["Glucophage", "Insulin", "Glucophage"]);
+}
+
+#[test]
+fn parse_food_payload_extracts_item_grams_and_note() {
+    let options = &InputOptions {
+        tz: Tz::UTC,
+        note_max_len: 0,
+        timestamp_precision: TimestampPrecision::Minute,
+        clock: Arc::new(clock::RealClock),
+        glucose_unit: settings::GlucoseUnit::Mmol,
+        sync_writes: false,
+        log_entry_notes: false,
+    };
+    assert_eq!(
+        parse_food_payload("oatmeal 45g @breakfast", options).unwrap(),
+        (
+            "oatmeal".to_string(),
+            Some(45.0),
+            Some("breakfast".to_string())
+        )
+    );
+    assert_eq!(
+        parse_food_payload("rye bread 30,5 g", options).unwrap(),
+        ("rye bread".to_string(), Some(30.5), None)
+    );
+    assert_eq!(
+        parse_food_payload("apple", options).unwrap(),
+        ("apple".to_string(), None, None)
+    );
+    assert_eq!(
+        parse_food_payload("7up", options).unwrap(),
+        ("7up".to_string(), None, None)
+    );
+    assert!(
+        parse_food_payload("45g", options)
+            .is_ok_and(|(item, grams, _)| item == "45g" && grams.is_none())
+    );
+    assert!(parse_food_payload("soup 0g", options).is_err());
+    assert!(parse_food_payload("@just a note", options).is_err());
+}
+
+#[tokio::test]
+async fn food_command_and_button_save_to_food_csv() {
+    let h = Harness::new();
+    h.send("/food oatmeal 45g @breakfast").await;
+    h.send(BTN_FOOD).await;
+    h.send("apple").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Food saved ✅ (oatmeal, 45 g)");
+    assert_eq!(texts[2], "Food saved ✅ (apple)");
+    let food = h.dir.read(FOOD_FILE);
+    let rows = data_lines(&food);
+    assert!(food.starts_with(FOOD_HEADER));
+    assert!(rows[0].ends_with(",1,\"oatmeal\",45,\"breakfast\""));
+    assert!(rows[1].ends_with(",1,\"apple\",,\"\""));
+}
+
+#[tokio::test]
+async fn dual_units_toggle_changes_confirmation_only() {
+    let h = Harness::new();
+    h.send("/addgb 5.8").await;
+    h.send("/dualunits on").await;
+    h.send("/addga 5.8, 7.2 2024/2/1 10:00").await;
+    h.send(BTN_GLUCOSE_BEFORE_MEAL).await;
+    h.send("4.4").await;
+    h.send("/dualunits off").await;
+    h.send("/dualunits").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Glucose entry saved ✅");
+    assert_eq!(
+        texts[1],
+        "Dual units display on ✅ e.g. 5.8 mmol/L (104 mg/dL)"
+    );
+    assert_eq!(
+        texts[2],
+        "Glucose entries saved ✅ (2) 5.8 mmol/L (104 mg/dL), 7.2 mmol/L (130 mg/dL)"
+    );
+    assert_eq!(texts[4], "Saved ✅ 4.4 mmol/L (79 mg/dL)");
+    assert_eq!(texts[5], "Dual units display off ✅");
+    assert_eq!(texts[6], "Dual units display is off.");
+    let glucose = h.dir.read(GLUCOSE_FILE);
+    assert!(data_lines(&glucose)[1].contains(",after_meal,5.8,"));
+}
+
+#[tokio::test]
+async fn stale_pending_entries_are_swept() {
+    let h = Harness::new();
+    h.send(BTN_WEIGHT).await;
+    set_pending(&h.state, ChatId(2), PendingEntry::Weight).await;
+    {
+        let mut lock = h.state.pending_by_chat.lock().await;
+        let stale = Instant::now()
+            .checked_sub(PENDING_EXPIRY + Duration::from_secs(1))
+            .unwrap();
+        lock.get_mut(&CHAT).unwrap().created = stale;
+    }
+
+    // An expired entry is ignored even before the sweep runs.
+    assert!(get_pending(&h.state, CHAT).await.is_none());
+    assert_eq!(sweep_stale_pending(&h.state, Instant::now()).await, 1);
+    let lock = h.state.pending_by_chat.lock().await;
+    assert!(!lock.contains_key(&CHAT));
+    assert!(lock.contains_key(&ChatId(2)));
+}
+
+#[test]
+fn clinic_summary_reports_averages_a1c_and_weight() {
+    let now = utc("2024-03-31T12:00:00+00:00");
+    let record = |days_ago: i64, tag: GlucoseTag, value: f64| {
+        tagged_glucose_record(
+            &(now - chrono::Duration::days(days_ago)).to_rfc3339(),
+            tag,
+            value,
+        )
+    };
+    let mut glucose = (0..40)
+        .flat_map(|d| {
+            [
+                record(d, GlucoseTag::BeforeMeal, 5.0),
+                record(d, GlucoseTag::AfterMeal, 8.0),
+            ]
+        })
+        .collect::<Vec<_>>();
+    glucose.push(record(120, GlucoseTag::AfterMeal, 20.0));
+    let weights =
+        [(28, 80.0), (14, 79.0), (0, 78.0)].map(|(days_ago, value)| records::WeightRecord {
+            timestamp: now - chrono::Duration::days(days_ago),
+            value,
+        });
+    let target = settings::UserSettings::default().general_range();
+
+    let summary = summary::clinic_summary(&glucose, &weights, target, chrono_tz::UTC, now);
+    let lines = summary::summary_lines(&summary);
+    assert_eq!(lines[0], "Period: 2024-01-02 to 2024-03-31 (last 90 days)");
+    assert_eq!(lines[1], "Glucose readings: 80 on 40 days");
+    assert_eq!(lines[2], "Average glucose: 6.5 mmol/L (117 mg/dL)");
+    assert_eq!(lines[3], "Before meal: 5.0 mmol/L (40 readings)");
+    assert_eq!(lines[4], "After meal: 8.0 mmol/L (40 readings)");
+    assert_eq!(
+        lines[5],
+        "Estimated A1c: 5.7 % (from average glucose, not a lab result)"
+    );
+    assert_eq!(
+        lines.last().unwrap(),
+        "Weight: 80.0 kg -> 78.0 kg (-2.0 kg), trend -0.50 kg per week"
+    );
+
+    let axis = chart::GlucoseAxis::for_settings(&settings::UserSettings::default());
+    let pdf = summary::render_pdf(&summary, axis).unwrap();
+    assert!(pdf.starts_with(b"%PDF-"));
+    let text = pdf_text(&pdf);
+    assert!(text.contains("Estimated A1c: 5.7 % (from average glucose, not a lab result)"));
+    assert!(text.contains(pdf::DISCLAIMER));
+}
+
+#[tokio::test]
+async fn summary_command_sends_pdf_even_with_limited_data() {
+    let h = Harness::new();
+    h.send("/summary").await;
+    h.send("/addgb 6.1").await;
+    h.send("/summary").await;
+
+    let sent = h.bot.take();
+    let (name, bytes) = sent[0].document.clone().unwrap();
+    assert_eq!(name, "glucose-summary.pdf");
+    assert_eq!(sent[0].text, "Glucose summary for the last 90 days");
+    assert!(pdf_text(&bytes).contains("No glucose readings in this period."));
+    let (_, bytes) = sent[2].document.clone().unwrap();
+    let text = pdf_text(&bytes);
+    assert!(text.contains("Limited data: fewer than 30 readings or 14 days."));
+    assert!(text.contains("Average glucose: 6.1 mmol/L (110 mg/dL)\n"));
+}
+
+#[test]
+fn weight_goal_progress_handles_loss_and_gain_goals() {
+    assert_eq!(
+        reports::weight_goal_progress(80.0, 75.2, 72.0),
+        "Goal 72.0 kg: 3.2 kg to go (60% of the way from 80.0 kg)"
+    );
+    assert_eq!(
+        reports::weight_goal_progress(60.0, 62.5, 65.0),
+        "Goal 65.0 kg: 2.5 kg to go (50% of the way from 60.0 kg)"
+    );
+    assert_eq!(
+        reports::weight_goal_progress(80.0, 81.0, 72.0),
+        "Goal 72.0 kg: 9.0 kg to go (0% of the way from 80.0 kg)"
+    );
+    assert_eq!(
+        reports::weight_goal_progress(80.0, 71.8, 72.0),
+        "Goal of 72.0 kg reached 🎉"
+    );
+    assert_eq!(
+        reports::weight_goal_progress(60.0, 65.0, 65.0),
+        "Goal of 65.0 kg reached 🎉"
+    );
+}
+
+#[tokio::test]
+async fn weight_goal_is_stored_and_reported_after_weight_entries() {
+    let h = Harness::new();
+    h.send("/weightgoal").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("80").await;
+    h.send("/weightgoal 72,0").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("76").await;
+    h.send("/weightgoal abc").await;
+    h.send("/weightgoal off").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("75").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "No weight goal set. Use /weightgoal <kg>.");
+    assert_eq!(texts[2], "Saved ✅");
+    assert_eq!(texts[3], "Weight goal set ✅ (72.0 kg)");
+    assert_eq!(
+        texts[5],
+        "Saved ✅\nGoal 72.0 kg: 4.0 kg to go (50% of the way from 80.0 kg)"
+    );
+    assert_eq!(texts[6], "Usage: /weightgoal <kg|off>");
+    assert_eq!(texts[7], "Weight goal cleared ✅");
+    assert_eq!(texts[9], "Saved ✅");
+}
+
+#[tokio::test]
+async fn weight_alert_notes_quick_changes_within_a_week() {
+    let h = Harness::new();
+    let weights = user_data_dir(&h.dir.0, CHAT).join(WEIGHT_FILE);
+    let seed = |days: i64, value: &str| {
+        let at = Utc::now() - chrono::Duration::days(days);
+        fs_err::create_dir_all(weights.parent().unwrap()).unwrap();
+        fs_err::write(
+            &weights,
+            format!(
+                "timestamp,chat_id,value_kg\n{},1,{value}\n",
+                at.format("%Y-%m-%dT%H:%M:%S+00:00")
+            ),
+        )
+        .unwrap();
+    };
+    h.send("/weightalert").await;
+    h.send("/weightalert 2").await;
+    seed(2, "80.0");
+    h.send(BTN_WEIGHT).await;
+    h.send("83").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("82,5").await;
+    seed(30, "70.0");
+    h.send(BTN_WEIGHT).await;
+    h.send("75").await;
+    h.send("/weightalert abc").await;
+    h.send("/weightalert off").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Weight change notes are off. Use /weightalert <kg>."
+    );
+    assert_eq!(texts[1], "Weight change note set ✅ (over 2.0 kg)");
+    assert_eq!(
+        texts[3],
+        "Saved ✅\nNote: weight changed by +3.0 kg since 2 days ago (80.0 kg). \
+Quick changes can be worth mentioning to your care team."
+    );
+    assert_eq!(texts[5], "Saved ✅");
+    assert_eq!(texts[7], "Saved ✅");
+    assert_eq!(texts[8], "Usage: /weightalert <kg|off>");
+    assert_eq!(texts[9], "Weight change notes turned off ✅");
+
+    // A row dated after the new entry is not what the weight changed from.
+    let at =
+        |days: i64| (Utc::now() - chrono::Duration::days(days)).format("%Y-%m-%dT%H:%M:%S+00:00");
+    fs_err::write(
+        &weights,
+        format!(
+            "timestamp,chat_id,value_kg\n{},1,80.0\n{},1,60.0\n",
+            at(2),
+            at(-1)
+        ),
+    )
+    .unwrap();
+    h.send("/weightalert 2").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("83").await;
+    assert_eq!(
+        h.bot.take_texts()[2],
+        "Saved ✅\nNote: weight changed by +3.0 kg since 2 days ago (80.0 kg). \
+Quick changes can be worth mentioning to your care team."
+    );
+}
+
+#[tokio::test]
+async fn commands_are_listed_by_category_from_the_registry() {
+    let h = Harness::new();
+    h.send("/commands").await;
+    h.send("/help").await;
+
+    let texts = h.bot.take_texts();
+    let sections = texts[0].split("\n\n").collect::<Vec<_>>();
+    assert_eq!(sections.len(), 4);
+    assert!(sections[0].starts_with(
+        "Entry:\n/addgb <value> [date time] [@note] - add glucose before meal\n  e.g. /addgb 5.8"
+    ));
+    assert!(sections[1].starts_with("Reports:\n"));
+    assert!(sections[2].starts_with("Settings:\n"));
+    assert!(sections[3].starts_with("Admin:\n/menu - show menu buttons\n/menutext - "));
+    for command in commands::BotCommand::ALL {
+        assert_eq!(
+            texts[0]
+                .matches(&format!("\n{} - ", command.usage()))
+                .count(),
+            1
+        );
+        assert!(texts[1].contains(&format!(
+            "\n{} - {}\n",
+            command.usage(),
+            command.description()
+        )));
+    }
+}
+
+#[tokio::test]
+async fn malformed_csv_rows_are_collected_and_reported() {
+    let h = Harness::new();
+    for day in 2..=6 {
+        h.send(&format!("/addgb 6.0 2024/2/{day} 8:00")).await;
+    }
+    let path = user_data_dir(&h.dir.0, CHAT).join(GLUCOSE_FILE);
+    let mut content = h.dir.read(GLUCOSE_FILE);
+    content.push_str("yesterday,1,before_meal,6.0,,manual,\n");
+    content.push_str("2024-02-07T08:00:00+00:00,1,before_meal,six,,manual,\n");
+    fs_err::write(&path, &content).unwrap();
+
+    let records = records::read_records::<records::GlucoseRecord>(&h.dir.0, CHAT).unwrap();
+    assert_eq!(records.rows.len(), 5);
+    assert_eq!(records.skipped(), 2);
+    assert_eq!(records.errors[0].line, 7);
+    assert_eq!(records.errors[0].reason, "invalid timestamp \"yesterday\"");
+    assert_eq!(records.errors[1].reason, "invalid value \"six\"");
+
+    let mut bytes = fs_err::read(&path).unwrap();
+    bytes.extend_from_slice(
+        b"2024-02-08T08:00:00+00:00,1,before_meal,6.0,caf\xe9,manual,,mmol/L,,,\n",
+    );
+    bytes.extend_from_slice(b"2024-02-09T08:00:00+00:00,1,before_meal,6.1,,manual,,mmol/L,,,\n");
+    fs_err::write(&path, bytes).unwrap();
+    let records = records::read_records::<records::GlucoseRecord>(&h.dir.0, CHAT).unwrap();
+    assert_eq!(records.rows.len(), 6);
+    assert_eq!(records.skipped(), 3);
+    assert_eq!(records.errors[2].line, 9);
+    assert_eq!(records.errors[2].reason, "invalid UTF-8 in note");
+    fs_err::write(&path, content).unwrap();
+
+    h.bot.take();
+    h.send("/trend 365").await;
+    h.send("/mealeffect").await;
+    let texts = h.bot.take_texts();
+    assert!(texts[0].ends_with("\n(2 rows skipped)"));
+    assert!(texts[1].ends_with("\n(2 rows skipped)"));
+}
+
+#[test]
+fn spoken_numbers_are_parsed_only_when_unambiguous() {
+    assert_eq!(voice::parse_spoken_number("5.8"), Some(5.8));
+    assert_eq!(voice::parse_spoken_number("It's 5,8."), Some(5.8));
+    assert_eq!(voice::parse_spoken_number("five point eight"), Some(5.8));
+    assert_eq!(voice::parse_spoken_number("Twelve"), Some(12.0));
+    assert_eq!(voice::parse_spoken_number("6 point 25"), Some(6.25));
+    assert_eq!(voice::parse_spoken_number("five or six"), None);
+    assert_eq!(voice::parse_spoken_number("no idea"), None);
+    assert_eq!(voice::parse_spoken_number("nan"), None);
+}
+
+#[derive(Debug)]
+struct FixedTranscriber(anyhow::Result<String>);
+
+impl voice::Transcriber for FixedTranscriber {
+    fn transcribe(&self, _audio: Vec<u8>) -> voice::TranscriptFuture<'_> {
+        let result = match &self.0 {
+            Ok(text) => Ok(text.clone()),
+            Err(err) => Err(anyhow::anyhow!("{err}")),
+        };
+        Box::pin(async move { result })
+    }
+}
+
+#[tokio::test]
+async fn voice_reply_to_glucose_prompt_is_transcribed() {
+    let mut h = Harness::new();
+    h.send_voice().await;
+    assert!(h.bot.take().is_empty());
+    h.send(BTN_GLUCOSE_BEFORE_MEAL).await;
+    h.send_voice().await;
+    assert_eq!(
+        h.bot.take_texts()[1],
+        "Voice input is not available. Please type the value."
+    );
+
+    Arc::get_mut(&mut h.state).unwrap().transcriber =
+        Some(Arc::new(FixedTranscriber(Ok("five or six".to_string()))));
+    h.send_voice().await;
+    Arc::get_mut(&mut h.state).unwrap().transcriber =
+        Some(Arc::new(FixedTranscriber(Err(anyhow::anyhow!("offline")))));
+    h.send_voice().await;
+    Arc::get_mut(&mut h.state).unwrap().transcriber = Some(Arc::new(FixedTranscriber(Ok(
+        "five point eight".to_string(),
+    ))));
+    h.send_voice().await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Could not recognize a single number. Please type the value."
+    );
+    assert_eq!(texts[1], texts[0]);
+    assert!(texts[2].starts_with("Saved ✅"));
+    let glucose = h.dir.read(GLUCOSE_FILE);
+    assert!(data_lines(&glucose)[0].contains(",before_meal,5.8,"));
+}
+
+#[tokio::test]
+async fn command_transcriber_reads_stdout_of_the_configured_program() {
+    let transcriber = voice::CommandTranscriber::parse("cat").unwrap();
+    let transcript = voice::Transcriber::transcribe(&transcriber, b"6,1\n".to_vec())
+        .await
+        .unwrap();
+    assert_eq!(transcript, "6,1");
+    assert!(voice::CommandTranscriber::parse("  ").is_err());
+}
+
+#[tokio::test]
+async fn export_sends_rows_within_date_range() {
+    let h = Harness::new();
+    h.send("/addgb 5.1 2024/1/31 23:30").await;
+    h.send("/addgb 5.2 2024/2/1 0:10").await;
+    h.send("/addga 7.5 2024/2/29 21:00").await;
+    h.send("/addgb 5.3 2024/3/1 8:00").await;
+    h.bot.take();
+
+    h.send("/export glucose 2024-02-01 2024-02-29").await;
+    h.send("/export weight 2024-02-01 2024-02-29").await;
+    h.send("/export glucose 2024-03-01 2024-02-01").await;
+    h.send("/export glucose 2024-02-30 2024-03-01").await;
+    h.send("/export blood 2024-02-01 2024-02-29").await;
+
+    let sent = h.bot.take();
+    let (name, bytes) = sent[0].document.clone().unwrap();
+    assert_eq!(name, "glucose_2024-02-01_2024-02-29.csv");
+    assert_eq!(
+        sent[0].text,
+        "glucose from 2024-02-01 to 2024-02-29: 2 rows"
+    );
+    let content = String::from_utf8(bytes).unwrap();
+    let lines = content.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("timestamp,chat_id,tag,value"));
+    assert!(lines[1].starts_with("2024-02-01T00:10:00+00:00,1,before_meal,5.2,"));
+    assert!(lines[2].starts_with("2024-02-29T21:00:00+00:00,1,after_meal,7.5,"));
+    assert_eq!(
+        sent[1].text,
+        "No weight entries from 2024-02-01 to 2024-02-29."
+    );
+    assert!(sent[1].document.is_none());
+    assert_eq!(
+        sent[2].text,
+        "Start date 2024-03-01 is after end date 2024-02-01."
+    );
+    assert_eq!(
+        sent[3].text,
+        "Invalid date '2024-02-30'. Use YYYY-MM-DD, e.g. 2024-01-31."
+    );
+    assert!(sent[4].text.starts_with("Usage: /export <glucose|weight|"));
+}
+
+#[test]
+fn sha256_matches_known_digests() {
+    assert_eq!(
+        checksum::sha256_hex(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        checksum::sha256_hex(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    // Two blocks: the padding does not fit after 56 bytes of input.
+    assert_eq!(
+        checksum::sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+}
+
+#[tokio::test]
+async fn export_checksums_send_a_sha256_file_over_the_sent_bytes() {
+    let h = Harness::new();
+    h.send("/addgb 5.2 2024/2/1 0:10").await;
+    h.send("/export glucose 2024-02-01 2024-02-29").await;
+    h.send("/checksums on").await;
+    h.send("/export glucose 2024-02-01 2024-02-29").await;
+    h.send("/checksums").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent.len(), 6);
+    assert!(sent[1].document.is_some());
+    assert_eq!(sent[2].text, "Export checksums on ✅");
+    let (name, bytes) = sent[3].document.clone().unwrap();
+    assert_eq!(name, "glucose_2024-02-01_2024-02-29.csv");
+    let (checksum_name, checksum) = sent[4].document.clone().unwrap();
+    assert_eq!(checksum_name, "glucose_2024-02-01_2024-02-29.csv.sha256");
+    assert_eq!(
+        String::from_utf8(checksum).unwrap(),
+        format!("{}  {name}\n", checksum::sha256_hex(&bytes))
+    );
+    assert_eq!(
+        sent[4].text,
+        "SHA-256 of glucose_2024-02-01_2024-02-29.csv. Check it with: sha256sum -c glucose_2024-02-01_2024-02-29.csv.sha256"
+    );
+    assert_eq!(sent[5].text, "Export checksums are on.");
+}
+
+#[tokio::test]
+async fn export_health_writes_apple_health_records() {
+    let h = Harness::new();
+    h.send("/addgb 5.8 2024/2/1 9:00").await;
+    h.send("/addga 7.25 2024/2/1 11:00").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("78.4").await;
+    h.bot.take();
+
+    h.send("/export health").await;
+    h.send("/export health 2024-02-01 2024-02-01").await;
+    h.send("/export health 2024-02-01").await;
+
+    let sent = h.bot.take();
+    let (name, bytes) = sent[0].document.clone().unwrap();
+    assert_eq!(name, "apple_health.xml");
+    assert_eq!(sent[0].text, "Apple Health export: 3 records");
+    let content = String::from_utf8(bytes).unwrap();
+    assert!(content.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<HealthData"));
+    assert!(content.contains(
+        " <Record type=\"HKQuantityTypeIdentifierBloodGlucose\" sourceName=\"Personal Diabetes Diary\" \
+unit=\"mg/dL\" creationDate=\"2024-02-01 09:00:00 +0000\" startDate=\"2024-02-01 09:00:00 +0000\" \
+endDate=\"2024-02-01 09:00:00 +0000\" value=\"104.4\">\n  \
+<MetadataEntry key=\"HKBloodGlucoseMealTime\" value=\"1\"/>\n </Record>\n"
+    ));
+    assert!(content.contains(
+        "value=\"130.5\">\n  <MetadataEntry key=\"HKBloodGlucoseMealTime\" value=\"2\"/>"
+    ));
+    assert!(content.contains("type=\"HKQuantityTypeIdentifierBodyMass\""));
+    assert!(content.contains("unit=\"kg\""));
+    assert!(content.contains("value=\"78.4\"/>\n"));
+    assert!(content.ends_with("</HealthData>\n"));
+
+    let (name, _) = sent[1].document.clone().unwrap();
+    assert_eq!(name, "apple_health_2024-02-01_2024-02-01.xml");
+    assert_eq!(
+        sent[1].text,
+        "Apple Health export from 2024-02-01 to 2024-02-01: 2 records"
+    );
+    assert!(sent[2].text.starts_with("Usage: /export"));
+}
+
+#[tokio::test]
+async fn export_health_without_entries_sends_no_document() {
+    let h = Harness::new();
+    h.send("/export health").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent[0].text, "No glucose or weight entries.");
+    assert!(sent[0].document.is_none());
+}
+
+#[tokio::test]
+async fn export_long_merges_measurements_into_one_sorted_csv() {
+    let h = Harness::new();
+    h.send("/export long").await;
+    h.send("/addga 7.25 2024/2/1 11:00 @pasta, \"big\"").await;
+    h.send("/addinsulin 4.5 2024/2/1 10:55 @lunch").await;
+    h.send("/addketone 0.4 2024/2/2 8:05").await;
+    h.send("/addgb 5.8 2024/2/1 9:00").await;
+    h.bot.take();
+
+    h.send("/export long").await;
+    h.send("/export long 2024-02-02 2024-02-02").await;
+
+    let sent = h.bot.take();
+    assert_eq!(sent.len(), 2);
+    let (name, bytes) = sent[0].document.clone().unwrap();
+    assert_eq!(name, "long.csv");
+    assert_eq!(sent[0].text, "Long-format export: 4 values");
+    assert_eq!(
+        String::from_utf8(bytes).unwrap(),
+        "timestamp,metric,value,unit,tag,note\n\
+2024-02-01T09:00:00+00:00,glucose,5.8,mmol/L,before_meal,\"\"\n\
+2024-02-01T10:55:00+00:00,insulin,4.5,U,meal,\"lunch\"\n\
+2024-02-01T11:00:00+00:00,glucose,7.25,mmol/L,after_meal,\"pasta, \"\"big\"\"\"\n\
+2024-02-02T08:05:00+00:00,ketones,0.4,mmol/L,,\"\"\n"
+    );
+    let (name, bytes) = sent[1].document.clone().unwrap();
+    assert_eq!(name, "long_2024-02-02_2024-02-02.csv");
+    assert_eq!(
+        sent[1].text,
+        "Long-format export from 2024-02-02 to 2024-02-02: 1 values"
+    );
+    assert!(
+        String::from_utf8(bytes)
+            .unwrap()
+            .ends_with(",ketones,0.4,mmol/L,,\"\"\n")
+    );
+}
+
+#[tokio::test]
+async fn privacy_notice_is_shown_once_and_on_request() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().privacy_notice = true;
+    h.send("/addgb 5.8").await;
+    h.send("/addgb 6.1").await;
+    h.send("/privacy").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts.len(), 4);
+    assert!(texts[0].starts_with("Privacy notice: your entries are stored as plain text"));
+    assert_eq!(texts[1], "Glucose entry saved ✅");
+    assert_eq!(texts[2], "Glucose entry saved ✅");
+    assert_eq!(texts[3], texts[0]);
+    let glucose = h.dir.read(GLUCOSE_FILE);
+    assert_eq!(data_lines(&glucose).len(), 2);
+    assert!(
+        h.dir
+            .read("settings.toml")
+            .contains("privacy_acknowledged = true")
+    );
+}
+
+#[tokio::test]
+async fn time_of_day_ranges_take_precedence_over_meal_ranges() {
+    let h = Harness::new();
+    h.send("/setrange before 4 7").await;
+    h.send("/setrange 22:00-6:00 5 8").await;
+    h.send("/setrange 12:00-12:00 5 8").await;
+    h.send("/setrange 14:00-16:00 off").await;
+    h.send("/addgb 4.5 2024/2/1 23:30").await;
+    h.send("/addgb 4.5 2024/2/1 6:00").await;
+    h.send("/addga 8.5 2024/2/2 5:59").await;
+    h.send("/setrange").await;
+
+    let texts = h.bot.take_texts();
+    assert!(texts[1].ends_with("\n- 22:00-06:00: 5.0-8.0"));
+    assert!(texts[2].starts_with("Usage: /setrange [before|after|HH:MM-HH:MM]"));
+    assert_eq!(texts[3], "No target range is set for 14:00-16:00");
+    assert_eq!(
+        texts[4],
+        "Glucose entry saved ✅\n⚠️ 4.5 is below your 22:00-06:00 target (5.0-8.0)"
+    );
+    assert_eq!(texts[5], "Glucose entry saved ✅");
+    assert_eq!(
+        texts[6],
+        "Glucose entry saved ✅\n⚠️ 8.5 is above your 22:00-06:00 target (5.0-8.0)"
+    );
+    assert!(texts[7].ends_with("- after meal: general\n- 22:00-06:00: 5.0-8.0"));
+    let saved = settings::load_settings(&h.dir.0, CHAT).unwrap();
+    assert_eq!(saved.time_ranges.len(), 1);
+}
+
+#[tokio::test]
+async fn tail_shows_the_raw_last_line_to_admins() {
+    let mut h = Harness::new();
+    h.send("/addgb 5.8 2024-02-01T09:05 @before, breakfast")
+        .await;
+    h.bot.take();
+    h.send("/tail glucose").await;
+    let sent = h.bot.take();
+    assert!(!sent[0].preformatted);
+    assert!(sent[0].text.starts_with("Choose an action from menu."));
+    assert!(!sent[0].text.contains("before, breakfast"));
+    Arc::get_mut(&mut h.state).unwrap().admin_chat_ids = HashSet::from([CHAT]);
+    h.send("/tail glucose").await;
+    h.send("/tail ketones").await;
+    h.send("/tail blood").await;
+
+    let sent = h.bot.take();
+    assert!(sent[0].preformatted);
+    let glucose = h.dir.read(GLUCOSE_FILE);
+    assert_eq!(
+        sent[0].text,
+        format!("glucose.csv line 2:\n{}", glucose.lines().last().unwrap())
+    );
+    assert!(sent[0].text.contains("\"before, breakfast\""));
+    assert_eq!(sent[1].text, "ketones.csv is empty.");
+    assert_eq!(
+        sent[2].text,
+        "Usage: /tail <glucose|weight|ketones|insulin|meals|food|medlog>"
+    );
+
+    let path = user_data_dir(&h.state.data_dir, CHAT).join(GLUCOSE_FILE);
+    fs_err::write(
+        &path,
+        format!("{glucose}2024-02-01T10:00:00+00:00,1,bad\r\n"),
+    )
+    .unwrap();
+    h.send("/tail glucose").await;
+    assert_eq!(
+        h.bot.take_texts(),
+        ["glucose.csv line 3, CRLF:\n2024-02-01T10:00:00+00:00,1,bad"]
+    );
+
+    h.send("/addmed Tail").await;
+    assert!(h.bot.take_texts()[0].starts_with("\"Tail\" is reserved"));
+}
+
+#[tokio::test]
+async fn debug_reports_route_without_side_effects_for_admins() {
+    let mut h = Harness::new();
+    h.send("/debug /addgb 5.8").await;
+    Arc::get_mut(&mut h.state).unwrap().admin_chat_ids = HashSet::from([CHAT]);
+    h.send("/debug /addgb 5,8 @fasting").await;
+    h.send("/debug /clear food").await;
+    h.send(BTN_WEIGHT).await;
+    h.send("/debug 78,4").await;
+    h.send("/debug hello").await;
+    h.send("/debug").await;
+
+    let texts = h.bot.take_texts();
+    assert!(texts[0].starts_with("Choose an action from menu."));
+    assert_eq!(
+        texts[1],
+        "Input: \"/addgb 5,8 @fasting\"\n\
+Route: glucose add (before_meal)\n\
+Parsed: GlucoseInput { values: [5.8], timestamp: None, note: Some(\"fasting\"), source: None, confirmed: false, trend: None }"
+    );
+    assert_eq!(
+        texts[2],
+        "Input: \"/clear food\"\nRoute: command /clear\nParsed: Some(Food)"
+    );
+    assert_eq!(
+        texts[4],
+        "Input: \"78,4\"\nRoute: pending Weight\nParsed: Some(78.4)"
+    );
+    assert_eq!(
+        texts[5],
+        "Input: \"hello\"\nRoute: pending Weight\nParsed: None"
+    );
+    assert_eq!(texts[6], "Usage: /debug <raw text>");
+    assert!(!user_data_dir(&h.dir.0, CHAT).join(GLUCOSE_FILE).exists());
+    assert!(matches!(
+        get_pending(&h.state, CHAT).await,
+        Some(PendingEntry::Weight)
+    ));
+}
+
+#[tokio::test]
+async fn status_reports_operational_counts_for_admins() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-01T12:30:00Z")));
+    h.send("/addgb 5.8 2024-02-01T08:00").await;
+    h.send("/addketone 0.6 2024-02-01T09:00").await;
+    h.send("/addgb 6.1 2024-01-31T08:00").await;
+    h.bot.take();
+    h.send("/status").await;
+    let state = Arc::get_mut(&mut h.state).unwrap();
+    state.admin_chat_ids = HashSet::from([CHAT]);
+    state.started_at = utc("2024-01-30T06:00:00Z");
+    state.backup = Some(backup::BackupOptions {
+        dir: h.dir.0.join("backups"),
+        keep: 2,
+    });
+    h.send("/status").await;
+
+    let texts = h.bot.take_texts();
+    assert!(texts[0].starts_with("Choose an action from menu."));
+    let expected = format!(
+        "Uptime: 2d 6h 30m (since 2024-01-30 06:00)\n\
+Chats: 1 configured, 0 registered, 1 active today\n\
+Entries today: 2\n\
+Next runs (UTC):\n\
+- backup: 2024-02-02 06:00\n\
+- usage stats: off\n\
+- nudge check: 2024-02-01 13:00\n\
+Storage: CSV files in {}",
+        h.dir.0.display()
+    );
+    assert_eq!(texts[1], expected);
+}
+
+/// Log output written on this thread while the guard from [`LogCapture::install`] lives.
+#[derive(Clone, Default)]
+struct LogCapture(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogCapture {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl LogCapture {
+    fn install(&self) -> tracing::subscriber::DefaultGuard {
+        let writer = self.clone();
+        tracing::subscriber::set_default(
+            tracing_subscriber::fmt()
+                .with_ansi(false)
+                .with_writer(move || writer.clone())
+                .finish(),
+        )
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[tokio::test]
+async fn rewrites_log_entry_saved_like_new_entries() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-01T09:00:00+00:00")));
+    h.send("/addgb 5.8 2024/2/1 8:00").await;
+    h.send("/history").await;
+    let listing = h.bot.take().pop().unwrap();
+    let day = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+
+    let logs = LogCapture::default();
+    let guard = logs.install();
+    h.tap(&listing.inline_data("02/01 08:00 glucose 5.8 (before meal)"))
+        .await;
+    let actions = h.bot.take().pop().unwrap();
+    h.tap(&actions.inline_data("✏️ Value")).await;
+    h.send("6,2").await;
+    h.send("/note dizzy").await;
+    tagging::tag_glucose(&h.state, CHAT, "#sick", (day, day), Tz::UTC).unwrap();
+    drop(guard);
+    let text = logs.text();
+    assert_eq!(text.matches("entry saved").count(), 3, "{text}");
+    assert!(text.contains("value=6.2"));
+    assert_eq!(text.matches("has_note=true").count(), 2);
+    assert!(!text.contains("dizzy") && !text.contains("#sick"));
+
+    Arc::get_mut(&mut h.state).unwrap().input.log_entry_notes = true;
+    let logs = LogCapture::default();
+    let guard = logs.install();
+    tagging::tag_glucose(&h.state, CHAT, "#travel", (day, day), Tz::UTC).unwrap();
+    drop(guard);
+    assert!(logs.text().contains("note=\"#travel\""));
+}
+
+#[test]
+fn sync_writes_still_append_and_replace_files() {
+    let dir = TestDir::new();
+    let path = dir.0.join("synced.csv");
+    append_line_if_needed(&path, "a,b", true).unwrap();
+    append_csv_line(&path, "1,2", true).unwrap();
+    write_file_atomically(&dir.0.join("replaced.txt"), "x\n", true).unwrap();
+
+    assert_eq!(fs_err::read_to_string(&path).unwrap(), "a,b\n1,2\n");
+    assert_eq!(
+        fs_err::read_to_string(dir.0.join("replaced.txt")).unwrap(),
+        "x\n"
+    );
+}
+
+#[test]
+fn startup_self_test_round_trips_and_cleans_up() {
+    let dir = TestDir::new();
+    storage::self_test(&dir.0, &clock::RealClock).unwrap();
+    assert_eq!(fs_err::read_dir(&dir.0).unwrap().count(), 0);
+
+    // A directory in the way of the scratch file makes the write fail.
+    fs_err::create_dir(dir.0.join(".startup-selftest.csv")).unwrap();
+    let err = storage::self_test(&dir.0, &clock::RealClock)
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with(&format!(
+        "startup self-test failed in {}: ",
+        dir.0.display()
+    )));
+    assert!(err.ends_with("Check that the bot can write UTF-8 files there"));
+}
+
+#[tokio::test]
+async fn backups_snapshot_user_files_and_keep_newest() {
+    let h = Harness::new();
+    h.send("/addgb 5.8").await;
+    let options = backup::BackupOptions {
+        dir: h.dir.0.join("backups"),
+        keep: 2,
+    };
+    let user_backups = options.dir.join(CHAT.0.to_string());
+    fs_err::create_dir_all(user_backups.join("2024-02-03.partial")).unwrap();
+    for day in 1..=4 {
+        let date = NaiveDate::from_ymd_opt(2024, 2, day).unwrap();
+        backup::backup_all(&h.dir.0, &options, date).unwrap();
+    }
+
+    let mut names = fs_err::read_dir(&user_backups)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["2024-02-03", "2024-02-04"]);
+    assert_eq!(
+        fs_err::read_to_string(user_backups.join("2024-02-04").join(GLUCOSE_FILE)).unwrap(),
+        h.dir.read(GLUCOSE_FILE)
+    );
+    assert!(!options.dir.join("backups").exists());
+}
+
+#[test]
+fn operator_stats_count_entries_and_active_users_per_finished_day() {
+    let dir = TestDir::new();
+    let write = |chat: i64, file: &str, content: &str| {
+        let user = user_data_dir(&dir.0, ChatId(chat));
+        fs_err::create_dir_all(&user).unwrap();
+        fs_err::write(user.join(file), content).unwrap();
+    };
+    write(
+        1,
+        GLUCOSE_FILE,
+        "timestamp,chat_id,tag,value_mmol_l,note\n\
+2024-02-01T08:00:00+00:00,1,before_meal,5.8,\"secret note\"\n\
+2024-02-01T22:30:00+00:00,1,after_meal,7.1,\n\
+2024-02-03T08:00:00+00:00,1,before_meal,6.0,\n",
+    );
+    write(
+        1,
+        WEIGHT_FILE,
+        "timestamp,chat_id,value_kg\n2024-02-02T08:00:00+00:00,1,80.5\n",
+    );
+    write(
+        2,
+        WEIGHT_FILE,
+        "timestamp,chat_id,value_kg\n2024-02-01T09:00:00+00:00,2,70.0\n",
+    );
+
+    // 22:30 UTC is already February 2 in Kyiv; February 3 is today and left out.
+    let today = NaiveDate::from_ymd_opt(2024, 2, 3).unwrap();
+    stats::write_stats(&dir.0, chrono_tz::Europe::Kyiv, today, false).unwrap();
+    assert_eq!(
+        fs_err::read_to_string(dir.0.join(stats::STATS_FILE)).unwrap(),
+        "date,entries,active_users\n2024-02-01,2,2\n2024-02-02,2,1\n"
+    );
+}
+
+#[test]
+fn glucose_unit_round_trip_keeps_readings() {
+    use settings::GlucoseUnit;
+
+    for value in [5.8, 6.25, 3.9, 12.1] {
+        let mgdl: f64 = GlucoseUnit::Mgdl.format_stored(value).parse().unwrap();
+        let back = GlucoseUnit::Mmol.format_stored(GlucoseUnit::Mgdl.to_mmol_l(mgdl));
+        assert_eq!(back, value.to_string());
+    }
+    assert_eq!(GlucoseUnit::Mgdl.format_stored(5.8), "104.4");
+    assert_eq!(GlucoseUnit::Mgdl.format_stored(6.25), "112.5");
+}
+
+#[tokio::test]
+async fn convert_replies_with_the_other_unit_without_saving() {
+    let h = Harness::new();
+    h.send("/convert 104 mgdl").await;
+    h.send("/convert 5,8 mmol/L").await;
+    h.send("/convert 700 mgdl").await;
+    h.send("/convert 5.8").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "104 mg/dL = 5.8 mmol/L\n(mg/dL ÷ 18 = mmol/L; not saved)"
+    );
+    assert_eq!(
+        texts[1],
+        "5.8 mmol/L = 104 mg/dL\n(mmol/L × 18 = mg/dL; not saved)"
+    );
+    assert_eq!(
+        texts[2],
+        "Glucose value 700 mg/dL is out of range (1.0-35.0 mmol/L, 18-630 mg/dL)"
+    );
+    assert_eq!(
+        texts[3],
+        "Usage: /convert <value> <mmol|mgdl>, e.g. /convert 104 mgdl"
+    );
+    assert!(!user_data_dir(&h.dir.0, CHAT).join(GLUCOSE_FILE).exists());
+}
+
+#[tokio::test]
+async fn units_convert_without_rows_asks_nothing() {
+    let h = Harness::new();
+    h.send("/units mgdl convert").await;
+    h.send("yes").await;
+    assert_eq!(
+        h.bot.take_texts()[0],
+        "Nothing to convert: there are no glucose rows yet. \
+Use /units mgdl to store new readings in mg/dL."
+    );
+    assert!(!user_data_dir(&h.dir.0, CHAT).join(GLUCOSE_FILE).exists());
+    assert_eq!(settings_or_default(&h.state, CHAT).glucose_unit, None);
+}
+
+#[tokio::test]
+async fn units_convert_rewrites_rows_after_confirmation() {
+    let h = Harness::new();
+    h.send("/addgb 5.8 2024/2/1 9:00 @a, b").await;
+    h.send("/addga 6.25 2024/2/1 11:00").await;
+    h.send("/units mgdl convert").await;
+    h.send("no").await;
+    assert_eq!(h.dir.read(GLUCOSE_FILE).matches("mmol/L").count(), 2);
+
+    h.send("/units mgdl convert").await;
+    h.send("yes").await;
+    h.send("/addgb 4.4 2024/2/2 9:00").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[2],
+        "This will rewrite all 2 glucose rows in mg/dL. A backup copy is saved first. \
+Reply \"yes\" to confirm, anything else cancels."
+    );
+    assert_eq!(texts[3], "Conversion cancelled.");
+    assert!(texts[5].starts_with("Converted 2 glucose rows to mg/dL ✅\nBackup: glucose.csv."));
+
+    let glucose = h.dir.read(GLUCOSE_FILE);
+    let rows = data_lines(&glucose);
+    assert!(rows[0].ends_with(",before_meal,104.4,\"a, b\",manual,1,mg/dL,,,"));
+    assert!(rows[1].ends_with(",after_meal,112.5,\"\",manual,2,mg/dL,,,"));
+    assert!(rows[2].ends_with(",before_meal,79.2,\"\",manual,7,mg/dL,,,"));
+
+    let backups = fs_err::read_dir(user_data_dir(&h.dir.0, CHAT))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".bak"))
+        .count();
+    assert_eq!(backups, 1);
+
+    let values = records::read_glucose_records(&h.dir.0, CHAT)
+        .unwrap()
+        .into_iter()
+        .map(|record| record.value)
+        .collect::<Vec<_>>();
+    assert_eq!(values, vec![5.8, 6.25, 4.4]);
+}
+
+#[test]
+fn long_messages_split_at_line_boundaries() {
+    use paging::split_message;
+
+    assert_eq!(split_message("short\ntext", 20), vec!["short\ntext"]);
+    assert_eq!(
+        split_message("line one\nline two\nline three", 18),
+        vec!["line one\nline two", "line three"]
+    );
+
+    let text = "intro\n```\ncode 1\ncode 2\n```\noutro";
+    assert_eq!(
+        split_message(text, 24),
+        vec!["intro", "```\ncode 1\ncode 2\n```", "outro"]
+    );
+
+    let parts = split_message("```\naaaa\nbbbb\ncccc\n```", 17);
+    assert_eq!(parts, vec!["```\naaaa\nbbbb\n```", "```\ncccc\n```"]);
+    assert!(parts.iter().all(|part| part.chars().count() <= 17));
+
+    assert_eq!(split_message("abcdefgh", 3), vec!["abc", "def", "gh"]);
+    assert_eq!(split_message("🩸🩸🩸", 4), vec!["🩸🩸", "🩸"]);
+}
+
+#[tokio::test]
+async fn help_and_commands_fit_in_one_message() {
+    let h = Harness::new();
+    h.send("/help").await;
+    h.send("/commands").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts.len(), 2);
+    assert!(
+        texts
+            .iter()
+            .all(|text| text.encode_utf16().count() <= paging::MESSAGE_MAX_LEN)
+    );
+}
+
+#[test]
+fn every_listed_and_unlisted_command_has_a_route() {
+    use commands::{BotCommand, UNLISTED_COMMANDS};
+
+    let texts = BotCommand::ALL
+        .iter()
+        .map(|command| command.example())
+        .chain(UNLISTED_COMMANDS);
+    for text in texts {
+        assert!(
+            !matches!(routing::parse_command(text), Command::Plain { .. }),
+            "{text} is not routed"
+        );
+    }
+}
+
+#[test]
+fn parse_command_precedence() {
+    use routing::{EntryButton, parse_command};
+
+    assert_eq!(parse_command(" /help "), Command::Help);
+    assert_eq!(
+        parse_command("/help me"),
+        Command::Plain { text: "/help me" }
+    );
+    assert_eq!(parse_command("/start"), Command::Menu);
+    assert_eq!(
+        parse_command("/start register_abc"),
+        Command::Start {
+            payload: "register_abc"
+        }
+    );
+    assert_eq!(parse_command(BTN_SHOW_MENU), Command::Menu);
+    assert_eq!(
+        parse_command(BTN_WEIGHT),
+        Command::EntryButton(EntryButton::Weight)
+    );
+    assert_eq!(
+        parse_command("💊 menu"),
+        Command::MedButton { name: "menu" }
+    );
+    assert_eq!(
+        parse_command("💊 /help"),
+        Command::MedButton { name: "/help" }
+    );
+    assert_eq!(
+        parse_command("/addmed /help"),
+        Command::AddMed { name: "/help" }
+    );
+    assert_eq!(
+        parse_command("/addgb  5.8 @x "),
+        Command::AddGlucose {
+            tag: GlucoseTag::BeforeMeal,
+            payload: "5.8 @x"
+        }
+    );
+    assert_eq!(
+        parse_command("/add_glucose_after"),
+        Command::AddGlucose {
+            tag: GlucoseTag::AfterMeal,
+            payload: ""
+        }
+    );
+    assert_eq!(
+        parse_command("/addgbx 5.8"),
+        Command::Plain {
+            text: "/addgbx 5.8"
+        }
+    );
+    assert_eq!(parse_command("/mealeffect"), Command::MealEffect);
+    assert_eq!(
+        parse_command("/meal 12:30"),
+        Command::Meal { payload: "12:30" }
+    );
+    assert_eq!(
+        parse_command("/setbolus"),
+        Command::SetBolus { payload: "" }
+    );
+    assert_eq!(
+        parse_command("/bolus 60 8.2"),
+        Command::Bolus { payload: "60 8.2" }
+    );
+    assert_eq!(parse_command("/trend"), Command::Trend(Ok(14)));
+    assert!(matches!(parse_command("/trend 0"), Command::Trend(Err(_))));
+    assert_eq!(parse_command("5.8"), Command::Plain { text: "5.8" });
+}
+
+#[test]
+fn multi_line_text_is_never_a_button() {
+    let text = "💊 Metformin\n5.8 2024/2/1 9:00";
+    assert_eq!(routing::parse_command(text), Command::Plain { text });
+}
+
+#[tokio::test]
+async fn medication_named_like_a_command_is_logged() {
+    let h = Harness::new();
+    let path = user_data_dir(&h.dir.0, CHAT).join(MEDICATIONS_FILE);
+    fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+    fs_err::write(&path, "menu\n").unwrap();
+    h.send("💊 menu").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Medication usage saved ✅ (menu)");
+    assert!(h.dir.read(MEDICATION_LOG_FILE).contains("\"menu\""));
+}
+
+#[tokio::test]
+async fn addmed_rejects_names_reserved_for_buttons_and_commands() {
+    let h = Harness::new();
+    h.send("/addmed Weight").await;
+    h.send("/addmed show  MENU").await;
+    h.send("/addmed help").await;
+    h.send("/addmed 💊 Insulin").await;
+    h.send("/addmed ⚖️ scale").await;
+    h.send("/addmed /last").await;
+    h.send("/addmed Weight loss tabs").await;
+    h.send("/renamemed Weight loss tabs -> Ketones").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "\"Weight\" is reserved for a menu button or command. Choose another medication name."
+    );
+    assert_eq!(
+        texts[1],
+        "\"show MENU\" is reserved for a menu button or command. Choose another medication name."
+    );
+    assert!(texts[2].starts_with("\"help\" is reserved"));
+    assert_eq!(texts[3], "Medication added: Insulin");
+    assert!(texts[4].starts_with("Medication name can't start with"));
+    assert!(texts[5].starts_with("Medication name can't start with"));
+    assert_eq!(texts[6], "Medication added: Weight loss tabs");
+    assert!(texts[7].starts_with("\"Ketones\" is reserved"));
+    assert_eq!(h.dir.read(MEDICATIONS_FILE), "Insulin\nWeight loss tabs\n");
+}
+
+#[tokio::test]
+async fn precision_rounds_insulin_to_half_units() {
+    let h = Harness::new();
+    h.send("/precision insulin 0.5").await;
+    h.send("/addinsulin 4.3 2024/2/1 8:00").await;
+    h.send("/addinsulin 4.2 2024/2/1 12:00").await;
+    h.send("/addinsulin 4.75 2024/2/1 18:00").await;
+    h.send("/precision insulin 0.3").await;
+    h.send("/precision").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "New insulin entries are rounded to 0.5 U ✅");
+    assert_eq!(texts[1], "Insulin saved ✅ 4.5 U");
+    assert_eq!(texts[2], "Insulin saved ✅ 4 U");
+    assert_eq!(texts[3], "Insulin saved ✅ 5 U");
+    assert_eq!(texts[4], PRECISION_USAGE);
+    assert_eq!(
+        texts[5],
+        format!(
+            "New entries are rounded to:\n- glucose: 0.01 mmol/L\n- weight: 0.1 kg\n\
+- ketones: 0.1 mmol/L\n- insulin: 0.5 U\n- carbs: 1 g\n{PRECISION_USAGE}"
+        )
+    );
+    let units = data_lines(&h.dir.read(INSULIN_FILE))
+        .iter()
+        .map(|line| line.split(',').nth(2).unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(units, ["4.5", "4", "5"]);
+
+    h.send("/precision insulin 0.05").await;
+    assert!(!h.dir.read("settings.toml").contains("precision"));
+}
+
+#[tokio::test]
+async fn glucose_precision_steps_follow_the_glucose_unit() {
+    let h = Harness::new();
+    h.send("/precision glucose 1").await;
+    h.send("/precision glucose 0.1").await;
+    h.send("/addgb 3.64 2024/2/1 8:00").await;
+    h.send("/units mgdl").await;
+    h.send("/precision").await;
+    h.send("/precision glucose 1").await;
+    h.send("/addgb 5.53 2024/2/1 9:00").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Steps for glucose in mmol/L: 0.1, 0.05, 0.01.");
+    assert_eq!(texts[1], "New glucose entries are rounded to 0.1 mmol/L ✅");
+    assert!(texts[4].contains("- glucose: 0.1 mg/dL\n"), "{}", texts[4]);
+    assert_eq!(texts[5], "New glucose entries are rounded to 1 mg/dL ✅");
+    let values = data_lines(&h.dir.read(GLUCOSE_FILE))
+        .iter()
+        .map(|line| line.split(',').nth(3).unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(values, ["3.6", "100"]);
+}
+
+#[test]
+fn values_are_rounded_to_whole_steps() {
+    assert_eq!(settings::round_to_step(4.25, 0.5), 4.5);
+    assert_eq!(settings::round_to_step(4.24, 0.5), 4.0);
+    assert_eq!(settings::round_to_step(5.64, 0.1), 5.6);
+    assert_eq!(settings::round_to_step(7.256, 0.01), 7.26);
+    assert_eq!(settings::round_to_step(62.5, 1.0), 63.0);
+    assert_eq!(settings::parse_precision_step("0,5"), Some(0.5));
+    assert_eq!(settings::parse_precision_step("0.2"), None);
+}
+
+#[tokio::test]
+async fn uploaded_text_file_imports_medications() {
+    let h = Harness::new();
+    h.send("/addmed Metformin").await;
+    h.bot.take();
+    h.send_document(
+        "meds.txt",
+        "metformin\nInsulin\n\n  Aspirin \n/menu\nInsulin\n",
+    )
+    .await;
+    h.send_document("meds.pdf", "Insulin").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Imported meds.txt: 2 added, 2 skipped as already in your list, 1 rejected.\n\
+- line 5: Medication name can't start with / or a menu button emoji: /menu"
+    );
+    assert_eq!(
+        texts[1],
+        "To import medications, send a .txt file with one name per line, like medications.txt."
+    );
+    assert_eq!(
+        h.dir.read(MEDICATIONS_FILE),
+        "Metformin\nInsulin\nAspirin\n"
+    );
+}
+
+#[test]
+fn confirmed_flag_is_parsed_from_tag_or_trailing_bang() {
+    let tz = &InputOptions {
+        tz: chrono_tz::UTC,
+        note_max_len: 0,
+        timestamp_precision: TimestampPrecision::Minute,
+        clock: Arc::new(clock::RealClock),
+        glucose_unit: settings::GlucoseUnit::Mmol,
+        sync_writes: false,
+        log_entry_notes: false,
+    };
+    let parse = |payload| parse_glucose_payload(payload, tz).unwrap();
+
+    assert!(!parse("2.1").confirmed);
+    let input = parse("2.1!");
+    assert!(input.confirmed);
+    assert_eq!(input.values, vec![2.1]);
+    assert!(parse("2.1 2/1 9:05 !").confirmed);
+    assert!(parse("2.1 2/1 9:05!").confirmed);
+    let input = parse("2.1 #Confirmed !meter @real hypo!");
+    assert!(input.confirmed);
+    assert_eq!(input.source.as_deref(), Some("meter"));
+    assert_eq!(input.note.as_deref(), Some("real hypo!"));
+    assert!(!parse("2.1 @checked #confirmed").confirmed);
+}
+
+#[tokio::test]
+async fn confirmed_reading_skips_range_warning_and_is_flagged() {
+    let h = Harness::new();
+    h.send("/addgb 2.1 2024/2/1 9:00").await;
+    h.send("/addgb 2.1 2024/2/1 9:30!").await;
+    h.send(BTN_GLUCOSE_AFTER_MEAL).await;
+    h.send("15.2 #confirmed").await;
+
+    let texts = h.bot.take_texts();
+    assert!(texts[0].contains("⚠️ 2.1 is below"));
+    assert_eq!(texts[1], "Glucose entry saved ✅");
+    assert_eq!(texts[3], "Saved ✅");
+
+    let glucose = h.dir.read(GLUCOSE_FILE);
+    let rows = data_lines(&glucose);
+    assert!(rows[0].ends_with(",mmol/L,,,"));
+    assert!(rows[1].ends_with(",before_meal,2.1,\"\",manual,2,mmol/L,yes,,"));
+    assert!(rows[2].ends_with(",after_meal,15.2,\"\",manual,4,mmol/L,yes,,"));
+}
+
+#[tokio::test]
+async fn nudge_command_toggles_setting() {
+    let h = Harness::new();
+    h.send("/nudge").await;
+    h.send("/nudge on").await;
+    h.send("/nudge").await;
+    h.send("/nudge off").await;
+    h.send("/nudge maybe").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Nudges are off.");
+    assert_eq!(
+        texts[1],
+        "Nudges on ✅ You'll get one reminder after 3 days without entries."
+    );
+    assert_eq!(texts[2], "Nudges are on.");
+    assert_eq!(texts[3], "Nudges off ✅");
+    assert_eq!(texts[4], "Usage: /nudge <on|off>");
+}
+
+#[tokio::test]
+async fn confirm_sets_the_mark_of_save_confirmations() {
+    let h = Harness::new();
+    h.send("/confirm").await;
+    h.send("/confirm 👍").await;
+    h.send("/addgb 5.8").await;
+    h.send("/addketone 0.4").await;
+    h.send("/food apple 120g").await;
+    h.send("/confirm this is far too long").await;
+    h.send("/confirm reset").await;
+    h.send("/addgb 6.1").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Saves are confirmed with ✅.");
+    assert_eq!(texts[1], "Confirmation updated 👍");
+    assert_eq!(texts[2], "Glucose entry saved 👍");
+    assert_eq!(texts[3], "Ketones saved 👍");
+    assert_eq!(texts[4], "Food saved 👍 (apple, 120 g)");
+    assert_eq!(
+        texts[5],
+        "The confirmation must be one line of at most 16 characters. Usage: /confirm [mark|reset|<metric> <short|detailed>]"
+    );
+    assert_eq!(texts[6], "Confirmation reset to ✅");
+    assert_eq!(texts[7], "Glucose entry saved ✅");
+}
+
+#[tokio::test]
+async fn confirm_styles_are_set_per_metric() {
+    let h = Harness::new();
+    h.send("/confirm glucose short").await;
+    h.send("/addgb 5.8").await;
+    h.send("/addgb 15.2").await;
+    h.send("/addketone 0.4").await;
+    h.send("/confirm med shorty").await;
+    h.send("/confirm").await;
+    h.send("/confirm glucose detailed").await;
+    h.send("/addgb 6.1").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Confirmations for glucose are now short.");
+    assert_eq!(texts[1], "✅");
+    assert!(texts[2].starts_with("✅\n⚠️"), "{}", texts[2]);
+    assert_eq!(texts[3], "Ketones saved ✅");
+    assert_eq!(texts[4], "Usage: /confirm <metric> <short|detailed>");
+    assert_eq!(texts[5], "Saves are confirmed with ✅.\nShort for: glucose");
+    assert_eq!(texts[6], "Confirmations for glucose are now detailed.");
+    assert_eq!(texts[7], "Glucose entry saved ✅");
+}
+
+#[tokio::test]
+async fn nudge_is_sent_once_per_quiet_spell_outside_quiet_hours() {
+    let h = Harness::new();
+    h.send("/addgb 5.8 2024/2/1 9:00").await;
+    assert_eq!(
+        nudge::send_due_nudges(&h.bot, &h.state, utc("2024-02-05T12:00:00Z")).await,
+        0,
+        "nudges are off by default"
+    );
+    h.send("/nudge on").await;
+    h.bot.take();
+
+    assert_eq!(
+        nudge::send_due_nudges(&h.bot, &h.state, utc("2024-02-03T12:00:00Z")).await,
+        0
+    );
+    assert_eq!(
+        nudge::send_due_nudges(&h.bot, &h.state, utc("2024-02-04T23:00:00Z")).await,
+        0,
+        "quiet hours"
+    );
+    assert_eq!(
+        nudge::send_due_nudges(&h.bot, &h.state, utc("2024-02-05T12:00:00Z")).await,
+        1
+    );
+    assert_eq!(h.bot.take_texts(), vec![nudge::nudge_text(4)]);
+    assert_eq!(
+        nudge::send_due_nudges(&h.bot, &h.state, utc("2024-02-20T12:00:00Z")).await,
+        0,
+        "no new entry since the last nudge"
+    );
+
+    h.send("/addgb 6.1 2024/2/6 9:00").await;
+    h.bot.take();
+    assert_eq!(
+        nudge::send_due_nudges(&h.bot, &h.state, utc("2024-02-10T12:00:00Z")).await,
+        0,
+        "within the minimum interval"
+    );
+    assert_eq!(
+        nudge::send_due_nudges(&h.bot, &h.state, utc("2024-02-12T12:00:00Z")).await,
+        1
+    );
+}
+
+#[test]
+fn iob_fraction_follows_the_model() {
+    let minutes = chrono::Duration::minutes;
+    let linear = insulin::IobOptions {
+        model: insulin::IobModel::Linear,
+        duration_minutes: 240,
+    };
+    assert_eq!(linear.remaining_fraction(minutes(0)), 1.0);
+    assert_eq!(linear.remaining_fraction(minutes(120)), 0.5);
+    assert_eq!(linear.remaining_fraction(minutes(240)), 0.0);
+    assert_eq!(linear.remaining_fraction(minutes(-30)), 1.0);
+
+    let bilinear = insulin::IobOptions::default();
+    assert_eq!(bilinear.remaining_fraction(minutes(0)), 1.0);
+    assert!((bilinear.remaining_fraction(minutes(60)) - 0.85).abs() < 1e-9);
+    // Both pieces of the curve meet at the 100-minute peak.
+    let at_peak = bilinear.remaining_fraction(minutes(100));
+    assert!((at_peak - 140.0 / 240.0).abs() < 1e-9);
+    assert!((bilinear.remaining_fraction(minutes(180)) - 3600.0 / 33600.0).abs() < 1e-9);
+    assert_eq!(bilinear.remaining_fraction(minutes(300)), 0.0);
+}
+
+#[tokio::test]
+async fn correction_insulin_is_logged_and_reported_apart() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-01T12:00:00Z")));
+    fs_err::create_dir_all(user_data_dir(&h.dir.0, CHAT)).unwrap();
+    fs_err::write(
+        user_data_dir(&h.dir.0, CHAT).join(INSULIN_FILE),
+        "timestamp,chat_id,units,note,message_id\n2024-02-01T08:00:00+00:00,1,3,\"\",\n",
+    )
+    .unwrap();
+    storage::upgrade_headers(&h.dir.0, false).unwrap();
+    h.send("/addinsulin 4 meal 2024/2/1 9:00").await;
+    h.send("/addinsulin 2 Correction 2024/2/1 11:00 @high")
+        .await;
+    h.send("/addinsulin 1 basal").await;
+    h.send("/addinsulin 1 today 11:30").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Insulin saved ✅ 4 U");
+    assert_eq!(texts[1], "Insulin saved ✅ 2 U correction");
+    assert_eq!(
+        texts[2],
+        "Unknown insulin type 'basal'. Use meal or correction."
+    );
+    assert_eq!(texts[3], "Insulin saved ✅ 1 U");
+    let insulin = h.dir.read(INSULIN_FILE);
+    assert!(insulin.starts_with(&format!("{}\n", insulin::INSULIN_HEADER)));
+    let rows = data_lines(&insulin);
+    assert_eq!(rows[0], "2024-02-01T08:00:00+00:00,1,3,\"\",,");
+    assert!(rows[1].ends_with(",meal"));
+    assert!(rows[2].ends_with(",2,\"high\",2,correction"));
+
+    let report = insulin::iob_report(&h.state, CHAT, utc("2024-02-01T12:00:00Z")).unwrap();
+    let lines = report.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "Insulin on board: 3.1 U (estimate), of it correction: 1.7 U"
+    );
+    assert_eq!(lines[2], "- 2 U correction at 02/01 11:00: 1.7 U left");
+
+    h.send("/today").await;
+    assert_eq!(
+        h.bot.take()[0].text,
+        "Today, 2024-02-01: no glucose readings, insulin 10.0 U (2.0 U correction), no carbs, meals: 0"
+    );
+}
+
+#[tokio::test]
+async fn iob_sums_remaining_insulin_of_recent_doses() {
+    let h = Harness::new();
+    h.send("/addinsulin 4 2024/2/1 9:00").await;
+    h.send("/addinsulin 2u 2024/2/1 11:00 @snack").await;
+    h.send("/addinsulin 0").await;
+    h.send("/addinsulin").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Insulin saved ✅ 4 U");
+    assert_eq!(texts[1], "Insulin saved ✅ 2 U");
+    assert_eq!(texts[2], "Insulin units must be above 0 and at most 100 U");
+    assert_eq!(
+        texts[3],
+        "Usage: /addinsulin <units> [meal|correction] [date time] [@note]"
+    );
+    let insulin = h.dir.read(INSULIN_FILE);
+    let rows = data_lines(&insulin);
+    assert_eq!(rows.len(), 2);
+    assert!(rows[1].ends_with(",2,\"snack\",2,meal"));
+
+    let report = insulin::iob_report(&h.state, CHAT, utc("2024-02-01T12:00:00Z")).unwrap();
+    assert_eq!(
+        report.lines().take(4).collect::<Vec<_>>(),
+        vec![
+            "Insulin on board: 2.1 U (estimate)",
+            "- 4 U at 02/01 09:00: 0.4 U left",
+            "- 2 U at 02/01 11:00: 1.7 U left",
+            "Model: bilinear, 4 h duration of action.",
+        ]
+    );
+    assert!(report.contains("not for dosing decisions"));
+
+    let later = insulin::iob_report(&h.state, CHAT, utc("2024-02-01T16:00:00Z")).unwrap();
+    assert!(
+        later.starts_with("Insulin on board: 0.0 U (estimate)\nNo insulin doses in the last 4 h.")
+    );
+}
+
+#[test]
+fn trend_tokens_map_to_nightscout_directions() {
+    let options = &InputOptions {
+        tz: Tz::UTC,
+        note_max_len: 0,
+        timestamp_precision: TimestampPrecision::Minute,
+        clock: Arc::new(clock::RealClock),
+        glucose_unit: settings::GlucoseUnit::Mmol,
+        sync_writes: false,
+        log_entry_notes: false,
+    };
+    let direction = |payload: &str| {
+        parse_glucose_payload(payload, options)
+            .unwrap()
+            .trend
+            .map(GlucoseTrend::nightscout_direction)
+    };
+    assert_eq!(direction("5.8 ^^"), Some("DoubleUp"));
+    assert_eq!(direction("5.8 ↑↑"), Some("DoubleUp"));
+    assert_eq!(direction("5.8 ^ 2/1 9:05"), Some("SingleUp"));
+    assert_eq!(direction("5.8 ⬆️"), Some("SingleUp"));
+    assert_eq!(direction("5.8 ↗"), Some("FortyFiveUp"));
+    assert_eq!(direction("5.8 ->"), Some("Flat"));
+    assert_eq!(direction("5.8 →"), Some("Flat"));
+    assert_eq!(direction("5.8 ↘ !cgm"), Some("FortyFiveDown"));
+    assert_eq!(direction("5.8 V"), Some("SingleDown"));
+    assert_eq!(direction("5.8 vv @falling ->"), Some("DoubleDown"));
+    assert_eq!(direction("5.8 2/1 9:05"), None);
+
+    let input = parse_glucose_payload("2.9 ↓ 2/1 9:05!", options).unwrap();
+    assert_eq!(input.values, vec![2.9]);
+    assert!(input.timestamp.is_some());
+    assert!(input.confirmed);
+    assert!(parse_glucose_payload("5.8 ^ v", options).is_err());
+}
+
+#[tokio::test]
+async fn trend_arrow_is_stored_as_direction() {
+    let h = Harness::new();
+    h.send("/addgb 5.8 ^ 2024/2/1 9:00 !cgm").await;
+    h.send("/addga 7.2 2024/2/1 11:00").await;
+    h.send("/addga 7.4 ^^ vv").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[2], "Only one trend arrow is allowed");
+    let glucose = h.dir.read(GLUCOSE_FILE);
+    let rows = data_lines(&glucose);
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].ends_with(",before_meal,5.8,\"\",cgm,1,mmol/L,,SingleUp,"));
+    assert!(rows[1].ends_with(",after_meal,7.2,\"\",manual,2,mmol/L,,,"));
+}
+
+#[tokio::test]
+async fn merge_from_another_chat_adds_rows_once_and_sorts_them() {
+    let mut h = Harness::new();
+    h.send("/addgb 5.8 2024/2/2 9:00").await;
+    let source = user_data_dir(&h.dir.0, ChatId(2));
+    fs_err::create_dir_all(&source).unwrap();
+    fs_err::write(
+        source.join(GLUCOSE_FILE),
+        format!(
+            "{GLUCOSE_HEADER}\n\
+2024-02-02T09:00:00+00:00,2,before_meal,5.8,\"\",manual,9,mmol/L,,,\n\
+2024-02-01T09:00:00+00:00,2,before_meal,6.1,\"old, row\",manual,5,mmol/L,,,\n"
+        ),
+    )
+    .unwrap();
+    fs_err::write(
+        source.join(WEIGHT_FILE),
+        "timestamp,chat_id,value_kg\n2024-02-03T08:00:00+00:00,2,80.5\n",
+    )
+    .unwrap();
+    h.send("/merge 2").await;
+    Arc::get_mut(&mut h.state).unwrap().admin_chat_ids = HashSet::from([CHAT]);
+    h.send("/merge 2").await;
+    h.send("/merge 2").await;
+    h.send("/merge").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[1], "Only admins can merge another chat's data.");
+    let first = texts[2].lines().collect::<Vec<_>>();
+    assert_eq!(
+        first[..3],
+        [
+            "Merged from chat 2 ✅ 2 rows added, 1 duplicates skipped.",
+            "- glucose: 1 added, 1 duplicates",
+            "- weight: 1 added, 0 duplicates",
+        ]
+    );
+    assert!(first[3].starts_with("Backups: glucose.csv.") && first[3].ends_with(".bak"));
+    assert_eq!(
+        texts[3],
+        "Merged from chat 2 ✅ 0 rows added, 3 duplicates skipped.\n\
+- glucose: 0 added, 2 duplicates\n\
+- weight: 0 added, 1 duplicates"
+    );
+    assert_eq!(texts[4], "Usage: /merge <profile|chat id>");
+
+    assert_eq!(
+        data_lines(&h.dir.read(GLUCOSE_FILE)),
+        vec![
+            "2024-02-01T09:00:00+00:00,1,before_meal,6.1,\"old, row\",manual,,mmol/L,,,",
+            "2024-02-02T09:00:00+00:00,1,before_meal,5.8,\"\",manual,1,mmol/L,,,",
+        ]
+    );
+    assert_eq!(
+        h.dir.read(WEIGHT_FILE),
+        format!("{WEIGHT_HEADER}\n2024-02-03T08:00:00+00:00,1,80.5,\n")
+    );
+    assert_eq!(
+        data_lines(&fs_err::read_to_string(source.join(GLUCOSE_FILE)).unwrap()).len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn merge_from_profile_reads_its_data_dir() {
+    let mut h = Harness::new();
+    let old_data = h.dir.0.join("old");
+    let config = h.dir.0.join("config.toml");
+    fs_err::write(
+        &config,
+        format!(
+            "data_dir = \"unused\"\n[profiles.old]\ndata_dir = {:?}\n",
+            old_data.display().to_string()
+        ),
+    )
+    .unwrap();
+    let source = user_data_dir(&old_data, CHAT);
+    fs_err::create_dir_all(&source).unwrap();
+    fs_err::write(
+        source.join(KETONES_FILE),
+        format!("{KETONES_HEADER}\n2024-02-01T09:00:00+00:00,1,0.4,\"\",3\n"),
+    )
+    .unwrap();
+    Arc::get_mut(&mut h.state).unwrap().config_path = Some(config);
+    h.send("/merge old").await;
+    h.send("/merge missing").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Merged from profile 'old' ✅ 1 rows added, 0 duplicates skipped.\n\
+- ketones: 1 added, 0 duplicates"
+    );
+    assert_eq!(
+        texts[1],
+        "Cannot read profile 'missing': profile 'missing' not found in config"
+    );
+    assert_eq!(
+        data_lines(&h.dir.read(KETONES_FILE)),
+        vec!["2024-02-01T09:00:00+00:00,1,0.4,\"\","]
+    );
+}
+
+#[test]
+fn today_data_keeps_the_local_day_and_summarizes_it() {
+    let tz: Tz = "Europe/Kyiv".parse().unwrap();
+    let day = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+    let data = today::day_data(
+        &[
+            glucose_record("2024-02-01T10:00:00Z", 7.8),
+            glucose_record("2024-01-31T23:30:00Z", 5.2),
+            glucose_record("2024-01-31T21:00:00Z", 9.9),
+            glucose_record("2024-02-01T22:30:00Z", 4.4),
+        ],
+        &[records::InsulinRecord {
+            timestamp: utc("2024-02-01T10:05:00Z"),
+            units: 4.5,
+            kind: insulin::InsulinKind::Meal,
+        }],
+        &[],
+        &[records::MealRecord {
+            timestamp: utc("2024-02-01T10:10:00Z"),
+            note: "вівсянка".to_string(),
+        }],
+        tz,
+        day,
+    );
+    assert_eq!(
+        data.glucose.iter().map(|r| r.value).collect::<Vec<_>>(),
+        vec![5.2, 7.8]
+    );
+    assert_eq!(
+        today::caption(&data),
+        "Today, 2024-02-01: glucose avg 6.5 mmol/L (readings: 2, 5.2-7.8), \
+insulin 4.5 U, no carbs, meals: 1"
+    );
+
+    let axis = chart::GlucoseAxis::for_settings(&settings::UserSettings::default());
+    let render = |data, format| {
+        today::render(data, settings::DEFAULT_TARGET_RANGE, axis, tz, format).unwrap()
+    };
+    assert!(render(&data, chart::ChartFormat::Png).starts_with(PNG_SIGNATURE));
+    let svg = String::from_utf8(render(&data, chart::ChartFormat::Svg)).unwrap();
+    let texts = svg_texts(&svg);
+    assert!(texts.contains(&"Insulin (U)") && texts.contains(&"4.5"));
+    assert!(texts.contains(&"вівсянка"));
+
+    let empty = today::day_data(&[], &[], &[], &[], tz, day);
+    assert_eq!(
+        today::caption(&empty),
+        "Today, 2024-02-01: nothing logged yet."
+    );
+    let svg = String::from_utf8(render(&empty, chart::ChartFormat::Svg)).unwrap();
+    let texts = svg_texts(&svg);
+    assert!(texts.contains(&"No glucose readings today.") && texts.contains(&"None today."));
+}
+
+#[tokio::test]
+async fn today_command_sends_the_chart() {
+    let h = Harness::new();
+    h.send("/today").await;
+
+    let sent = h.bot.take();
+    assert!(sent[0].photo);
+    let (name, bytes) = sent[0].document.clone().unwrap();
+    assert!(name.starts_with("today-") && name.ends_with(".png"));
+    assert!(bytes.starts_with(PNG_SIGNATURE));
+    assert!(sent[0].text.ends_with(": nothing logged yet."));
+}
+
+#[tokio::test]
+async fn flags_lists_out_of_range_readings_furthest_first() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-10T12:00:00Z")));
+    h.send("/flags").await;
+    assert_eq!(
+        h.bot.take_texts(),
+        vec!["No glucose readings in the last 14 days."]
+    );
+    h.send("/setrange after 4 9").await;
+    h.send("/addgb 5.5 2024-02-09T07:00").await;
+    h.send("/flags").await;
+    assert_eq!(
+        h.bot.take_texts().last().unwrap(),
+        "All in range 🎉 (readings in the last 14 days: 1)"
+    );
+    h.send("/addga 11.2 2024-02-09T13:00 @pizza").await;
+    h.send("/addgb 3.1 2024-02-10T07:00").await;
+    h.send("/addga 9.5 2024-02-08T13:00").await;
+    h.send("/addgb 2.0 2024-01-01T07:00").await;
+    h.bot.take();
+    h.send("/flags 7").await;
+    h.send("/flags 0").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(
+        texts[0],
+        "Out of range in the last 7 days: 3 of 4 readings, furthest first\n\
+2024-02-09 13:00  11.2  2.2 above after-meal 4.0-9.0 @pizza\n\
+2024-02-10 07:00  3.1  0.8 below before-meal 3.9-10.0\n\
+2024-02-08 13:00  9.5  0.5 above after-meal 4.0-9.0"
+    );
+    assert_eq!(texts[1], "Usage: /flags [1-365]");
+}
+
+#[tokio::test]
+async fn chart_draws_the_chosen_metric_over_the_last_days() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-10T12:00:00Z")));
+    h.send("/addketone 0.4 2024-02-09T08:00").await;
+    h.send("/addketone 1.2 2024-02-10T08:00").await;
+    h.send("/addketone 2.0 2024-01-20T08:00").await;
+    h.bot.take();
+    h.send("/chart ketones 7").await;
+    h.send("/chart insulin").await;
+    h.send("/chart blood_pressure").await;
+    h.send("/chart ketones 0").await;
+
+    let sent = h.bot.take();
+    assert!(sent[0].photo);
+    let (name, bytes) = sent[0].document.clone().unwrap();
+    assert_eq!(name, "chart-ketones-2024-02-10.png");
+    assert!(bytes.starts_with(PNG_SIGNATURE));
+    assert_eq!(
+        sent[0].text,
+        "Ketones, last 7 days: avg 0.8 mmol/L (readings: 2, 0.4-1.2)"
+    );
+    assert_eq!(sent[1].text, "Insulin, last 14 days: nothing logged.");
+    let usage = "Usage: /chart <glucose|weight|ketones|insulin|carbs> [1-365] [png|svg], e.g. /chart weight 90";
+    assert_eq!(sent[2].text, usage);
+    assert_eq!(sent[3].text, usage);
+}
+
+#[tokio::test]
+async fn charts_can_be_sent_as_svg() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-10T12:00:00Z")));
+    h.send("/addgb 5.8 2024-02-10T08:00").await;
+    h.send("/addinsulin 4 2024-02-10T08:00").await;
+    h.send("/meal 2024-02-10T08:00 @tea & toast").await;
+    h.bot.take();
+    h.send("/chart glucose 7 svg").await;
+    h.send("/chart insulin SVG").await;
+    h.send("/today svg").await;
+    h.send("/today pdf").await;
+
+    let sent = h.bot.take();
+    // Telegram does not preview SVG, so these stay documents.
+    assert!(sent[..3].iter().all(|message| !message.photo));
+    let names = sent[..3]
+        .iter()
+        .map(|message| message.document.clone().unwrap().0)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "chart-glucose-2024-02-10.svg",
+            "chart-insulin-2024-02-10.svg",
+            "today-2024-02-10.svg"
+        ]
+    );
+    assert_eq!(
+        sent[0].text,
+        "Glucose, last 7 days: avg 5.8 mmol/L (readings: 1, 5.8-5.8)"
+    );
+    let svgs = sent[..3]
+        .iter()
+        .map(|message| String::from_utf8(message.document.clone().unwrap().1).unwrap())
+        .collect::<Vec<_>>();
+    for svg in &svgs {
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(!svg.contains("NaN") && !svg.contains("inf"));
+    }
+    assert!(svg_texts(&svgs[0]).contains(&"Glucose (mmol/L)"));
+    assert!(svgs[0].contains("<polyline "));
+    assert!(svg_texts(&svgs[2]).contains(&"tea &amp; toast"));
+    assert_eq!(sent[3].text, "Usage: /today [png|svg]");
+}
+
+#[tokio::test]
+async fn charts_of_identical_values_are_drawn() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-10T12:00:00Z")));
+    for _ in 0..3 {
+        h.send("/addgb 5.8 2024-02-10T08:00").await;
+        h.send("/addinsulin 4 2024-02-10T08:00").await;
+        h.send(BTN_WEIGHT).await;
+        h.send("78.4").await;
+    }
+    h.bot.take();
+    for command in [
+        "/chart glucose",
+        "/chart weight 1",
+        "/chart insulin",
+        "/today",
+        "/chart glucose svg",
+        "/chart weight 1 svg",
+        "/chart insulin svg",
+        "/today svg",
+        "/summary",
+    ] {
+        h.send(command).await;
+    }
+
+    let sent = h.bot.take();
+    assert_eq!(sent.len(), 9);
+    for message in &sent {
+        let (name, bytes) = message.document.clone().unwrap();
+        assert_eq!(message.photo, name.ends_with(".png"));
+        let text = if name.ends_with(".png") {
+            assert!(bytes.starts_with(PNG_SIGNATURE));
+            continue;
+        } else if name.ends_with(".svg") {
+            String::from_utf8(bytes).unwrap()
+        } else {
+            pdf_text(&bytes)
+        };
+        assert!(!text.contains("NaN") && !text.contains("inf"));
+    }
+}
+
+#[tokio::test]
+async fn glucose_axis_follows_the_chat_unit() {
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().input.clock =
+        Arc::new(clock::FixedClock(utc("2024-02-10T12:00:00Z")));
+    h.send("/addgb 5.8 2024-02-10T08:00").await;
+    h.send("/setrange 4 10").await;
+    let svg =
+        |message: &SentMessage| String::from_utf8(message.document.clone().unwrap().1).unwrap();
+    h.bot.take();
+    h.send("/chart glucose svg").await;
+    let mmol = svg(&h.bot.take()[0]);
+    let texts = svg_texts(&mmol);
+    assert!(texts.contains(&"Glucose (mmol/L)"));
+    assert!(texts.contains(&"20") && texts.contains(&"7.0"));
+
+    h.send("/units mgdl").await;
+    h.bot.take();
+    for command in ["/chart glucose svg", "/today svg", "/summary"] {
+        h.send(command).await;
+    }
+    let sent = h.bot.take();
+    assert_eq!(sent.len(), 3);
+    for message in &sent[..2] {
+        let svg = svg(message);
+        let texts = svg_texts(&svg);
+        assert!(texts.contains(&"Glucose (mg/dL)") && texts.contains(&"300"));
+        // Reference line at the middle of 4-10 mmol/L.
+        assert!(texts.contains(&"126"));
+        assert!(!svg.contains("mmol/L)"));
+    }
+    let summary = pdf_text(&sent[2].document.clone().unwrap().1);
+    assert!(summary.contains("Daily average glucose (mg/dL)"));
+}
+
+#[tokio::test]
+async fn chart_that_cannot_be_drawn_is_sent_as_numbers() {
+    let h = Harness::new();
+    send_chart(
+        &h.bot,
+        &h.state,
+        CHAT,
+        "chart.png",
+        || -> anyhow::Result<Vec<u8>> { panic!("font missing") },
+        "Caption".to_string(),
+        || "2024-02-10  5.8 mmol/L".to_string(),
+    )
+    .await
+    .unwrap();
+    send_chart(
+        &h.bot,
+        &h.state,
+        CHAT,
+        "chart.png",
+        || {
+            let chart = chart::Chart::new(
+                chart::ChartMetric::Weight,
+                vec![(utc("2024-02-10T08:00:00Z"), f64::NAN)],
+                chrono_tz::UTC,
+                utc("2024-02-10T12:00:00Z"),
+                7,
+            );
+            let axis = chart::GlucoseAxis::for_settings(&settings::UserSettings::default());
+            let range = settings::DEFAULT_TARGET_RANGE;
+            chart::render(&chart, chrono_tz::UTC, range, axis, chart::ChartFormat::Png)
+        },
+        "Caption".to_string(),
+        || "2024-02-10  5.8 mmol/L".to_string(),
+    )
+    .await
+    .unwrap();
+
+    let sent = h.bot.take();
+    assert!(sent.iter().all(|message| message.document.is_none()));
+    assert_eq!(
+        sent[0].text,
+        "Could not draw the chart (the chart renderer crashed). Here are its numbers:\n\
+2024-02-10  5.8 mmol/L"
+    );
+    assert!(
+        sent[1]
+            .text
+            .starts_with("Could not draw the chart (a value to draw is not a finite number).")
+    );
+}
+
+#[test]
+fn pdf_text_is_written_in_any_script() {
+    let mut page = pdf::PdfPage::new("Щоденник").unwrap();
+    page.text(50.0, 800.0, pdf::Font::Bold, 12.0, "Глюкоза (ммоль/л)");
+    page.text(
+        50.0,
+        780.0,
+        pdf::Font::Regular,
+        10.0,
+        "Сніданок: вівсянка, їжак",
+    );
+    assert_eq!(
+        pdf_text(&page.into_pdf().unwrap()),
+        "Глюкоза (ммоль/л)\nСніданок: вівсянка, їжак"
+    );
+}
+
+#[tokio::test]
+async fn appends_wait_for_a_rewrite_of_the_same_chat() {
+    let h = Harness::new();
+    let (started, rewriting) = std::sync::mpsc::channel();
+    let (release, released) = std::sync::mpsc::channel::<()>();
+    let rewrite = std::thread::spawn({
+        let state = Arc::clone(&h.state);
+        move || {
+            state.rewrite_files(CHAT, || {
+                started.send(()).unwrap();
+                released.recv().unwrap();
+            })
+        }
+    });
+    rewriting.recv().unwrap();
+
+    let send = h.send("/addgb 5.8");
+    tokio::pin!(send);
+    let waiting = tokio::time::timeout(Duration::from_millis(100), &mut send).await;
+    assert!(waiting.is_err(), "the append must wait for the rewrite");
+    assert!(
+        !user_data_dir(&h.state.data_dir, CHAT)
+            .join(GLUCOSE_FILE)
+            .exists()
+    );
+
+    release.send(()).unwrap();
+    send.await;
+    rewrite.join().unwrap();
+    assert_eq!(h.bot.take_texts(), ["Glucose entry saved ✅"]);
+    assert_eq!(data_lines(&h.dir.read(GLUCOSE_FILE)).len(), 1);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn slow_glucose_write_does_not_stall_other_tasks() {
+    let mut h = Harness::new();
+    // Without the duplicate check the append is the only access to the glucose file.
+    Arc::get_mut(&mut h.state)
+        .unwrap()
+        .duplicates
+        .window_seconds = 0;
+    // A FIFO stands in for a slow disk: opening it to append waits until it is read.
+    let dir = user_data_dir(&h.state.data_dir, CHAT);
+    fs_err::create_dir_all(&dir).unwrap();
+    let path = dir.join(GLUCOSE_FILE);
+    let mkfifo = std::process::Command::new("mkfifo").arg(&path).status();
+    assert!(mkfifo.unwrap().success());
+    let disk = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        fs_err::read_to_string(&path).unwrap()
+    });
+    let ticks = Arc::new(AtomicUsize::new(0));
+    let ticker = tokio::spawn({
+        let ticks = Arc::clone(&ticks);
+        async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                ticks.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+
+    tokio::time::timeout(Duration::from_secs(10), h.send("/addgb 5.8"))
+        .await
+        .unwrap();
+    ticker.abort();
+
+    assert_eq!(h.bot.take_texts(), ["Glucose entry saved ✅"]);
+    assert!(
+        ticks.load(Ordering::SeqCst) >= 5,
+        "other tasks must keep running during a slow write"
+    );
+    let written = disk.join().unwrap();
+    assert_eq!(written.lines().count(), 1, "{written}");
+    assert!(written.contains(",before_meal,5.8,"), "{written}");
+}
+
+#[cfg(feature = "api")]
+#[tokio::test]
+async fn read_api_requires_the_token_and_an_allowed_chat() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let h = Harness::new();
+    h.send(&format!(
+        "/addgb 5.8 {} @fasting",
+        (Utc::now() - chrono::Duration::days(1)).format("%Y/%m/%d %H:%M")
+    ))
+    .await;
+    h.send("/addgb 9.9 2020/1/1 8:00").await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let token = "0123456789abcdef";
+    api::spawn_api_server(listener, token, Arc::clone(&h.state));
+
+    let get = |path: String, auth: Option<&'static str>| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let auth = auth
+            .map(|auth| format!("Authorization: {auth}\r\n"))
+            .unwrap_or_default();
+        stream
+            .write_all(
+                format!("GET {path} HTTP/1.1\r\nHost: test\r\n{auth}Connection: close\r\n\r\n")
+                    .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+    let bearer = Some("Bearer 0123456789abcdef");
+
+    let ok = get(format!("/api/glucose?chat={}&days=7", CHAT.0), bearer).await;
+    assert!(ok.starts_with("HTTP/1.1 200"), "{ok}");
+    assert!(ok.contains("\"chat\":1,\"days\":7,\"rows\":[{"), "{ok}");
+    assert!(
+        ok.contains("\"value_mmol_l\":5.8,\"note\":\"fasting\""),
+        "{ok}"
+    );
+    assert!(!ok.contains("9.9"), "{ok}");
+
+    let missing = get(format!("/api/glucose?chat={}", CHAT.0), None).await;
+    assert!(missing.starts_with("HTTP/1.1 401"), "{missing}");
+    let wrong = get(
+        format!("/api/glucose?chat={}", CHAT.0),
+        Some("Bearer 0123456789abcdeX"),
+    )
+    .await;
+    assert!(wrong.starts_with("HTTP/1.1 401"), "{wrong}");
+    let other = get("/api/weight?chat=2".to_string(), bearer).await;
+    assert!(other.starts_with("HTTP/1.1 404"), "{other}");
+    let days = get(format!("/api/insulin?chat={}&days=0", CHAT.0), bearer).await;
+    assert!(days.starts_with("HTTP/1.1 400"), "{days}");
+}
+
+#[tokio::test]
+async fn share_without_the_read_api_explains_what_is_missing() {
+    let h = Harness::new();
+
+    h.send("/share 0").await;
+    h.send("/share").await;
+    h.send("/revoke").await;
+
+    assert_eq!(
+        h.bot.take_texts(),
+        [
+            "Usage: /share [1-90 days]",
+            "Sharing needs the read API: build the bot with --features api and set api_listen.",
+            "You have no share links.",
+        ]
+    );
+}
+
+#[cfg(feature = "api")]
+#[tokio::test]
+async fn share_link_reads_one_chat_until_revoked() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut h = Harness::new();
+    Arc::get_mut(&mut h.state).unwrap().share_url = Some("https://diary.example".to_string());
+    h.send(&format!(
+        "/addgb 5.8 {}",
+        (Utc::now() - chrono::Duration::days(2)).format("%Y/%m/%d %H:%M")
+    ))
+    .await;
+    h.send(&format!(
+        "/addgb 9.9 {}",
+        (Utc::now() - chrono::Duration::days(10)).format("%Y/%m/%d %H:%M")
+    ))
+    .await;
+    h.bot.take_texts();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    api::spawn_api_server(listener, "0123456789abcdef", Arc::clone(&h.state));
+    let get = |path: String| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                format!("GET {path} HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n")
+                    .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    h.send("/share 7").await;
+    let reply = h.bot.take_texts().remove(0);
+    let url = reply
+        .lines()
+        .find(|line| line.starts_with("https://diary.example/api/share/"))
+        .unwrap();
+    let token = url.trim_start_matches("https://diary.example/api/share/");
+    assert_eq!(token.len(), 48, "{reply}");
+    assert!(!h.dir.read("settings.toml").contains(token));
+
+    let ok = get(format!("/api/share/{token}")).await;
+    assert!(ok.starts_with("HTTP/1.1 200"), "{ok}");
+    assert!(ok.contains("\"days\":7,\"glucose\":[{"), "{ok}");
+    assert!(ok.contains("5.8") && !ok.contains("9.9"), "{ok}");
+    let guessed = get(format!("/api/share/{}", "0".repeat(48))).await;
+    assert!(guessed.starts_with("HTTP/1.1 404"), "{guessed}");
+
+    h.send("/revoke").await;
+    assert_eq!(
+        h.bot.take_texts(),
+        ["Share link revoked ✅ It no longer works."]
+    );
+    let revoked = get(format!("/api/share/{token}")).await;
+    assert!(revoked.starts_with("HTTP/1.1 404"), "{revoked}");
+}
+
+#[tokio::test]
+async fn long_medication_lists_keep_every_medication_reachable() {
+    let h = Harness::new();
+    let names = (1..=30).map(|n| format!("Med{n:02}")).collect::<Vec<_>>();
+    for name in &names {
+        h.send(&format!("/addmed {name}")).await;
+    }
+    h.bot.take();
+
+    h.send("/menu").await;
+    let menu = h.bot.take().remove(0).button_labels();
+    assert_eq!(menu.len(), 6 + MENU_MEDICATION_BUTTONS + 1);
+    assert_eq!(menu.last().unwrap(), BTN_MORE_MEDS);
+
+    h.send(BTN_MORE_MEDS).await;
+    let more = h.bot.take().remove(0);
+    assert_eq!(
+        more.text,
+        "More medications (22). Tap one to log it, or 📋 Show menu to go back."
+    );
+    let more = more.button_labels();
+    assert_eq!(more.last().unwrap(), BTN_SHOW_MENU);
+    let reachable = menu
+        .iter()
+        .chain(&more)
+        .filter_map(|label| label.strip_prefix(MED_BUTTON_PREFIX))
+        .collect::<Vec<_>>();
+    assert_eq!(reachable, names);
+
+    h.send(&more[21]).await;
+    assert_eq!(h.bot.take_texts(), ["Medication usage saved ✅ (Med30)"]);
+    assert_eq!(data_lines(&h.dir.read(MEDICATION_LOG_FILE)).len(), 1);
+}
+
+#[tokio::test]
+async fn postmeal_setting_is_saved_and_respects_quiet_hours() {
+    let h = Harness::new();
+    h.send("/postmeal").await;
+    h.send("/postmeal on").await;
+    h.send("/postmeal").await;
+    h.send("/postmeal later").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Post-meal reminder is off.");
+    assert!(texts[1].starts_with(
+        "Post-meal reminder on ✅ After a before-meal reading you'll get one reminder 2 hours later"
+    ));
+    assert_eq!(texts[2], "Post-meal reminder is on.");
+    assert_eq!(texts[3], "Usage: /postmeal <on|off>");
+    assert!(
+        h.dir
+            .read("settings.toml")
+            .contains("postmeal_reminder = true")
+    );
+
+    let quiet = |at: &str| nudge::in_quiet_hours(&h.state.nudge, utc(at), Tz::UTC);
+    assert!(quiet("2024-02-01T23:30:00Z"));
+    assert!(quiet("2024-02-01T07:59:00Z"));
+    assert!(!quiet("2024-02-01T08:00:00Z"));
+    assert!(!quiet("2024-02-01T14:00:00Z"));
+}
+
+#[tokio::test]
+async fn postmeal_reminder_fires_once_unless_cancelled_or_quiet() {
+    tokio::time::pause();
+    let mut h = Harness::new();
+    let set_clock = |h: &mut Harness, at: &str| {
+        Arc::get_mut(&mut h.state).unwrap().input.clock = Arc::new(clock::FixedClock(utc(at)));
+    };
+    let wait_minutes = |minutes: u64| tokio::time::sleep(Duration::from_secs(minutes * 60));
+    set_clock(&mut h, "2024-02-01T12:00:00Z");
+    h.send("/postmeal on").await;
+    h.bot.take();
+
+    schedule_postmeal_reminder(&h.bot, &h.state, CHAT).await;
+    wait_minutes(POSTMEAL_REMINDER_MINUTES - 1).await;
+    assert!(h.bot.take_texts().is_empty(), "not due yet");
+    wait_minutes(2).await;
+    assert_eq!(h.bot.take_texts(), ["Time to measure glucose after meal."]);
+    wait_minutes(POSTMEAL_REMINDER_MINUTES * 2).await;
+    assert!(h.bot.take_texts().is_empty(), "the reminder fires once");
+
+    h.send("/addgb 5.8").await;
+    wait_minutes(30).await;
+    h.send("/addga 7.9").await;
+    h.bot.take();
+    wait_minutes(POSTMEAL_REMINDER_MINUTES).await;
+    assert!(
+        h.bot.take_texts().is_empty(),
+        "an after-meal reading cancels it"
+    );
+
+    set_clock(&mut h, "2024-02-01T23:00:00Z");
+    h.send("/addgb 5.8").await;
+    h.bot.take();
+    wait_minutes(POSTMEAL_REMINDER_MINUTES + 1).await;
+    assert!(
+        h.bot.take_texts().is_empty(),
+        "quiet hours drop the reminder"
+    );
+}
+
+#[tokio::test]
+async fn in_range_note_is_opt_in_and_only_for_mid_range_readings() {
+    let h = Harness::new();
+    h.send("/addgb 6.5").await;
+    h.send("/inrange on").await;
+    h.send("/inrange").await;
+    h.send("/addgb 6.5, 7.0").await;
+    h.send("/addgb 5.0").await;
+    h.send("/addgb 6.5, 11.0").await;
+    h.send("/noemoji on").await;
+    h.send("/addgb 7.0").await;
+    h.send("/inrange maybe").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Glucose entry saved ✅");
+    assert!(texts[1].starts_with("In-range note on ✅"));
+    assert_eq!(texts[2], "In-range note is on.");
+    assert_eq!(texts[3], "Glucose entries saved ✅ (2)\nNicely in range 🎯");
+    assert_eq!(texts[4], "Glucose entry saved ✅");
+    assert_eq!(
+        texts[5],
+        "Glucose entries saved ✅ (2)\n⚠️ 11 is above your before-meal target (3.9-10.0)"
+    );
+    assert!(texts[7].ends_with("\nNicely in range"));
+    assert_eq!(texts[8], "Usage: /inrange <on|off>");
+    assert!(h.dir.read("settings.toml").contains("in_range_note = true"));
+}
+
+#[test]
+fn medication_effect_report_splits_days_by_logged_medication() {
+    let taken = |timestamp: &str, name: &str| (utc(timestamp), name.to_string());
+    let glucose = vec![
+        glucose_record("2024-02-01T08:00:00+00:00", 6.0),
+        glucose_record("2024-02-01T20:00:00+00:00", 7.0),
+        glucose_record("2024-02-02T08:00:00+00:00", 6.5),
+        // Late on the 3rd in UTC is already the 4th in Kyiv, a day without the medication.
+        glucose_record("2024-02-03T23:30:00+00:00", 9.0),
+        glucose_record("2024-02-05T08:00:00+00:00", 8.0),
+        glucose_record("2024-02-06T08:00:00+00:00", 8.5),
+        glucose_record("2024-02-07T08:00:00+00:00", 5.7),
+    ];
+    let med_log = vec![
+        taken("2024-02-01T07:00:00+00:00", "Metformin"),
+        taken("2024-02-02T07:00:00+00:00", "metformin"),
+        taken("2024-02-03T07:00:00+00:00", "Metformin"),
+        taken("2024-02-05T07:00:00+00:00", "Aspirin"),
+        taken("2024-02-07T07:00:00+00:00", "💊 Metformin"),
+    ];
+    let now = utc("2024-02-08T12:00:00+00:00");
+    let report = |med_log: &[_], days| {
+        reports::medication_effect_report(
+            &glucose,
+            med_log,
+            "Metformin",
+            chrono_tz::Europe::Kyiv,
+            now,
+            days,
+        )
+    };
+    let compared = report(&med_log, 30);
+    assert!(compared.starts_with(
+        "Glucose with vs without Metformin (last 30 days, mmol/L):\n\
+Days with Metformin: avg 6.3, readings: 4, days: 3\n\
+Days without: avg 8.5, readings: 3, days: 3\n\
+Difference: -2.2 mmol/L on days with Metformin\n\
+ℹ️ This shows a correlation, not that the medication caused it"
+    ));
+    assert!(report(&med_log, 3).contains(
+        "Days with Metformin: avg 5.7, readings: 1, days: 1\n\
+Days without: avg 8.5, readings: 1, days: 1\n\
+Not enough data to compare: each group needs readings on at least 3 days."
+    ));
+    assert_eq!(
+        report(&med_log[3..4], 30),
+        "No Metformin uses logged in the last 30 days."
+    );
+}
+
+#[tokio::test]
+async fn medeffect_command_matches_the_medication_and_validates_arguments() {
+    let h = Harness::new();
+    h.send("/medeffect").await;
+    h.send("/addmed Vitamin D 1000").await;
+    h.send("💊 Vitamin D 1000").await;
+    h.send("/addgb 6.1").await;
+    h.send("/medeffect vitamin d 1000").await;
+    h.send("/medeffect Vitamn D 1000 14").await;
+
+    let texts = h.bot.take_texts();
+    assert_eq!(texts[0], "Usage: /medeffect <medication> [1-365]");
+    assert!(texts[4].starts_with(
+        "Glucose with vs without Vitamin D 1000 (last 30 days, mmol/L):\n\
+Days with Vitamin D 1000: avg 6.1, readings: 1, days: 1\n\
+Days without: no readings\n\
+Not enough data to compare"
+    ));
+    assert!(texts[5].starts_with("Glucose with vs without Vitamin D 1000 (last 14 days"));
+    assert_eq!(
+        routing::parse_command("/medeffect Vitamin D 1000"),
+        Command::MedEffect(Ok(("Vitamin D 1000", 30)))
+    );
+    assert_eq!(
+        routing::parse_command("/medeffect Vitamin D 7"),
+        Command::MedEffect(Ok(("Vitamin D", 7)))
+    );
+}
+
+#[tokio::test]
+async fn exports_can_write_local_or_utc_timestamps() {
+    let h = Harness::new();
+    h.send("/timezone Europe/Kyiv").await;
+    h.send("/addgb 5.2 2024/2/1 0:10 @late").await;
+    h.send("/addketone 0.4 2024/2/1 8:05").await;
+    h.bot.take();
+
+    h.send("/export glucose 2024-02-01 2024-02-01").await;
+    h.send("/export glucose 2024-02-01 2024-02-01 local").await;
+    h.send("/export glucose 2024-02-01 2024-02-01 UTC").await;
+    h.send("/export long local").await;
+    h.send("/export long 2024-02-01 2024-02-01 utc").await;
+    h.send("/export health utc").await;
+
+    let sent = h.bot.take();
+    let first_rows = sent[..5]
+        .iter()
+        .map(|message| {
+            let (_, bytes) = message.document.clone().unwrap();
+            String::from_utf8(bytes)
+                .unwrap()
+                .lines()
+                .nth(1)
+                .unwrap()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    assert!(first_rows[0].starts_with("2024-02-01T00:10:00+02:00,1,before_meal,5.2,"));
+    assert!(first_rows[1].starts_with("2024-02-01 00:10:00,1,before_meal,5.2,\"late\""));
+    assert!(first_rows[2].starts_with("2024-01-31T22:10:00Z,1,before_meal,5.2,"));
+    assert_eq!(
+        first_rows[3],
+        "2024-02-01 00:10:00,glucose,5.2,mmol/L,before_meal,\"late\""
+    );
+    assert_eq!(
+        first_rows[4],
+        "2024-01-31T22:10:00Z,glucose,5.2,mmol/L,before_meal,\"late\""
+    );
+    assert_eq!(
+        sent[5].text,
+        "Apple Health XML always has local times with their offset; local and utc apply to CSV exports."
+    );
+    assert_eq!(
+        routing::parse_command("/export long"),
+        Command::Export(Ok(export::ExportRequest::Long(
+            None,
+            export::ExportTimestamps::Stored
+        )))
+    );
+}
@@ -0,0 +1,128 @@
+use super::*;
+
+/// Zones offered as buttons by the first-use prompt and `/timezone`; any IANA name can
+/// still be typed.
+const COMMON_TIMEZONES: [&str; 10] = [
+    "Europe/Kyiv",
+    "Europe/Warsaw",
+    "Europe/Berlin",
+    "Europe/London",
+    "America/New_York",
+    "America/Chicago",
+    "America/Los_Angeles",
+    "Asia/Kolkata",
+    "Asia/Tokyo",
+    "UTC",
+];
+
+/// Buttons that send `/timezone <zone>` for the common zones, two per row, and
+/// [`BTN_SHOW_MENU`] to go back without choosing.
+pub(super) fn timezone_keyboard() -> KeyboardMarkup {
+    let mut rows = COMMON_TIMEZONES
+        .chunks(2)
+        .map(|zones| {
+            zones
+                .iter()
+                .map(|zone| KeyboardButton::new(format!("/timezone {zone}")))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    rows.push(vec![KeyboardButton::new(BTN_SHOW_MENU)]);
+    KeyboardMarkup::new(rows).resize_keyboard()
+}
+
+/// IANA zone `name`, ignoring case, e.g. `europe/kyiv`.
+fn parse_timezone(name: &str) -> Option<Tz> {
+    name.parse().ok().or_else(|| {
+        chrono_tz::TZ_VARIANTS
+            .into_iter()
+            .find(|tz| tz.name().eq_ignore_ascii_case(name))
+    })
+}
+
+fn prompt_text(default: Tz) -> String {
+    format!(
+        "Your timezone is not set, so dates and times you enter are read as {default} \
+and days start at midnight {default}. Choose your timezone below, or send \
+/timezone <Area/City>, e.g. /timezone Europe/Kyiv."
+    )
+}
+
+/// Asks a chat without a `/timezone` to choose one, once per chat and only when
+/// `timezone_prompt` is on. Returns whether the prompt was sent.
+pub(super) async fn prompt_if_unset<R: Responder>(
+    bot: &R,
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+) -> anyhow::Result<bool> {
+    if !state.timezone_prompt {
+        return Ok(false);
+    }
+    let prompt = storage::blocking(state, move |state| {
+        let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+        if user_settings.timezone.is_some() || user_settings.timezone_prompted == Some(true) {
+            return Ok(false);
+        }
+        user_settings.timezone_prompted = Some(true);
+        settings::save_settings(state, chat_id, &user_settings)?;
+        Ok(true)
+    })
+    .await?;
+    if prompt {
+        bot.send_text(chat_id, prompt_text(state.input.tz), timezone_keyboard())
+            .await?;
+    }
+    Ok(prompt)
+}
+
+/// Handles `/timezone [<Area/City>|off]` and returns the reply with the keyboard to send.
+pub(super) async fn timezone_command(
+    state: &Arc<AppState>,
+    chat_id: ChatId,
+    payload: &str,
+) -> anyhow::Result<(String, KeyboardMarkup)> {
+    let default = state.input.tz;
+    if payload.is_empty() {
+        let user_settings = storage::blocking(state, move |state| {
+            settings::load_settings(&state.data_dir, chat_id)
+        })
+        .await?;
+        let current = match user_settings.tz() {
+            Some(tz) => format!("Your timezone is {tz}."),
+            None => format!("Your timezone is not set; {default} is used."),
+        };
+        return Ok((
+            format!("{current} Choose one below, or send /timezone <Area/City> or /timezone off."),
+            timezone_keyboard(),
+        ));
+    }
+    let payload = payload.to_string();
+    let reply = storage::blocking(state, move |state| {
+        let mut user_settings = settings::load_settings(&state.data_dir, chat_id)?;
+        let reply = match payload.as_str() {
+            off if off.eq_ignore_ascii_case("off") => {
+                user_settings.timezone = None;
+                settings::save_settings(state, chat_id, &user_settings)?;
+                format!("Timezone reset ✅ Dates and times are read as {default}.")
+            }
+            name => match parse_timezone(name) {
+                Some(tz) => {
+                    user_settings.timezone = Some(tz.name().to_string());
+                    settings::save_settings(state, chat_id, &user_settings)?;
+                    let now = state.now().with_timezone(&tz);
+                    format!(
+                        "Timezone set to {tz} ✅ Your local time is {}.",
+                        now.format("%Y-%m-%d %H:%M")
+                    )
+                }
+                None => format!(
+                    "Unknown timezone '{name}'. Use an IANA name such as Europe/Kyiv, \
+America/New_York or UTC."
+                ),
+            },
+        };
+        Ok(reply)
+    })
+    .await?;
+    Ok((reply, menu_keyboard(state, chat_id).await))
+}
@@ -0,0 +1,300 @@
+use super::chart::{ChartFormat, GlucoseAxis, ensure_finite, midnight};
+use super::insulin::InsulinKind;
+use super::plot::{self, Plot};
+use super::records::{CarbRecord, GlucoseRecord, InsulinRecord, MealRecord};
+use super::settings::GlucoseRange;
+use chrono::{DateTime, Days, Duration, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, VPos};
+
+const TIME_FORMAT: &str = "%H:%M";
+const GLUCOSE_COLOR: RGBColor = RGBColor(26, 51, 179);
+const INSULIN_COLOR: RGBColor = RGBColor(128, 51, 153);
+const CARBS_COLOR: RGBColor = RGBColor(230, 128, 26);
+const MEAL_COLOR: RGBColor = RGBColor(51, 153, 51);
+
+/// Everything logged on one local day, each list in time order.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct DayData {
+    pub(super) day: NaiveDate,
+    pub(super) glucose: Vec<GlucoseRecord>,
+    pub(super) insulin: Vec<InsulinRecord>,
+    pub(super) carbs: Vec<CarbRecord>,
+    pub(super) meals: Vec<MealRecord>,
+}
+
+/// Keeps the rows whose timestamp falls on `day` in `tz`.
+pub(super) fn day_data(
+    glucose: &[GlucoseRecord],
+    insulin: &[InsulinRecord],
+    carbs: &[CarbRecord],
+    meals: &[MealRecord],
+    tz: Tz,
+    day: NaiveDate,
+) -> DayData {
+    fn on_day<T: Clone>(
+        rows: &[T],
+        timestamp: impl Fn(&T) -> DateTime<Utc>,
+        tz: Tz,
+        day: NaiveDate,
+    ) -> Vec<T> {
+        let mut rows = rows
+            .iter()
+            .filter(|row| timestamp(row).with_timezone(&tz).date_naive() == day)
+            .cloned()
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|row| timestamp(row));
+        rows
+    }
+    DayData {
+        day,
+        glucose: on_day(glucose, |row| row.timestamp, tz, day),
+        insulin: on_day(insulin, |row| row.timestamp, tz, day),
+        carbs: on_day(carbs, |row| row.timestamp, tz, day),
+        meals: on_day(meals, |row| row.timestamp, tz, day),
+    }
+}
+
+pub(super) fn file_name(data: &DayData, format: ChartFormat) -> String {
+    format!("today-{}.{}", data.day, format.extension())
+}
+
+/// One-line summary of the day, used as the chart caption.
+pub(super) fn caption(data: &DayData) -> String {
+    if data.glucose.is_empty()
+        && data.insulin.is_empty()
+        && data.carbs.is_empty()
+        && data.meals.is_empty()
+    {
+        return format!("Today, {}: nothing logged yet.", data.day);
+    }
+    let values = data
+        .glucose
+        .iter()
+        .map(|record| record.value)
+        .collect::<Vec<_>>();
+    let glucose = match (
+        values.iter().copied().reduce(f64::min),
+        values.iter().copied().reduce(f64::max),
+    ) {
+        (Some(low), Some(high)) => format!(
+            "glucose avg {:.1} mmol/L (readings: {}, {low:.1}-{high:.1})",
+            values.iter().sum::<f64>() / values.len() as f64,
+            values.len()
+        ),
+        _ => "no glucose readings".to_string(),
+    };
+    let correction = data
+        .insulin
+        .iter()
+        .filter(|dose| dose.kind == InsulinKind::Correction)
+        .map(|dose| dose.units)
+        .sum::<f64>();
+    let insulin = match data.insulin.len() {
+        0 => "no insulin".to_string(),
+        _ if correction > 0.0 => format!(
+            "insulin {:.1} U ({correction:.1} U correction)",
+            data.insulin.iter().map(|dose| dose.units).sum::<f64>()
+        ),
+        _ => format!(
+            "insulin {:.1} U",
+            data.insulin.iter().map(|dose| dose.units).sum::<f64>()
+        ),
+    };
+    let carbs = match data.carbs.len() {
+        0 => "no carbs".to_string(),
+        _ => format!(
+            "carbs {:.0} g",
+            data.carbs.iter().map(|carbs| carbs.grams).sum::<f64>()
+        ),
+    };
+    format!(
+        "Today, {}: {glucose}, {insulin}, {carbs}, meals: {}",
+        data.day,
+        data.meals.len()
+    )
+}
+
+/// Text form of the day for when the chart cannot be drawn: the caption, then every
+/// entry in local time order.
+pub(super) fn numbers(data: &DayData, tz: Tz) -> String {
+    let time = |timestamp: DateTime<Utc>| timestamp.with_timezone(&tz).format("%H:%M");
+    let mut entries = Vec::new();
+    entries.extend(data.glucose.iter().map(|record| {
+        (
+            record.timestamp,
+            format!("{} glucose {} mmol/L", time(record.timestamp), record.value),
+        )
+    }));
+    entries.extend(data.insulin.iter().map(|dose| {
+        (
+            dose.timestamp,
+            format!(
+                "{} insulin {} U {}",
+                time(dose.timestamp),
+                dose.units,
+                dose.kind.name()
+            ),
+        )
+    }));
+    entries.extend(data.carbs.iter().map(|carbs| {
+        (
+            carbs.timestamp,
+            format!("{} carbs {} g", time(carbs.timestamp), carbs.grams),
+        )
+    }));
+    entries.extend(data.meals.iter().map(|meal| {
+        (
+            meal.timestamp,
+            format!("{} meal {}", time(meal.timestamp), meal.note)
+                .trim_end()
+                .to_string(),
+        )
+    }));
+    entries.sort_by_key(|(timestamp, _)| *timestamp);
+    std::iter::once(caption(data))
+        .chain(entries.into_iter().map(|(_, line)| line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders the day as an image in `format`: glucose over a 24-hour axis with the target
+/// band and meal markers labeled with their notes, then insulin and carb bars on the
+/// same time axis below.
+pub(super) fn render(
+    data: &DayData,
+    target: GlucoseRange,
+    axis: GlucoseAxis,
+    tz: Tz,
+    format: ChartFormat,
+) -> anyhow::Result<Vec<u8>> {
+    ensure_finite(
+        data.glucose
+            .iter()
+            .map(|record| record.value)
+            .chain(data.insulin.iter().map(|dose| dose.units))
+            .chain(data.carbs.iter().map(|carbs| carbs.grams)),
+    )?;
+    format.render(&DayPlot {
+        data,
+        target,
+        axis,
+        tz,
+    })
+}
+
+struct DayPlot<'a> {
+    data: &'a DayData,
+    target: GlucoseRange,
+    axis: GlucoseAxis,
+    tz: Tz,
+}
+
+impl Plot for DayPlot<'_> {
+    const SIZE: (u32, u32) = (1200, 1300);
+
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) -> DrawResult<(), DB> {
+        let DayPlot {
+            data,
+            target,
+            axis,
+            tz,
+        } = *self;
+        let area = plot::page(root, &format!("Today, {}", data.day), &caption(data))?;
+        let times = (midnight(data.day), midnight(data.day + Days::new(1)));
+        let time = |timestamp: DateTime<Utc>| timestamp.with_timezone(&tz).naive_local();
+        let (_, height) = area.dim_in_pixel();
+        let (glucose_area, strips) = area.split_vertically(height * 11 / 20);
+        let (insulin_area, carbs_area) = strips.split_vertically((height - height * 11 / 20) / 2);
+
+        let mut plot = axis.plot(&glucose_area, times, TIME_FORMAT, target)?;
+        let top = axis.bounds().end;
+        plot.draw_series(data.meals.iter().map(|meal| {
+            let at = time(meal.timestamp);
+            PathElement::new(vec![(at, 0.0), (at, top)], MEAL_COLOR.stroke_width(2))
+        }))?
+        .label("meal")
+        .legend(|(x, y)| {
+            PathElement::new(vec![(x, y - 7), (x, y + 7)], MEAL_COLOR.stroke_width(2))
+        });
+        plot.draw_series(
+            data.meals
+                .iter()
+                .filter(|meal| !meal.note.is_empty())
+                .map(|meal| {
+                    Text::new(
+                        meal.note.clone(),
+                        (time(meal.timestamp) + Duration::minutes(10), top),
+                        plot::anchored(13.0, HPos::Left, VPos::Top),
+                    )
+                }),
+        )?;
+        let readings = data
+            .glucose
+            .iter()
+            .map(|record| (time(record.timestamp), axis.position(record.value)))
+            .collect::<Vec<_>>();
+        if readings.is_empty() {
+            plot::draw_empty(&mut plot, "No glucose readings today.")?;
+        }
+        plot.draw_series(LineSeries::new(readings, GLUCOSE_COLOR.stroke_width(2)).point_size(4))?
+            .label("glucose")
+            .legend(|(x, y)| Circle::new((x, y), 4, GLUCOSE_COLOR.filled()));
+        plot.configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .label_font(plot::font(14.0))
+            .draw()?;
+
+        draw_strip(
+            &insulin_area,
+            times,
+            "Insulin (U)",
+            data.insulin
+                .iter()
+                .map(|dose| (time(dose.timestamp), dose.units, dose.units.to_string()))
+                .collect(),
+            INSULIN_COLOR,
+        )?;
+        draw_strip(
+            &carbs_area,
+            times,
+            "Carbs (g)",
+            data.carbs
+                .iter()
+                .map(|carbs| {
+                    (
+                        time(carbs.timestamp),
+                        carbs.grams,
+                        format!("{:.0}", carbs.grams),
+                    )
+                })
+                .collect(),
+            CARBS_COLOR,
+        )
+    }
+}
+
+/// Bars scaled to the largest value, each labeled above, or a note that there are none.
+fn draw_strip<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    times: (NaiveDateTime, NaiveDateTime),
+    title: &str,
+    bars: Vec<(NaiveDateTime, f64, String)>,
+    color: RGBColor,
+) -> DrawResult<(), DB> {
+    let max = bars.iter().map(|(_, value, _)| *value).fold(0.0, f64::max);
+    let values = 0.0..if max > 0.0 { max * 1.25 } else { 1.0 };
+    let mut plot = plot::time_plot(area, times, values, title, TIME_FORMAT)?;
+    if max <= 0.0 {
+        return plot::draw_empty(&mut plot, "None today.");
+    }
+    let bars = bars
+        .into_iter()
+        .map(|(time, value, label)| (time, value, Some(label)))
+        .collect::<Vec<_>>();
+    plot::draw_bars(&mut plot, &bars, Duration::minutes(6), color)
+}
@@ -0,0 +1,131 @@
+use super::records::{glucose_unit_field, parse_csv_line, parse_timestamp};
+use super::settings::GlucoseUnit;
+use super::*;
+
+const NOTE_COLUMN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum UnitsRequest {
+    Show,
+    /// Store new rows in this unit; existing rows keep theirs.
+    Set(GlucoseUnit),
+    /// Also rewrite existing rows, after a yes-confirmation.
+    Convert(GlucoseUnit),
+}
+
+pub(super) fn parse_units_args(args: &str) -> Result<UnitsRequest, String> {
+    let usage = || "Usage: /units <mmol|mgdl> [convert]".to_string();
+    let parts = args.split_whitespace().collect::<Vec<_>>();
+    match parts.as_slice() {
+        [] => Ok(UnitsRequest::Show),
+        [unit] => GlucoseUnit::parse(unit)
+            .map(UnitsRequest::Set)
+            .ok_or_else(usage),
+        [unit, convert] if convert.eq_ignore_ascii_case("convert") => GlucoseUnit::parse(unit)
+            .map(UnitsRequest::Convert)
+            .ok_or_else(usage),
+        _ => Err(usage()),
+    }
+}
+
+/// Parses `/convert <value> <mmol|mgdl>`: a glucose value and the unit it is given in.
+/// The value must be a plausible reading once converted to mmol/L.
+pub(super) fn parse_convert_args(args: &str) -> Result<(f64, GlucoseUnit), String> {
+    let usage = || "Usage: /convert <value> <mmol|mgdl>, e.g. /convert 104 mgdl".to_string();
+    let [value, unit] = args.split_whitespace().collect::<Vec<_>>()[..] else {
+        return Err(usage());
+    };
+    let (Some(value), Some(unit)) = (parse_decimal(value), GlucoseUnit::parse(unit)) else {
+        return Err(usage());
+    };
+    if !(GLUCOSE_MIN_MMOL_L..=GLUCOSE_MAX_MMOL_L).contains(&unit.to_mmol_l(value)) {
+        return Err(format!(
+            "Glucose value {value} {} is out of range ({GLUCOSE_MIN_MMOL_L:.1}-{GLUCOSE_MAX_MMOL_L:.1} mmol/L, \
+{:.0}-{:.0} mg/dL)",
+            unit.csv_name(),
+            mmol_l_to_mg_dl(GLUCOSE_MIN_MMOL_L),
+            mmol_l_to_mg_dl(GLUCOSE_MAX_MMOL_L)
+        ));
+    }
+    Ok((value, unit))
+}
+
+/// `/convert` reply: the value in the other unit and the factor used. Nothing is saved.
+pub(super) fn convert_reply(value: f64, unit: GlucoseUnit) -> String {
+    match unit {
+        GlucoseUnit::Mmol => format!(
+            "{value:.1} mmol/L = {:.0} mg/dL\n(mmol/L × {MG_DL_PER_MMOL_L:.0} = mg/dL; not saved)",
+            mmol_l_to_mg_dl(value)
+        ),
+        GlucoseUnit::Mgdl => format!(
+            "{value:.0} mg/dL = {:.1} mmol/L\n(mg/dL ÷ {MG_DL_PER_MMOL_L:.0} = mmol/L; not saved)",
+            unit.to_mmol_l(value)
+        ),
+    }
+}
+
+/// Result of [`convert_glucose_file`].
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Conversion {
+    pub(super) converted: usize,
+    /// Name of the copy made before rewriting, in the user's data directory.
+    pub(super) backup: Option<String>,
+}
+
+/// Rewrites every glucose row in `unit`, converting its value and `unit` column. The
+/// original file is copied to `glucose.csv.<timestamp>.bak` first and the rewrite is
+/// atomic. Rows that cannot be parsed are kept unchanged.
+pub(super) fn convert_glucose_file(
+    data_dir: &Path,
+    chat_id: ChatId,
+    unit: GlucoseUnit,
+    now: DateTime<Utc>,
+    sync: bool,
+) -> anyhow::Result<Conversion> {
+    let dir = user_data_dir(data_dir, chat_id);
+    let path = dir.join(GLUCOSE_FILE);
+    if !path.exists() {
+        return Ok(Conversion {
+            converted: 0,
+            backup: None,
+        });
+    }
+    let backup = format!("{GLUCOSE_FILE}.{}.bak", now.format("%Y%m%dT%H%M%SZ"));
+    fs_err::copy(&path, dir.join(&backup))?;
+
+    let content = fs_err::read_to_string(&path)?;
+    let mut rewritten = format!("{GLUCOSE_HEADER}\n");
+    let mut converted = 0;
+    for line in content.lines().skip(1) {
+        match convert_row(line, unit) {
+            Some(row) => {
+                rewritten.push_str(&row);
+                converted += 1;
+            }
+            None => rewritten.push_str(line),
+        }
+        rewritten.push('\n');
+    }
+    write_file_atomically(&path, &rewritten, sync)?;
+    tracing::info!(
+        "chat {} converted {converted} glucose rows to {}",
+        chat_id.0,
+        unit.csv_name()
+    );
+    Ok(Conversion {
+        converted,
+        backup: Some(backup),
+    })
+}
+
+fn convert_row(line: &str, unit: GlucoseUnit) -> Option<String> {
+    let mut fields = parse_csv_line(line);
+    parse_timestamp(fields.first()?)?;
+    let row_unit = glucose_unit_field(&fields).ok()?;
+    let value = row_unit.to_mmol_l(fields.get(3)?.parse().ok()?);
+    fields.resize(GLUCOSE_HEADER.split(',').count(), String::new());
+    fields[3] = unit.format_stored(value);
+    fields[GLUCOSE_UNIT_COLUMN] = unit.csv_name().to_string();
+    fields[NOTE_COLUMN] = format!("\"{}\"", csv_escape(&fields[NOTE_COLUMN]));
+    Some(fields.join(","))
+}
@@ -0,0 +1,144 @@
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Upper bound for one transcription, so a stuck provider does not hold the handler.
+const TRANSCRIPTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub(super) type TranscriptFuture<'a> =
+    Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + 'a>>;
+
+/// Speech-to-text provider for voice replies to a glucose prompt.
+pub(super) trait Transcriber: std::fmt::Debug + Send + Sync {
+    /// Returns the transcript of an OGG/Opus voice message.
+    fn transcribe(&self, audio: Vec<u8>) -> TranscriptFuture<'_>;
+}
+
+/// Runs `voice_transcription_command` with the path of the voice file appended as the
+/// last argument and takes its standard output as the transcript.
+#[derive(Debug, Clone)]
+pub(super) struct CommandTranscriber {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandTranscriber {
+    pub(super) fn parse(command: &str) -> anyhow::Result<Self> {
+        let mut parts = command.split_whitespace().map(str::to_string);
+        let program = parts
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("voice_transcription_command is empty"))?;
+        Ok(CommandTranscriber {
+            program,
+            args: parts.collect(),
+        })
+    }
+
+    async fn run(&self, audio: Vec<u8>) -> anyhow::Result<String> {
+        // A new file with a random name, readable only by us; nothing planted at a guessed
+        // path in the shared temp dir is followed. It is removed when `file` is dropped.
+        let mut file = tempfile::Builder::new()
+            .prefix("pdd-bot-voice-")
+            .suffix(".ogg")
+            .tempfile()?;
+        file.write_all(&audio)?;
+        file.flush()?;
+        let output = tokio::time::timeout(
+            TRANSCRIPTION_TIMEOUT,
+            tokio::process::Command::new(&self.program)
+                .args(&self.args)
+                .arg(file.path())
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await;
+        drop(file);
+        let output = output
+            .map_err(|_| anyhow::anyhow!("transcription timed out"))?
+            .map_err(|e| anyhow::anyhow!("failed to run {}: {e}", self.program))?;
+        if !output.status.success() {
+            anyhow::bail!("{} exited with {}", self.program, output.status);
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+impl Transcriber for CommandTranscriber {
+    fn transcribe(&self, audio: Vec<u8>) -> TranscriptFuture<'_> {
+        Box::pin(self.run(audio))
+    }
+}
+
+const NUMBER_WORDS: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+/// Finds the single number in a transcript, e.g. "five point eight" or "it's 5,8".
+/// Returns `None` when there is no number or more than one.
+pub(super) fn parse_spoken_number(transcript: &str) -> Option<f64> {
+    let lower = transcript.to_lowercase();
+    let tokens = lower
+        .split(|ch: char| ch.is_whitespace() || ch == '-')
+        .map(|token| {
+            token.trim_matches(|ch: char| !ch.is_ascii_alphanumeric() && ch != '.' && ch != ',')
+        })
+        .map(|token| token.trim_end_matches(['.', ',']))
+        .filter(|token| !token.is_empty())
+        .map(
+            |token| match NUMBER_WORDS.iter().position(|word| *word == token) {
+                Some(number) => number.to_string(),
+                None if token == "point" || token == "comma" => ".".to_string(),
+                None => token.replace(',', "."),
+            },
+        )
+        .collect::<Vec<_>>();
+
+    let mut numbers = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        if !is_numeral(&tokens[index]) {
+            index += 1;
+            continue;
+        }
+        let mut number = tokens[index].clone();
+        if tokens.get(index + 1).map(String::as_str) == Some(".")
+            && tokens
+                .get(index + 2)
+                .is_some_and(|digits| !digits.contains('.') && is_numeral(digits))
+        {
+            number = format!("{number}.{}", tokens[index + 2]);
+            index += 2;
+        }
+        numbers.push(number.parse::<f64>().ok()?);
+        index += 1;
+    }
+    match numbers.as_slice() {
+        [value] => Some(*value),
+        _ => None,
+    }
+}
+
+fn is_numeral(token: &str) -> bool {
+    token.chars().any(|ch| ch.is_ascii_digit())
+        && token.chars().all(|ch| ch.is_ascii_digit() || ch == '.')
+}